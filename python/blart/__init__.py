@@ -1,6 +1,17 @@
 """High-performance adaptive radix tree for Python."""
 
+from collections.abc import ItemsView, KeysView, MutableMapping, ValuesView
+
+from blart._blart import PyConcurrentTreeMap as ConcurrentTreeMap
 from blart._blart import PyTreeMap as TreeMap
+from blart._blart import PyTreeCounter as TreeCounter
+from blart._blart import PyTreeMapItemsView, PyTreeMapKeysView, PyTreeMapValuesView
+from blart._blart import PyTreeMultiMap as TreeMultiMap
+
+MutableMapping.register(TreeMap)
+KeysView.register(PyTreeMapKeysView)
+ValuesView.register(PyTreeMapValuesView)
+ItemsView.register(PyTreeMapItemsView)
 
 __version__ = "0.1.0"
-__all__ = ["TreeMap"]
+__all__ = ["ConcurrentTreeMap", "TreeCounter", "TreeMap", "TreeMultiMap"]
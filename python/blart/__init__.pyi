@@ -1,8 +1,62 @@
 """Type stubs for blart package."""
 
-from typing import Any, Dict, Iterable, Iterator, Optional, Tuple, overload
+from typing import (
+    Any,
+    Callable,
+    Dict,
+    Generic,
+    Iterable,
+    ItemsView,
+    Iterator,
+    KeysView,
+    List,
+    Literal,
+    Mapping,
+    Optional,
+    Tuple,
+    TypeVar,
+    Union,
+    ValuesView,
+    overload,
+)
+
+_T = TypeVar("_T")
+_K = TypeVar("_K")
+_V = TypeVar("_V")
+
+class TreeMapIterator(Iterator[_T]):
+    """Iterator returned by TreeMap iteration, prefix and fuzzy queries.
+
+    Knows how many results are left, so list() can pre-allocate and
+    progress bars can show totals.
+    """
+
+    def __next__(self) -> _T: ...
+    def remaining(self) -> int:
+        """Number of results not yet yielded."""
+        ...
+    def __length_hint__(self) -> int: ...
+
+class FuzzyIterator(Iterator[Tuple[_K, _V, Union[int, float]]], Generic[_K, _V]):
+    """Iterator over fuzzy_search() results, as (key, value, distance)."""
+
+    def remaining(self) -> Optional[int]:
+        """Number of results not yet yielded, or None while matches are
+        still being found lazily."""
+        ...
+    def __length_hint__(self) -> int: ...
+
+class TreeMapILoc(Generic[_K]):
+    """Positional view of a TreeMap's keys, returned by TreeMap.iloc."""
+
+    @overload
+    def __getitem__(self, index: int) -> _K: ...
+    @overload
+    def __getitem__(self, index: slice) -> List[_K]: ...
+    def __delitem__(self, index: Union[int, slice]) -> None: ...
+    def __len__(self) -> int: ...
 
-class TreeMap:
+class TreeMap(Generic[_K, _V]):
     """Adaptive radix tree implementation using an adaptive radix tree (ART).
 
     TreeMap provides a dictionary-like interface with efficient operations
@@ -22,30 +76,322 @@ class TreeMap:
     """
 
     @overload
-    def __init__(self) -> None:
+    def __init__(
+        self,
+        *,
+        default_factory: Optional[Callable[[], Any]] = None,
+        key_type: Any = None,
+        separator: Optional[str] = None,
+        errors: Optional[Literal["strict", "replace", "surrogateescape", "bytes"]] = None,
+        case_insensitive: bool = False,
+        normalize: Optional[Literal["NFC", "NFD", "NFKC", "NFKD"]] = None,
+        did_you_mean: bool = False,
+        substring_index: bool = False,
+        order_statistics: bool = False,
+        max_len: Optional[int] = None,
+        eviction: Literal["reject", "evict_first", "evict_last", "evict_oldest"] = "reject",
+        loader: Optional[Callable[[Any], Any]] = None,
+        versioned: bool = False,
+        max_versions: Optional[int] = None,
+        value_type: Optional[Literal["int", "float", "bytes"]] = None,
+        store_pickled: bool = False,
+        pickle_cache_size: int = 128,
+        intern_values: bool = False,
+        compress_values: Optional[Literal["zstd", "zlib"]] = None,
+        compress_threshold: int = 1024,
+    ) -> None:
         """Create an empty TreeMap."""
         ...
 
     @overload
-    def __init__(self, data: Dict[str, Any]) -> None:
-        """Create a TreeMap from a dictionary."""
+    def __init__(
+        self,
+        data: Mapping[_K, _V],
+        *,
+        default_factory: Optional[Callable[[], Any]] = None,
+        key_type: Any = None,
+        separator: Optional[str] = None,
+        errors: Optional[Literal["strict", "replace", "surrogateescape", "bytes"]] = None,
+        case_insensitive: bool = False,
+        normalize: Optional[Literal["NFC", "NFD", "NFKC", "NFKD"]] = None,
+        did_you_mean: bool = False,
+        substring_index: bool = False,
+        order_statistics: bool = False,
+        max_len: Optional[int] = None,
+        eviction: Literal["reject", "evict_first", "evict_last", "evict_oldest"] = "reject",
+        loader: Optional[Callable[[Any], Any]] = None,
+        versioned: bool = False,
+        max_versions: Optional[int] = None,
+        value_type: Optional[Literal["int", "float", "bytes"]] = None,
+        store_pickled: bool = False,
+        pickle_cache_size: int = 128,
+        intern_values: bool = False,
+        compress_values: Optional[Literal["zstd", "zlib"]] = None,
+        compress_threshold: int = 1024,
+    ) -> None:
+        """Create a TreeMap from a mapping, such as a dict or another TreeMap."""
         ...
 
     @overload
-    def __init__(self, data: Iterable[Tuple[str, Any]]) -> None:
+    def __init__(
+        self,
+        data: Iterable[Tuple[_K, _V]],
+        *,
+        default_factory: Optional[Callable[[], Any]] = None,
+        key_type: Any = None,
+        separator: Optional[str] = None,
+        errors: Optional[Literal["strict", "replace", "surrogateescape", "bytes"]] = None,
+        case_insensitive: bool = False,
+        normalize: Optional[Literal["NFC", "NFD", "NFKC", "NFKD"]] = None,
+        did_you_mean: bool = False,
+        substring_index: bool = False,
+        order_statistics: bool = False,
+        max_len: Optional[int] = None,
+        eviction: Literal["reject", "evict_first", "evict_last", "evict_oldest"] = "reject",
+        loader: Optional[Callable[[Any], Any]] = None,
+        versioned: bool = False,
+        max_versions: Optional[int] = None,
+        value_type: Optional[Literal["int", "float", "bytes"]] = None,
+        store_pickled: bool = False,
+        pickle_cache_size: int = 128,
+        intern_values: bool = False,
+        compress_values: Optional[Literal["zstd", "zlib"]] = None,
+        compress_threshold: int = 1024,
+    ) -> None:
         """Create a TreeMap from an iterable of (key, value) tuples."""
         ...
 
-    def insert(self, key: str, value: Any) -> None:
+    @property
+    def default_factory(self) -> Optional[Callable[[], Any]]:
+        """The callable used by tree[key] to create missing values, or None."""
+        ...
+
+    @property
+    def loader(self) -> Optional[Callable[[Any], Any]]:
+        """The callable used by get() and tree[key] to load missing keys, or None."""
+        ...
+
+    @property
+    def key_type(self) -> Any:
+        """How keys are stored: "str", "bytes", "int", "uint", "tuple",
+        "datetime", "uuid", "ip", "path" or a custom codec.
+
+        Integer keys are encoded as fixed-width big-endian bytes (with the
+        sign bit flipped for "int"), so iteration, first() and last() follow
+        numeric order.
+
+        Tuple keys may contain None, bool, int, float, str, bytes and nested
+        tuples. They sort field by field, and prefix_iter() accepts a shorter
+        tuple to scan every key that starts with those fields.
+
+        Datetime keys accept datetime objects or epoch seconds and are stored
+        with microsecond precision. Naive datetimes are taken to be UTC, aware
+        ones are converted to UTC, and keys come back as naive UTC datetimes.
+
+        UUID keys must be uuid.UUID instances and are stored as their 16 raw
+        bytes rather than 36-character strings.
+
+        IP keys accept ipaddress addresses, networks, or their string forms
+        and come back as ip_network objects. Use lookup() for longest-prefix
+        match, and prefix_iter(network) to list the subnets inside a network.
+
+        Path keys accept str or os.PathLike values. Slashes, backslashes and
+        the configured separator all split components; empty and "."
+        components are dropped, and keys come back as separator-joined str.
+
+        A custom codec provides ``encode(key) -> bytes`` and
+        ``decode(data) -> key``; its encoding must preserve key ordering.
+        """
+        ...
+
+    @property
+    def errors(self) -> Optional[str]:
+        """How stored str keys that are not valid UTF-8 are returned.
+
+        "strict" raises UnicodeDecodeError, "replace" substitutes U+FFFD,
+        "surrogateescape" round-trips the raw bytes through lone surrogates
+        (accepted on input as well), and "bytes" returns such keys as bytes
+        (and accepts bytes keys on input). None for non-str key types.
+        """
+        ...
+
+    @property
+    def case_insensitive(self) -> bool:
+        """Whether keys are compared case-insensitively.
+
+        In case-insensitive mode keys are case folded for storage and lookup
+        (so "Straße" and "STRASSE" are the same key),
+        while iteration returns each key as it was first inserted.
+        """
+        ...
+
+    @property
+    def normalize(self) -> Optional[str]:
+        """The Unicode normalization form applied to keys, or None.
+
+        When set, keys are normalized on insert and on every lookup so that
+        canonically equivalent strings (e.g. precomposed and decomposed
+        accents) refer to the same entry.
+        """
+        ...
+
+    @property
+    def did_you_mean(self) -> bool:
+        """Whether KeyErrors for missing str keys name the closest stored keys.
+
+        When enabled, ``tree["colr"]``, ``remove()`` and ``pop()`` raise e.g.
+        ``KeyError: "'colr' (did you mean 'color'?)"``. Can be assigned.
+        """
+        ...
+    @did_you_mean.setter
+    def did_you_mean(self, enabled: bool) -> None: ...
+
+    @property
+    def substring_index(self) -> bool:
+        """Whether the tree keeps a suffix index for contains_substring()."""
+        ...
+
+    @property
+    def suffix_index(self) -> bool:
+        """Whether enable_suffix_index() has been called."""
+        ...
+
+    @property
+    def order_statistics(self) -> bool:
+        """Whether the tree keeps subtree sizes for counting and ranking.
+
+        When enabled, count_range(), count_prefix(), rank() and select()
+        take O(log n) time instead of walking the keys.
+        """
+        ...
+
+    @property
+    def max_len(self) -> Optional[int]:
+        """The most entries the tree may hold, or None for no limit."""
+        ...
+
+    @property
+    def eviction(self) -> Literal["reject", "evict_first", "evict_last", "evict_oldest"]:
+        """What inserting a new key into a full tree does.
+
+        "reject" raises OverflowError, "evict_first" and "evict_last" remove
+        the smallest or largest key, and "evict_oldest" removes the key
+        inserted longest ago (replacing a value keeps its place).
+        """
+        ...
+
+    @property
+    def value_type(self) -> Optional[Literal["int", "float", "bytes"]]:
+        """The type values are restricted to, or None for any value.
+
+        Values are converted on insert (an int to a float, a bytearray to
+        bytes) and anything else raises TypeError at once. They are kept as
+        native values rather than Python objects, unless the tree also
+        pickles, compresses or interns them.
+        """
+        ...
+
+    @property
+    def store_pickled(self) -> bool:
+        """Whether values are stored pickled and unpickled on access.
+
+        Reads return a fresh copy unless the value is among the
+        pickle_cache_size most recently read, so values should be treated
+        as immutable.
+        """
+        ...
+
+    @property
+    def intern_values(self) -> bool:
+        """Whether equal immutable values are stored as one shared object."""
+        ...
+
+    @property
+    def compress_values(self) -> Optional[Literal["zstd", "zlib"]]:
+        """The codec large values are compressed with, or None."""
+        ...
+
+    def insert(self, key: _K, value: _V, ttl: Optional[float] = None) -> None:
         """Insert or update a key-value pair.
 
+        With a ttl, the entry expires that many seconds later: the next read
+        or write of the tree removes it, reporting it to observers as
+        evicted. Storing a new value under the key without a ttl makes it
+        permanent again.
+
+        Args:
+            key: The key to insert (must be a string)
+            value: The value to associate with the key
+            ttl: Optional lifetime of the entry in seconds
+
+        Raises:
+            ValueError: If ttl is not a positive number of seconds
+        """
+        ...
+
+    def try_insert(self, key: _K, value: _V) -> bool:
+        """Insert a key-value pair only if the key is not already present.
+
         Args:
             key: The key to insert (must be a string)
             value: The value to associate with the key
+
+        Returns:
+            True if the value was inserted, False if the key already existed
+        """
+        ...
+
+    def get_or_insert(self, key: _K, factory: Callable[[], _V]) -> _V:
+        """Get the value for a key, inserting factory() if the key is missing.
+
+        The factory is called at most once, and only when the key is absent.
+
+        Args:
+            key: The key to look up (must be a string)
+            factory: Callable producing the value to insert
+
+        Returns:
+            The stored value, or the newly inserted value
+        """
+        ...
+
+    def apply(
+        self, key: _K, func: Callable[[_V], _V], default: Optional[_V] = None
+    ) -> _V:
+        """Replace the value for a key with func(value).
+
+        Args:
+            key: The key to update (must be a string)
+            func: Callable taking the current value and returning the new one
+            default: Value passed to func when the key is missing. If None,
+                a missing key raises KeyError instead.
+
+        Returns:
+            The new value stored under the key
+
+        Raises:
+            KeyError: If the key does not exist and no default is given
+        """
+        ...
+
+    def increment(self, key: _K, delta: Any = 1) -> Any:
+        """Add delta to the value stored under a key.
+
+        Missing keys are treated as 0.
+
+        Args:
+            key: The key to increment (must be a string)
+            delta: Amount to add (default: 1)
+
+        Returns:
+            The new value stored under the key
+
+        Raises:
+            TypeError: If the stored value and delta cannot be added
         """
         ...
 
-    def get(self, key: str, default: Optional[Any] = None) -> Optional[Any]:
+    def get(self, key: _K, default: Optional[_V] = None) -> Optional[_V]:
         """Get a value by key with optional default.
 
         Args:
@@ -53,11 +399,13 @@ class TreeMap:
             default: Value to return if key is not found (default: None)
 
         Returns:
-            The value associated with the key, or default if not found
+            The value associated with the key, or default if not found. With
+            a loader, a missing key is loaded and stored instead; if the
+            loader raises, the key counts as not found.
         """
         ...
 
-    def remove(self, key: str) -> Any:
+    def remove(self, key: _K) -> _V:
         """Remove a key and return its value.
 
         Args:
@@ -71,176 +419,1832 @@ class TreeMap:
         """
         ...
 
-    def clear(self) -> None:
-        """Remove all entries from the TreeMap."""
+    def rename(self, old_key: _K, new_key: _K, overwrite: bool = False) -> None:
+        """Move a value to a new key in one call.
+
+        Either the move happens or, when an error is raised, the tree is
+        unchanged.
+
+        Args:
+            old_key: Key to move
+            new_key: Key to move the value to
+            overwrite: Replace (or evict) whatever is stored at new_key
+
+        Raises:
+            KeyError: If old_key does not exist, or new_key is taken (or
+                would evict a stored key) and overwrite is False
+
+        Examples:
+            >>> tree = TreeMap({"draft": 1})
+            >>> tree.rename("draft", "published")
+            >>> list(tree.items())
+            [('published', 1)]
+        """
         ...
 
-    def is_empty(self) -> bool:
-        """Check if the TreeMap is empty.
+    def rename_prefix(
+        self, old_prefix: str, new_prefix: str, overwrite: bool = False
+    ) -> int:
+        """Move every key under one prefix to another prefix.
+
+        Without overwrite, nothing is moved if a new key is already stored
+        or would evict a stored key.
+
+        Args:
+            old_prefix: Prefix of the keys to move
+            new_prefix: Prefix to put in its place
+            overwrite: Replace (or evict) stored keys in the way
+
+        Returns:
+            Number of keys moved
+
+        Raises:
+            TypeError: If the tree does not have str or bytes keys
+            KeyError: If a new key is taken and overwrite is False
+
+        Examples:
+            >>> tree = TreeMap({"acme:1": "ann", "acme:2": "bob"})
+            >>> tree.rename_prefix("acme:", "initech:")
+            2
+        """
+        ...
+
+    @overload
+    def pop(self, key: _K) -> _V: ...
+    @overload
+    def pop(self, key: _K, default: _T) -> Union[_V, _T]:
+        """Remove a key and return its value, or default if it is missing.
+
+        Args:
+            key: The key to remove
+            default: Optional value returned when the key is missing
+
+        Raises:
+            KeyError: If the key does not exist and no default was given
+        """
+        ...
+
+    def popitem(self) -> Tuple[_K, _V]:
+        """Remove and return the last (key, value) pair.
+
+        Raises:
+            KeyError: If the TreeMap is empty
+        """
+        ...
+
+    def setdefault(self, key: _K, default: Optional[_V] = None) -> _V:
+        """Return the value for key, inserting default first if it is missing."""
+        ...
+
+    def update(self, other: Any = None, **kwargs: Any) -> None:
+        """Update from a mapping or iterable of (key, value) pairs, and kwargs.
+
+        All keys are encoded first, so a key of the wrong type leaves the tree
+        unchanged.
+        """
+        ...
+
+    def insert_from_numpy(self, keys: Any, values: Any, *, offsets: Any = None) -> None:
+        """Insert keys from a NumPy array and values from a matching sequence.
+
+        Bytes ("S") and unicode ("U") arrays are read straight from the array's
+        memory. With `offsets`, `keys` is a flat buffer of concatenated keys
+        and key `i` spans `keys[offsets[i]:offsets[i + 1]]`.
+
+        Raises:
+            ValueError: If keys and values differ in length, the offsets are
+                out of range, or str keys are not valid UTF-8
+            TypeError: If the keys don't match the key_type
+        """
+        ...
+
+    def get_many_numpy(
+        self,
+        keys: Any,
+        *,
+        dtype: Literal["float64", "int64"] = "float64",
+        fill: Optional[float] = None,
+        offsets: Any = None,
+    ) -> Tuple[Any, Any]:
+        """Look up many keys at once into NumPy arrays of numbers.
+
+        Keys are read like insert_from_numpy() and the tree is searched with
+        the GIL released. Missing keys get `fill` (NaN for float64, 0 for
+        int64 by default) and are False in the mask. Values of an "int" or
+        "float" value_type tree are read with the GIL released too.
+
+        Returns:
+            Tuple of (values, found) NumPy arrays, with found a bool mask
+
+        Raises:
+            ValueError: If dtype is not supported
+            TypeError: If a value found can't be converted to dtype
+        """
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Equal to any mapping holding the same items."""
+        ...
+
+    @overload
+    def diff(
+        self, other: "TreeMap[_K, Any]", values: Literal[False] = False
+    ) -> Tuple[List[_K], List[_K], List[_K]]: ...
+    @overload
+    def diff(
+        self, other: "TreeMap[_K, _V]", values: Literal[True]
+    ) -> Tuple[List[Tuple[_K, _V]], List[_K], List[Tuple[_K, _V]]]: ...
+    def diff(self, other: "TreeMap[_K, Any]", values: bool = False) -> Tuple[List[Any], List[_K], List[Any]]:
+        """Compare the keys and values of two TreeMaps in one ordered pass.
+
+        Args:
+            other: TreeMap to compare against, with the same key type
+            values: Report added and changed entries as (key, value) pairs
+                holding other's values, so the result is a patch that
+                apply_patch() applies without a source
+
+        Returns:
+            Tuple of (added, removed, changed) key lists in key order: keys
+            only in other, keys only in this tree, and shared keys whose
+            values differ (compared with ==)
+
+        Raises:
+            TypeError: If the trees have different key types
+
+        Examples:
+            >>> old = TreeMap({"a": 1, "b": 2, "c": 3})
+            >>> old.diff(TreeMap({"b": 2, "c": 30, "d": 4}))
+            (['d'], ['a'], ['c'])
+            >>> old.diff(TreeMap({"b": 2, "c": 30, "d": 4}), values=True)
+            ([('d', 4)], ['a'], [('c', 30)])
+        """
+        ...
+
+    def union_keys(self, other: Union["TreeMap[_K, Any]", Iterable[_K]]) -> List[_K]:
+        """Keys stored in this tree, in other, or in both.
+
+        Args:
+            other: TreeMap with the same key type, or any iterable of keys
+
+        Returns:
+            Keys in either, in key order
+
+        Raises:
+            TypeError: If other is a TreeMap with a different key type
+
+        Examples:
+            >>> TreeMap({"a": 1, "b": 2}).union_keys(TreeMap({"b": 3, "c": 4}))
+            ['a', 'b', 'c']
+        """
+        ...
+
+    def intersection_keys(self, other: Union["TreeMap[_K, Any]", Iterable[_K]]) -> List[_K]:
+        """Keys stored both in this tree and in other.
+
+        Args:
+            other: TreeMap with the same key type, or any iterable of keys
+
+        Returns:
+            Keys in both, in key order
+
+        Raises:
+            TypeError: If other is a TreeMap with a different key type
+
+        Examples:
+            >>> TreeMap({"a": 1, "b": 2}).intersection_keys(TreeMap({"b": 3, "c": 4}))
+            ['b']
+        """
+        ...
+
+    def difference_keys(self, other: Union["TreeMap[_K, Any]", Iterable[_K]]) -> List[_K]:
+        """Keys stored in this tree but not in other.
+
+        Args:
+            other: TreeMap with the same key type, or any iterable of keys
+
+        Returns:
+            Keys only in this tree, in key order
+
+        Raises:
+            TypeError: If other is a TreeMap with a different key type
+
+        Examples:
+            >>> TreeMap({"a": 1, "b": 2}).difference_keys(TreeMap({"b": 3, "c": 4}))
+            ['a']
+        """
+        ...
+
+    def symmetric_difference_keys(self, other: Union["TreeMap[_K, Any]", Iterable[_K]]) -> List[_K]:
+        """Keys stored in exactly one of this tree and other.
+
+        Args:
+            other: TreeMap with the same key type, or any iterable of keys
+
+        Returns:
+            Keys in only one of them, in key order
+
+        Raises:
+            TypeError: If other is a TreeMap with a different key type
+
+        Examples:
+            >>> TreeMap({"a": 1, "b": 2}).symmetric_difference_keys(TreeMap({"b": 3, "c": 4}))
+            ['a', 'c']
+        """
+        ...
+
+    def issubset(self, other: "TreeMap", values: bool = False) -> bool:
+        """Whether every key of this tree is also stored in other.
+
+        Stops at the first key other is missing.
+
+        Args:
+            other: TreeMap with the same key type
+            values: Also require the values to be equal
+
+        Raises:
+            TypeError: If the trees have different key types
+
+        Examples:
+            >>> TreeMap({"a": 1}).issubset(TreeMap({"a": 2, "b": 3}))
+            True
+        """
+        ...
+
+    def issuperset(self, other: "TreeMap", values: bool = False) -> bool:
+        """Whether every key of other is also stored in this tree.
+
+        Args:
+            other: TreeMap with the same key type
+            values: Also require the values to be equal
+
+        Raises:
+            TypeError: If the trees have different key types
+
+        Examples:
+            >>> TreeMap({"a": 1, "b": 2}).issuperset(TreeMap({"b": 2}), values=True)
+            True
+        """
+        ...
+
+    def apply_patch(
+        self,
+        patch: Tuple[Any, Iterable[str], Any],
+        source: Optional[Mapping[str, Any]] = None,
+        atomic: bool = True,
+    ) -> int:
+        """Apply a patch of inserts, deletes and updates, as returned by diff().
+
+        Added and changed entries are mappings or (key, value) pairs (as
+        diff(values=True) returns), or plain keys whose values are read from
+        source. Deletes are applied first,
+        then updates, then inserts. With atomic, every operation is checked
+        first and nothing changes if one does not apply; without it, such
+        operations are skipped.
+
+        Args:
+            patch: Tuple of (added, removed, changed)
+            source: Mapping the values of plain added and changed keys are
+                read from
+            atomic: Apply all operations or none
+
+        Returns:
+            Number of operations applied
+
+        Raises:
+            KeyError: If atomic is True and an operation does not apply
+            ValueError: If the patch is not a tuple of three parts
+            TypeError: If added or changed holds plain keys and no source
+                is given
+
+        Examples:
+            >>> old = TreeMap({"a": 1, "b": 2, "c": 3})
+            >>> new = TreeMap({"b": 2, "c": 30, "d": 4})
+            >>> old.apply_patch(old.diff(new), new)
+            3
+        """
+        ...
+
+    def merge(
+        self,
+        other: "TreeMap",
+        resolve: Optional[Callable[[str, Any, Any], Any]] = None,
+        *,
+        inplace: bool = False,
+    ) -> Optional["TreeMap"]:
+        """Combine this tree with another, resolving keys stored in both.
+
+        Keys stored in only one tree are copied without calling resolve.
+        Without resolve, the value from other wins, as with update().
+
+        Args:
+            other: TreeMap to merge in, with the same key type
+            resolve: Called as resolve(key, ours, theirs) for each key stored
+                in both trees; returns the value to keep
+            inplace: Update this tree instead of returning a new one
+
+        Returns:
+            The merged TreeMap, or None when inplace is True
+
+        Raises:
+            TypeError: If the trees have different key types
+
+        Examples:
+            >>> a = TreeMap({"x": 1, "y": 2})
+            >>> dict(a.merge(TreeMap({"y": 10, "z": 3}), lambda k, o, t: o + t))
+            {'x': 1, 'y': 12, 'z': 3}
+        """
+        ...
+
+    def merge3(
+        self,
+        base: "TreeMap",
+        theirs: "TreeMap",
+        on_conflict: Optional[Callable[[str, Any, Any, Any], Any]] = None,
+    ) -> List[str]:
+        """Merge the changes theirs made to base, a common ancestor, into this tree.
+
+        A key changed on only one side takes that side's value. Only keys
+        both sides changed differently are passed to on_conflict, whose
+        return value is stored. An error leaves this tree untouched.
+
+        Args:
+            base: Common ancestor of this tree and theirs
+            theirs: Tree holding the other changes to base
+            on_conflict: Called as on_conflict(key, base, ours, theirs) for
+                each conflicting key, with None for a side the key is missing
+                from; returns the value to store
+
+        Returns:
+            List of the conflicting keys, in key order
+
+        Raises:
+            ValueError: If a key conflicts and on_conflict is None
+            TypeError: If the trees have different key types
+
+        Examples:
+            >>> base = TreeMap({"a": 1, "b": 2, "c": 3})
+            >>> ours = TreeMap({"a": 10, "b": 2, "c": 30})
+            >>> theirs = TreeMap({"a": 1, "c": 300, "d": 4})
+            >>> ours.merge3(base, theirs, on_conflict=lambda k, b, o, t: o + t)
+            ['c']
+        """
+        ...
+
+    def __ne__(self, other: object) -> bool: ...
+    def clear(self) -> None:
+        """Remove all entries from the TreeMap."""
+        ...
+
+    def expire(self) -> int:
+        """Remove every entry whose ttl has run out.
+
+        Reads and writes sweep expired entries out before they look at the
+        tree, so they never show up; calling this frees their memory in a
+        tree that is not otherwise being used.
+
+        Returns:
+            Number of entries removed
+        """
+        ...
+
+    def on_change(
+        self, callback: Callable[[str, _K, Optional[_V], Optional[_V]], Any]
+    ) -> Callable[[str, _K, Optional[_V], Optional[_V]], Any]:
+        """Register a callable to be told about every change to the tree.
+
+        The callback is called as callback(event, key, old_value, new_value)
+        after the change is made, with event one of "insert", "overwrite",
+        "remove" or "evict" and None for a missing value. "evict" covers keys
+        the tree drops by itself: keys displaced by an inserted key that
+        prefixes or extends them, max_len evictions and expired entries.
+        Bulk operations report one event per key. The tree is locked while
+        callbacks run, and their exceptions propagate to the caller.
+
+        Args:
+            callback: Callable taking (event, key, old_value, new_value)
+
+        Returns:
+            The callback, so on_change can be used as a decorator
+
+        Raises:
+            TypeError: If callback is not callable
+        """
+        ...
+
+    def off_change(self, callback: Callable[[str, _K, Optional[_V], Optional[_V]], Any]) -> None:
+        """Unregister a callback added with on_change().
+
+        Raises:
+            ValueError: If the callback is not registered
+        """
+        ...
+
+    def metrics(self) -> Dict[str, int]:
+        """Return counters of the operations run on the tree since it was
+        created or reset_metrics() was last called.
+
+        Lookups are get(), tree[key] and `in` checks, each counted as a hit
+        or a miss. Inserts count every value stored, including overwrites.
+        Removals count keys removed on request, evictions keys the tree
+        dropped by itself (prefix displacement, max_len and ttl expiry), and
+        fuzzy_scans the fuzzy searches and suggestions run over the keys.
+
+        Returns:
+            Dict with "lookups", "hits", "misses", "inserts", "removals",
+            "evictions" and "fuzzy_scans" counts
+
+        Examples:
+            >>> cache = TreeMap({"a": 1})
+            >>> cache.get("a"), cache.get("b")
+            (1, None)
+            >>> stats = cache.metrics()
+            >>> stats["hits"] / stats["lookups"]
+            0.5
+        """
+        ...
+
+    def reset_metrics(self) -> None:
+        """Set every counter reported by metrics() back to zero."""
+        ...
+
+    def retain(self, predicate: Callable[[_K, _V], Any]) -> None:
+        """Keep only the entries for which predicate(key, value) is truthy.
+
+        The tree is filtered in place in a single pass.
+
+        Args:
+            predicate: Callable taking (key, value) and returning a truthy value
+                for entries that should be kept
+        """
+        ...
+
+    def map_values(self, func: Callable[[_V], _T]) -> "TreeMap[_K, _T]":
+        """Return a new TreeMap with the same keys and func(value) as values.
+
+        Args:
+            func: Callable taking a value and returning the transformed value
+
+        Returns:
+            A new TreeMap; the original is left unchanged
+        """
+        ...
+
+    def to_nested_dict(self, separator: Optional[str] = None) -> Dict[str, Any]:
+        """Explode the keys at a separator into nested dicts.
+
+        Args:
+            separator: Separator between the levels of a key (defaults to ".")
+
+        Returns:
+            A dict of dicts, with the stored values at the leaves
+
+        Raises:
+            TypeError: If the tree does not have str or bytes keys
+
+        Examples:
+            >>> config = TreeMap({"db.host": "localhost", "db.port": 5432})
+            >>> config.to_nested_dict()
+            {'db': {'host': 'localhost', 'port': 5432}}
+        """
+        ...
+
+    @classmethod
+    def from_nested_dict(
+        cls, data: Dict[str, Any], separator: Optional[str] = None, **kwargs: Any
+    ) -> "TreeMap":
+        """Build a TreeMap from nested dicts, joining the keys at each level.
+
+        Values that are not dicts, and empty dicts, are stored under the keys
+        leading to them joined with separator (defaults to ".").
+
+        Args:
+            data: Dict whose values may be dicts themselves
+            separator: Separator to join the levels of a key
+            **kwargs: Options passed on to the TreeMap constructor
+
+        Examples:
+            >>> tree = TreeMap.from_nested_dict({"db": {"host": "localhost"}})
+            >>> list(tree.items())
+            [('db.host', 'localhost')]
+        """
+        ...
+
+    @classmethod
+    def from_arrow(
+        cls, data: Any, key_column: str, value_column: str, **kwargs: Any
+    ) -> "TreeMap":
+        """Build a TreeMap from two columns of Arrow data.
+
+        String and binary key columns are read straight from their Arrow
+        buffers. data is a pyarrow Table or RecordBatch, or anything
+        pyarrow.table() accepts through the Arrow PyCapsule interface.
+
+        Args:
+            data: Arrow table or record batch
+            key_column: Name of the column holding the keys
+            value_column: Name of the column holding the values
+            **kwargs: Options passed on to the TreeMap constructor
+
+        Raises:
+            ValueError: If the key column contains nulls
+            TypeError: If the keys don't match the key_type
+        """
+        ...
+
+    def keys_to_arrow(self) -> Any:
+        """Return the keys, in key order, as a pyarrow array.
+
+        str and bytes keys are copied straight into the buffers of a string
+        or binary array, without creating a Python object per key.
+        """
+        ...
+
+    def to_arrow(self) -> Any:
+        """Return the entries, in key order, as a pyarrow RecordBatch.
+
+        The "value" column is converted by pyarrow.array(), so values should
+        be of one simple type such as int, float, str or bytes.
+
+        Returns:
+            pyarrow.RecordBatch with "key" and "value" columns
+        """
+        ...
+
+    @classmethod
+    def from_csv(
+        cls,
+        path: Any,
+        key_column: Union[str, int],
+        value_column: Union[str, int],
+        delimiter: str = ",",
+        header: bool = True,
+        **kwargs: Any,
+    ) -> "TreeMap":
+        """Build a TreeMap from two columns of a CSV or TSV file.
+
+        The file is parsed in Rust with the GIL released, reading records as
+        csv.reader() does. Fields may be quoted with '"' to hold delimiters
+        and newlines; keys and values are stored as str.
+
+        Args:
+            path: Path of the file, which must be UTF-8
+            key_column: Column holding the keys, by header name or position
+            value_column: Column holding the values, by header name or position
+            delimiter: Single-character field separator ("\\t" for TSV)
+            header: Whether the first line names the columns (and is skipped)
+            **kwargs: Options passed on to the TreeMap constructor
+
+        Raises:
+            OSError: If the file cannot be read
+            ValueError: If a column is unknown, or a record is malformed (the
+                message gives its line number)
+        """
+        ...
+
+    @classmethod
+    def from_jsonl(
+        cls, path: Any, key_field: str, value_field: Optional[str] = None, **kwargs: Any
+    ) -> "TreeMap":
+        """Build a TreeMap from a JSON Lines file, one record per line.
+
+        The file is parsed in Rust with the GIL released. Each line holds a
+        JSON object whose key_field is the key; the value is its value_field,
+        or the whole record (key included) as a dict. Blank lines are skipped. Records
+        load as json.loads() has them: the last of repeated fields wins, and
+        lone surrogates, NaN and Infinity are accepted.
+
+        Raises:
+            OSError: If the file cannot be read
+            ValueError: If a line is not a JSON object or lacks a field (the
+                message gives its line number)
+            TypeError: If a key is not of the tree's key type (the message
+                gives its line number)
+        """
+        ...
+
+    @classmethod
+    def from_parquet(
+        cls, path: Any, key_column: str, value_column: str, **kwargs: Any
+    ) -> "TreeMap":
+        """Build a TreeMap from two columns of a Parquet file.
+
+        The file is decoded in Rust with the GIL released, without pyarrow,
+        and only the two columns are read. Columns may hold booleans,
+        integers, floats, strings or binary values, with nulls read as None.
+
+        Raises:
+            OSError: If the file cannot be read
+            ValueError: If the file is not valid Parquet, a column is unknown,
+                or the key column contains nulls
+            TypeError: If a column holds another type (dates, decimals,
+                nested values)
+        """
+        ...
+
+    @classmethod
+    def from_polars(
+        cls, df: Any, key_column: str, value_column: str, **kwargs: Any
+    ) -> "TreeMap":
+        """Build a TreeMap from two columns of a polars DataFrame.
+
+        The DataFrame is handed over as Arrow data and loaded like from_arrow().
+
+        Raises:
+            ValueError: If the key column contains nulls
+        """
+        ...
+
+    def to_polars(self) -> Any:
+        """Return the entries, in key order, as a polars DataFrame with "key"
+        and "value" columns."""
+        ...
+
+    @classmethod
+    def from_pandas(cls, data: Any, column: Any = None, **kwargs: Any) -> "TreeMap":
+        """Build a TreeMap from a pandas Series or DataFrame, keyed by its index.
+
+        The index goes through Arrow like from_arrow(). With a DataFrame the
+        values come from column, which may be left out when there is only one.
+
+        Raises:
+            ValueError: If a DataFrame has several columns and none is given,
+                or the index contains nulls
+        """
+        ...
+
+    def to_pandas(self) -> Any:
+        """Return the entries as a pandas Series indexed by key, in key order."""
+        ...
+
+    def snapshot(self) -> "TreeMapSnapshot":
+        """Return a read-only snapshot of the tree as it is now.
+
+        Taking a snapshot is O(1): it shares the tree's nodes, keys and
+        indexes. The first write to this tree while the snapshot is alive
+        copies them (O(n), once), so later writes don't affect it. Values
+        are never copied.
+
+        Examples:
+            >>> tree = TreeMap({"a": 1, "b": 2})
+            >>> snap = tree.snapshot()
+            >>> del tree["a"]
+            >>> snap.items()
+            [('a', 1), ('b', 2)]
+        """
+        ...
+
+    def transaction(self) -> "TreeMapTransaction":
+        """Group changes so they are all kept or all undone.
+
+        Inside `with tree.transaction():` the tree is read and written as
+        usual, while the state of each key before its first change is logged.
+        If an exception escapes the block, every changed key (including keys
+        evicted along the way) is restored and the exception propagates.
+        Restoring costs time proportional to the number of changed keys.
+
+        Raises:
+            RuntimeError: On entering, if a transaction is already open
+
+        Examples:
+            >>> tree = TreeMap({"a": 1})
+            >>> with tree.transaction():
+            ...     tree["b"] = 2
+            ...     raise ValueError("abort")
+            Traceback (most recent call last):
+            ValueError: abort
+            >>> "b" in tree
+            False
+        """
+        ...
+
+    def at_version(self, version: int) -> "TreeMapSnapshot":
+        """Return a read-only snapshot of the tree as it was at an earlier version.
+
+        Only available on trees created with versioned=True. Every change to a
+        key (an insert, overwrite, removal or eviction, as reported to
+        on_change()) bumps the version by one, starting from 0 for the empty
+        tree.
+
+        The snapshot is rebuilt on every call: the current tree is shared
+        with it, and undoing the changes logged since version copies the
+        tree's nodes and keys once (values are shared, not copied) and then
+        takes one step per change. Reading the current version costs
+        nothing; reading an old version of a large tree costs a full copy.
+
+        Args:
+            version: Version to read, between tree.oldest_version and
+                tree.version
+
+        Raises:
+            ValueError: If the tree is not versioned
+            IndexError: If version is greater than the current version, or
+                older than the oldest version kept
+
+        Examples:
+            >>> tree = TreeMap(versioned=True)
+            >>> tree["a"] = 1
+            >>> v = tree.version
+            >>> tree["a"] = 2
+            >>> tree.at_version(v)["a"]
+            1
+        """
+        ...
+
+    @property
+    def version(self) -> Optional[int]:
+        """Number of changes made to a versioned tree, or None if not versioned."""
+        ...
+
+    @property
+    def oldest_version(self) -> Optional[int]:
+        """Earliest version at_version() can still read, or None if not versioned."""
+        ...
+
+    def prune_versions(self, version: int) -> int:
+        """Forget the changes made before a version, freeing the keys and
+        values the log kept for them.
+
+        Versions before version can no longer be read afterwards.
+
+        Args:
+            version: Oldest version to keep; at most tree.version
+
+        Returns:
+            Number of logged changes dropped
+
+        Raises:
+            ValueError: If the tree is not versioned
+            IndexError: If version is greater than the current version
+        """
+        ...
+
+    def share(self, name: Optional[str] = None) -> "SharedTreeMap":
+        """Copy the tree into shared memory that other processes can attach to.
+
+        Values are pickled into the block and the shared map is read-only.
+        Call unlink() on the returned map (or use it as a context manager)
+        once every process is done with it.
+
+        Raises:
+            TypeError: If the tree uses a custom key_type codec
+        """
+        ...
+
+    @staticmethod
+    def attach(name: str) -> "SharedTreeMap":
+        """Attach to a TreeMap shared by another process with share().
+
+        Raises:
+            FileNotFoundError: If no block with that name exists
+            ValueError: If the block does not hold a shared TreeMap
+        """
+        ...
+
+    def memory_usage(self, deep: bool = False) -> int:
+        """Report the memory used by the tree, in bytes.
+
+        Counts the radix tree nodes and stored key bytes, and everything kept
+        beside the tree: original key spellings, secondary indexes, ttl
+        deadlines, history, an open transaction's journal, the unpickling
+        cache and the intern table. With deep=True the sys.getsizeof() of
+        every distinct value is added, including values only kept by the
+        history, journal or cache, recursing into values that are themselves
+        TreeMaps.
+        """
+        ...
+
+    def compression_stats(self) -> Optional[Dict[str, Any]]:
+        """How well the values of a compress_values tree compress.
+
+        Returns:
+            None if values aren't compressed, otherwise a dict with the
+            codec and threshold, the number of compressed_values, their
+            raw_bytes and compressed_bytes, and the ratio of the two
+        """
+        ...
+
+    def compact(self) -> int:
+        """Rebuild the tree to release memory left over from removed keys.
+
+        Also shrinks the internal tables, prunes the intern table and
+        empties the cache of unpickled values. Returns the number of bytes
+        reclaimed, as measured by memory_usage().
+        """
+        ...
+
+    def __sizeof__(self) -> int: ...
+
+    def stats(self) -> Dict[str, Any]:
+        """Describe the shape of the radix tree.
+
+        Returns:
+            Dict with node4/node16/node48/node256 and inner_nodes counts,
+            leaves, max_depth and avg_depth (inner nodes above a leaf),
+            key_bytes, prefix_bytes (bytes folded into compressed prefixes)
+            and prefix_savings (estimated bytes saved by prefix compression)
+        """
+        ...
+    @overload
+    def to_dot(self, max_nodes: Optional[int] = None) -> str: ...
+    @overload
+    def to_dot(self, max_nodes: Optional[int] = None, *, path: Any) -> None:
+        """Render the radix tree structure as a Graphviz DOT graph.
+
+        Inner nodes show their type and compressed prefix, edges are labelled
+        with the key byte they branch on, and leaves show their key.
+
+        Args:
+            max_nodes: Stop after this many nodes, summarizing the rest
+            path: If given, write the graph to this file and return None
+        """
+        ...
+
+    def pretty_print(self, max_depth: Optional[int] = None, file: Optional[Any] = None) -> None:
+        """Print the radix tree structure as an indented ASCII tree.
+
+        Inner nodes show their type and compressed prefix, branches the key
+        byte they are taken on, and leaves their key.
+
+        Args:
+            max_depth: Only expand this many levels of inner nodes,
+                summarizing the keys below
+            file: Stream to write to, as for print()
+
+        Examples:
+            >>> TreeMap({"apple": 1, "apricot": 2, "banana": 3}).pretty_print()
+            Node4
+            |-- a: Node4 prefix="p"
+            |   |-- p: 'apple'
+            |   `-- r: 'apricot'
+            `-- b: 'banana'
+        """
+        ...
+
+    def validate(self) -> None:
+        """Check the tree's structural integrity.
+
+        Verifies the radix tree invariants, key ordering, and that every key
+        survives a decode/encode round trip through the key codec.
+
+        Raises:
+            RuntimeError: Describing the first problem found
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Check if the TreeMap is empty.
+
+        Returns:
+            True if the TreeMap has no entries, False otherwise
+        """
+        ...
+
+    def __getitem__(self, key: _K) -> _V:
+        """Get a value using square bracket notation.
+
+        If the TreeMap has a default_factory, a missing key is inserted with
+        default_factory() and that value is returned.
+
+        Args:
+            key: The key to look up
+
+        Returns:
+            The value associated with the key
+
+        Raises:
+            KeyError: If the key does not exist and there is no default_factory
+        """
+        ...
+
+    def __setitem__(self, key: _K, value: _V) -> None:
+        """Set a value using square bracket notation.
+
+        Args:
+            key: The key to set
+            value: The value to associate with the key
+        """
+        ...
+
+    def __delitem__(self, key: _K) -> None:
+        """Delete a key using del statement.
+
+        Args:
+            key: The key to delete
+
+        Raises:
+            KeyError: If the key does not exist
+        """
+        ...
+
+    def __contains__(self, key: _K) -> bool:
+        """Check if a key exists using 'in' operator.
+
+        Args:
+            key: The key to check
+
+        Returns:
+            True if the key exists, False otherwise
+        """
+        ...
+
+    def __len__(self) -> int:
+        """Get the number of entries in the TreeMap.
+
+        Returns:
+            The number of key-value pairs
+        """
+        ...
+
+    def __repr__(self) -> str:
+        """Get a debug string representation.
+
+        Shows the first few entries and the length, like
+        "TreeMap({'a': 1, 'ab': 2, ...}, len=10423)".
+        """
+        ...
+
+    def __str__(self) -> str:
+        """Get a human-readable string representation."""
+        ...
+
+    @staticmethod
+    def set_repr_limit(limit: int) -> int:
+        """Set how many entries repr() shows for every TreeMap.
+
+        Args:
+            limit: Number of entries to show (5 by default); 0 shows only
+                the length
+
+        Returns:
+            The previous limit
+        """
+        ...
+
+    def __iter__(self) -> TreeMapIterator[_K]:
+        """Iterate over keys in the TreeMap.
+
+        Returns:
+            An iterator over the keys in lexicographic order
+        """
+        ...
+
+    def keys(self) -> KeysView[_K]:
+        """Get a live view of the keys.
+
+        Like dict.keys(), the view reflects later changes to the tree and
+        supports len(), membership tests and set operations (&, |, -, ^).
+
+        Returns:
+            A view over the keys in lexicographic order
+        """
+        ...
+
+    def values(self) -> ValuesView[_V]:
+        """Get a live view of the values.
+
+        Returns:
+            A view over the values in key order
+        """
+        ...
+
+    @overload
+    def items(self) -> ItemsView[_K, _V]: ...
+    @overload
+    def items(
+        self, *, offset: int = 0, limit: Optional[int] = None
+    ) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Get a live view of the (key, value) pairs.
+
+        Like dict.items(), the view reflects later changes to the tree and
+        supports len(), membership tests and set operations. With offset or
+        limit, an iterator over that page of the pairs is returned instead.
+
+        Args:
+            offset: Number of leading pairs to skip
+            limit: Maximum number of pairs to return, or None for no limit
+
+        Returns:
+            A view over (key, value) tuples in key order, or an iterator
+            over one page of them
+        """
+        ...
+
+    def to_dict(self) -> Dict[_K, _V]:
+        """Copy the entries into a new dict, in key order.
+
+        Built in one pass into a dict sized up front, which is much faster
+        than dict(tree.items()).
+        """
+        ...
+
+    def to_list(self) -> List[Tuple[_K, _V]]:
+        """Copy the entries into a new list of (key, value) pairs, in key order."""
+        ...
+
+    def get_prefix(self, prefix: str) -> Optional[Tuple[_K, _V]]:
+        """Get the first key-value pair matching a prefix.
+
+        Returns the first key-value pair where the key starts with the given prefix,
+        in lexicographic order. Returns None if no keys match the prefix.
+
+        Args:
+            prefix: The prefix to search for
+
+        Returns:
+            A tuple of (key, value) for the first matching entry, or None if no match
+
+        Examples:
+            >>> tree = TreeMap()
+            >>> tree["apple"] = 1
+            >>> tree["application"] = 2
+            >>> tree["banana"] = 3
+            >>> tree.get_prefix("app")
+            ('apple', 1)
+            >>> tree.get_prefix("ban")
+            ('banana', 3)
+            >>> tree.get_prefix("orange")
+            None
+
+        Note:
+            Due to blart's adaptive radix tree design with prefix compression,
+            when a key is a prefix of another key, inserting the longer key will
+            remove the shorter prefix key. This is expected behavior.
+        """
+        ...
+
+    def has_subtrie(self, prefix: _K) -> bool:
+        """Check whether any stored key is longer than prefix and starts with it."""
+        ...
+
+    def has_key(self, key: _K) -> bool:
+        """Check whether a key is stored, as pygtrie's Trie.has_key()."""
+        ...
+
+    def shortest_prefix(self, key: _K) -> Optional[Tuple[_K, _V]]:
+        """Find the stored key that a key starts with; the same entry as
+        longest_prefix(), since stored keys never prefix one another.
+
+        Raises:
+            TypeError: If the tree does not have str or bytes keys
+        """
+        ...
+
+    def longest_prefix(self, key: _K) -> Optional[Tuple[_K, _V]]:
+        """Find the stored key that a key starts with, which may be the key itself.
+
+        Returns:
+            A tuple of (key, value) for the stored prefix, or None
+
+        Raises:
+            TypeError: If the tree does not have str or bytes keys
+
+        Examples:
+            >>> routes = TreeMap({"/api/": "api", "/static/": "files"})
+            >>> routes.longest_prefix("/static/app.js")
+            ('/static/', 'files')
+        """
+        ...
+
+    def iteritems(self, prefix: Optional[_K] = None) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Iterate over the entries under a prefix, as pygtrie's Trie.iteritems().
+
+        Raises:
+            KeyError: If no stored key starts with the prefix
+        """
+        ...
+
+    def iterkeys(self, prefix: Optional[_K] = None) -> TreeMapIterator[_K]:
+        """Iterate over the keys under a prefix, as pygtrie's Trie.iterkeys().
+
+        Raises:
+            KeyError: If no stored key starts with the prefix
+        """
+        ...
+
+    def itervalues(self, prefix: Optional[_K] = None) -> TreeMapIterator[_V]:
+        """Iterate over the values under a prefix, as pygtrie's Trie.itervalues().
+
+        Raises:
+            KeyError: If no stored key starts with the prefix
+        """
+        ...
+
+    def traverse(self, node_factory: Callable[..., _T], prefix: Optional[_K] = None) -> _T:
+        """Walk the keys node by node, as pygtrie's Trie.traverse().
+
+        The keys are treated as a character trie (a byte trie for bytes
+        keys). node_factory is called as node_factory(path_conv, path,
+        children), with a fourth value argument when a key ends at the
+        node; children is an iterator over the results for the child
+        nodes, each visited only when the iterator reaches it.
+
+        Raises:
+            TypeError: If the tree does not have str or bytes keys
+            KeyError: If no stored key starts with the prefix
+
+        Examples:
+            >>> tree = TreeMap({"ab": 1, "ac": 2})
+            >>> def count(path_conv, path, children, value=None):
+            ...     return (value is not None) + sum(children)
+            >>> tree.traverse(count)
+            2
+        """
+        ...
+
+    def prefix_iter(
+        self, prefix: str, *, offset: int = 0, limit: Optional[int] = None
+    ) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Get an iterator over all key-value pairs with a given prefix.
+
+        Returns an iterator that yields (key, value) tuples for all keys
+        that start with the given prefix, in lexicographic order.
+
+        Args:
+            prefix: The prefix to search for
+            offset: Number of leading matches to skip
+            limit: Maximum number of matches to return, or None for no limit
+
+        Returns:
+            An iterator over (key, value) tuples matching the prefix
+
+        Examples:
+            >>> tree = TreeMap()
+            >>> tree["apple"] = 1
+            >>> tree["application"] = 2
+            >>> tree["apply"] = 3
+            >>> tree["banana"] = 4
+            >>> for key, value in tree.prefix_iter("app"):
+            ...     print(f"{key}: {value}")
+            apple: 1
+            application: 2
+            apply: 3
+            >>> list(tree.prefix_iter("ban"))
+            [('banana', 4)]
+            >>> list(tree.prefix_iter("orange"))
+            []
+
+        Note:
+            An empty prefix ("") matches all keys in the tree.
+        """
+        ...
+
+    def contains_substring(self, fragment: Any) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Get an iterator over the entries whose key contains a fragment.
+
+        Fast when the tree was created with substring_index=True, which
+        indexes every suffix of every key; otherwise every key is scanned.
+
+        Args:
+            fragment: Text (or bytes, for bytes keys) to look for
+
+        Returns:
+            An iterator over (key, value) tuples, in key order
+
+        Raises:
+            TypeError: If the tree does not have str or bytes keys
+
+        Examples:
+            >>> tree = TreeMap({"book": 1, "cook": 2, "cake": 3}, substring_index=True)
+            >>> list(tree.contains_substring("oo"))
+            [('book', 1), ('cook', 2)]
+        """
+        ...
+
+    def regex_iter(self, pattern: str) -> Iterator[Tuple[_K, _V]]:
+        """Get an iterator over the entries whose key matches a regex.
+
+        The pattern must match the whole key, like re.fullmatch. It is
+        compiled with Rust's regex crate (no lookaround or backreferences),
+        and only the keys under the pattern's literal prefix are scanned,
+        lazily as the iterator advances.
+
+        Args:
+            pattern: Regular expression the keys must match
+
+        Returns:
+            An iterator over (key, value) tuples, in key order
+
+        Raises:
+            ValueError: If the pattern is not a valid regular expression
+            TypeError: If the tree does not have str or bytes keys
+
+        Examples:
+            >>> logs = TreeMap({"log/2024-01-02": 1, "log/2024-02-10": 2})
+            >>> list(logs.regex_iter(r"log/2024-01-\d+"))
+            [('log/2024-01-02', 1)]
+        """
+        ...
+
+    def iter_chunks(
+        self, size: int, prefix: Optional[Any] = None
+    ) -> Iterator[List[Tuple[_K, _V]]]:
+        """Get an iterator over the entries in lists of size pairs.
+
+        Chunks are collected lazily, one per step, and the last one may be
+        shorter. Batch consumers avoid the per-item overhead of a Python
+        loop over single pairs.
+
+        Args:
+            size: Number of (key, value) pairs per chunk
+            prefix: Only visit keys starting with this prefix
+
+        Returns:
+            An iterator over lists of (key, value) tuples, in key order
+
+        Raises:
+            ValueError: If size is 0
+
+        Examples:
+            >>> tree = TreeMap({"a": 1, "b": 2, "c": 3})
+            >>> list(tree.iter_chunks(2))
+            [[('a', 1), ('b', 2)], [('c', 3)]]
+        """
+        ...
+
+    def scan(
+        self,
+        callback: Callable[[Any, Any], Any],
+        prefix: Optional[Any] = None,
+        reverse: bool = False,
+    ) -> int:
+        """Call a function on each entry in key order until it returns False.
+
+        Entries are converted one at a time, so stopping early never
+        materializes the rest of the tree. Any return value other than
+        False, including None, continues the scan. The tree cannot be
+        changed from the callback.
+
+        Args:
+            callback: Function called as callback(key, value)
+            prefix: Only visit keys starting with this prefix
+            reverse: If True, visit the keys from largest to smallest
+
+        Returns:
+            Number of entries the callback was called on
+
+        Examples:
+            >>> tree = TreeMap({"a": 1, "b": 2, "c": 3})
+            >>> tree.scan(lambda key, value: value < 2)
+            2
+        """
+        ...
+
+    def scan_text(self, text: Any) -> List[Tuple[int, int, Any, Any]]:
+        """Find every occurrence of any stored key inside a text.
+
+        Each position of the text is matched against the tree in Rust with
+        the GIL released; occurrences may overlap.
+
+        Args:
+            text: Text to scan (bytes for bytes keys)
+
+        Returns:
+            (start, end, key, value) tuples ordered by start, where
+            text[start:end] is the occurrence
+
+        Examples:
+            >>> places = TreeMap({"New York": "US", "York": "UK", "Paris": "FR"})
+            >>> places.scan_text("From New York to Paris")
+            [(5, 13, 'New York', 'US'), (9, 13, 'York', 'UK'), (17, 22, 'Paris', 'FR')]
+        """
+        ...
+
+    def segment(
+        self,
+        text: Any,
+        *,
+        strategy: Literal["greedy", "optimal"] = "greedy",
+    ) -> List[Tuple[Any, Any]]:
+        """Split a text into stored keys and the runs of text between them.
+
+        "greedy" takes a stored key wherever one starts, reading left to
+        right; "optimal" minimizes the unmatched characters, then the number
+        of tokens.
+
+        Args:
+            text: Text to split (bytes for bytes keys)
+            strategy: "greedy" or "optimal"
+
+        Returns:
+            (token, value) tuples that concatenate back to the text; runs
+            matching no key have value None
+
+        Examples:
+            >>> units = TreeMap({"kg": 1000, "g": 1, "mg": 0.001})
+            >>> units.segment("12kg")
+            [('12', None), ('kg', 1000)]
+        """
+        ...
+
+    def replace_all(
+        self,
+        text: Any,
+        formatter: Optional[Callable[[Any, Any], Any]] = None,
+    ) -> Any:
+        """Replace every occurrence of a stored key in a text with its value.
+
+        Matching is a single left-to-right pass; replacements never overlap.
+
+        Args:
+            text: Text to rewrite (bytes for bytes keys)
+            formatter: Optional callable taking (key, value) and returning
+                the replacement
+
+        Returns:
+            The rewritten text, of the same type as `text`
+
+        Raises:
+            TypeError: If a replacement is not of the text's type
+
+        Examples:
+            >>> slang = TreeMap({"brb": "be right back"})
+            >>> slang.replace_all("ok brb")
+            'ok be right back'
+        """
+        ...
+
+    def enable_suffix_index(self) -> None:
+        """Start maintaining an index of reversed keys for endswith_iter().
+
+        Built from the current keys, then kept up to date on every insert
+        and removal. Calling it again does nothing.
+
+        Raises:
+            TypeError: If the tree does not have str or bytes keys
+        """
+        ...
+
+    def endswith_iter(self, suffix: Any) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Get an iterator over the entries whose key ends with a suffix.
+
+        Uses the reversed-key index after enable_suffix_index(), otherwise
+        checks every key.
+
+        Args:
+            suffix: The suffix to search for
+
+        Returns:
+            An iterator over (key, value) tuples, in key order
+
+        Examples:
+            >>> files = TreeMap({"a.json": 1, "b.txt": 2, "c/d.json": 3})
+            >>> files.enable_suffix_index()
+            >>> list(files.endswith_iter(".json"))
+            [('a.json', 1), ('c/d.json', 3)]
+        """
+        ...
+
+    def autocomplete(
+        self,
+        prefix: str,
+        k: int = 10,
+        score: Union[None, str, Callable[[Any], float]] = None,
+    ) -> List[Tuple[_K, _V]]:
+        """Return the k best completions of a prefix, highest score first.
+
+        Entries under the prefix are scored during the subtree walk and only
+        the k best are kept, in a bounded heap.
+
+        Args:
+            prefix: The prefix typed so far
+            k: Maximum number of completions
+            score: None to score by the value itself, the name of a field of
+                the value (item for mappings, attribute otherwise), or a
+                callable taking the value
+
+        Returns:
+            Up to k (key, value) tuples, best first; ties keep key order
+
+        Raises:
+            TypeError: If a score is not a number
+
+        Examples:
+            >>> words = TreeMap({"help": 40, "hello": 120, "helm": 3})
+            >>> words.autocomplete("hel", 2)
+            [('hello', 120), ('help', 40)]
+        """
+        ...
+
+    def children(
+        self, path: Optional[Any] = None, separator: Optional[str] = None
+    ) -> List[str]:
+        """List the distinct segments directly below a prefix.
+
+        Children are implied by deeper keys, so children("a") includes "b"
+        when only "a:b:c" is stored. In a "path" tree the prefix is a
+        directory and the tree's own separator is used.
+
+        Args:
+            path: Prefix (or directory) to list, or None for the top level
+            separator: Separator between segments for str and bytes keys
+                (defaults to ":")
+
+        Returns:
+            Sorted list of unique child names
+
+        Raises:
+            TypeError: If the tree does not have str, bytes or path keys
+            ValueError: If separator is empty, or given for a "path" tree
+
+        Examples:
+            >>> fs = TreeMap(key_type="path")
+            >>> fs["src/lib.rs"] = 1
+            >>> fs["src/codec/mod.rs"] = 2
+            >>> fs.children("src")
+            ['codec', 'lib.rs']
+            >>> users = TreeMap({"user:1:name": "ann", "user:2:name": "bob"})
+            >>> users.children("user")
+            ['1', '2']
+        """
+        ...
+
+    def descendants(self, path: Optional[Any] = None) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Get an iterator over every entry below a directory of a "path" tree.
+
+        Args:
+            path: Directory to walk, or None for the whole tree
+
+        Returns:
+            An iterator over (key, value) tuples in key order
+
+        Raises:
+            TypeError: If the tree was not created with key_type="path"
+        """
+        ...
+
+    def lookup(self, address: Any) -> Optional[Tuple[_K, _V]]:
+        """Find the most specific network containing an address.
+
+        Performs a longest-prefix match over a tree created with
+        key_type="ip", as used for routing tables.
+
+        Args:
+            address: IP address (ipaddress object or string) to look up
+
+        Returns:
+            (network, value) tuple for the longest matching network, or None
+
+        Raises:
+            TypeError: If the tree was not created with key_type="ip"
+            ValueError: If address is not a valid IP address
+
+        Examples:
+            >>> routes = TreeMap(key_type="ip")
+            >>> routes["10.0.0.0/8"] = "core"
+            >>> routes["10.1.0.0/16"] = "lab"
+            >>> routes.lookup("10.1.2.3")
+            (IPv4Network('10.1.0.0/16'), 'lab')
+        """
+        ...
+
+    def prefix_keys(
+        self, prefix: str, *, offset: int = 0, limit: Optional[int] = None
+    ) -> TreeMapIterator[_K]:
+        """Get an iterator over the keys with a given prefix.
+
+        Like prefix_iter() without building (key, value) tuples, for callers
+        such as autocomplete that only need the keys.
+
+        Args:
+            prefix: The prefix to search for
+            offset: Number of leading matches to skip
+            limit: Maximum number of keys to return, or None for no limit
+
+        Examples:
+            >>> tree = TreeMap({"apple": 1, "application": 2, "banana": 3})
+            >>> list(tree.prefix_keys("app"))
+            ['apple', 'application']
+        """
+        ...
+
+    def prefix_values(
+        self, prefix: str, *, offset: int = 0, limit: Optional[int] = None
+    ) -> TreeMapIterator[Any]:
+        """Get an iterator over the values of the keys with a given prefix.
+
+        Args:
+            prefix: The prefix to search for
+            offset: Number of leading matches to skip
+            limit: Maximum number of values to return, or None for no limit
+
+        Examples:
+            >>> tree = TreeMap({"apple": 1, "application": 2, "banana": 3})
+            >>> list(tree.prefix_values("app"))
+            [1, 2]
+        """
+        ...
+
+    def prefix_page(
+        self, prefix: str, limit: int, after_key: Optional[str] = None
+    ) -> Tuple[List[Tuple[_K, _V]], Optional[_K]]:
+        """Get one page of the entries with a given prefix.
+
+        The last key of the page is returned as a token to resume from, so
+        stateless services can paginate huge prefixes across requests.
+
+        Args:
+            prefix: The prefix to search for
+            limit: Maximum number of entries in the page
+            after_key: Token returned with the previous page, or None for
+                the first page
+
+        Returns:
+            Tuple of the (key, value) pairs in the page and the token for
+            the next page, or None after the last page
+
+        Examples:
+            >>> tree = TreeMap({"user:1": "a", "user:2": "b", "user:3": "c"})
+            >>> items, token = tree.prefix_page("user:", 2)
+            >>> tree.prefix_page("user:", 2, after_key=token)
+            ([('user:3', 'c')], None)
+        """
+        ...
+
+    def range_between(
+        self,
+        start: Optional[Any] = None,
+        end: Optional[Any] = None,
+        *,
+        offset: int = 0,
+        limit: Optional[int] = None,
+    ) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Get an iterator over key-value pairs with start <= key < end.
+
+        Keys are compared in stored order, so ranges follow numeric order for
+        "int" keys and chronological order for "datetime" keys.
+
+        Args:
+            start: Inclusive lower bound, or None for no lower bound
+            end: Exclusive upper bound, or None for no upper bound
+            offset: Number of leading entries in the range to skip
+            limit: Maximum number of entries to return, or None for no limit
+
+        Returns:
+            An iterator over (key, value) tuples in the range
+
+        Examples:
+            >>> from datetime import datetime
+            >>> tree = TreeMap(key_type="datetime")
+            >>> tree[datetime(2024, 1, 1)] = "new year"
+            >>> tree[datetime(2024, 2, 1)] = "february"
+            >>> list(tree.range_between(datetime(2024, 1, 1), datetime(2024, 1, 31)))
+            [(datetime.datetime(2024, 1, 1, 0, 0), 'new year')]
+        """
+        ...
+
+    def delete_range(
+        self,
+        start: Optional[Any],
+        stop: Optional[Any],
+        inclusive: Tuple[bool, bool] = (True, False),
+    ) -> int:
+        """Remove every entry whose key lies between two bounds.
+
+        Args:
+            start: Lower bound, or None for no lower bound
+            stop: Upper bound, or None for no upper bound
+            inclusive: Whether start and stop themselves are removed
+
+        Returns:
+            Number of entries removed
+
+        Examples:
+            >>> log = TreeMap({"2024-01": 1, "2024-02": 2, "2024-03": 3})
+            >>> log.delete_range("2024-01", "2024-03")
+            2
+        """
+        ...
+
+    def count_range(
+        self,
+        start: Optional[Any],
+        stop: Optional[Any],
+        inclusive: Tuple[bool, bool] = (True, False),
+    ) -> int:
+        """Count the entries whose key lies between two bounds.
+
+        Args:
+            start: Lower bound, or None for no lower bound
+            stop: Upper bound, or None for no upper bound
+            inclusive: Whether start and stop themselves are counted
 
         Returns:
-            True if the TreeMap has no entries, False otherwise
+            Number of keys in the range
+
+        Examples:
+            >>> log = TreeMap({"2024-01": 1, "2024-02": 2, "2024-03": 3})
+            >>> log.count_range("2024-01", "2024-03")
+            2
         """
         ...
 
-    def __getitem__(self, key: str) -> Any:
-        """Get a value using square bracket notation.
+    def count_prefix(self, prefix: str) -> int:
+        """Count the entries whose key starts with a prefix.
 
         Args:
-            key: The key to look up
+            prefix: Prefix to count
 
         Returns:
-            The value associated with the key
+            Number of keys starting with prefix
 
-        Raises:
-            KeyError: If the key does not exist
+        Examples:
+            >>> tree = TreeMap({"apple": 1, "apply": 2, "banana": 3})
+            >>> tree.count_prefix("app")
+            2
         """
         ...
 
-    def __setitem__(self, key: str, value: Any) -> None:
-        """Set a value using square bracket notation.
+    def group_by_prefix(
+        self,
+        prefix: Optional[str] = None,
+        separator: Optional[str] = None,
+        *,
+        op: Literal["count", "sum", "min", "max", "mean"] = "count",
+    ) -> Dict[str, Any]:
+        """Summarize the keys under a prefix by their next segment.
+
+        Each distinct segment following prefix up to the next separator is
+        reported with the number of keys under it, or another aggregate of
+        their values.
 
         Args:
-            key: The key to set
-            value: The value to associate with the key
+            prefix: Prefix to group under (None for the whole tree)
+            separator: Separator between segments (defaults to "/")
+            op: "count", "sum", "min", "max" or "mean"
+
+        Returns:
+            A dict mapping each segment to its aggregate, in key order
+
+        Examples:
+            >>> files = TreeMap({"src/a.rs": 10, "src/b.rs": 20, "docs/x.md": 5})
+            >>> files.group_by_prefix()
+            {'docs': 1, 'src': 2}
+            >>> files.group_by_prefix("src/", op="sum")
+            {'a.rs': 10, 'b.rs': 20}
         """
         ...
 
-    def __delitem__(self, key: str) -> None:
-        """Delete a key using del statement.
+    def aggregate(
+        self,
+        prefix_or_range: Union[str, slice, None] = None,
+        op: Literal["sum", "min", "max", "mean", "count"] = "sum",
+    ) -> Any:
+        """Aggregate the numeric values under a prefix or in a key range.
+
+        A slice selects the keys start <= key < stop; anything else is a
+        prefix, and None selects the whole tree.
 
         Args:
-            key: The key to delete
+            prefix_or_range: Key prefix, slice(start, stop) of keys, or None
+            op: "sum", "min", "max", "mean" or "count"
+
+        Returns:
+            The aggregate; 0 for an empty "sum" or "count", None for an
+            empty "min", "max" or "mean"
 
         Raises:
-            KeyError: If the key does not exist
+            ValueError: If op is unknown or the slice has a step
+            TypeError: If a selected value is not a number
+
+        Examples:
+            >>> sales = TreeMap({"eu:paris:a": 3, "eu:paris:b": 4, "us:nyc:a": 10})
+            >>> sales.aggregate("eu:")
+            7
+            >>> sales.aggregate(slice("eu:paris:b", "us:"), op="max")
+            4
         """
         ...
 
-    def __contains__(self, key: str) -> bool:
-        """Check if a key exists using 'in' operator.
+    def closest(self, key: _K) -> Optional[Tuple[_K, _V]]:
+        """Find the stored key next to a key in sort order.
+
+        Chooses between the floor and the ceiling of key, preferring the one
+        that shares the longer prefix with key (the floor on a tie).
 
         Args:
-            key: The key to check
+            key: Key to look around
 
         Returns:
-            True if the key exists, False otherwise
+            A tuple of (key, value) for the closest stored key, or None if
+            the tree is empty
+
+        Examples:
+            >>> ids = TreeMap({"user-0100": 1, "user-0200": 2, "zone-1": 3})
+            >>> ids.closest("user-02")
+            ('user-0200', 2)
         """
         ...
+    def rank(self, key: _K) -> int:
+        """Return the number of stored keys that sort before a key.
 
-    def __len__(self) -> int:
-        """Get the number of entries in the TreeMap.
+        Args:
+            key: Key to rank; it need not be stored
 
         Returns:
-            The number of key-value pairs
+            Number of keys less than key
+
+        Examples:
+            >>> tree = TreeMap({"a": 1, "c": 3, "e": 5}, order_statistics=True)
+            >>> tree.rank("d")
+            2
         """
         ...
 
-    def __repr__(self) -> str:
-        """Get a debug string representation."""
-        ...
+    def select(self, index: int) -> Tuple[_K, _V]:
+        """Return the entry at a position in key order.
 
-    def __str__(self) -> str:
-        """Get a human-readable string representation."""
-        ...
+        Negative positions count from the end.
 
-    def __iter__(self) -> Iterator[str]:
-        """Iterate over keys in the TreeMap.
+        Args:
+            index: Position of the entry
 
         Returns:
-            An iterator over the keys in lexicographic order
-        """
-        ...
+            A tuple of (key, value)
 
-    def keys(self) -> Iterator[str]:
-        """Get an iterator over keys.
+        Raises:
+            IndexError: If the position is out of range
 
-        Returns:
-            An iterator over the keys in lexicographic order
+        Examples:
+            >>> tree = TreeMap({"a": 1, "c": 3, "e": 5}, order_statistics=True)
+            >>> tree.select(1)
+            ('c', 3)
         """
         ...
 
-    def values(self) -> Iterator[Any]:
-        """Get an iterator over values.
+    def bisect_left(self, key: _K) -> int:
+        """Return the position where a key would be inserted, before any
+        equal stored key. The same as rank(), as in SortedDict."""
+        ...
 
-        Returns:
-            An iterator over the values in key order
-        """
+    def bisect_right(self, key: _K) -> int:
+        """Return the position where a key would be inserted, after any
+        equal stored key."""
         ...
 
-    def items(self) -> Iterator[Tuple[str, Any]]:
-        """Get an iterator over (key, value) pairs.
+    def index(self, key: _K, start: Optional[int] = None, stop: Optional[int] = None) -> int:
+        """Return the position of a stored key in key order.
 
-        Returns:
-            An iterator over (key, value) tuples in key order
+        Args:
+            key: Key to look for
+            start: First position to consider (defaults to 0)
+            stop: Position to stop before (defaults to the end)
+
+        Raises:
+            ValueError: If the key is not stored between start and stop
         """
         ...
 
-    def get_prefix(self, prefix: str) -> Optional[Tuple[str, Any]]:
-        """Get the first key-value pair matching a prefix.
-
-        Returns the first key-value pair where the key starts with the given prefix,
-        in lexicographic order. Returns None if no keys match the prefix.
+    def peekitem(self, index: int = -1) -> Tuple[_K, _V]:
+        """Return the entry at a position in key order, the last by default.
 
-        Args:
-            prefix: The prefix to search for
+        Raises:
+            IndexError: If the tree is empty or the position is out of range
+        """
+        ...
 
-        Returns:
-            A tuple of (key, value) for the first matching entry, or None if no match
+    def irange(
+        self,
+        minimum: Optional[_K] = None,
+        maximum: Optional[_K] = None,
+        inclusive: Tuple[bool, bool] = (True, True),
+        reverse: bool = False,
+    ) -> TreeMapIterator[_K]:
+        """Return an iterator over the keys between a minimum and a maximum,
+        both included by default, as SortedDict.irange().
 
         Examples:
-            >>> tree = TreeMap()
-            >>> tree["apple"] = 1
-            >>> tree["application"] = 2
-            >>> tree["banana"] = 3
-            >>> tree.get_prefix("app")
-            ('apple', 1)
-            >>> tree.get_prefix("ban")
-            ('banana', 3)
-            >>> tree.get_prefix("orange")
-            None
-
-        Note:
-            Due to blart's adaptive radix tree design with prefix compression,
-            when a key is a prefix of another key, inserting the longer key will
-            remove the shorter prefix key. This is expected behavior.
+            >>> tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+            >>> list(tree.irange("b", "c"))
+            ['b', 'c']
         """
         ...
 
-    def prefix_iter(self, prefix: str) -> Iterator[Tuple[str, Any]]:
-        """Get an iterator over all key-value pairs with a given prefix.
+    @property
+    def iloc(self) -> "TreeMapILoc[_K]":
+        """Positional access to the keys: tree.iloc[i], tree.iloc[i:j] and
+        del tree.iloc[i]."""
+        ...
 
-        Returns an iterator that yields (key, value) tuples for all keys
-        that start with the given prefix, in lexicographic order.
+    def sample(self, n: int = 1, seed: Optional[int] = None) -> List[Tuple[_K, _V]]:
+        """Return entries chosen uniformly at random, without replacement.
 
         Args:
-            prefix: The prefix to search for
+            n: Number of entries to return
+            seed: Optional integer seed, for a reproducible sample
 
         Returns:
-            An iterator over (key, value) tuples matching the prefix
+            A list of n distinct (key, value) tuples, in random order
 
-        Examples:
-            >>> tree = TreeMap()
-            >>> tree["apple"] = 1
-            >>> tree["application"] = 2
-            >>> tree["apply"] = 3
-            >>> tree["banana"] = 4
-            >>> for key, value in tree.prefix_iter("app"):
-            ...     print(f"{key}: {value}")
-            apple: 1
-            application: 2
-            apply: 3
-            >>> list(tree.prefix_iter("ban"))
-            [('banana', 4)]
-            >>> list(tree.prefix_iter("orange"))
-            []
+        Raises:
+            ValueError: If n is larger than the number of entries
 
-        Note:
-            An empty prefix ("") matches all keys in the tree.
+        Examples:
+            >>> tree = TreeMap({"a": 1, "b": 2, "c": 3})
+            >>> len(tree.sample(2))
+            2
         """
         ...
-
-    def first(self) -> Optional[Tuple[str, Any]]:
+    def first(self) -> Optional[Tuple[_K, _V]]:
         """Get the first (minimum) key-value pair.
 
         Returns the first key-value pair in lexicographic order,
@@ -261,7 +2265,7 @@ class TreeMap:
         """
         ...
 
-    def last(self) -> Optional[Tuple[str, Any]]:
+    def last(self) -> Optional[Tuple[_K, _V]]:
         """Get the last (maximum) key-value pair.
 
         Returns the last key-value pair in lexicographic order,
@@ -282,7 +2286,29 @@ class TreeMap:
         """
         ...
 
-    def pop_first(self) -> Optional[Tuple[str, Any]]:
+    def first_n(self, n: int) -> List[Tuple[_K, _V]]:
+        """Get the n smallest key-value pairs, in key order.
+
+        Only the first n entries are visited.
+
+        Examples:
+            >>> TreeMap({"c": 3, "a": 1, "b": 2}).first_n(2)
+            [('a', 1), ('b', 2)]
+        """
+        ...
+
+    def last_n(self, n: int) -> List[Tuple[_K, _V]]:
+        """Get the n largest key-value pairs, in key order.
+
+        Only the last n entries are visited.
+
+        Examples:
+            >>> TreeMap({"c": 3, "a": 1, "b": 2}).last_n(2)
+            [('b', 2), ('c', 3)]
+        """
+        ...
+
+    def pop_first(self) -> Optional[Tuple[_K, _V]]:
         """Remove and return the first (minimum) key-value pair.
 
         Returns and removes the first key-value pair in lexicographic order,
@@ -306,7 +2332,7 @@ class TreeMap:
         """
         ...
 
-    def pop_last(self) -> Optional[Tuple[str, Any]]:
+    def pop_last(self) -> Optional[Tuple[_K, _V]]:
         """Remove and return the last (maximum) key-value pair.
 
         Returns and removes the last key-value pair in lexicographic order,
@@ -331,8 +2357,18 @@ class TreeMap:
         ...
 
     def fuzzy_search(
-        self, key: str, max_distance: int
-    ) -> Iterator[Tuple[str, Any, int]]:
+        self,
+        key: _K,
+        max_distance: Union[int, float],
+        *,
+        max_results: Optional[int] = None,
+        sort_by_distance: bool = False,
+        metric: Literal["levenshtein", "damerau", "hamming"] = "levenshtein",
+        insert_cost: Optional[float] = None,
+        delete_cost: Optional[float] = None,
+        substitute_cost: Optional[float] = None,
+        substitution_costs: Optional[Dict[Tuple[Any, Any], float]] = None,
+    ) -> "FuzzyIterator[_K, _V]":
         """Fuzzy search for keys within a Levenshtein distance threshold.
 
         Returns an iterator that yields (key, value, distance) tuples for all keys
@@ -341,13 +2377,33 @@ class TreeMap:
         edits (insertions, deletions, or substitutions) required to change one string
         into another.
 
+        Matches are found lazily in key order, so taking the first few hits
+        only searches as much of the tree as needed. With sort_by_distance=True
+        the closest matches come first (ties in key order), ranked in Rust with
+        a heap bounded by max_results.
+
         Args:
             key: The search key to match against
             max_distance: Maximum Levenshtein distance (edit distance) allowed
+            max_results: Stop after this many matches (defaults to no limit)
+            sort_by_distance: Yield the closest matches first instead of in
+                key order
+            metric: "levenshtein", "damerau" (adjacent swaps count as one
+                edit) or "hamming" (substitutions only, same-length keys)
+            insert_cost: Cost of a stored-key character missing from key
+            delete_cost: Cost of a key character missing from the stored key
+            substitute_cost: Cost of replacing (or, with "damerau", swapping)
+                characters
+            substitution_costs: Per-pair substitution costs, keyed by
+                (key_char, stored_char); byte values for non-str keys
 
         Returns:
             An iterator over (key, value, distance) tuples where distance is the
-            Levenshtein distance from the search key
+            Levenshtein distance from the search key, or the total cost as a
+            float when any cost argument is given
+
+        Raises:
+            ValueError: If a cost is negative
 
         Examples:
             >>> tree = TreeMap()
@@ -360,9 +2416,10 @@ class TreeMap:
             >>> # Results include exact match (distance=0) and close matches
             >>> for key, value, distance in results:
             ...     print(f"{key}: {value} (distance={distance})")
+            best: 4 (distance=1)
+            tent: 3 (distance=1)
             test: 1 (distance=0)
             text: 2 (distance=1)
-            best: 4 (distance=1)
             >>> # Search for typo with distance 2
             >>> list(tree.fuzzy_search("tset", 2))
             [('test', 1, 2), ...]
@@ -373,5 +2430,419 @@ class TreeMap:
             but may be slower for large trees.
         """
         ...
+    def fuzzy_prefix(
+        self,
+        prefix: str,
+        max_distance: Union[int, float],
+        *,
+        max_results: Optional[int] = None,
+        sort_by_distance: bool = False,
+        metric: Literal["levenshtein", "damerau", "hamming"] = "levenshtein",
+        insert_cost: Optional[float] = None,
+        delete_cost: Optional[float] = None,
+        substitute_cost: Optional[float] = None,
+        substitution_costs: Optional[Dict[Tuple[Any, Any], float]] = None,
+    ) -> "FuzzyIterator[_K, _V]":
+        """Find keys that start with something close to a prefix.
+
+        A key matches when any of its prefixes is within max_distance of
+        prefix; the distance reported is that of its closest prefix. Takes
+        the same keyword arguments as fuzzy_search().
+
+        Args:
+            prefix: Prefix typed so far
+            max_distance: Maximum edit distance between prefix and the start
+                of a key
+
+        Returns:
+            An iterator over (key, value, distance) tuples
+
+        Examples:
+            >>> tree = TreeMap({"hello world": 1, "help": 2, "yellow": 3})
+            >>> list(tree.fuzzy_prefix("helo", 1))
+            [('hello world', 1, 1), ('help', 2, 1)]
+        """
+        ...
+    def fuzzy_search_many(
+        self,
+        queries: Iterable[str],
+        max_distance: Union[int, float],
+        *,
+        max_results: Optional[int] = None,
+        sort_by_distance: bool = False,
+        metric: Literal["levenshtein", "damerau", "hamming"] = "levenshtein",
+        insert_cost: Optional[float] = None,
+        delete_cost: Optional[float] = None,
+        substitute_cost: Optional[float] = None,
+        substitution_costs: Optional[Dict[Tuple[Any, Any], float]] = None,
+        parallel: bool = True,
+    ) -> List[List[Tuple[_K, _V, Union[int, float]]]]:
+        """Run fuzzy_search() for many queries in one call.
+
+        The queries are searched in Rust with the GIL released, on several
+        threads unless parallel=False. Takes the same keyword arguments as
+        fuzzy_search().
+
+        Args:
+            queries: Iterable of keys to search for
+            max_distance: Maximum edit distance allowed for every query
+            parallel: Search the queries on several threads
+
+        Returns:
+            One list of (key, value, distance) tuples per query, in order
+
+        Examples:
+            >>> names = TreeMap({"alice": 1, "alicia": 2, "bob": 3})
+            >>> names.fuzzy_search_many(["alise", "bobb", "zed"], 1)
+            [[('alice', 1, 1)], [('bob', 3, 1)], []]
+        """
+        ...
+
+    def nearest(
+        self,
+        key: _K,
+        k: int = 1,
+        *,
+        metric: Literal["levenshtein", "damerau", "hamming"] = "levenshtein",
+    ) -> List[Tuple[_K, _V, int]]:
+        """Return the k stored keys closest to a key, however far away.
+
+        No distance cutoff is needed: the search radius grows until k keys
+        are found.
+
+        Args:
+            key: Key to search for
+            k: Number of keys to return
+            metric: "levenshtein", "damerau" or "hamming"
+
+        Returns:
+            Up to k (key, value, distance) tuples, closest first
+
+        Examples:
+            >>> tree = TreeMap({"apple": 1, "banana": 2, "cherry": 3})
+            >>> tree.nearest("bandana", k=2)
+            [('banana', 2, 1), ('apple', 1, 6)]
+        """
+        ...
+    def suggest(
+        self,
+        word: str,
+        n: int = 5,
+        *,
+        max_distance: int = 2,
+        metric: Literal["levenshtein", "damerau", "hamming"] = "levenshtein",
+    ) -> List[Any]:
+        """Suggest stored keys close to a possibly misspelled word.
+
+        Keys are ranked by edit distance, then by value (highest first), so
+        storing word frequencies as values puts the most common spelling
+        first. Non-numeric values rank after numeric ones at the same
+        distance.
+
+        Args:
+            word: Word to find suggestions for
+            n: Maximum number of suggestions
+            max_distance: Maximum edit distance of a suggestion
+            metric: "levenshtein", "damerau" or "hamming"
+
+        Returns:
+            Up to n keys, best suggestion first
+
+        Examples:
+            >>> words = TreeMap({"python": 120, "pylon": 3, "typhoon": 15})
+            >>> words.suggest("pyton")
+            ['python', 'pylon']
+        """
+        ...
+
+class TreeMapTransaction:
+    """Context manager returned by TreeMap.transaction().
+
+    Entering returns the tree. Leaving with an exception restores every key
+    changed inside the block; leaving normally keeps the changes.
+    """
+
+    def __enter__(self) -> TreeMap: ...
+    def __exit__(self, exc_type: Any, exc_value: Any, traceback: Any) -> bool: ...
+
+class TreeMapSnapshot:
+    """Read-only, point-in-time copy of a TreeMap (returned by .snapshot()).
+
+    Writes to the original tree never show up in a snapshot, and a snapshot
+    cannot be modified, so any thread can read it without locking.
+    """
+
+    def get(self, key: Any, default: Any = None) -> Any: ...
+    def prefix_iter(self, prefix: Any) -> TreeMapIterator[Tuple[Any, Any]]: ...
+    def first(self) -> Optional[Tuple[Any, Any]]: ...
+    def last(self) -> Optional[Tuple[Any, Any]]: ...
+    def keys(self) -> List[Any]: ...
+    def values(self) -> List[Any]: ...
+    def items(self) -> List[Tuple[Any, Any]]: ...
+    def __getitem__(self, key: Any) -> Any: ...
+    def __contains__(self, key: Any) -> bool: ...
+    def __len__(self) -> int: ...
+    def __iter__(self) -> TreeMapIterator[Any]: ...
+
+class SharedTreeMap:
+    """Read-only TreeMap stored in shared memory (from share() or attach()).
+
+    Keys are searched in place in the shared block; values are unpickled on
+    every access. Pickling a SharedTreeMap attaches to the same block, so it
+    can be passed straight to multiprocessing workers.
+    """
+
+    @property
+    def name(self) -> str:
+        """Name of the shared memory block, for TreeMap.attach()."""
+        ...
+    @property
+    def key_type(self) -> str: ...
+    def get(self, key: Any, default: Any = None) -> Any: ...
+    def prefix_iter(self, prefix: Any) -> TreeMapIterator[Tuple[Any, Any]]: ...
+    def first(self) -> Optional[Tuple[Any, Any]]: ...
+    def last(self) -> Optional[Tuple[Any, Any]]: ...
+    def keys(self) -> List[Any]: ...
+    def values(self) -> List[Any]: ...
+    def items(self) -> List[Tuple[Any, Any]]: ...
+    def close(self) -> None:
+        """Detach from the shared memory block. The block itself is kept."""
+        ...
+    def unlink(self) -> None:
+        """Free the shared memory block once every process has closed it."""
+        ...
+    def __enter__(self) -> "SharedTreeMap": ...
+    def __exit__(self, *args: Any) -> None:
+        """Close the map, and free the block if this process created it."""
+        ...
+    def __getitem__(self, key: Any) -> Any: ...
+    def __contains__(self, key: Any) -> bool: ...
+    def __len__(self) -> int: ...
+    def __iter__(self) -> TreeMapIterator[Any]: ...
+
+class ConcurrentTreeMap(Generic[_K, _V]):
+    """A thread-safe TreeMap for sharing one index between Python threads.
+
+    The tree lives behind a read-write lock. Lookups and prefix scans take the
+    shared lock with the GIL released, so several threads can query at once;
+    writes take the exclusive lock. Iteration, prefix scans and keys()/
+    values()/items() work on snapshots, so concurrent writes never
+    invalidate them.
+
+    Examples:
+        >>> index = ConcurrentTreeMap({"apple": 1, "apricot": 2})
+        >>> index["banana"] = 3  # from any thread
+        >>> list(index.prefix_iter("ap"))
+        [('apple', 1), ('apricot', 2)]
+    """
+
+    def __init__(
+        self,
+        data: Optional[Any] = None,
+        *,
+        key_type: Any = None,
+        separator: Optional[str] = None,
+        errors: Optional[Literal["strict", "replace", "surrogateescape", "bytes"]] = None,
+    ) -> None: ...
+    def insert(self, key: _K, value: _V) -> None:
+        """Insert a key-value pair, replacing any existing value."""
+        ...
+    def get(self, key: _K, default: Any = None) -> Optional[_V]:
+        """Get a value by key, with the lookup running without the GIL."""
+        ...
+    def remove(self, key: _K) -> _V:
+        """Remove a key and return its value.
+
+        Raises:
+            KeyError: If the key does not exist
+        """
+        ...
+    def clear(self) -> None:
+        """Remove all entries."""
+        ...
+    def prefix_iter(self, prefix: Any) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Iterate over a snapshot of the pairs whose key starts with prefix."""
+        ...
+    def keys(self) -> List[_K]:
+        """Snapshot list of the keys, in key order."""
+        ...
+    def values(self) -> List[_V]:
+        """Snapshot list of the values, in key order."""
+        ...
+    def items(self) -> List[Tuple[_K, _V]]:
+        """Snapshot list of the (key, value) pairs, in key order."""
+        ...
+    @property
+    def key_type(self) -> Any:
+        """How keys are stored: a key_type name such as "str", or the codec object."""
+        ...
+    def __getitem__(self, key: _K) -> _V: ...
+    def __setitem__(self, key: _K, value: _V) -> None: ...
+    def __delitem__(self, key: _K) -> None: ...
+    def __contains__(self, key: _K) -> bool: ...
+    def __len__(self) -> int: ...
+    def __iter__(self) -> TreeMapIterator[_K]: ...
+
+class TreeMultiMap(Generic[_K, _V]):
+    """A sorted map holding any number of values per key.
+
+    Inserting a key that is already stored appends to its values instead of
+    replacing them. The values of a key keep their insertion order, and
+    len() counts values across all keys.
+
+    Examples:
+        >>> index = TreeMultiMap([("apple", 1), ("apple", 7)])
+        >>> index.get_all("apple")
+        [1, 7]
+    """
+
+    def __init__(
+        self,
+        data: Optional[Any] = None,
+        *,
+        key_type: Any = None,
+        separator: Optional[str] = None,
+        errors: Optional[Literal["strict", "replace", "surrogateescape", "bytes"]] = None,
+    ) -> None: ...
+    def insert(self, key: _K, value: _V) -> None:
+        """Add a value under a key, after any values already stored there."""
+        ...
+    def extend(self, key: _K, values: Iterable[_V]) -> None:
+        """Add several values under a key, after any values already stored there."""
+        ...
+    def get_all(self, key: _K) -> List[_V]:
+        """All values stored under a key, in insertion order; empty if missing."""
+        ...
+    def get(self, key: _K, default: Any = None) -> Optional[_V]:
+        """The first value stored under a key, or default."""
+        ...
+    def remove(self, key: _K) -> List[_V]:
+        """Remove a key and return all of its values.
+
+        Raises:
+            KeyError: If the key does not exist
+        """
+        ...
+    def remove_value(self, key: _K, value: _V) -> None:
+        """Remove the first occurrence of a value under a key.
+
+        Raises:
+            KeyError: If the key does not exist
+            ValueError: If the value is not stored under the key
+        """
+        ...
+    def clear(self) -> None:
+        """Remove all keys and values."""
+        ...
+    def keys(self) -> List[_K]:
+        """List of the distinct keys, in key order."""
+        ...
+    def values(self) -> List[_V]:
+        """List of every value, in key order."""
+        ...
+    def items(self, flatten: bool = True) -> List[Tuple[_K, Any]]:
+        """List of (key, value) pairs for every value, or (key, values) per key."""
+        ...
+    def prefix_iter(self, prefix: Any) -> TreeMapIterator[Tuple[_K, _V]]:
+        """Iterate over (key, value) for every value whose key starts with prefix."""
+        ...
+    @property
+    def key_type(self) -> Any:
+        """How keys are stored: a key_type name such as "str", or the codec object."""
+        ...
+    def __getitem__(self, key: _K) -> List[_V]: ...
+    def __setitem__(self, key: _K, value: _V) -> None: ...
+    def __delitem__(self, key: _K) -> None: ...
+    def __contains__(self, key: _K) -> bool: ...
+    def __len__(self) -> int: ...
+    def __iter__(self) -> TreeMapIterator[_K]: ...
+
+class TreeCounter(Generic[_K]):
+    """A collections.Counter over sorted keys, with native 64-bit counts.
+
+    Missing keys count as 0, and most_common() and total() can be
+    restricted to a prefix.
+
+    Examples:
+        >>> words = TreeCounter(["apple", "apricot", "apple", "banana"])
+        >>> words.most_common(1)
+        [('apple', 2)]
+        >>> words.total(prefix="ap")
+        3
+    """
+
+    def __init__(
+        self,
+        data: Optional[Union[Iterable[_K], Mapping[_K, int]]] = None,
+        *,
+        key_type: Any = None,
+        separator: Optional[str] = None,
+        errors: Optional[Literal["strict", "replace", "surrogateescape", "bytes"]] = None,
+    ) -> None: ...
+    def update(
+        self, data: Optional[Union[Iterable[_K], Mapping[_K, int]]] = None, **kwargs: int
+    ) -> None:
+        """Add counts from an iterable of keys, a mapping or another TreeCounter."""
+        ...
+    def subtract(
+        self, data: Optional[Union[Iterable[_K], Mapping[_K, int]]] = None, **kwargs: int
+    ) -> None:
+        """Subtract counts, keeping keys whose count drops to zero or below."""
+        ...
+    def increment(self, key: _K, delta: int = 1) -> int:
+        """Add to the count of a key and return the new count.
+
+        Raises:
+            OverflowError: If the count no longer fits in 64 bits
+        """
+        ...
+    def most_common(
+        self, n: Optional[int] = None, *, prefix: Optional[Any] = None
+    ) -> List[Tuple[_K, int]]:
+        """The keys with the highest counts, highest first; ties in key order.
+
+        Args:
+            n: Number of entries to return (defaults to all)
+            prefix: Only consider keys starting with this prefix
+        """
+        ...
+    def total(self, *, prefix: Optional[Any] = None) -> int:
+        """Sum of the counts, optionally only of keys starting with prefix."""
+        ...
+    def keys(self) -> List[_K]:
+        """List of the keys, in key order."""
+        ...
+    def values(self) -> List[int]:
+        """List of the counts, in key order."""
+        ...
+    def items(self) -> List[Tuple[_K, int]]:
+        """List of the (key, count) pairs, in key order."""
+        ...
+    def prefix_iter(self, prefix: Any) -> TreeMapIterator[Tuple[_K, int]]:
+        """Iterate over the (key, count) pairs whose key starts with prefix."""
+        ...
+    def clear(self) -> None:
+        """Remove all counts."""
+        ...
+    @property
+    def key_type(self) -> Any:
+        """How keys are stored: a key_type name such as "str", or the codec object."""
+        ...
+    def __getitem__(self, key: _K) -> int:
+        """The count of a key, or 0 if it is missing."""
+        ...
+    def __setitem__(self, key: _K, count: int) -> None: ...
+    def __delitem__(self, key: _K) -> None: ...
+    def __contains__(self, key: _K) -> bool: ...
+    def __len__(self) -> int: ...
+    def __iter__(self) -> TreeMapIterator[_K]: ...
+    def __add__(self, other: "TreeCounter[_K]") -> "TreeCounter[_K]":
+        """Sum of the counts, keeping only positive counts."""
+        ...
+    def __sub__(self, other: "TreeCounter[_K]") -> "TreeCounter[_K]":
+        """Difference of the counts, keeping only positive counts."""
+        ...
+    def __iadd__(self, other: "TreeCounter[_K]") -> "TreeCounter[_K]": ...
+    def __isub__(self, other: "TreeCounter[_K]") -> "TreeCounter[_K]": ...
 
-__all__ = ["TreeMap"]
+__all__ = ["ConcurrentTreeMap", "TreeCounter", "TreeMap", "TreeMultiMap"]
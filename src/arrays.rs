@@ -0,0 +1,265 @@
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList};
+
+/// Keys read straight out of an array's memory.
+pub struct RawKeys {
+    pub keys: Vec<Box<[u8]>>,
+    /// Whether the keys are text (UTF-8 encoded from a unicode array) rather
+    /// than bytes
+    pub text: bool,
+}
+
+/// Read the keys of a NumPy "S" (bytes) or "U" (unicode) array, or of a
+/// flat data buffer split at `offsets` (as in Arrow string arrays).
+///
+/// Returns None for arrays of any other dtype, whose elements have to be
+/// converted one by one.
+pub fn array_keys(
+    keys: &Bound<'_, PyAny>,
+    offsets: Option<&Bound<'_, PyAny>>,
+) -> PyResult<Option<RawKeys>> {
+    if let Some(offsets) = offsets {
+        return split_buffer(keys, offsets).map(Some);
+    }
+    let Ok(dtype) = keys.getattr("dtype") else {
+        return Ok(None);
+    };
+    let kind: String = dtype.getattr("kind")?.extract()?;
+    if kind != "S" && kind != "U" {
+        return Ok(None);
+    }
+    let width: usize = dtype.getattr("itemsize")?.extract()?;
+    let data = keys.call_method0("tobytes")?;
+    let data = data.cast::<PyBytes>()?.as_bytes();
+    if width == 0 {
+        let len: usize = keys.getattr("size")?.extract()?;
+        return Ok(Some(RawKeys {
+            keys: vec![Box::default(); len],
+            text: kind == "U",
+        }));
+    }
+    if kind == "S" {
+        // NumPy pads shorter values with trailing NULs
+        let keys = data
+            .chunks_exact(width)
+            .map(|item| trim_end(item, |b| *b == 0).into())
+            .collect();
+        return Ok(Some(RawKeys { keys, text: false }));
+    }
+    let big_endian = match dtype.getattr("byteorder")?.extract::<String>()?.as_str() {
+        ">" => true,
+        "<" => false,
+        _ => cfg!(target_endian = "big"),
+    };
+    let keys = data
+        .chunks_exact(width)
+        .map(|item| ucs4_to_utf8(item, big_endian))
+        .collect::<PyResult<_>>()?;
+    Ok(Some(RawKeys { keys, text: true }))
+}
+
+/// Read the keys of a pyarrow string or binary array (or chunked array)
+/// from its offsets and data buffers.
+///
+/// Returns None for columns of any other type, whose elements have to be
+/// converted one by one.
+pub fn arrow_keys(column: &Bound<'_, PyAny>) -> PyResult<Option<RawKeys>> {
+    let (width, text) = match column.getattr("type")?.str()?.to_str()? {
+        "string" | "utf8" => (4, true),
+        "large_string" | "large_utf8" => (8, true),
+        "binary" => (4, false),
+        "large_binary" => (8, false),
+        _ => return Ok(None),
+    };
+    if column.getattr("null_count")?.extract::<usize>()? > 0 {
+        return Err(PyValueError::new_err("key column contains nulls"));
+    }
+    let chunks = match column.getattr("chunks") {
+        Ok(chunks) => chunks.extract()?,
+        Err(_) => vec![column.clone()],
+    };
+    let mut keys = Vec::new();
+    for chunk in chunks {
+        let start: usize = chunk.getattr("offset")?.extract()?;
+        let len = chunk.len()?;
+        let buffers = chunk.call_method0("buffers")?;
+        let offsets = buffers.get_item(1)?;
+        let data = buffers.get_item(2)?;
+        read_arrow_chunk(&offsets, &data, start, len, width, &mut keys)?;
+    }
+    Ok(Some(RawKeys { keys, text }))
+}
+
+/// Slice `len` keys, starting at key `start`, out of one chunk's buffers.
+/// The buffers are read in place rather than copied to Python bytes.
+fn read_arrow_chunk(
+    offsets: &Bound<'_, PyAny>,
+    data: &Bound<'_, PyAny>,
+    start: usize,
+    len: usize,
+    width: usize,
+    keys: &mut Vec<Box<[u8]>>,
+) -> PyResult<()> {
+    if len == 0 {
+        return Ok(());
+    }
+    let py = offsets.py();
+    let invalid = || PyValueError::new_err("invalid offsets in arrow array");
+    let offsets = PyBuffer::<u8>::get(offsets)?;
+    let offsets = offsets.as_slice(py).ok_or_else(invalid)?;
+    let offsets = offsets
+        .get(start * width..(start + len + 1) * width)
+        .ok_or_else(invalid)?;
+    let offsets = offsets
+        .chunks_exact(width)
+        .map(|bytes| {
+            let mut raw = [0; 8];
+            for (to, from) in raw.iter_mut().zip(bytes) {
+                *to = from.get();
+            }
+            match width {
+                4 => i32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]) as i64,
+                _ => i64::from_ne_bytes(raw),
+            }
+        })
+        .map(|o| usize::try_from(o).map_err(|_| invalid()))
+        .collect::<PyResult<Vec<_>>>()?;
+    // An array of empty values may have no data buffer
+    let buffer = match data.is_none() {
+        true => None,
+        false => Some(PyBuffer::<u8>::get(data)?),
+    };
+    let data = match &buffer {
+        Some(buffer) => buffer.as_slice(py).ok_or_else(invalid)?,
+        None => &[],
+    };
+    for pair in offsets.windows(2) {
+        let key = data.get(pair[0]..pair[1]).ok_or_else(invalid)?;
+        keys.push(key.iter().map(|b| b.get()).collect());
+    }
+    Ok(())
+}
+
+/// Build a pyarrow string (or binary) array from keys, in one data buffer
+/// and one offsets buffer. Switches to 64-bit offsets when the keys don't
+/// fit 32-bit ones.
+pub fn arrow_array<'py>(
+    py: Python<'py>,
+    keys: &[&[u8]],
+    text: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    let pa = py.import("pyarrow")?;
+    let total: usize = keys.iter().map(|key| key.len()).sum();
+    let large = total > i32::MAX as usize;
+    let mut offsets = Vec::with_capacity((keys.len() + 1) * if large { 8 } else { 4 });
+    let mut data = Vec::with_capacity(total);
+    let push_offset = |offset: usize, offsets: &mut Vec<u8>| match large {
+        true => offsets.extend((offset as i64).to_ne_bytes()),
+        false => offsets.extend((offset as i32).to_ne_bytes()),
+    };
+    push_offset(0, &mut offsets);
+    for key in keys {
+        data.extend_from_slice(key);
+        push_offset(data.len(), &mut offsets);
+    }
+    let kind = match (text, large) {
+        (true, false) => "string",
+        (true, true) => "large_string",
+        (false, false) => "binary",
+        (false, true) => "large_binary",
+    };
+    // No validity buffer, as keys are never null
+    let buffers = PyList::new(
+        py,
+        [
+            py.None().into_bound(py),
+            pa.call_method1("py_buffer", (PyBytes::new(py, &offsets),))?,
+            pa.call_method1("py_buffer", (PyBytes::new(py, &data),))?,
+        ],
+    )?;
+    pa.getattr("Array")?.call_method1(
+        "from_buffers",
+        (pa.call_method0(kind)?, keys.len(), buffers),
+    )
+}
+
+/// Split a flat byte buffer at `offsets`, which holds one more entry than
+/// there are keys.
+fn split_buffer(data: &Bound<'_, PyAny>, offsets: &Bound<'_, PyAny>) -> PyResult<RawKeys> {
+    let py = data.py();
+    let data = PyBuffer::<u8>::get(data)?.to_vec(py)?;
+    let offsets = offset_list(offsets)?;
+    let mut keys = Vec::with_capacity(offsets.len().saturating_sub(1));
+    for pair in offsets.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        if start > end || end > data.len() {
+            return Err(PyValueError::new_err(format!(
+                "offsets must be non-decreasing and within the {} byte buffer",
+                data.len()
+            )));
+        }
+        keys.push(data[start..end].into());
+    }
+    Ok(RawKeys { keys, text: false })
+}
+
+/// Read an integer array of offsets, as 32 or 64-bit, signed or unsigned.
+fn offset_list(offsets: &Bound<'_, PyAny>) -> PyResult<Vec<usize>> {
+    let py = offsets.py();
+    let negative = || PyValueError::new_err("offsets must not be negative");
+    if let Ok(buffer) = PyBuffer::<i64>::get(offsets) {
+        return buffer
+            .to_vec(py)?
+            .into_iter()
+            .map(|o| usize::try_from(o).map_err(|_| negative()))
+            .collect();
+    }
+    if let Ok(buffer) = PyBuffer::<i32>::get(offsets) {
+        return buffer
+            .to_vec(py)?
+            .into_iter()
+            .map(|o| usize::try_from(o).map_err(|_| negative()))
+            .collect();
+    }
+    if let Ok(buffer) = PyBuffer::<u64>::get(offsets) {
+        return Ok(buffer.to_vec(py)?.into_iter().map(|o| o as usize).collect());
+    }
+    Ok(PyBuffer::<u32>::get(offsets)?
+        .to_vec(py)?
+        .into_iter()
+        .map(|o| o as usize)
+        .collect())
+}
+
+fn trim_end<T>(items: &[T], padding: impl Fn(&T) -> bool) -> &[T] {
+    let len = items
+        .iter()
+        .rposition(|item| !padding(item))
+        .map_or(0, |i| i + 1);
+    &items[..len]
+}
+
+/// Encode one fixed-width UCS-4 item of a "U" array as UTF-8.
+fn ucs4_to_utf8(item: &[u8], big_endian: bool) -> PyResult<Box<[u8]>> {
+    let units: Vec<u32> = item
+        .chunks_exact(4)
+        .map(|unit| {
+            let unit = [unit[0], unit[1], unit[2], unit[3]];
+            if big_endian {
+                u32::from_be_bytes(unit)
+            } else {
+                u32::from_le_bytes(unit)
+            }
+        })
+        .collect();
+    let mut text = String::with_capacity(units.len());
+    for &unit in trim_end(&units, |u| *u == 0) {
+        let c = char::from_u32(unit).ok_or_else(|| {
+            PyValueError::new_err(format!("invalid code point {unit:#x} in unicode array"))
+        })?;
+        text.push(c);
+    }
+    Ok(text.into_bytes().into_boxed_slice())
+}
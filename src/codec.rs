@@ -0,0 +1,795 @@
+use pyo3::exceptions::{PyOverflowError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::sync::PyOnceLock;
+use pyo3::types::{
+    IntoPyDict, PyBool, PyBytes, PyDateTime, PyDelta, PyDeltaAccess, PyFloat, PyInt, PyString,
+    PyTuple, PyType, PyTzInfo,
+};
+use pyo3::{PyTraverseError, PyVisit};
+
+/// Conversion between Python keys and the byte strings stored in the tree.
+///
+/// Every codec must preserve order: if `a < b` then `encode(a) < encode(b)`
+/// when compared bytewise, so that iteration and boundary operations return
+/// keys in their natural order.
+pub enum KeyCodec {
+    /// `str` keys stored as UTF-8 (the default)
+    Str(DecodeErrors),
+    /// `bytes` keys stored as-is
+    Bytes,
+    /// Signed 64-bit `int` keys, big-endian with the sign bit flipped
+    Int,
+    /// Unsigned 64-bit `int` keys, big-endian
+    UInt,
+    /// Composite `tuple` keys, encoded field by field with type tags
+    Tuple,
+    /// `datetime` keys stored as signed microseconds since the Unix epoch
+    DateTime,
+    /// `uuid.UUID` keys stored as their 16 raw bytes
+    Uuid,
+    /// `ipaddress` networks and addresses, stored one byte per prefix bit
+    Ip,
+    /// `str` or `os.PathLike` paths, normalized to use a single separator
+    Path(u8),
+    /// A Python object providing `encode(key) -> bytes` and `decode(data) -> key`
+    Custom(Py<PyAny>),
+}
+
+/// What to do with stored `str` keys that are not valid UTF-8.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DecodeErrors {
+    /// Raise UnicodeDecodeError
+    Strict,
+    /// Substitute U+FFFD for invalid sequences
+    Replace,
+    /// Round-trip invalid bytes through lone surrogates, like `os.fsdecode`
+    SurrogateEscape,
+    /// Return invalid keys as `bytes`, and accept `bytes` keys on input
+    Bytes,
+}
+
+impl DecodeErrors {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Replace => "replace",
+            Self::SurrogateEscape => "surrogateescape",
+            Self::Bytes => "bytes",
+        }
+    }
+}
+
+impl KeyCodec {
+    /// Build a codec from the `key_type` constructor argument.
+    ///
+    /// Accepts None (str keys), a codec name, the builtin `str`/`bytes` types,
+    /// or any object with `encode` and `decode` methods.
+    pub fn from_key_type(key_type: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let Some(key_type) = key_type else {
+            return Ok(Self::Str(DecodeErrors::Strict));
+        };
+        let py = key_type.py();
+        if let Ok(name) = key_type.cast::<PyString>() {
+            return match name.to_str()? {
+                "str" => Ok(Self::Str(DecodeErrors::Strict)),
+                "bytes" => Ok(Self::Bytes),
+                "int" => Ok(Self::Int),
+                "uint" => Ok(Self::UInt),
+                "tuple" => Ok(Self::Tuple),
+                "datetime" => Ok(Self::DateTime),
+                "uuid" => Ok(Self::Uuid),
+                "ip" => Ok(Self::Ip),
+                "path" => Ok(Self::Path(b'/')),
+                other => Err(PyValueError::new_err(format!(
+                    "Unknown key_type '{}', expected 'str', 'bytes', 'int', 'uint', 'tuple', \
+                     'datetime', 'uuid', 'ip', 'path' or a codec object",
+                    other
+                ))),
+            };
+        }
+        if let Ok(ty) = key_type.cast::<PyType>() {
+            if ty.is(py.get_type::<PyString>()) {
+                return Ok(Self::Str(DecodeErrors::Strict));
+            }
+            if ty.is(py.get_type::<PyBytes>()) {
+                return Ok(Self::Bytes);
+            }
+            if ty.is(py.get_type::<PyInt>()) {
+                return Ok(Self::Int);
+            }
+            if ty.is(py.get_type::<PyTuple>()) {
+                return Ok(Self::Tuple);
+            }
+            if ty.is(py.get_type::<PyDateTime>()) {
+                return Ok(Self::DateTime);
+            }
+            if ty.is(uuid_class(py)?) {
+                return Ok(Self::Uuid);
+            }
+            if ty.is_subclass(pure_path_class(py)?)? {
+                return Ok(Self::Path(b'/'));
+            }
+        }
+        if key_type.hasattr("encode")? && key_type.hasattr("decode")? {
+            return Ok(Self::Custom(key_type.clone().unbind()));
+        }
+        Err(PyTypeError::new_err(
+            "key_type must be a codec name or an object with encode() and decode() methods",
+        ))
+    }
+
+    /// Use a different policy for stored `str` keys that are not valid UTF-8.
+    pub fn with_errors(self, errors: Option<&str>) -> PyResult<Self> {
+        let Some(errors) = errors else {
+            return Ok(self);
+        };
+        let Self::Str(_) = self else {
+            return Err(PyValueError::new_err("errors requires str keys"));
+        };
+        let errors = match errors {
+            "strict" => DecodeErrors::Strict,
+            "replace" => DecodeErrors::Replace,
+            "surrogateescape" => DecodeErrors::SurrogateEscape,
+            "bytes" => DecodeErrors::Bytes,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "errors must be 'strict', 'replace', 'surrogateescape' or 'bytes', got '{}'",
+                    other
+                )))
+            }
+        };
+        Ok(Self::Str(errors))
+    }
+
+    /// The decode policy of a `str` codec.
+    pub fn errors(&self) -> Option<&'static str> {
+        match self {
+            Self::Str(errors) => Some(errors.name()),
+            _ => None,
+        }
+    }
+
+    /// Use a different separator for "path" keys.
+    pub fn with_separator(self, separator: Option<&str>) -> PyResult<Self> {
+        let Some(separator) = separator else {
+            return Ok(self);
+        };
+        let Self::Path(_) = self else {
+            return Err(PyValueError::new_err(
+                "separator requires key_type=\"path\"",
+            ));
+        };
+        match separator.as_bytes() {
+            [byte] if *byte != 0 && byte.is_ascii() => Ok(Self::Path(*byte)),
+            _ => Err(PyValueError::new_err(
+                "separator must be a single ASCII character",
+            )),
+        }
+    }
+
+    /// The separator byte used by "path" keys.
+    pub fn path_separator(&self) -> Option<u8> {
+        match self {
+            Self::Path(separator) => Some(*separator),
+            _ => None,
+        }
+    }
+
+    /// Whether keys are plain strings, which enables string-only key options.
+    pub fn is_str(&self) -> bool {
+        matches!(self, Self::Str(_))
+    }
+
+    /// Whether keys are stored as their own text or bytes, so a fragment of
+    /// a key is also a fragment of its encoding.
+    pub fn is_verbatim(&self) -> bool {
+        matches!(self, Self::Str(_) | Self::Bytes)
+    }
+
+    /// Encode a Python key into the bytes stored in the tree.
+    pub fn encode(&self, key: &Bound<'_, PyAny>) -> PyResult<Box<[u8]>> {
+        match self {
+            Self::Str(errors) => {
+                if let (DecodeErrors::Bytes, Ok(raw)) = (errors, key.cast::<PyBytes>()) {
+                    return Ok(raw.as_bytes().into());
+                }
+                let text = key.cast::<PyString>()?;
+                match (errors, text.to_str()) {
+                    (_, Ok(text)) => Ok(text.as_bytes().into()),
+                    // Lone surrogates produced by a surrogateescape decode map
+                    // back to the original bytes
+                    (DecodeErrors::SurrogateEscape, Err(_)) => Ok(text
+                        .call_method1("encode", ("utf-8", "surrogateescape"))?
+                        .cast::<PyBytes>()?
+                        .as_bytes()
+                        .into()),
+                    (_, Err(err)) => Err(err),
+                }
+            }
+            Self::Bytes => Ok(key.cast::<PyBytes>()?.as_bytes().into()),
+            Self::Int => {
+                let value: i64 = key.cast::<PyInt>()?.extract()?;
+                Ok(((value as u64) ^ (1 << 63)).to_be_bytes().into())
+            }
+            Self::UInt => {
+                let value: u64 = key.cast::<PyInt>()?.extract()?;
+                Ok(value.to_be_bytes().into())
+            }
+            Self::Tuple => {
+                let mut out = Vec::new();
+                encode_fields(key.cast::<PyTuple>()?, 0, &mut out)?;
+                out.push(TAG_END);
+                Ok(out.into())
+            }
+            Self::DateTime => {
+                let micros = epoch_micros(key)?;
+                Ok(((micros as u64) ^ (1 << 63)).to_be_bytes().into())
+            }
+            Self::Uuid => {
+                if !key.is_instance(uuid_class(key.py())?)? {
+                    return Err(PyTypeError::new_err(
+                        "uuid keys must be uuid.UUID instances",
+                    ));
+                }
+                Ok(key.getattr("bytes")?.cast::<PyBytes>()?.as_bytes().into())
+            }
+            Self::Ip => {
+                let (version, bits) = network_bits(key)?;
+                let mut out = Vec::with_capacity(bits.len() + 2);
+                out.push(version);
+                out.extend(bits);
+                out.push(TAG_END);
+                Ok(out.into())
+            }
+            Self::Path(separator) => {
+                let mut out = normalize_path(key, *separator)?.into_bytes();
+                out.push(TAG_END);
+                Ok(out.into())
+            }
+            Self::Custom(codec) => {
+                let encoded = codec.bind(key.py()).call_method1("encode", (key,))?;
+                let bytes = encoded.cast::<PyBytes>().map_err(|_| {
+                    PyTypeError::new_err(format!(
+                        "Key codec encode() must return bytes, not {}",
+                        encoded
+                            .get_type()
+                            .name()
+                            .map_or("?".into(), |n| n.to_string())
+                    ))
+                })?;
+                Ok(bytes.as_bytes().into())
+            }
+        }
+    }
+
+    /// Encode a key used as a prefix query.
+    ///
+    /// For tuple keys the trailing terminator is left off, so `("a",)`
+    /// matches every key whose first field is `"a"`; likewise an "ip" network
+    /// matches every stored subnet inside it.
+    pub fn encode_prefix(&self, key: &Bound<'_, PyAny>) -> PyResult<Box<[u8]>> {
+        match self {
+            Self::Tuple => {
+                let mut out = Vec::new();
+                encode_fields(key.cast::<PyTuple>()?, 0, &mut out)?;
+                Ok(out.into())
+            }
+            Self::Ip => {
+                let (version, bits) = network_bits(key)?;
+                let mut out = vec![version];
+                out.extend(bits);
+                Ok(out.into())
+            }
+            Self::Path(separator) => Ok(normalize_path(key, *separator)?.into_bytes().into()),
+            _ => self.encode(key),
+        }
+    }
+
+    /// Whether encoded keys can be prefixes of each other ("a" and "ab"),
+    /// which blart does not allow to coexist. Other key types have a fixed
+    /// width or end in a terminator.
+    fn needs_terminator(&self) -> bool {
+        matches!(self, Self::Str(_) | Self::Bytes | Self::Custom(_))
+    }
+
+    /// Encode a key so that no stored key is a prefix of another, for maps
+    /// that must hold "a" and "ab" at once. Where needed, zero bytes are
+    /// escaped as in tuple fields and two zero bytes end the key, which the
+    /// escaped bytes never contain; key order is unchanged.
+    pub fn encode_terminated(&self, key: &Bound<'_, PyAny>) -> PyResult<Box<[u8]>> {
+        let encoded = self.encode(key)?;
+        if !self.needs_terminator() {
+            return Ok(encoded);
+        }
+        let mut out = Vec::with_capacity(encoded.len() + 2);
+        escape_into(&encoded, &mut out);
+        out.push(TAG_END);
+        Ok(out.into())
+    }
+
+    /// Encode a prefix query against keys from `encode_terminated`.
+    pub fn encode_prefix_terminated(&self, key: &Bound<'_, PyAny>) -> PyResult<Box<[u8]>> {
+        let encoded = self.encode_prefix(key)?;
+        if !self.needs_terminator() {
+            return Ok(encoded);
+        }
+        let mut out = Vec::with_capacity(encoded.len() + 1);
+        escape_into(&encoded, &mut out);
+        out.pop();
+        Ok(out.into())
+    }
+
+    /// Decode a key stored by `encode_terminated`.
+    pub fn decode_terminated(&self, py: Python, data: &[u8]) -> PyResult<Py<PyAny>> {
+        if !self.needs_terminator() {
+            return self.decode(py, data);
+        }
+        self.decode(py, &unescape(data, &mut 0)?)
+    }
+
+    /// Keys of every network that could contain an address, longest first.
+    ///
+    /// Only meaningful for "ip" keys; used for longest-prefix-match lookups.
+    pub fn covering_networks(&self, address: &Bound<'_, PyAny>) -> PyResult<Vec<Box<[u8]>>> {
+        if !matches!(self, Self::Ip) {
+            return Err(PyTypeError::new_err("lookup() requires key_type=\"ip\""));
+        }
+        let (version, bits) = network_bits(address)?;
+        Ok((0..=bits.len())
+            .rev()
+            .map(|len| {
+                let mut key = Vec::with_capacity(len + 2);
+                key.push(version);
+                key.extend_from_slice(&bits[..len]);
+                key.push(TAG_END);
+                key.into()
+            })
+            .collect())
+    }
+
+    /// Decode stored bytes back into a Python key.
+    pub fn decode(&self, py: Python, data: &[u8]) -> PyResult<Py<PyAny>> {
+        match self {
+            Self::Str(errors) => match std::str::from_utf8(data) {
+                Ok(text) => Ok(PyString::new(py, text).into_any().unbind()),
+                Err(_) => match errors {
+                    DecodeErrors::Replace => Ok(PyString::new(py, &String::from_utf8_lossy(data))
+                        .into_any()
+                        .unbind()),
+                    DecodeErrors::Bytes => Ok(PyBytes::new(py, data).into_any().unbind()),
+                    // Let Python raise its own UnicodeDecodeError or apply the escape
+                    _ => Ok(PyBytes::new(py, data)
+                        .call_method1("decode", ("utf-8", errors.name()))?
+                        .unbind()),
+                },
+            },
+            Self::Bytes => Ok(PyBytes::new(py, data).into_any().unbind()),
+            Self::Int => {
+                let value = (u64::from_be_bytes(fixed_width(data)?) ^ (1 << 63)) as i64;
+                Ok(value.into_pyobject(py)?.into_any().unbind())
+            }
+            Self::UInt => {
+                let value = u64::from_be_bytes(fixed_width(data)?);
+                Ok(value.into_pyobject(py)?.into_any().unbind())
+            }
+            Self::Tuple => {
+                let mut pos = 0;
+                let tuple = decode_fields(py, data, &mut pos, 0)?;
+                Ok(tuple.into_any().unbind())
+            }
+            Self::DateTime => {
+                let micros = (u64::from_be_bytes(fixed_width(data)?) ^ (1 << 63)) as i64;
+                let delta = PyDelta::new(
+                    py,
+                    micros.div_euclid(MICROS_PER_DAY) as i32,
+                    (micros.rem_euclid(MICROS_PER_DAY) / 1_000_000) as i32,
+                    micros.rem_euclid(1_000_000) as i32,
+                    true,
+                )?;
+                Ok(unix_epoch(py, false)?.add(delta)?.unbind())
+            }
+            Self::Uuid => {
+                let kwargs = [("bytes", PyBytes::new(py, data))].into_py_dict(py)?;
+                Ok(uuid_class(py)?.call((), Some(&kwargs))?.unbind())
+            }
+            Self::Ip => {
+                let (&version, rest) = data.split_first().ok_or_else(corrupt)?;
+                let bits = rest.strip_suffix(&[TAG_END]).ok_or_else(corrupt)?;
+                let width = if version == 4 { 4 } else { 16 };
+                let mut packed = vec![0u8; width];
+                for (i, &bit) in bits.iter().enumerate() {
+                    if bit == BIT_ONE {
+                        packed[i / 8] |= 0x80 >> (i % 8);
+                    }
+                }
+                let address = (PyBytes::new(py, &packed), bits.len());
+                Ok(ip_network(py)?.call1((address,))?.unbind())
+            }
+            Self::Path(_) => {
+                let path = data.strip_suffix(&[TAG_END]).ok_or_else(corrupt)?;
+                Ok(PyString::new(py, &String::from_utf8_lossy(path))
+                    .into_any()
+                    .unbind())
+            }
+            Self::Custom(codec) => Ok(codec
+                .bind(py)
+                .call_method1("decode", (PyBytes::new(py, data),))?
+                .unbind()),
+        }
+    }
+
+    /// The value reported by the `key_type` attribute.
+    pub fn key_type(&self, py: Python) -> Py<PyAny> {
+        match self {
+            Self::Str(_) => PyString::new(py, "str").into_any().unbind(),
+            Self::Bytes => PyString::new(py, "bytes").into_any().unbind(),
+            Self::Int => PyString::new(py, "int").into_any().unbind(),
+            Self::UInt => PyString::new(py, "uint").into_any().unbind(),
+            Self::Tuple => PyString::new(py, "tuple").into_any().unbind(),
+            Self::DateTime => PyString::new(py, "datetime").into_any().unbind(),
+            Self::Uuid => PyString::new(py, "uuid").into_any().unbind(),
+            Self::Ip => PyString::new(py, "ip").into_any().unbind(),
+            Self::Path(_) => PyString::new(py, "path").into_any().unbind(),
+            Self::Custom(codec) => codec.clone_ref(py),
+        }
+    }
+
+    /// Clone the codec, including any custom codec object reference.
+    pub fn clone_ref(&self, py: Python) -> Self {
+        match self {
+            Self::Str(errors) => Self::Str(*errors),
+            Self::Bytes => Self::Bytes,
+            Self::Int => Self::Int,
+            Self::UInt => Self::UInt,
+            Self::Tuple => Self::Tuple,
+            Self::DateTime => Self::DateTime,
+            Self::Uuid => Self::Uuid,
+            Self::Ip => Self::Ip,
+            Self::Path(separator) => Self::Path(*separator),
+            Self::Custom(codec) => Self::Custom(codec.clone_ref(py)),
+        }
+    }
+
+    /// Report the custom codec object, if any, to the garbage collector.
+    pub fn traverse(&self, visit: &PyVisit<'_>) -> Result<(), PyTraverseError> {
+        match self {
+            Self::Custom(codec) => visit.call(codec),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Interpret stored bytes as a fixed-width 64-bit integer encoding.
+fn fixed_width(data: &[u8]) -> PyResult<[u8; 8]> {
+    data.try_into()
+        .map_err(|_| PyValueError::new_err("Stored integer key is not 8 bytes long"))
+}
+
+/// The `uuid.UUID` class.
+fn uuid_class(py: Python<'_>) -> PyResult<&Bound<'_, PyType>> {
+    static UUID: PyOnceLock<Py<PyType>> = PyOnceLock::new();
+    UUID.import(py, "uuid", "UUID")
+}
+
+/// The `pathlib.PurePath` class.
+fn pure_path_class(py: Python<'_>) -> PyResult<&Bound<'_, PyType>> {
+    static PURE_PATH: PyOnceLock<Py<PyType>> = PyOnceLock::new();
+    PURE_PATH.import(py, "pathlib", "PurePath")
+}
+
+/// Normalize a `str` or `os.PathLike` key to `separator`-joined components.
+///
+/// Both slashes and backslashes are accepted as input separators; empty and `.`
+/// components are dropped, so `"./dir//file"` and `Path("dir/file")` map to
+/// the same key. A leading separator is kept, so absolute and relative paths
+/// stay distinct keys.
+pub fn normalize_path(key: &Bound<'_, PyAny>, separator: u8) -> PyResult<String> {
+    static FSPATH: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+    let path = FSPATH.import(key.py(), "os", "fspath")?.call1((key,))?;
+    let path = path
+        .cast::<PyString>()
+        .map_err(|_| PyTypeError::new_err("path keys must be str or os.PathLike, not bytes"))?;
+    let separator = char::from(separator);
+    let path = path.to_str()?;
+    let absolute = path.starts_with(['/', '\\', separator]);
+    let components: Vec<&str> = path
+        .split(['/', '\\', separator])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    if components.iter().any(|part| part.contains('\0')) {
+        return Err(PyValueError::new_err("path keys must not contain NUL"));
+    }
+    let mut buf = [0; 4];
+    let separator = separator.encode_utf8(&mut buf);
+    let joined = components.join(separator);
+    Ok(match absolute {
+        true => format!("{separator}{joined}"),
+        false => joined,
+    })
+}
+
+/// The `ipaddress.ip_network` function.
+fn ip_network(py: Python<'_>) -> PyResult<&Bound<'_, PyAny>> {
+    static IP_NETWORK: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+    IP_NETWORK.import(py, "ipaddress", "ip_network")
+}
+
+// Bytes used for each prefix bit of an "ip" key. Both sort after the
+// terminator, so a network sorts before the subnets it contains.
+const BIT_ZERO: u8 = 0x01;
+const BIT_ONE: u8 = 0x02;
+
+/// IP version and prefix bits of an address, network, or string form.
+fn network_bits(key: &Bound<'_, PyAny>) -> PyResult<(u8, Vec<u8>)> {
+    let network = ip_network(key.py())?.call1((key,))?;
+    let version: u8 = network.getattr("version")?.extract()?;
+    let prefix_len: usize = network.getattr("prefixlen")?.extract()?;
+    let packed = network.getattr("network_address")?.getattr("packed")?;
+    let packed = packed.cast::<PyBytes>()?.as_bytes();
+    let bits = (0..prefix_len)
+        .map(|i| {
+            if packed[i / 8] & (0x80 >> (i % 8)) != 0 {
+                BIT_ONE
+            } else {
+                BIT_ZERO
+            }
+        })
+        .collect();
+    Ok((version, bits))
+}
+
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+/// The Unix epoch as a naive or UTC-aware datetime.
+fn unix_epoch<'py>(py: Python<'py>, aware: bool) -> PyResult<Bound<'py, PyDateTime>> {
+    let utc = PyTzInfo::utc(py)?;
+    let tzinfo = if aware { Some(&*utc) } else { None };
+    PyDateTime::new(py, 1970, 1, 1, 0, 0, 0, 0, tzinfo)
+}
+
+/// Microseconds since the Unix epoch for a datetime or a number of seconds.
+///
+/// Naive datetimes are taken to be UTC; aware datetimes are converted to UTC.
+fn epoch_micros(key: &Bound<'_, PyAny>) -> PyResult<i64> {
+    let py = key.py();
+    if let Ok(dt) = key.cast::<PyDateTime>() {
+        let aware = !dt.getattr("tzinfo")?.is_none();
+        let delta = dt.sub(unix_epoch(py, aware)?)?;
+        let delta = delta.cast::<PyDelta>()?;
+        return Ok(delta.get_days() as i64 * MICROS_PER_DAY
+            + delta.get_seconds() as i64 * 1_000_000
+            + delta.get_microseconds() as i64);
+    }
+    if key.is_instance_of::<PyBool>() {
+        return Err(PyTypeError::new_err(
+            "datetime keys must be datetime or epoch seconds",
+        ));
+    }
+    if let Ok(seconds) = key.cast::<PyInt>() {
+        let seconds: i64 = seconds.extract()?;
+        return seconds
+            .checked_mul(1_000_000)
+            .ok_or_else(|| PyOverflowError::new_err("timestamp out of range"));
+    }
+    if let Ok(seconds) = key.cast::<PyFloat>() {
+        return Ok((seconds.value() * 1e6).round() as i64);
+    }
+    Err(PyTypeError::new_err(
+        "datetime keys must be datetime or epoch seconds",
+    ))
+}
+
+// Type tags for tuple fields. Tags sort in the order the field types compare
+// against each other, and none of them is zero so that the terminator always
+// sorts first: ("a",) < ("a", None) < ("a", 1).
+const TAG_END: u8 = 0x00;
+const TAG_NONE: u8 = 0x01;
+const TAG_BYTES: u8 = 0x02;
+const TAG_STR: u8 = 0x03;
+const TAG_TUPLE: u8 = 0x05;
+const TAG_NUMBER: u8 = 0x15;
+const TAG_FALSE: u8 = 0x26;
+const TAG_TRUE: u8 = 0x27;
+
+// Ranges of a number field, which ints and floats share so that they
+// compare by value: (1,) and (1.0,) are the same key and (1.5,) < (2,).
+const NUM_BELOW: u8 = 0x01;
+const NUM_INT_RANGE: u8 = 0x02;
+const NUM_ABOVE: u8 = 0x03;
+const NUM_NAN: u8 = 0x04;
+
+/// Lower and upper bound of the i64 range, as floats.
+const I64_START: f64 = -9_223_372_036_854_775_808.0;
+const I64_END: f64 = 9_223_372_036_854_775_808.0;
+
+/// Float bits that sort in numeric order as unsigned integers.
+fn ordered_float_bits(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits ^ (1 << 63)
+    }
+}
+
+fn float_from_ordered_bits(bits: u64) -> f64 {
+    f64::from_bits(if bits >> 63 == 1 {
+        bits ^ (1 << 63)
+    } else {
+        !bits
+    })
+}
+
+/// Append a number field. Numbers within the i64 range are stored as their
+/// floor and the fractional part, zero for ints and integral floats, so
+/// integral floats are stored (and read back) as ints.
+fn encode_number(integer: Option<i64>, float: f64, out: &mut Vec<u8>) {
+    out.push(TAG_NUMBER);
+    let (floor, fraction) = match integer {
+        Some(integer) => (integer, 0.0),
+        None if float.is_nan() => return out.push(NUM_NAN),
+        None if float < I64_START => {
+            out.push(NUM_BELOW);
+            return out.extend_from_slice(&ordered_float_bits(float).to_be_bytes());
+        }
+        None if float >= I64_END => {
+            out.push(NUM_ABOVE);
+            return out.extend_from_slice(&ordered_float_bits(float).to_be_bytes());
+        }
+        // Both are exact: the floor is in range, and the fraction has no
+        // more significant bits than the float
+        None => (float.floor() as i64, float - float.floor()),
+    };
+    out.push(NUM_INT_RANGE);
+    out.extend_from_slice(&((floor as u64) ^ (1 << 63)).to_be_bytes());
+    // The fraction is 0 or positive, whose bits sort in numeric order
+    out.extend_from_slice(&fraction.to_bits().to_be_bytes());
+}
+
+/// Read a number field written by `encode_number`.
+fn decode_number<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    pos: &mut usize,
+) -> PyResult<Bound<'py, PyAny>> {
+    let range = *data.get(*pos).ok_or_else(corrupt)?;
+    *pos += 1;
+    Ok(match range {
+        NUM_NAN => PyFloat::new(py, f64::NAN).into_any(),
+        NUM_BELOW | NUM_ABOVE => {
+            let bits = u64::from_be_bytes(read_fixed(data, pos)?);
+            PyFloat::new(py, float_from_ordered_bits(bits)).into_any()
+        }
+        NUM_INT_RANGE => {
+            let floor = (u64::from_be_bytes(read_fixed(data, pos)?) ^ (1 << 63)) as i64;
+            let fraction = f64::from_bits(u64::from_be_bytes(read_fixed(data, pos)?));
+            if fraction == 0.0 {
+                floor.into_pyobject(py)?.into_any()
+            } else {
+                PyFloat::new(py, floor as f64 + fraction).into_any()
+            }
+        }
+        _ => return Err(corrupt()),
+    })
+}
+
+/// Byte following a zero inside a string field to mark it as data rather
+/// than the end of the field.
+const ESCAPE: u8 = 0xff;
+
+/// Tuple keys nested deeper than this are rejected rather than risking the
+/// stack.
+const MAX_DEPTH: usize = 512;
+
+/// Append each tuple element with its type tag. `depth` counts the tuples
+/// this one is nested in.
+fn encode_fields(tuple: &Bound<'_, PyTuple>, depth: usize, out: &mut Vec<u8>) -> PyResult<()> {
+    if depth > MAX_DEPTH {
+        return Err(PyValueError::new_err(format!(
+            "tuple keys can nest at most {MAX_DEPTH} levels deep"
+        )));
+    }
+    for item in tuple.iter() {
+        if item.is_none() {
+            out.push(TAG_NONE);
+        } else if let Ok(flag) = item.cast::<PyBool>() {
+            out.push(if flag.is_true() { TAG_TRUE } else { TAG_FALSE });
+        } else if let Ok(value) = item.cast::<PyInt>() {
+            let value: i64 = value.extract()?;
+            encode_number(Some(value), 0.0, out);
+        } else if let Ok(value) = item.cast::<PyFloat>() {
+            let value = value.value();
+            let integral = (value.fract() == 0.0 && (I64_START..I64_END).contains(&value))
+                .then_some(value as i64);
+            encode_number(integral, value, out);
+        } else if let Ok(value) = item.cast::<PyString>() {
+            out.push(TAG_STR);
+            escape_into(value.to_str()?.as_bytes(), out);
+        } else if let Ok(value) = item.cast::<PyBytes>() {
+            out.push(TAG_BYTES);
+            escape_into(value.as_bytes(), out);
+        } else if let Ok(nested) = item.cast::<PyTuple>() {
+            out.push(TAG_TUPLE);
+            encode_fields(nested, depth + 1, out)?;
+            out.push(TAG_END);
+        } else {
+            return Err(PyTypeError::new_err(format!(
+                "Unsupported tuple key field type: {}",
+                item.get_type().name()?
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Append a byte string, escaping zero bytes and terminating the field.
+fn escape_into(data: &[u8], out: &mut Vec<u8>) {
+    for &byte in data {
+        out.push(byte);
+        if byte == 0 {
+            out.push(ESCAPE);
+        }
+    }
+    out.push(TAG_END);
+}
+
+/// Read tuple elements up to the next terminator.
+fn decode_fields<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    pos: &mut usize,
+    depth: usize,
+) -> PyResult<Bound<'py, PyTuple>> {
+    if depth > MAX_DEPTH {
+        return Err(corrupt());
+    }
+    let mut items: Vec<Bound<'py, PyAny>> = Vec::new();
+    loop {
+        let tag = *data.get(*pos).ok_or_else(corrupt)?;
+        *pos += 1;
+        let item = match tag {
+            TAG_END => return PyTuple::new(py, items),
+            TAG_NONE => py.None().into_bound(py),
+            TAG_FALSE => PyBool::new(py, false).to_owned().into_any(),
+            TAG_TRUE => PyBool::new(py, true).to_owned().into_any(),
+            TAG_NUMBER => decode_number(py, data, pos)?,
+            TAG_STR => {
+                let raw = unescape(data, pos)?;
+                let text = String::from_utf8(raw).map_err(|_| corrupt())?;
+                PyString::new(py, &text).into_any()
+            }
+            TAG_BYTES => PyBytes::new(py, &unescape(data, pos)?).into_any(),
+            TAG_TUPLE => decode_fields(py, data, pos, depth + 1)?.into_any(),
+            _ => return Err(corrupt()),
+        };
+        items.push(item);
+    }
+}
+
+/// Read an escaped byte string up to its terminator.
+fn unescape(data: &[u8], pos: &mut usize) -> PyResult<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let byte = *data.get(*pos).ok_or_else(corrupt)?;
+        *pos += 1;
+        if byte == 0 {
+            if data.get(*pos) == Some(&ESCAPE) {
+                *pos += 1;
+            } else {
+                return Ok(out);
+            }
+        }
+        out.push(byte);
+    }
+}
+
+/// Read an 8-byte fixed-width field.
+fn read_fixed(data: &[u8], pos: &mut usize) -> PyResult<[u8; 8]> {
+    let bytes = data.get(*pos..*pos + 8).ok_or_else(corrupt)?;
+    *pos += 8;
+    fixed_width(bytes)
+}
+
+fn corrupt() -> PyErr {
+    PyValueError::new_err("Stored tuple key is malformed")
+}
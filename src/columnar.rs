@@ -0,0 +1,139 @@
+use crate::arrays::RawKeys;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use parquet::schema::types::Type;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyFloat, PyString};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A value of a Parquet column, read without the GIL and converted
+/// afterwards.
+pub enum Cell {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl Cell {
+    fn from_field(column: &str, field: Field) -> PyResult<Self> {
+        Ok(match field {
+            Field::Null => Cell::Null,
+            Field::Bool(b) => Cell::Bool(b),
+            Field::Byte(i) => Cell::Int(i.into()),
+            Field::Short(i) => Cell::Int(i.into()),
+            Field::Int(i) => Cell::Int(i.into()),
+            Field::Long(i) => Cell::Int(i),
+            Field::UByte(i) => Cell::Int(i.into()),
+            Field::UShort(i) => Cell::Int(i.into()),
+            Field::UInt(i) => Cell::Int(i.into()),
+            Field::ULong(i) => Cell::UInt(i),
+            Field::Float16(f) => Cell::Float(f.to_f64()),
+            Field::Float(f) => Cell::Float(f.into()),
+            Field::Double(f) => Cell::Float(f),
+            Field::Str(s) => Cell::Str(s),
+            Field::Bytes(b) => Cell::Bytes(b.data().to_vec()),
+            _ => {
+                return Err(PyTypeError::new_err(format!(
+                    "column {column:?} is not of a supported type (booleans, numbers, \
+                     strings or binary)"
+                )))
+            }
+        })
+    }
+
+    pub fn into_py(self, py: Python) -> PyResult<Py<PyAny>> {
+        Ok(match self {
+            Cell::Null => py.None(),
+            Cell::Bool(b) => b.into_pyobject(py)?.to_owned().into_any().unbind(),
+            Cell::Int(i) => i.into_pyobject(py)?.into_any().unbind(),
+            Cell::UInt(i) => i.into_pyobject(py)?.into_any().unbind(),
+            Cell::Float(f) => PyFloat::new(py, f).into_any().unbind(),
+            Cell::Str(s) => PyString::new(py, &s).into_any().unbind(),
+            Cell::Bytes(b) => PyBytes::new(py, &b).into_any().unbind(),
+        })
+    }
+}
+
+/// The keys of a column of strings or of binary values, as stored bytes,
+/// or else the cells as they were.
+pub fn raw_keys(cells: Vec<Cell>) -> Result<RawKeys, Vec<Cell>> {
+    let text = match cells.first() {
+        Some(Cell::Str(_)) => true,
+        Some(Cell::Bytes(_)) => false,
+        _ => return Err(cells),
+    };
+    let uniform = cells.iter().all(|cell| match cell {
+        Cell::Str(_) => text,
+        Cell::Bytes(_) => !text,
+        _ => false,
+    });
+    if !uniform {
+        return Err(cells);
+    }
+    let keys = cells
+        .into_iter()
+        .map(|cell| match cell {
+            Cell::Str(s) => s.into_bytes().into_boxed_slice(),
+            Cell::Bytes(b) => b.into_boxed_slice(),
+            _ => unreachable!("checked above"),
+        })
+        .collect();
+    Ok(RawKeys { keys, text })
+}
+
+/// Read two columns of a Parquet file, decoding only those columns.
+pub fn read_columns(
+    path: &Path,
+    key_column: &str,
+    value_column: &str,
+) -> PyResult<(Vec<Cell>, Vec<Cell>)> {
+    let invalid = |err: parquet::errors::ParquetError| PyValueError::new_err(err.to_string());
+    let reader = SerializedFileReader::new(std::fs::File::open(path)?).map_err(invalid)?;
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let fields = schema.root_schema().get_fields();
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|field| field.name() == name)
+            .map(Arc::clone)
+            .ok_or_else(|| PyValueError::new_err(format!("no column named {name:?}")))
+    };
+    // Asking for a column twice would give the rows two of that name
+    let projection = match key_column == value_column {
+        true => vec![field(key_column)?],
+        false => vec![field(key_column)?, field(value_column)?],
+    };
+    let projection = Type::group_type_builder(schema.root_schema().name())
+        .with_fields(projection)
+        .build()
+        .map_err(invalid)?;
+    let (mut keys, mut values) = (Vec::new(), Vec::new());
+    for row in reader.get_row_iter(Some(projection)).map_err(invalid)? {
+        let mut key = None;
+        let mut value = None;
+        for (name, field) in row.map_err(invalid)?.into_columns() {
+            if name == key_column && name == value_column {
+                key = Some(Cell::from_field(&name, field.clone())?);
+                value = Some(Cell::from_field(&name, field)?);
+            } else if name == key_column {
+                key = Some(Cell::from_field(&name, field)?);
+            } else {
+                value = Some(Cell::from_field(&name, field)?);
+            }
+        }
+        match key {
+            None | Some(Cell::Null) => {
+                return Err(PyValueError::new_err("key column contains nulls"))
+            }
+            Some(key) => keys.push(key),
+        }
+        values.push(value.unwrap_or(Cell::Null));
+    }
+    Ok((keys, values))
+}
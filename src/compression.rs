@@ -0,0 +1,180 @@
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyString, PyTuple};
+use std::io::{Read, Write};
+
+/// A value stored compressed, as it sits in the tree.
+#[pyclass(frozen, name = "CompressedValue", module = "blart")]
+pub struct Compressed {
+    data: Box<[u8]>,
+    /// Whether the value was a str, stored as compressed UTF-8
+    text: bool,
+    raw_len: usize,
+}
+
+impl Compressed {
+    /// The compressed data and whether it is text, which equal values share.
+    pub fn contents<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        Ok(PyTuple::new(
+            py,
+            [
+                PyBytes::new(py, &self.data).into_any(),
+                self.text.into_pyobject(py)?.to_owned().into_any(),
+            ],
+        )?
+        .into_any())
+    }
+}
+
+/// Algorithms values can be compressed with.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    Zstd,
+    Zlib,
+}
+
+impl Codec {
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "zstd" => Ok(Self::Zstd),
+            "zlib" => Ok(Self::Zlib),
+            _ => Err(PyValueError::new_err(format!(
+                "compress_values must be 'zstd', 'zlib' or None, got '{name}'"
+            ))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Zlib => "zlib",
+        }
+    }
+
+    /// Compress at the default level of each format, as Python's modules do.
+    fn compress(self, raw: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Zstd => zstd::bulk::compress(raw, zstd::DEFAULT_COMPRESSION_LEVEL),
+            Self::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(raw)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    fn decompress(self, data: &[u8], raw_len: usize) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Zstd => zstd::bulk::decompress(data, raw_len),
+            Self::Zlib => {
+                let mut raw = Vec::with_capacity(raw_len);
+                ZlibDecoder::new(data).read_to_end(&mut raw)?;
+                Ok(raw)
+            }
+        }
+    }
+}
+
+/// Compression of large str and bytes values, for `compress_values` trees.
+///
+/// Values are compressed and decompressed in Rust with the GIL released.
+#[derive(Clone, Copy)]
+pub struct Compression {
+    codec: Codec,
+    /// Values shorter than this many bytes are stored as they are
+    threshold: usize,
+}
+
+impl Compression {
+    pub fn new(codec: &str, threshold: usize) -> PyResult<Self> {
+        Ok(Self {
+            codec: Codec::from_name(codec)?,
+            threshold,
+        })
+    }
+
+    pub fn codec(&self) -> &'static str {
+        self.codec.name()
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Compress a str or bytes value of at least the threshold size. Other
+    /// values, and those that don't get smaller, are stored as they are.
+    pub fn compress(&self, py: Python, value: Py<PyAny>) -> PyResult<Py<PyAny>> {
+        let bound = value.bind(py);
+        let (raw, text) = if let Ok(text) = bound.cast_exact::<PyString>() {
+            // Strings that aren't valid UTF-8 (lone surrogates) stay as they are
+            let Ok(text) = text.to_str() else {
+                return Ok(value);
+            };
+            (text.as_bytes(), true)
+        } else if let Ok(bytes) = bound.cast_exact::<PyBytes>() {
+            (bytes.as_bytes(), false)
+        } else {
+            return Ok(value);
+        };
+        let raw_len = raw.len();
+        if raw_len < self.threshold {
+            return Ok(value);
+        }
+        // `value` keeps the immutable str or bytes alive while detached
+        let data = py.detach(|| self.codec.compress(raw))?;
+        if data.len() >= raw_len {
+            return Ok(value);
+        }
+        let compressed = Compressed {
+            data: data.into_boxed_slice(),
+            text,
+            raw_len,
+        };
+        Ok(Bound::new(py, compressed)?.into_any().unbind())
+    }
+
+    /// The original value of a stored one.
+    pub fn decompress(&self, py: Python, stored: &Py<PyAny>) -> PyResult<Py<PyAny>> {
+        let Ok(compressed) = stored.bind(py).cast::<Compressed>() else {
+            return Ok(stored.clone_ref(py));
+        };
+        let compressed = compressed.get();
+        let raw = py.detach(|| self.codec.decompress(&compressed.data, compressed.raw_len))?;
+        match compressed.text {
+            true => Ok(PyString::from_bytes(py, &raw)?.into_any().unbind()),
+            false => Ok(PyBytes::new(py, &raw).into_any().unbind()),
+        }
+    }
+
+    /// Counts and sizes of the compressed values among `values`.
+    pub fn stats<'py, 'a>(
+        &self,
+        py: Python<'py>,
+        values: impl Iterator<Item = &'a Py<PyAny>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let (mut count, mut raw, mut stored) = (0usize, 0usize, 0usize);
+        for value in values {
+            if let Ok(compressed) = value.bind(py).cast::<Compressed>() {
+                let compressed = compressed.get();
+                count += 1;
+                raw += compressed.raw_len;
+                stored += compressed.data.len();
+            }
+        }
+        let stats = PyDict::new(py);
+        stats.set_item("codec", self.codec())?;
+        stats.set_item("threshold", self.threshold)?;
+        stats.set_item("compressed_values", count)?;
+        stats.set_item("raw_bytes", raw)?;
+        stats.set_item("compressed_bytes", stored)?;
+        let ratio = if stored == 0 {
+            1.0
+        } else {
+            raw as f64 / stored as f64
+        };
+        stats.set_item("ratio", ratio)?;
+        Ok(stats)
+    }
+}
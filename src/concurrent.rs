@@ -0,0 +1,289 @@
+use crate::codec::KeyCodec;
+use crate::iterators::{PyPrefixIter, PyTreeMapIter};
+use crate::treemap::{generic_alias, key_error};
+use blart::TreeMap;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple, PyType};
+use pyo3::{PyTraverseError, PyVisit};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Stored values are reference counted on the Rust side, so lookups can hand
+/// them out while detached from the interpreter.
+type Shared = Arc<Py<PyAny>>;
+
+type Tree = TreeMap<Box<[u8]>, Shared>;
+
+/// A thread-safe TreeMap for sharing one index between Python threads.
+///
+/// The tree lives behind a read-write lock. Lookups and prefix scans take the
+/// shared lock with the GIL released, so several threads can query at once
+/// and other Python threads keep running meanwhile; writes take the exclusive
+/// lock. Keys are encoded and decoded while attached to the interpreter, so
+/// only the tree walk itself runs in parallel.
+///
+/// Iteration and prefix scans return a snapshot taken under the read lock,
+/// so concurrent writes never invalidate them.
+///
+/// Examples:
+///     >>> index = ConcurrentTreeMap({"apple": 1, "apricot": 2})
+///     >>> index["banana"] = 3  # from any thread
+///     >>> list(index.prefix_iter("ap"))
+///     [('apple', 1), ('apricot', 2)]
+#[pyclass(name = "PyConcurrentTreeMap", frozen, weakref)]
+pub struct PyConcurrentTreeMap {
+    inner: RwLock<Tree>,
+    codec: KeyCodec,
+}
+
+impl PyConcurrentTreeMap {
+    /// Run `f` under the shared lock with the GIL released.
+    fn read<T, F>(&self, py: Python, f: F) -> T
+    where
+        T: Send,
+        F: FnOnce(&Tree) -> T + Send,
+    {
+        py.detach(|| f(&self.inner.read().unwrap_or_else(PoisonError::into_inner)))
+    }
+
+    /// Run `f` under the exclusive lock with the GIL released.
+    fn write<T, F>(&self, py: Python, f: F) -> T
+    where
+        T: Send,
+        F: FnOnce(&mut Tree) -> T + Send,
+    {
+        py.detach(|| f(&mut self.inner.write().unwrap_or_else(PoisonError::into_inner)))
+    }
+
+    /// Store a value, returning the one it replaced so it is released with
+    /// the GIL held.
+    fn store(&self, py: Python, key: Box<[u8]>, value: Py<PyAny>) -> Option<Shared> {
+        let value = Arc::new(value);
+        self.write(py, move |tree| match tree.get_mut(&key) {
+            Some(slot) => Some(std::mem::replace(slot, value)),
+            None => {
+                tree.force_insert(key, value);
+                None
+            }
+        })
+    }
+
+    /// Decode (key, value) pairs collected under the lock.
+    fn decode_items(
+        &self,
+        py: Python,
+        entries: Vec<(Box<[u8]>, Shared)>,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        entries
+            .into_iter()
+            .map(|(k, v)| Ok((self.codec.decode(py, &k)?, v.clone_ref(py))))
+            .collect()
+    }
+}
+
+#[pymethods]
+impl PyConcurrentTreeMap {
+    /// Create a new ConcurrentTreeMap.
+    ///
+    /// Args:
+    ///     data: Optional dict or list of (key, value) tuples to initialize from
+    ///     key_type: How keys are encoded, as for TreeMap
+    ///     separator: Path separator for key_type="path"
+    ///     errors: Decode policy for str keys that are not valid UTF-8
+    ///
+    /// Examples:
+    ///     >>> index = ConcurrentTreeMap({"a": 1}, key_type="str")
+    #[new]
+    #[pyo3(signature = (data=None, *, key_type=None, separator=None, errors=None))]
+    fn new(
+        py: Python,
+        data: Option<&Bound<'_, PyAny>>,
+        key_type: Option<&Bound<'_, PyAny>>,
+        separator: Option<&str>,
+        errors: Option<&str>,
+    ) -> PyResult<Self> {
+        let codec = KeyCodec::from_key_type(key_type)?
+            .with_separator(separator)?
+            .with_errors(errors)?;
+        let map = Self {
+            inner: RwLock::new(TreeMap::new()),
+            codec,
+        };
+        if let Some(data) = data {
+            if let Ok(dict) = data.cast_exact::<PyDict>() {
+                for (key, value) in dict.iter() {
+                    map.insert(py, &key, value.unbind())?;
+                }
+            } else if let Ok(list) = data.cast_exact::<PyList>() {
+                for item in list.iter() {
+                    let tuple = item.cast_exact::<PyTuple>()?;
+                    if tuple.len() != 2 {
+                        return Err(pyo3::exceptions::PyValueError::new_err(
+                            "Items must be (key, value) tuples",
+                        ));
+                    }
+                    let value = tuple.get_item(1)?.unbind();
+                    map.insert(py, &tuple.get_item(0)?, value)?;
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Parameterize the class for type annotations, as in
+    /// `ConcurrentTreeMap[str, int]`.
+    ///
+    /// Returns:
+    ///     A types.GenericAlias; the parameters are not checked at runtime
+    #[classmethod]
+    fn __class_getitem__<'py>(
+        cls: &Bound<'py, PyType>,
+        params: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        generic_alias(cls, params)
+    }
+
+    /// Insert a key-value pair, replacing any existing value.
+    ///
+    /// Args:
+    ///     key: Key to insert
+    ///     value: Python object to store
+    fn insert(&self, py: Python, key: &Bound<'_, PyAny>, value: Py<PyAny>) -> PyResult<()> {
+        let key_bytes = self.codec.encode(key)?;
+        drop(self.store(py, key_bytes, value));
+        Ok(())
+    }
+
+    /// Get a value by key, with optional default.
+    ///
+    /// The lookup itself runs with the GIL released.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///     default: Value to return if key not found (defaults to None)
+    #[pyo3(signature = (key, default=None))]
+    fn get(
+        &self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let key_bytes = self.codec.encode(key)?;
+        match self.read(py, |tree| tree.get(&key_bytes).cloned()) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    /// Remove a key and return its value.
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist
+    fn remove(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        let key_bytes = self.codec.encode(key)?;
+        match self.write(py, |tree| tree.remove(&key_bytes)) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(key_error(key)),
+        }
+    }
+
+    /// Remove all entries.
+    fn clear(&self, py: Python) {
+        let old = self.write(py, std::mem::take);
+        drop(old);
+    }
+
+    /// Return an iterator over a snapshot of the (key, value) pairs whose
+    /// key starts with a prefix.
+    ///
+    /// The tree is scanned with the GIL released.
+    fn prefix_iter(&self, py: Python, prefix: &Bound<'_, PyAny>) -> PyResult<PyPrefixIter> {
+        let prefix_bytes = self.codec.encode_prefix(prefix)?;
+        let entries = self.read(py, |tree| {
+            tree.prefix(&prefix_bytes)
+                .filter(|(k, _)| k.starts_with(&prefix_bytes))
+                .map(|(k, v)| (k.clone(), Arc::clone(v)))
+                .collect::<Vec<_>>()
+        });
+        Ok(PyPrefixIter::snapshot(self.decode_items(py, entries)?))
+    }
+
+    /// Return a snapshot list of the (key, value) pairs, in key order.
+    fn items(&self, py: Python) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let entries = self.read(py, |tree| {
+            tree.iter()
+                .map(|(k, v)| (k.clone(), Arc::clone(v)))
+                .collect::<Vec<_>>()
+        });
+        self.decode_items(py, entries)
+    }
+
+    /// Return a snapshot list of the keys, in key order.
+    fn keys(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        let keys = self.read(py, |tree| tree.keys().cloned().collect::<Vec<_>>());
+        keys.iter().map(|k| self.codec.decode(py, k)).collect()
+    }
+
+    /// Return a snapshot list of the values, in key order.
+    fn values(&self, py: Python) -> Vec<Py<PyAny>> {
+        let values = self.read(py, |tree| tree.values().cloned().collect::<Vec<_>>());
+        values.iter().map(|v| v.clone_ref(py)).collect()
+    }
+
+    /// How keys are stored: a key_type name such as "str" or "int", or the codec object.
+    #[getter]
+    fn key_type(&self, py: Python) -> Py<PyAny> {
+        self.codec.key_type(py)
+    }
+
+    fn __getitem__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        let key_bytes = self.codec.encode(key)?;
+        match self.read(py, |tree| tree.get(&key_bytes).cloned()) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(key_error(key)),
+        }
+    }
+
+    fn __setitem__(&self, py: Python, key: &Bound<'_, PyAny>, value: Py<PyAny>) -> PyResult<()> {
+        self.insert(py, key, value)
+    }
+
+    fn __delitem__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.remove(py, key)?;
+        Ok(())
+    }
+
+    fn __contains__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let key_bytes = self.codec.encode(key)?;
+        Ok(self.read(py, |tree| tree.contains_key(&key_bytes)))
+    }
+
+    fn __len__(&self, py: Python) -> usize {
+        self.read(py, |tree| tree.len())
+    }
+
+    /// Iterate over a snapshot of the keys, in key order.
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapIter> {
+        Ok(PyTreeMapIter::snapshot(self.keys(py)?))
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        format!("ConcurrentTreeMap(len={})", self.__len__(py))
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        // A writer holding the lock is detached; skipping its values only
+        // makes the collector more conservative
+        if let Ok(tree) = self.inner.try_read() {
+            for value in tree.values() {
+                visit.call(&**value)?;
+            }
+        }
+        self.codec.traverse(&visit)
+    }
+
+    fn __clear__(&self) {
+        if let Ok(mut tree) = self.inner.try_write() {
+            tree.clear();
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use crate::codec::KeyCodec;
+use crate::iterators::{PyPrefixIter, PyTreeMapIter};
+use crate::treemap::{generic_alias, key_error};
+use blart::TreeMap;
+use pyo3::exceptions::{PyOverflowError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyType};
+use pyo3::{PyTraverseError, PyVisit};
+
+/// Counts per key, stored as 64-bit integers.
+type Counts = TreeMap<Box<[u8]>, i64>;
+
+/// A `collections.Counter` over sorted keys.
+///
+/// Counts are native 64-bit integers rather than Python objects, so a large
+/// counter takes a fraction of the memory of a TreeMap of ints and
+/// `most_common()` and `total()` run without touching the interpreter.
+/// Missing keys count as 0, and every query can be restricted to a prefix.
+///
+/// Examples:
+///     >>> words = TreeCounter(["apple", "apricot", "apple", "banana"])
+///     >>> words["apple"]
+///     2
+///     >>> words.most_common(1)
+///     [('apple', 2)]
+///     >>> words.total(prefix="ap")
+///     3
+#[pyclass(name = "PyTreeCounter", module = "blart._blart", weakref)]
+pub struct PyTreeCounter {
+    inner: Counts,
+    codec: KeyCodec,
+}
+
+fn overflow() -> PyErr {
+    PyOverflowError::new_err("count does not fit in 64 bits")
+}
+
+impl PyTreeCounter {
+    /// Add to the count of an encoded key, returning the new count.
+    fn add(&mut self, key: Box<[u8]>, delta: i64) -> PyResult<i64> {
+        if let Some(count) = self.inner.get_mut(&key) {
+            *count = count.checked_add(delta).ok_or_else(overflow)?;
+            return Ok(*count);
+        }
+        self.inner.force_insert(key, delta);
+        Ok(delta)
+    }
+
+    /// Encoded (key, count) pairs from a TreeCounter, a mapping of counts or
+    /// an iterable of keys counted once each.
+    fn counts_of(&self, other: &Bound<'_, PyAny>) -> PyResult<Vec<(Box<[u8]>, i64)>> {
+        if let Ok(counter) = other.cast::<PyTreeCounter>() {
+            let (py, counter) = (other.py(), counter.borrow());
+            if !self
+                .codec
+                .key_type(py)
+                .bind(py)
+                .eq(counter.codec.key_type(py))?
+            {
+                return Err(PyTypeError::new_err(
+                    "cannot combine TreeCounters with different key types",
+                ));
+            }
+            return Ok(counter.inner.iter().map(|(k, c)| (k.clone(), *c)).collect());
+        }
+        let mut counts = Vec::new();
+        if other.hasattr("keys")? {
+            for key in other.call_method0("keys")?.try_iter()? {
+                let key = key?;
+                let count = other.get_item(&key)?.extract::<i64>()?;
+                counts.push((self.codec.encode_terminated(&key)?, count));
+            }
+        } else {
+            for key in other.try_iter()? {
+                counts.push((self.codec.encode_terminated(&key?)?, 1));
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Add (or subtract) counts gathered by `counts_of`.
+    fn combine(&mut self, counts: Vec<(Box<[u8]>, i64)>, negate: bool) -> PyResult<()> {
+        for (key, count) in counts {
+            let delta = match negate {
+                true => count.checked_neg().ok_or_else(overflow)?,
+                false => count,
+            };
+            self.add(key, delta)?;
+        }
+        Ok(())
+    }
+
+    /// Drop keys whose count is zero or negative, as Counter's `+` and `-` do.
+    fn keep_positive(&mut self) {
+        let dropped = self
+            .inner
+            .iter()
+            .filter(|(_, count)| **count <= 0)
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+        for key in dropped {
+            self.inner.remove(&key);
+        }
+    }
+
+    /// Entries whose key starts with `prefix`, or all entries.
+    fn entries<'a>(
+        &'a self,
+        prefix: Option<&'a [u8]>,
+    ) -> Box<dyn Iterator<Item = (&'a Box<[u8]>, &'a i64)> + 'a> {
+        match prefix {
+            Some(prefix) => Box::new(
+                self.inner
+                    .prefix(prefix)
+                    .filter(move |(k, _)| k.starts_with(prefix)),
+            ),
+            None => Box::new(self.inner.iter()),
+        }
+    }
+
+    fn encode_prefix(&self, prefix: Option<&Bound<'_, PyAny>>) -> PyResult<Option<Box<[u8]>>> {
+        prefix
+            .map(|p| self.codec.encode_prefix_terminated(p))
+            .transpose()
+    }
+
+    fn copy(&self, py: Python) -> Self {
+        let mut inner = Counts::new();
+        for (key, count) in self.inner.iter() {
+            inner.force_insert(key.clone(), *count);
+        }
+        Self {
+            inner,
+            codec: self.codec.clone_ref(py),
+        }
+    }
+}
+
+#[pymethods]
+impl PyTreeCounter {
+    /// Create a new TreeCounter.
+    ///
+    /// Args:
+    ///     data: Optional iterable of keys to count, or mapping of counts
+    ///     key_type: How keys are encoded, as for TreeMap
+    ///     separator: Path separator for key_type="path"
+    ///     errors: Decode policy for str keys that are not valid UTF-8
+    ///
+    /// Examples:
+    ///     >>> TreeCounter({"a": 2, "b": 1})["a"]
+    ///     2
+    #[new]
+    #[pyo3(signature = (data=None, *, key_type=None, separator=None, errors=None))]
+    fn new(
+        data: Option<&Bound<'_, PyAny>>,
+        key_type: Option<&Bound<'_, PyAny>>,
+        separator: Option<&str>,
+        errors: Option<&str>,
+    ) -> PyResult<Self> {
+        let codec = KeyCodec::from_key_type(key_type)?
+            .with_separator(separator)?
+            .with_errors(errors)?;
+        let mut counter = Self {
+            inner: Counts::new(),
+            codec,
+        };
+        if let Some(data) = data {
+            let counts = counter.counts_of(data)?;
+            counter.combine(counts, false)?;
+        }
+        Ok(counter)
+    }
+
+    /// Parameterize the class for type annotations, as in
+    /// `TreeCounter[str]`.
+    ///
+    /// Returns:
+    ///     A types.GenericAlias; the parameters are not checked at runtime
+    #[classmethod]
+    fn __class_getitem__<'py>(
+        cls: &Bound<'py, PyType>,
+        params: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        generic_alias(cls, params)
+    }
+
+    /// Add counts from an iterable of keys, a mapping or another TreeCounter.
+    ///
+    /// Args:
+    ///     data: Iterable of keys (each counted once) or mapping of counts
+    ///     **kwargs: str keys and their counts
+    #[pyo3(signature = (data=None, **kwargs))]
+    fn update(
+        slf: &Bound<'_, Self>,
+        data: Option<&Bound<'_, PyAny>>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        // Gather counts before borrowing, since data may be this counter
+        let mut counts = Vec::new();
+        for data in data.into_iter().chain(kwargs.map(|k| k.as_any())) {
+            counts.extend(slf.borrow().counts_of(data)?);
+        }
+        slf.borrow_mut().combine(counts, false)
+    }
+
+    /// Subtract counts, keeping keys whose count drops to zero or below.
+    ///
+    /// Args:
+    ///     data: Iterable of keys (each counted once) or mapping of counts
+    ///     **kwargs: str keys and their counts
+    #[pyo3(signature = (data=None, **kwargs))]
+    fn subtract(
+        slf: &Bound<'_, Self>,
+        data: Option<&Bound<'_, PyAny>>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        let mut counts = Vec::new();
+        for data in data.into_iter().chain(kwargs.map(|k| k.as_any())) {
+            counts.extend(slf.borrow().counts_of(data)?);
+        }
+        slf.borrow_mut().combine(counts, true)
+    }
+
+    /// Add to the count of a key in a single traversal.
+    ///
+    /// Args:
+    ///     key: Key to count
+    ///     delta: Amount to add (defaults to 1)
+    ///
+    /// Returns:
+    ///     The new count
+    ///
+    /// Raises:
+    ///     OverflowError: If the count no longer fits in 64 bits
+    #[pyo3(signature = (key, delta=1))]
+    fn increment(&mut self, key: &Bound<'_, PyAny>, delta: i64) -> PyResult<i64> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        self.add(key_bytes, delta)
+    }
+
+    /// List the keys with the highest counts, highest first.
+    ///
+    /// Ties are listed in key order. With `n`, only the top `n` entries are
+    /// sorted.
+    ///
+    /// Args:
+    ///     n: Number of entries to return (defaults to all)
+    ///     prefix: Only consider keys starting with this prefix
+    ///
+    /// Returns:
+    ///     List of (key, count) tuples
+    ///
+    /// Examples:
+    ///     >>> hits = TreeCounter({"/a": 5, "/b": 9, "/c": 5, "x": 20})
+    ///     >>> hits.most_common(2, prefix="/")
+    ///     [('/b', 9), ('/a', 5)]
+    #[pyo3(signature = (n=None, *, prefix=None))]
+    fn most_common(
+        &self,
+        py: Python,
+        n: Option<usize>,
+        prefix: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Vec<(Py<PyAny>, i64)>> {
+        let prefix = self.encode_prefix(prefix)?;
+        let mut entries = self
+            .entries(prefix.as_deref())
+            .map(|(key, count)| (&**key, *count))
+            .collect::<Vec<_>>();
+        let order = |a: &(&[u8], i64), b: &(&[u8], i64)| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0));
+        match n {
+            Some(n) if n < entries.len() => {
+                if n > 0 {
+                    entries.select_nth_unstable_by(n - 1, order);
+                }
+                entries.truncate(n);
+                entries.sort_unstable_by(order);
+            }
+            _ => entries.sort_unstable_by(order),
+        }
+        entries
+            .into_iter()
+            .map(|(key, count)| Ok((self.codec.decode_terminated(py, key)?, count)))
+            .collect()
+    }
+
+    /// Sum of the counts.
+    ///
+    /// Args:
+    ///     prefix: Only sum keys starting with this prefix
+    #[pyo3(signature = (*, prefix=None))]
+    fn total(&self, prefix: Option<&Bound<'_, PyAny>>) -> PyResult<i128> {
+        let prefix = self.encode_prefix(prefix)?;
+        Ok(self
+            .entries(prefix.as_deref())
+            .map(|(_, count)| i128::from(*count))
+            .sum())
+    }
+
+    /// Return a list of the keys, in key order.
+    fn keys(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .keys()
+            .map(|k| self.codec.decode_terminated(py, k))
+            .collect()
+    }
+
+    /// Return a list of the counts, in key order.
+    fn values(&self) -> Vec<i64> {
+        self.inner.values().copied().collect()
+    }
+
+    /// Return a list of the (key, count) pairs, in key order.
+    fn items(&self, py: Python) -> PyResult<Vec<(Py<PyAny>, i64)>> {
+        self.inner
+            .iter()
+            .map(|(k, count)| Ok((self.codec.decode_terminated(py, k)?, *count)))
+            .collect()
+    }
+
+    /// Return an iterator over the (key, count) pairs whose key starts with
+    /// a prefix.
+    fn prefix_iter(&self, py: Python, prefix: &Bound<'_, PyAny>) -> PyResult<PyPrefixIter> {
+        let prefix = self.codec.encode_prefix_terminated(prefix)?;
+        let items = self
+            .entries(Some(&prefix))
+            .map(|(k, count)| {
+                let count = count.into_pyobject(py)?.into_any().unbind();
+                Ok((self.codec.decode_terminated(py, k)?, count))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyPrefixIter::snapshot(items))
+    }
+
+    /// Remove all counts.
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// How keys are stored: a key_type name such as "str" or "int", or the codec object.
+    #[getter]
+    fn key_type(&self, py: Python) -> Py<PyAny> {
+        self.codec.key_type(py)
+    }
+
+    /// The count of a key, or 0 if it is missing.
+    fn __getitem__(&self, key: &Bound<'_, PyAny>) -> PyResult<i64> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        Ok(self.inner.get(&key_bytes).copied().unwrap_or(0))
+    }
+
+    fn __setitem__(&mut self, key: &Bound<'_, PyAny>, count: i64) -> PyResult<()> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        match self.inner.get_mut(&key_bytes) {
+            Some(slot) => *slot = count,
+            None => self.inner.force_insert(key_bytes, count),
+        }
+        Ok(())
+    }
+
+    fn __delitem__(&mut self, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        match self.inner.remove(&key_bytes) {
+            Some(_) => Ok(()),
+            None => Err(key_error(key)),
+        }
+    }
+
+    fn __contains__(&self, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        Ok(self.inner.contains_key(&key_bytes))
+    }
+
+    /// Number of distinct keys.
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Iterate over a snapshot of the keys, in key order.
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapIter> {
+        Ok(PyTreeMapIter::snapshot(self.keys(py)?))
+    }
+
+    /// Add another counter's counts, keeping only positive counts.
+    fn __add__(&self, py: Python, other: &Bound<'_, Self>) -> PyResult<Self> {
+        let mut sum = self.copy(py);
+        sum.combine(self.counts_of(other.as_any())?, false)?;
+        sum.keep_positive();
+        Ok(sum)
+    }
+
+    /// Subtract another counter's counts, keeping only positive counts.
+    fn __sub__(&self, py: Python, other: &Bound<'_, Self>) -> PyResult<Self> {
+        let mut difference = self.copy(py);
+        difference.combine(self.counts_of(other.as_any())?, true)?;
+        difference.keep_positive();
+        Ok(difference)
+    }
+
+    /// Add another counter's counts in place, keeping only positive counts.
+    fn __iadd__(slf: &Bound<'_, Self>, other: &Bound<'_, Self>) -> PyResult<()> {
+        let counts = slf.borrow().counts_of(other.as_any())?;
+        let mut counter = slf.borrow_mut();
+        counter.combine(counts, false)?;
+        counter.keep_positive();
+        Ok(())
+    }
+
+    /// Subtract another counter's counts in place, keeping only positive
+    /// counts.
+    fn __isub__(slf: &Bound<'_, Self>, other: &Bound<'_, Self>) -> PyResult<()> {
+        let counts = slf.borrow().counts_of(other.as_any())?;
+        let mut counter = slf.borrow_mut();
+        counter.combine(counts, true)?;
+        counter.keep_positive();
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TreeCounter(len={})", self.inner.len())
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        self.codec.traverse(&visit)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::indexes::KeyIndexes;
+use crate::stored::Stored;
+use blart::TreeMap;
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// Types that can make an independent copy of themselves.
+pub trait Duplicate {
+    fn duplicate(&self) -> Self;
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Duplicate for HashMap<K, V> {
+    fn duplicate(&self) -> Self {
+        self.clone()
+    }
+}
+
+impl Duplicate for TreeMap<Box<[u8]>, Stored> {
+    fn duplicate(&self) -> Self {
+        Python::attach(|py| {
+            let mut copy = TreeMap::new();
+            for (key, value) in self.iter() {
+                copy.force_insert(key.clone(), value.clone_ref(py));
+            }
+            copy
+        })
+    }
+}
+
+impl Duplicate for KeyIndexes {
+    fn duplicate(&self) -> Self {
+        KeyIndexes::duplicate(self)
+    }
+}
+
+/// A value shared between a tree and its snapshots until one side writes.
+///
+/// `share()` is O(1). The first mutable access while the value is shared
+/// copies it, so the cost of a snapshot is only paid when the tree changes
+/// while the snapshot is alive, and then once.
+#[derive(Default)]
+pub struct CopyOnWrite<T>(Arc<T>);
+
+impl<T> CopyOnWrite<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+
+    /// Another handle to the same value.
+    pub fn share(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+
+    /// Whether another handle refers to the same value.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.0) > 1
+    }
+
+    /// The value itself, or None if it is still shared.
+    pub fn try_into_inner(self) -> Option<T> {
+        Arc::try_unwrap(self.0).ok()
+    }
+}
+
+impl<T: Duplicate> CopyOnWrite<T> {
+    /// The value itself, copied out if it is still shared.
+    pub fn into_inner(self) -> T {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| shared.duplicate())
+    }
+}
+
+impl<T> From<T> for CopyOnWrite<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> Deref for CopyOnWrite<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Duplicate> DerefMut for CopyOnWrite<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        if Arc::get_mut(&mut self.0).is_none() {
+            self.0 = Arc::new(self.0.duplicate());
+        }
+        Arc::get_mut(&mut self.0).expect("a fresh copy is not shared")
+    }
+}
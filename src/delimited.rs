@@ -0,0 +1,103 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::fmt;
+
+/// A malformed record in a delimited file, with the line it starts on.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// The line a record starts on and its fields, which must be UTF-8.
+fn fields(record: csv::Result<csv::ByteRecord>) -> Result<(usize, Vec<String>), ParseError> {
+    let record = record.map_err(|err| ParseError {
+        line: err
+            .position()
+            .map_or(0, |position| position.line() as usize),
+        message: err.to_string(),
+    })?;
+    let line = record
+        .position()
+        .map_or(0, |position| position.line() as usize);
+    let fields = record
+        .iter()
+        .map(|field| String::from_utf8(field.to_vec()))
+        .collect::<Result<_, _>>()
+        .map_err(|_| ParseError {
+            line,
+            message: "field is not valid UTF-8".to_string(),
+        })?;
+    Ok((line, fields))
+}
+
+/// A column of a delimited file, by position or by header name.
+#[derive(FromPyObject)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+/// The key and value fields of every record.
+pub struct Columns {
+    pub keys: Vec<Box<[u8]>>,
+    pub values: Vec<String>,
+}
+
+/// Read the key and value columns of every record in `data`.
+///
+/// With `header`, the first record names the columns and is skipped.
+pub fn read_columns(
+    data: &[u8],
+    delimiter: u8,
+    header: bool,
+    key_column: &Column,
+    value_column: &Column,
+) -> PyResult<Columns> {
+    let mut records = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data)
+        .into_byte_records();
+    let parse_error = |e: ParseError| PyValueError::new_err(e.to_string());
+    let mut next_record = || match records.next() {
+        None => Ok(None),
+        Some(record) => fields(record).map(Some).map_err(parse_error),
+    };
+    let names = match header {
+        true => next_record()?.unwrap_or_default().1,
+        false => Vec::new(),
+    };
+    let position = |column: &Column| match column {
+        Column::Index(i) => Ok(*i),
+        Column::Name(name) if !header => Err(PyValueError::new_err(format!(
+            "column {name:?} given by name, but the file has no header"
+        ))),
+        Column::Name(name) => names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| PyValueError::new_err(format!("no column named {name:?}"))),
+    };
+    let (key_at, value_at) = (position(key_column)?, position(value_column)?);
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    while let Some((line, mut fields)) = next_record()? {
+        let needed = key_at.max(value_at) + 1;
+        if fields.len() < needed {
+            return Err(parse_error(ParseError {
+                line,
+                message: format!("expected at least {needed} fields, found {}", fields.len()),
+            }));
+        }
+        keys.push(fields[key_at].as_bytes().into());
+        values.push(std::mem::take(&mut fields[value_at]));
+    }
+    Ok(Columns { keys, values })
+}
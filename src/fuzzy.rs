@@ -0,0 +1,438 @@
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::Bound::{Excluded, Included};
+
+/// Split a stored key into the units edit distances are counted in, each
+/// paired with the byte offset where it ends.
+///
+/// String keys are compared by character. Bytes that are not valid UTF-8
+/// count as one replacement character per invalid sequence, as in
+/// `String::from_utf8_lossy`. Other keys are compared byte by byte.
+pub fn key_units(key: &[u8], chars: bool) -> Vec<(u32, usize)> {
+    if !chars {
+        return key
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| (u32::from(*byte), i + 1))
+            .collect();
+    }
+    let mut units = Vec::with_capacity(key.len());
+    let mut offset = 0;
+    for chunk in key.utf8_chunks() {
+        for (i, c) in chunk.valid().char_indices() {
+            units.push((u32::from(c), offset + i + c.len_utf8()));
+        }
+        offset += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            offset += chunk.invalid().len();
+            units.push((u32::from(char::REPLACEMENT_CHARACTER), offset));
+        }
+    }
+    units
+}
+
+/// Edit distance used to compare keys with the query.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Insertions, deletions and substitutions
+    Levenshtein,
+    /// Levenshtein plus swaps of two adjacent units, counted as one edit
+    /// (the optimal string alignment variant)
+    Damerau,
+    /// Substitutions only, so only keys of the query's length can match
+    Hamming,
+}
+
+impl Metric {
+    /// Parse the `metric=` argument of the fuzzy search methods.
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "levenshtein" => Ok(Self::Levenshtein),
+            "damerau" => Ok(Self::Damerau),
+            "hamming" => Ok(Self::Hamming),
+            _ => Err(PyValueError::new_err(format!(
+                "metric must be 'levenshtein', 'damerau' or 'hamming', got '{name}'"
+            ))),
+        }
+    }
+}
+
+/// Smallest byte string greater than every string starting with `prefix`,
+/// or None when no such string exists.
+pub(crate) fn successor(prefix: &[u8]) -> Option<Box<[u8]>> {
+    let mut next = prefix.to_vec();
+    while let Some(last) = next.pop() {
+        if last < u8::MAX {
+            next.push(last + 1);
+            return Some(next.into_boxed_slice());
+        }
+    }
+    None
+}
+
+/// Slack for comparing summed float costs with the search radius.
+const EPSILON: f64 = 1e-9;
+
+/// Cost of each edit operation, when transforming the query into a key.
+#[derive(Clone)]
+pub struct Costs {
+    /// A key unit with no counterpart in the query
+    pub insert: f64,
+    /// A query unit with no counterpart in the key
+    pub delete: f64,
+    /// Replacing one unit with another, and swapping two adjacent units
+    pub substitute: f64,
+    /// Substitution costs for specific (query unit, key unit) pairs
+    pub pairs: HashMap<(u32, u32), f64>,
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Self {
+            insert: 1.0,
+            delete: 1.0,
+            substitute: 1.0,
+            pairs: HashMap::new(),
+        }
+    }
+}
+
+impl Costs {
+    /// Parse the cost arguments of the fuzzy search methods, leaving unit
+    /// costs for the operations that were not given.
+    ///
+    /// `pairs` maps `(query_unit, key_unit)` tuples to a cost. Units are
+    /// single characters for string keys and byte values otherwise.
+    pub fn from_args(
+        insert: Option<f64>,
+        delete: Option<f64>,
+        substitute: Option<f64>,
+        pairs: Option<&Bound<'_, PyDict>>,
+        chars: bool,
+    ) -> PyResult<Self> {
+        let mut costs = Self::default();
+        for (name, cost, field) in [
+            ("insert_cost", insert, &mut costs.insert),
+            ("delete_cost", delete, &mut costs.delete),
+            ("substitute_cost", substitute, &mut costs.substitute),
+        ] {
+            if let Some(cost) = cost {
+                *field = check_cost(name, cost)?;
+            }
+        }
+        for (pair, cost) in pairs.into_iter().flat_map(|pairs| pairs.iter()) {
+            let (query_unit, key_unit): (Bound<'_, PyAny>, Bound<'_, PyAny>) = pair.extract()?;
+            let pair = (unit(&query_unit, chars)?, unit(&key_unit, chars)?);
+            costs
+                .pairs
+                .insert(pair, check_cost("substitution_costs", cost.extract()?)?);
+        }
+        Ok(costs)
+    }
+
+    fn substitution(&self, query_unit: u32, key_unit: u32) -> f64 {
+        if query_unit == key_unit {
+            0.0
+        } else {
+            self.pairs
+                .get(&(query_unit, key_unit))
+                .copied()
+                .unwrap_or(self.substitute)
+        }
+    }
+}
+
+fn check_cost(name: &str, cost: f64) -> PyResult<f64> {
+    if cost >= 0.0 && cost.is_finite() {
+        Ok(cost)
+    } else {
+        Err(PyValueError::new_err(format!(
+            "{name} must be a non-negative number, got {cost}"
+        )))
+    }
+}
+
+/// Read one side of a `substitution_costs` pair as a comparison unit.
+fn unit(obj: &Bound<'_, PyAny>, chars: bool) -> PyResult<u32> {
+    if chars {
+        if let Ok(s) = obj.cast::<PyString>() {
+            let s = s.to_cow()?;
+            let mut it = s.chars();
+            if let (Some(c), None) = (it.next(), it.next()) {
+                return Ok(u32::from(c));
+            }
+        }
+        Err(PyTypeError::new_err(
+            "substitution_costs keys must be pairs of single characters",
+        ))
+    } else {
+        obj.extract::<u8>().map(u32::from).map_err(|_| {
+            PyTypeError::new_err("substitution_costs keys must be pairs of byte values (0-255)")
+        })
+    }
+}
+
+/// Distance ordered for ranking matches, treating totals that only differ
+/// by float rounding as ties.
+struct Distance(f64);
+
+impl Distance {
+    fn rank(&self) -> i64 {
+        (self.0 / EPSILON).round() as i64
+    }
+}
+
+impl PartialEq for Distance {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank() == other.rank()
+    }
+}
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+/// Resumable edit distance search over the keys of a tree, in key order.
+///
+/// The search keeps no reference to the tree between matches, only the
+/// bound to continue from, so it can be driven one match at a time. Keys
+/// are walked in order and whole subtrees are skipped as soon as their
+/// shared prefix is further than `max_distance` from the query.
+#[derive(Clone)]
+pub struct FuzzyCursor {
+    query: Vec<u32>,
+    max_distance: f64,
+    /// Only accept distances strictly below `max_distance`
+    exclusive: bool,
+    chars: bool,
+    metric: Metric,
+    costs: Costs,
+    /// Compare the query with the closest prefix of each key instead of
+    /// the whole key
+    prefixes: bool,
+    /// Bound of the keys still to visit, and whether it is inclusive; None
+    /// once the search is exhausted
+    next: Option<(Box<[u8]>, bool)>,
+}
+
+/// Result of comparing one stored key against the query.
+enum Scan {
+    /// The key is within range, at this distance
+    Match(f64),
+    /// No key starting with the first this many bytes can be within range
+    Prune(usize),
+    /// The key is out of range but longer keys could still match
+    Miss,
+}
+
+impl FuzzyCursor {
+    pub fn new(query: &[u8], max_distance: f64, chars: bool, metric: Metric, costs: Costs) -> Self {
+        Self {
+            query: key_units(query, chars)
+                .into_iter()
+                .map(|(u, _)| u)
+                .collect(),
+            max_distance,
+            exclusive: false,
+            chars,
+            metric,
+            costs,
+            prefixes: false,
+            next: Some((Box::default(), true)),
+        }
+    }
+
+    /// Match keys that start with something within range of the query,
+    /// reporting the distance of their closest prefix.
+    pub fn matching_prefixes(mut self) -> Self {
+        self.prefixes = true;
+        self
+    }
+
+    /// A fresh search for another query, with the same radius, metric and
+    /// costs.
+    pub fn retarget(&self, query: &[u8]) -> Self {
+        Self {
+            query: key_units(query, self.chars)
+                .into_iter()
+                .map(|(u, _)| u)
+                .collect(),
+            next: Some((Box::default(), true)),
+            ..self.clone()
+        }
+    }
+
+    fn in_range(&self, distance: f64) -> bool {
+        if self.exclusive {
+            distance < self.max_distance - EPSILON
+        } else {
+            distance <= self.max_distance + EPSILON
+        }
+    }
+
+    /// Find the next matching key and its distance.
+    ///
+    /// `seek` returns the first stored key satisfying a lower bound.
+    pub fn next_match<'a, S>(&mut self, seek: S) -> Option<(&'a [u8], f64)>
+    where
+        S: Fn(std::ops::Bound<&[u8]>) -> Option<&'a [u8]>,
+    {
+        loop {
+            let (bound, inclusive) = self.next.take()?;
+            let key = seek(if inclusive {
+                Included(&bound)
+            } else {
+                Excluded(&bound)
+            })?;
+            match self.scan(key) {
+                Scan::Match(distance) => {
+                    self.next = Some((key.into(), false));
+                    return Some((key, distance));
+                }
+                Scan::Prune(len) => self.next = Some((successor(&key[..len])?, true)),
+                Scan::Miss => self.next = Some((key.into(), false)),
+            }
+        }
+    }
+
+    /// Collect the best matches, closest first and in key order among equal
+    /// distances, keeping at most `limit` of them.
+    ///
+    /// Once `limit` matches are held, the search radius shrinks to the worst
+    /// of them, so later subtrees are pruned more aggressively.
+    pub fn ranked<'a, S>(mut self, seek: S, limit: Option<usize>) -> Vec<(f64, &'a [u8])>
+    where
+        S: Fn(std::ops::Bound<&[u8]>) -> Option<&'a [u8]>,
+    {
+        if limit == Some(0) {
+            return Vec::new();
+        }
+        let mut best = BinaryHeap::new();
+        while let Some((key, distance)) = self.next_match(&seek) {
+            best.push((Distance(distance), key));
+            let Some(limit) = limit else {
+                continue;
+            };
+            if best.len() > limit {
+                best.pop();
+            }
+            if best.len() == limit {
+                // Keys arrive in order, so a later key only wins if it is
+                // strictly closer than the current worst
+                match best.peek() {
+                    Some((worst, _)) if worst.0 <= 0.0 => break,
+                    Some((worst, _)) => {
+                        self.max_distance = worst.0;
+                        self.exclusive = true;
+                    }
+                    None => {}
+                }
+            }
+        }
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|(distance, key)| (distance.0, key))
+            .collect()
+    }
+
+    fn scan(&self, key: &[u8]) -> Scan {
+        match self.metric {
+            Metric::Hamming => self.scan_hamming(key),
+            _ => self.scan_edits(key),
+        }
+    }
+
+    /// Compare a key with the query one row of the edit distance table at a
+    /// time, stopping once no entry of the row is within range.
+    ///
+    /// A transposition reaches back two rows, so with the Damerau metric the
+    /// search only stops once two consecutive rows are out of range.
+    fn scan_edits(&self, key: &[u8]) -> Scan {
+        let transpositions = self.metric == Metric::Damerau;
+        let costs = &self.costs;
+        let mut before = vec![f64::INFINITY; self.query.len() + 1];
+        let mut row: Vec<f64> = (0..=self.query.len())
+            .map(|j| j as f64 * costs.delete)
+            .collect();
+        let mut next_row = vec![0.0; row.len()];
+        let mut previous_unit = None;
+        let mut before_in_range = true;
+        // Distance of the closest key prefix, in prefix mode
+        let mut closest = row[self.query.len()];
+        for (unit, end) in key_units(key, self.chars) {
+            next_row[0] = row[0] + costs.insert;
+            for (j, query_unit) in self.query.iter().enumerate() {
+                let mut distance = (row[j + 1] + costs.insert)
+                    .min(next_row[j] + costs.delete)
+                    .min(row[j] + costs.substitution(*query_unit, unit));
+                if transpositions
+                    && j > 0
+                    && previous_unit == Some(*query_unit)
+                    && unit == self.query[j - 1]
+                {
+                    distance = distance.min(before[j - 1] + costs.substitute);
+                }
+                next_row[j + 1] = distance;
+            }
+            std::mem::swap(&mut before, &mut row);
+            std::mem::swap(&mut row, &mut next_row);
+            previous_unit = Some(unit);
+            closest = closest.min(row[self.query.len()]);
+            let row_in_range = row.iter().any(|d| self.in_range(*d));
+            if !row_in_range && (!transpositions || !before_in_range) {
+                return match closest {
+                    distance if self.prefixes && self.in_range(distance) => Scan::Match(distance),
+                    _ => Scan::Prune(end),
+                };
+            }
+            before_in_range = row_in_range;
+        }
+        match if self.prefixes {
+            closest
+        } else {
+            row[self.query.len()]
+        } {
+            distance if self.in_range(distance) => Scan::Match(distance),
+            _ => Scan::Miss,
+        }
+    }
+
+    /// Add up substitution costs, giving up on keys that are longer than the
+    /// query or already too far from it.
+    ///
+    /// In prefix mode, keys longer than the query are compared by their
+    /// first units only.
+    fn scan_hamming(&self, key: &[u8]) -> Scan {
+        let mut distance = 0.0;
+        let mut len = 0;
+        for (unit, end) in key_units(key, self.chars) {
+            match self.query.get(len) {
+                Some(query_unit) => distance += self.costs.substitution(*query_unit, unit),
+                None if self.prefixes => return Scan::Match(distance),
+                None => return Scan::Prune(end),
+            }
+            len += 1;
+            if !self.in_range(distance) {
+                return Scan::Prune(end);
+            }
+        }
+        if len == self.query.len() {
+            Scan::Match(distance)
+        } else {
+            Scan::Miss
+        }
+    }
+}
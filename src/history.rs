@@ -0,0 +1,110 @@
+use crate::stored::Stored;
+use pyo3::prelude::*;
+use pyo3::{PyTraverseError, PyVisit};
+use std::collections::{HashMap, VecDeque};
+
+/// The state of a key just before one change to it.
+pub struct Prior {
+    pub key: Box<[u8]>,
+    pub value: Option<Stored>,
+    /// Original spelling, in case-insensitive trees
+    pub spelling: Option<String>,
+}
+
+/// Change log of a versioned tree. Change `i` moved the tree from version
+/// `i` to version `i + 1`; only the changes from `oldest()` on are kept.
+pub struct History {
+    changes: VecDeque<Prior>,
+    /// Number of changes dropped from the front of the log
+    pruned: usize,
+    /// Most changes kept, or None for all of them
+    limit: Option<usize>,
+}
+
+impl History {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            changes: VecDeque::new(),
+            pruned: 0,
+            limit,
+        }
+    }
+
+    /// Log a change to a key, given its state just before.
+    pub fn record(
+        &mut self,
+        py: Python,
+        key: &[u8],
+        value: Option<&Stored>,
+        spelling: Option<&String>,
+    ) {
+        self.changes.push_back(Prior {
+            key: key.into(),
+            value: value.map(|v| v.clone_ref(py)),
+            spelling: spelling.cloned(),
+        });
+        if self.limit.is_some_and(|limit| self.changes.len() > limit) {
+            self.changes.pop_front();
+            self.pruned += 1;
+        }
+    }
+
+    /// Drop the changes made before `version`, returning how many were
+    /// dropped.
+    pub fn prune(&mut self, version: usize) -> usize {
+        let count = version.saturating_sub(self.pruned).min(self.changes.len());
+        self.changes.drain(..count);
+        self.pruned += count;
+        count
+    }
+
+    /// Bytes held by the log, not counting logged Python objects.
+    pub fn memory_usage(&self) -> usize {
+        self.changes.capacity() * std::mem::size_of::<Prior>()
+            + self
+                .changes
+                .iter()
+                .map(|prior| {
+                    prior.key.len()
+                        + prior.spelling.as_ref().map_or(0, String::capacity)
+                        + prior.value.as_ref().map_or(0, Stored::heap_size)
+                })
+                .sum::<usize>()
+    }
+
+    /// Python objects logged, as they were before each change.
+    pub fn values(&self) -> impl Iterator<Item = &Py<PyAny>> {
+        self.changes
+            .iter()
+            .filter_map(|prior| prior.value.as_ref()?.object())
+    }
+
+    /// The current version: the number of changes logged.
+    pub fn version(&self) -> usize {
+        self.pruned + self.changes.len()
+    }
+
+    /// The earliest version the log still reaches back to.
+    pub fn oldest(&self) -> usize {
+        self.pruned
+    }
+
+    /// The state at `version` of every key changed since then: the prior
+    /// state logged by the first change to it from that version on. Keys
+    /// not listed are the same now as they were then. `version` must be
+    /// between `oldest()` and `version()`.
+    pub fn since(&self, version: usize) -> HashMap<&[u8], &Prior> {
+        let mut state = HashMap::new();
+        for prior in self.changes.range(version - self.pruned..) {
+            state.entry(&*prior.key).or_insert(prior);
+        }
+        state
+    }
+
+    pub fn traverse(&self, visit: &PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for prior in &self.changes {
+            visit.call(prior.value.as_ref().and_then(Stored::object))?;
+        }
+        Ok(())
+    }
+}
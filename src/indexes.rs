@@ -0,0 +1,560 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::sync::Arc;
+
+/// Secondary indexes over the keys of a tree, kept in step with it.
+#[derive(Default)]
+pub struct KeyIndexes {
+    pub substrings: Option<SubstringIndex>,
+    pub suffixes: Option<SuffixIndex>,
+    pub counts: Option<CountIndex>,
+    pub order: Option<InsertionOrder>,
+}
+
+impl KeyIndexes {
+    /// Whether any index is enabled, so key changes must be reported.
+    pub fn is_active(&self) -> bool {
+        self.substrings.is_some()
+            || self.suffixes.is_some()
+            || self.counts.is_some()
+            || self.order.is_some()
+    }
+
+    pub fn add(&mut self, key: &[u8]) {
+        if let Some(index) = &mut self.substrings {
+            index.add(key);
+        }
+        if let Some(index) = &mut self.suffixes {
+            index.add(key);
+        }
+        if let Some(index) = &mut self.counts {
+            index.add(key);
+        }
+        if let Some(index) = &mut self.order {
+            index.add(key);
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        if let Some(index) = &mut self.substrings {
+            index.remove(key);
+        }
+        if let Some(index) = &mut self.suffixes {
+            index.remove(key);
+        }
+        if let Some(index) = &mut self.counts {
+            index.remove(key);
+        }
+        if let Some(index) = &mut self.order {
+            index.remove(key);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(index) = &mut self.substrings {
+            index.clear();
+        }
+        if let Some(index) = &mut self.suffixes {
+            index.clear();
+        }
+        if let Some(index) = &mut self.counts {
+            index.clear();
+        }
+        if let Some(index) = &mut self.order {
+            index.clear();
+        }
+    }
+
+    /// Release the memory still held for removed keys.
+    pub fn compact(&mut self) {
+        if let Some(index) = &mut self.counts {
+            index.compact();
+        }
+        if let Some(index) = &mut self.order {
+            index.tickets.shrink_to_fit();
+        }
+    }
+
+    /// Bytes held by the enabled indexes.
+    pub fn memory_usage(&self) -> usize {
+        self.substrings
+            .as_ref()
+            .map_or(0, SubstringIndex::memory_usage)
+            + self.suffixes.as_ref().map_or(0, SuffixIndex::memory_usage)
+            + self.counts.as_ref().map_or(0, CountIndex::memory_usage)
+            + self.order.as_ref().map_or(0, InsertionOrder::memory_usage)
+    }
+
+    /// Copy of the indexes, for trees duplicated with their keys.
+    pub fn duplicate(&self) -> Self {
+        Self {
+            substrings: self.substrings.as_ref().map(SubstringIndex::duplicate),
+            suffixes: self.suffixes.clone(),
+            counts: self.counts.clone(),
+            order: self.order.clone(),
+        }
+    }
+}
+
+/// Secondary index of every suffix of every key, for substring search.
+///
+/// A fragment occurs in a key exactly when some suffix of the key starts
+/// with it, so sorting all suffixes turns substring search into a range
+/// scan. Suffixes share their key's allocation and only store where they
+/// start, so the index costs one entry per key position rather than a copy
+/// of every suffix.
+pub struct SubstringIndex {
+    suffixes: BTreeSet<Suffix>,
+    /// Only index suffixes that start on a UTF-8 character boundary
+    chars: bool,
+}
+
+/// The part of a key starting at `start`.
+struct Suffix {
+    key: Arc<[u8]>,
+    start: usize,
+}
+
+impl Suffix {
+    fn bytes(&self) -> &[u8] {
+        &self.key[self.start..]
+    }
+}
+
+// Ordered by suffix, then by key. Comparing key lengths first makes a bare
+// fragment (a suffix of itself) sort before every key that contains it.
+impl Ord for Suffix {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.bytes()
+            .cmp(other.bytes())
+            .then(self.key.len().cmp(&other.key.len()))
+            .then(self.key.cmp(&other.key))
+    }
+}
+
+impl PartialOrd for Suffix {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Suffix {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Suffix {}
+
+impl SubstringIndex {
+    pub fn new(chars: bool) -> Self {
+        Self {
+            suffixes: BTreeSet::new(),
+            chars,
+        }
+    }
+
+    /// Offsets where an indexed suffix of `key` starts.
+    fn starts(&self, key: &[u8]) -> Vec<usize> {
+        (0..key.len())
+            .filter(|i| !self.chars || (key[*i] & 0xC0) != 0x80)
+            .collect()
+    }
+
+    /// Bytes held by the index: one entry per suffix, and each key's bytes
+    /// once, in the allocation its suffixes share.
+    pub fn memory_usage(&self) -> usize {
+        let keys: usize = self
+            .suffixes
+            .iter()
+            .filter(|suffix| suffix.start == 0)
+            .map(|suffix| 2 * std::mem::size_of::<usize>() + suffix.key.len())
+            .sum();
+        self.suffixes.len() * std::mem::size_of::<Suffix>() + keys
+    }
+
+    pub fn add(&mut self, key: &[u8]) {
+        let shared: Arc<[u8]> = key.into();
+        for start in self.starts(key) {
+            self.suffixes.insert(Suffix {
+                key: shared.clone(),
+                start,
+            });
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        let shared: Arc<[u8]> = key.into();
+        for start in self.starts(key) {
+            self.suffixes.remove(&Suffix {
+                key: shared.clone(),
+                start,
+            });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.suffixes.clear();
+    }
+
+    /// Keys containing `fragment`, in key order and without duplicates.
+    pub fn find(&self, fragment: &[u8]) -> BTreeSet<&[u8]> {
+        let probe = Suffix {
+            key: fragment.into(),
+            start: 0,
+        };
+        self.suffixes
+            .range(probe..)
+            .take_while(|suffix| suffix.bytes().starts_with(fragment))
+            .map(|suffix| &*suffix.key)
+            .collect()
+    }
+
+    /// Copy of the index, for trees duplicated with their keys.
+    pub fn duplicate(&self) -> Self {
+        Self {
+            suffixes: self
+                .suffixes
+                .iter()
+                .map(|suffix| Suffix {
+                    key: suffix.key.clone(),
+                    start: suffix.start,
+                })
+                .collect(),
+            chars: self.chars,
+        }
+    }
+}
+
+/// Every key stored reversed, for suffix search.
+///
+/// A key ends with a suffix exactly when its reversal starts with the
+/// reversed suffix, so suffix queries become prefix scans. Reversed keys
+/// can be prefixes of each other, which the radix tree does not allow, so
+/// they are kept in a B-tree.
+#[derive(Clone, Default)]
+pub struct SuffixIndex {
+    reversed: BTreeSet<Box<[u8]>>,
+}
+
+fn reversed(key: &[u8]) -> Box<[u8]> {
+    key.iter().rev().copied().collect()
+}
+
+impl SuffixIndex {
+    pub fn add(&mut self, key: &[u8]) {
+        self.reversed.insert(reversed(key));
+    }
+
+    /// Bytes held by the index, a reversed copy of every key.
+    pub fn memory_usage(&self) -> usize {
+        self.reversed
+            .iter()
+            .map(|key| std::mem::size_of::<Box<[u8]>>() + key.len())
+            .sum()
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        self.reversed.remove(&reversed(key));
+    }
+
+    pub fn clear(&mut self) {
+        self.reversed.clear();
+    }
+
+    /// Keys ending with `suffix`, in key order.
+    pub fn find(&self, suffix: &[u8]) -> Vec<Box<[u8]>> {
+        let start = reversed(suffix);
+        let mut keys: Vec<Box<[u8]>> = self
+            .reversed
+            .range(start.clone()..)
+            .take_while(|key| key.starts_with(&start))
+            .map(|key| reversed(key))
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+}
+
+/// Keys in the order they were first inserted, for evicting the oldest.
+///
+/// Each key gets an increasing ticket; replacing a key's value keeps its
+/// ticket, so this is insertion order rather than recency of use.
+#[derive(Clone, Default)]
+pub struct InsertionOrder {
+    keys: BTreeMap<u64, Box<[u8]>>,
+    tickets: HashMap<Box<[u8]>, u64>,
+    next: u64,
+}
+
+impl InsertionOrder {
+    pub fn add(&mut self, key: &[u8]) {
+        if self.tickets.contains_key(key) {
+            return;
+        }
+        self.keys.insert(self.next, key.into());
+        self.tickets.insert(key.into(), self.next);
+        self.next += 1;
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        if let Some(ticket) = self.tickets.remove(key) {
+            self.keys.remove(&ticket);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.tickets.clear();
+    }
+
+    /// Bytes held by the index: two copies of every key, with its ticket.
+    pub fn memory_usage(&self) -> usize {
+        let entry = std::mem::size_of::<(u64, Box<[u8]>)>();
+        self.keys
+            .values()
+            .map(|key| entry + key.len())
+            .sum::<usize>()
+            + self.tickets.capacity() * entry
+            + self.tickets.keys().map(|key| key.len()).sum::<usize>()
+    }
+
+    /// The key inserted longest ago.
+    pub fn oldest(&self) -> Option<&[u8]> {
+        self.keys.values().next().map(|key| &**key)
+    }
+}
+
+/// Every key in an order-statistic tree, for counting and selecting by rank.
+///
+/// A treap whose nodes record the size of their subtree, so the number of
+/// keys before a bound, and the key at a position, are found in a single
+/// descent in O(log n) expected time instead of a walk over the leaves.
+/// Nodes live in a vector and refer to each other by index.
+#[derive(Clone)]
+pub struct CountIndex {
+    nodes: Vec<CountNode>,
+    root: Option<usize>,
+    /// Slots of removed nodes, reused by later inserts
+    free: Vec<usize>,
+    /// State of the xorshift generator for node priorities
+    seed: u64,
+}
+
+#[derive(Clone)]
+struct CountNode {
+    key: Box<[u8]>,
+    /// Heap order of the treap; random, which keeps it balanced
+    priority: u64,
+    /// Number of keys in the subtree rooted here
+    size: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+impl Default for CountIndex {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            root: None,
+            free: Vec::new(),
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+impl CountIndex {
+    pub fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    /// Bytes held by the index, including the slots of removed keys.
+    pub fn memory_usage(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<CountNode>()
+            + self.nodes.iter().map(|node| node.key.len()).sum::<usize>()
+            + self.free.capacity() * std::mem::size_of::<usize>()
+    }
+
+    fn size(&self, node: Option<usize>) -> usize {
+        node.map_or(0, |i| self.nodes[i].size)
+    }
+
+    fn resize(&mut self, i: usize) {
+        let node = &self.nodes[i];
+        self.nodes[i].size = 1 + self.size(node.left) + self.size(node.right);
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+
+    /// Split a subtree into the keys before `key` (also `key` itself when
+    /// `inclusive`) and the rest.
+    fn split(
+        &mut self,
+        node: Option<usize>,
+        key: &[u8],
+        inclusive: bool,
+    ) -> (Option<usize>, Option<usize>) {
+        let Some(i) = node else {
+            return (None, None);
+        };
+        let before = match self.nodes[i].key.as_ref().cmp(key) {
+            Ordering::Less => true,
+            Ordering::Equal => inclusive,
+            Ordering::Greater => false,
+        };
+        if before {
+            let (left, right) = self.split(self.nodes[i].right, key, inclusive);
+            self.nodes[i].right = left;
+            self.resize(i);
+            (Some(i), right)
+        } else {
+            let (left, right) = self.split(self.nodes[i].left, key, inclusive);
+            self.nodes[i].left = right;
+            self.resize(i);
+            (left, Some(i))
+        }
+    }
+
+    /// Join two subtrees whose keys are all ordered before one another.
+    fn merge(&mut self, left: Option<usize>, right: Option<usize>) -> Option<usize> {
+        match (left, right) {
+            (None, node) | (node, None) => node,
+            (Some(l), Some(r)) if self.nodes[l].priority > self.nodes[r].priority => {
+                self.nodes[l].right = self.merge(self.nodes[l].right, right);
+                self.resize(l);
+                Some(l)
+            }
+            (Some(_), Some(r)) => {
+                self.nodes[r].left = self.merge(left, self.nodes[r].left);
+                self.resize(r);
+                Some(r)
+            }
+        }
+    }
+
+    pub fn add(&mut self, key: &[u8]) {
+        let (before, rest) = self.split(self.root, key, false);
+        let (existing, after) = self.split(rest, key, true);
+        let middle = match existing {
+            Some(node) => node,
+            None => {
+                let node = CountNode {
+                    key: key.into(),
+                    priority: self.next_priority(),
+                    size: 1,
+                    left: None,
+                    right: None,
+                };
+                match self.free.pop() {
+                    Some(slot) => {
+                        self.nodes[slot] = node;
+                        slot
+                    }
+                    None => {
+                        self.nodes.push(node);
+                        self.nodes.len() - 1
+                    }
+                }
+            }
+        };
+        let joined = self.merge(before, Some(middle));
+        self.root = self.merge(joined, after);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        let (before, rest) = self.split(self.root, key, false);
+        let (removed, after) = self.split(rest, key, true);
+        if let Some(slot) = removed {
+            self.nodes[slot].key = Box::default();
+            self.free.push(slot);
+        }
+        self.root = self.merge(before, after);
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = None;
+    }
+
+    /// Rebuild the treap from its keys, dropping the slots of removed nodes.
+    pub fn compact(&mut self) {
+        if self.free.is_empty() {
+            return;
+        }
+        let mut free = std::mem::take(&mut self.free);
+        free.sort_unstable();
+        let keys: Vec<Box<[u8]>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| free.binary_search(i).is_err())
+            .map(|(_, node)| node.key)
+            .collect();
+        self.root = None;
+        self.nodes.reserve_exact(keys.len());
+        for key in keys {
+            self.add(&key);
+        }
+    }
+
+    /// Number of keys before `key`, also counting `key` itself when
+    /// `inclusive`.
+    fn before(&self, key: &[u8], inclusive: bool) -> usize {
+        let mut count = 0;
+        let mut node = self.root;
+        while let Some(i) = node {
+            let current = &self.nodes[i];
+            let goes_left = match current.key.as_ref().cmp(key) {
+                Ordering::Less => false,
+                Ordering::Equal => !inclusive,
+                Ordering::Greater => true,
+            };
+            if goes_left {
+                node = current.left;
+            } else {
+                count += self.size(current.left) + 1;
+                node = current.right;
+            }
+        }
+        count
+    }
+
+    /// Number of keys between two bounds.
+    pub fn count(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> usize {
+        let end = match upper {
+            Included(key) => self.before(key, true),
+            Excluded(key) => self.before(key, false),
+            Unbounded => self.len(),
+        };
+        let start = match lower {
+            Included(key) => self.before(key, false),
+            Excluded(key) => self.before(key, true),
+            Unbounded => 0,
+        };
+        end.saturating_sub(start)
+    }
+
+    /// The key at a position in key order.
+    pub fn select(&self, mut position: usize) -> Option<&[u8]> {
+        let mut node = self.root;
+        while let Some(i) = node {
+            let current = &self.nodes[i];
+            let left = self.size(current.left);
+            match position.cmp(&left) {
+                Ordering::Less => node = current.left,
+                Ordering::Equal => return Some(&current.key),
+                Ordering::Greater => {
+                    position -= left + 1;
+                    node = current.right;
+                }
+            }
+        }
+        None
+    }
+}
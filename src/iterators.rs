@@ -1,32 +1,118 @@
+use crate::fuzzy::FuzzyCursor;
+use crate::pattern::KeyPattern;
+use crate::treemap::PyTreeMap;
+use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
 use pyo3::types::PyAny;
+use pyo3::{PyTraverseError, PyVisit};
+use std::ops::Bound::{Excluded, Included, Unbounded};
+
+/// Ties an iterator to the TreeMap it came from, so that adding or removing
+/// keys while iterating raises RuntimeError the way dicts do.
+pub struct IterGuard {
+    map: Py<PyTreeMap>,
+    generation: u64,
+}
+
+impl IterGuard {
+    pub fn new(map: &PyRef<'_, PyTreeMap>) -> Self {
+        let Ok(bound) = map.into_pyobject(map.py());
+        Self {
+            map: bound.to_owned().unbind(),
+            generation: map.generation(),
+        }
+    }
+
+    fn traverse(&self, visit: &PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.map)
+    }
+
+    /// Fail if the map's keys changed since the iterator was created.
+    fn check(&self, py: Python) -> PyResult<()> {
+        // A map that is mutably borrowed is in the middle of a mutation
+        let unchanged = self
+            .map
+            .try_borrow(py)
+            .is_ok_and(|map| map.generation() == self.generation);
+        if unchanged {
+            Ok(())
+        } else {
+            Err(PyRuntimeError::new_err(
+                "TreeMap changed size during iteration",
+            ))
+        }
+    }
+}
 
 /// Iterator for TreeMap keys
 #[pyclass]
 pub struct PyTreeMapIter {
-    keys: Vec<String>,
+    keys: Vec<Py<PyAny>>,
     index: usize,
+    guard: Option<IterGuard>,
 }
 
 impl PyTreeMapIter {
-    pub fn new(keys: Vec<String>) -> Self {
-        Self { keys, index: 0 }
+    pub fn new(keys: Vec<Py<PyAny>>, guard: IterGuard) -> Self {
+        Self {
+            keys,
+            index: 0,
+            guard: Some(guard),
+        }
+    }
+
+    /// Iterate over a snapshot that no map invalidates.
+    pub fn snapshot(keys: Vec<Py<PyAny>>) -> Self {
+        Self {
+            keys,
+            index: 0,
+            guard: None,
+        }
     }
 }
 
 #[pymethods]
 impl PyTreeMapIter {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for item in &self.keys {
+            visit.call(item)?;
+        }
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.keys.clear();
+        self.index = 0;
+        self.guard = None;
+    }
+
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<String> {
+    /// Number of results not yet yielded.
+    fn remaining(&self) -> usize {
+        self.keys.len() - self.index
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.remaining()
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> PyResult<Option<Py<PyAny>>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
         if slf.index < slf.keys.len() {
-            let key = slf.keys[slf.index].clone();
+            let key = slf.keys[slf.index].clone_ref(py);
             slf.index += 1;
-            Some(key)
+            Ok(Some(key))
         } else {
-            None
+            slf.guard = None;
+            Ok(None)
         }
     }
 }
@@ -34,29 +120,63 @@ impl PyTreeMapIter {
 /// Iterator for TreeMap keys (returned by .keys() method)
 #[pyclass]
 pub struct PyTreeMapKeys {
-    keys: Vec<String>,
+    keys: Vec<Py<PyAny>>,
     index: usize,
+    guard: Option<IterGuard>,
 }
 
 impl PyTreeMapKeys {
-    pub fn new(keys: Vec<String>) -> Self {
-        Self { keys, index: 0 }
+    pub fn new(keys: Vec<Py<PyAny>>, guard: IterGuard) -> Self {
+        Self {
+            keys,
+            index: 0,
+            guard: Some(guard),
+        }
     }
 }
 
 #[pymethods]
 impl PyTreeMapKeys {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for item in &self.keys {
+            visit.call(item)?;
+        }
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.keys.clear();
+        self.index = 0;
+        self.guard = None;
+    }
+
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<String> {
+    /// Number of results not yet yielded.
+    fn remaining(&self) -> usize {
+        self.keys.len() - self.index
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.remaining()
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> PyResult<Option<Py<PyAny>>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
         if slf.index < slf.keys.len() {
-            let key = slf.keys[slf.index].clone();
+            let key = slf.keys[slf.index].clone_ref(py);
             slf.index += 1;
-            Some(key)
+            Ok(Some(key))
         } else {
-            None
+            slf.guard = None;
+            Ok(None)
         }
     }
 }
@@ -66,27 +186,61 @@ impl PyTreeMapKeys {
 pub struct PyTreeMapValues {
     values: Vec<Py<PyAny>>,
     index: usize,
+    guard: Option<IterGuard>,
 }
 
 impl PyTreeMapValues {
-    pub fn new(values: Vec<Py<PyAny>>) -> Self {
-        Self { values, index: 0 }
+    pub fn new(values: Vec<Py<PyAny>>, guard: IterGuard) -> Self {
+        Self {
+            values,
+            index: 0,
+            guard: Some(guard),
+        }
     }
 }
 
 #[pymethods]
 impl PyTreeMapValues {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for item in &self.values {
+            visit.call(item)?;
+        }
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.values.clear();
+        self.index = 0;
+        self.guard = None;
+    }
+
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> Option<Py<PyAny>> {
+    /// Number of results not yet yielded.
+    fn remaining(&self) -> usize {
+        self.values.len() - self.index
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.remaining()
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> PyResult<Option<Py<PyAny>>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
         if slf.index < slf.values.len() {
             let value = slf.values[slf.index].clone_ref(py);
             slf.index += 1;
-            Some(value)
+            Ok(Some(value))
         } else {
-            None
+            slf.guard = None;
+            Ok(None)
         }
     }
 }
@@ -94,30 +248,68 @@ impl PyTreeMapValues {
 /// Iterator for TreeMap items (key-value pairs)
 #[pyclass]
 pub struct PyTreeMapItems {
-    items: Vec<(String, Py<PyAny>)>,
+    items: Vec<(Py<PyAny>, Py<PyAny>)>,
     index: usize,
+    guard: Option<IterGuard>,
 }
 
 impl PyTreeMapItems {
-    pub fn new(items: Vec<(String, Py<PyAny>)>) -> Self {
-        Self { items, index: 0 }
+    pub fn new(items: Vec<(Py<PyAny>, Py<PyAny>)>, guard: IterGuard) -> Self {
+        Self {
+            items,
+            index: 0,
+            guard: Some(guard),
+        }
     }
 }
 
 #[pymethods]
 impl PyTreeMapItems {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for (key, value) in &self.items {
+            visit.call(key)?;
+            visit.call(value)?;
+        }
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.items.clear();
+        self.index = 0;
+        self.guard = None;
+    }
+
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> Option<(String, Py<PyAny>)> {
+    /// Number of results not yet yielded.
+    fn remaining(&self) -> usize {
+        self.items.len() - self.index
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.remaining()
+    }
+
+    fn __next__(
+        mut slf: PyRefMut<'_, Self>,
+        py: Python,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
         if slf.index < slf.items.len() {
             let (key, value) = &slf.items[slf.index];
-            let result = (key.clone(), value.clone_ref(py));
+            let result = (key.clone_ref(py), value.clone_ref(py));
             slf.index += 1;
-            Some(result)
+            Ok(Some(result))
         } else {
-            None
+            slf.guard = None;
+            Ok(None)
         }
     }
 }
@@ -125,61 +317,401 @@ impl PyTreeMapItems {
 /// Iterator for prefix queries - returns (key, value) tuples
 #[pyclass]
 pub struct PyPrefixIter {
-    items: Vec<(String, Py<PyAny>)>,
+    items: Vec<(Py<PyAny>, Py<PyAny>)>,
     index: usize,
+    guard: Option<IterGuard>,
 }
 
 impl PyPrefixIter {
-    pub fn new(items: Vec<(String, Py<PyAny>)>) -> Self {
-        Self { items, index: 0 }
+    pub fn new(items: Vec<(Py<PyAny>, Py<PyAny>)>, guard: IterGuard) -> Self {
+        Self {
+            items,
+            index: 0,
+            guard: Some(guard),
+        }
+    }
+
+    /// Iterate over a snapshot that no map invalidates.
+    pub fn snapshot(items: Vec<(Py<PyAny>, Py<PyAny>)>) -> Self {
+        Self {
+            items,
+            index: 0,
+            guard: None,
+        }
     }
 }
 
 #[pymethods]
 impl PyPrefixIter {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for (key, value) in &self.items {
+            visit.call(key)?;
+            visit.call(value)?;
+        }
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.items.clear();
+        self.index = 0;
+        self.guard = None;
+    }
+
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> Option<(String, Py<PyAny>)> {
+    /// Number of results not yet yielded.
+    fn remaining(&self) -> usize {
+        self.items.len() - self.index
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.remaining()
+    }
+
+    fn __next__(
+        mut slf: PyRefMut<'_, Self>,
+        py: Python,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
         if slf.index < slf.items.len() {
             let (key, value) = &slf.items[slf.index];
-            let result = (key.clone(), value.clone_ref(py));
+            let result = (key.clone_ref(py), value.clone_ref(py));
             slf.index += 1;
-            Some(result)
+            Ok(Some(result))
         } else {
-            None
+            slf.guard = None;
+            Ok(None)
         }
     }
 }
 
+/// A fuzzy search result: (key, value, distance)
+pub type FuzzyMatch = (Py<PyAny>, Py<PyAny>, Py<PyAny>);
+
+/// Distance of a fuzzy match as a Python number: a float when custom edit
+/// costs were given, otherwise the integer edit count.
+pub fn fuzzy_distance(py: Python, distance: f64, weighted: bool) -> PyResult<Py<PyAny>> {
+    if weighted {
+        Ok(distance.into_pyobject(py)?.into_any().unbind())
+    } else {
+        Ok((distance.round() as usize)
+            .into_pyobject(py)?
+            .into_any()
+            .unbind())
+    }
+}
+
 /// Iterator for fuzzy search - returns (key, value, distance) tuples
+///
+/// Matches are either found lazily, one `next()` at a time, or collected up
+/// front when the caller asked for ranked results.
 #[pyclass]
 pub struct PyFuzzyIter {
-    items: Vec<(String, Py<PyAny>, usize)>,
+    items: Vec<FuzzyMatch>,
     index: usize,
+    /// Search still to run against the guarded map, in lazy mode
+    cursor: Option<FuzzyCursor>,
+    /// Number of lazy matches still allowed by max_results
+    limit: Option<usize>,
+    /// Whether lazy distances are reported as floats
+    weighted: bool,
+    guard: Option<IterGuard>,
 }
 
 impl PyFuzzyIter {
-    pub fn new(items: Vec<(String, Py<PyAny>, usize)>) -> Self {
-        Self { items, index: 0 }
+    pub fn new(items: Vec<FuzzyMatch>, guard: IterGuard) -> Self {
+        Self {
+            items,
+            index: 0,
+            cursor: None,
+            limit: None,
+            weighted: false,
+            guard: Some(guard),
+        }
+    }
+
+    /// Stream matches from the guarded map as they are requested, stopping
+    /// after `limit` of them.
+    pub fn lazy(
+        cursor: FuzzyCursor,
+        guard: IterGuard,
+        limit: Option<usize>,
+        weighted: bool,
+    ) -> Self {
+        Self {
+            items: Vec::new(),
+            index: 0,
+            cursor: Some(cursor),
+            limit,
+            weighted,
+            guard: Some(guard),
+        }
+    }
+
+    /// Run the lazy search up to its next match.
+    fn advance(&mut self, py: Python) -> PyResult<Option<FuzzyMatch>> {
+        let (Some(cursor), Some(guard)) = (&mut self.cursor, &self.guard) else {
+            return Ok(None);
+        };
+        if let Some(limit) = &mut self.limit {
+            match limit.checked_sub(1) {
+                Some(left) => *limit = left,
+                None => return Ok(None),
+            }
+        }
+        let map = PyTreeMap::live(guard.map.bind(py))?;
+        let Some((key, distance)) = cursor.next_match(|bound| map.seek(bound)) else {
+            return Ok(None);
+        };
+        let value = match map.tree().get(key) {
+            Some(value) => map.value_out(py, value)?,
+            None => return Ok(None),
+        };
+        let distance = fuzzy_distance(py, distance, self.weighted)?;
+        Ok(Some((map.decode_key(py, key)?, value, distance)))
     }
 }
 
 #[pymethods]
 impl PyFuzzyIter {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for (key, value, _) in &self.items {
+            visit.call(key)?;
+            visit.call(value)?;
+        }
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.items.clear();
+        self.index = 0;
+        self.cursor = None;
+        self.guard = None;
+    }
+
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> Option<(String, Py<PyAny>, usize)> {
+    /// Number of results not yet yielded, or None while matches are still
+    /// being found lazily.
+    fn remaining(&self) -> Option<usize> {
+        match self.cursor {
+            Some(_) => None,
+            None => Some(self.items.len() - self.index),
+        }
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.remaining().unwrap_or(0)
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> PyResult<Option<FuzzyMatch>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
         if slf.index < slf.items.len() {
             let (key, value, distance) = &slf.items[slf.index];
-            let result = (key.clone(), value.clone_ref(py), *distance);
+            let result = (
+                key.clone_ref(py),
+                value.clone_ref(py),
+                distance.clone_ref(py),
+            );
             slf.index += 1;
-            Some(result)
+            return Ok(Some(result));
+        }
+        let found = slf.advance(py)?;
+        if found.is_none() {
+            slf.cursor = None;
+            slf.guard = None;
+        }
+        Ok(found)
+    }
+}
+
+/// Iterator for regex_iter - returns (key, value) tuples
+///
+/// Keys are matched lazily, one `next()` at a time, walking only the part of
+/// the tree under the pattern's literal prefix.
+#[pyclass]
+pub struct PyRegexIter {
+    pattern: KeyPattern,
+    /// Bound of the keys still to visit, and whether it is inclusive; None
+    /// once the scan is over
+    next: Option<(Box<[u8]>, bool)>,
+    guard: Option<IterGuard>,
+}
+
+impl PyRegexIter {
+    pub fn new(pattern: KeyPattern, guard: IterGuard) -> Self {
+        let start = pattern.prefix().into();
+        Self {
+            pattern,
+            next: Some((start, true)),
+            guard: Some(guard),
+        }
+    }
+
+    /// Scan forward to the next matching key.
+    fn advance(&mut self, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let Some(guard) = &self.guard else {
+            return Ok(None);
+        };
+        let map = PyTreeMap::live(guard.map.bind(py))?;
+        while let Some((bound, inclusive)) = self.next.take() {
+            let Some(key) = map.seek(if inclusive {
+                Included(&bound)
+            } else {
+                Excluded(&bound)
+            }) else {
+                break;
+            };
+            if !key.starts_with(self.pattern.prefix()) {
+                break;
+            }
+            self.next = Some((key.into(), false));
+            if !self.pattern.is_match(key) {
+                continue;
+            }
+            if let Some(value) = map.tree().get(key) {
+                return Ok(Some((map.decode_key(py, key)?, map.value_out(py, value)?)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[pymethods]
+impl PyRegexIter {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.next = None;
+        self.guard = None;
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(
+        mut slf: PyRefMut<'_, Self>,
+        py: Python,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
+        let found = slf.advance(py)?;
+        if found.is_none() {
+            slf.next = None;
+            slf.guard = None;
+        }
+        Ok(found)
+    }
+}
+
+/// A list of (key, value) pairs yielded by iter_chunks
+type Chunk = Vec<(Py<PyAny>, Py<PyAny>)>;
+
+/// Iterator for iter_chunks - returns lists of (key, value) tuples
+///
+/// Each chunk is collected when it is requested, by seeking past the last
+/// key of the previous chunk, so only one chunk is held at a time.
+#[pyclass]
+pub struct PyChunkIter {
+    /// Encoded prefix every key must start with (empty for all keys)
+    prefix: Box<[u8]>,
+    size: usize,
+    /// Bound of the keys still to visit, and whether it is inclusive; None
+    /// once the scan is over
+    next: Option<(Box<[u8]>, bool)>,
+    guard: Option<IterGuard>,
+}
+
+impl PyChunkIter {
+    pub fn new(prefix: Box<[u8]>, size: usize, guard: IterGuard) -> Self {
+        Self {
+            next: Some((prefix.clone(), true)),
+            prefix,
+            size,
+            guard: Some(guard),
+        }
+    }
+
+    /// Collect the next chunk, empty once the scan is over.
+    fn advance(&mut self, py: Python) -> PyResult<Chunk> {
+        let (Some(guard), Some((bound, inclusive))) = (&self.guard, self.next.take()) else {
+            return Ok(Vec::new());
+        };
+        let map = PyTreeMap::live(guard.map.bind(py))?;
+        let start = map.seek(if inclusive {
+            Included(&bound)
         } else {
-            None
+            Excluded(&bound)
+        });
+        let Some(start) = start else {
+            return Ok(Vec::new());
+        };
+        let mut chunk = Vec::with_capacity(self.size);
+        for (key, value) in map
+            .tree()
+            .range::<[u8], _>((Included(start), Unbounded))
+            .take_while(|(key, _)| key.starts_with(&self.prefix))
+            .take(self.size)
+        {
+            chunk.push((map.decode_key(py, key)?, map.value_out(py, value)?));
+            self.next = Some((key.clone(), false));
+        }
+        if chunk.len() < self.size {
+            self.next = None;
+        }
+        Ok(chunk)
+    }
+}
+
+#[pymethods]
+impl PyChunkIter {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        match &self.guard {
+            Some(guard) => guard.traverse(&visit),
+            None => Ok(()),
+        }
+    }
+
+    fn __clear__(&mut self) {
+        self.next = None;
+        self.guard = None;
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> PyResult<Option<Chunk>> {
+        if let Some(guard) = &slf.guard {
+            guard.check(py)?;
+        }
+        let chunk = slf.advance(py)?;
+        if chunk.is_empty() {
+            slf.next = None;
+            slf.guard = None;
+            return Ok(None);
         }
+        Ok(Some(chunk))
     }
 }
@@ -0,0 +1,344 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyInt, PyList, PyString};
+
+/// Nesting deeper than this is rejected rather than risking the stack.
+const MAX_DEPTH: usize = 512;
+
+/// A parsed JSON value, built without the GIL and converted afterwards.
+#[derive(Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// An integer too large for i64, kept as its digits
+    BigInt(String),
+    Float(f64),
+    /// UTF-8, except that lone surrogates from `\ud800`-style escapes are
+    /// encoded like any other code point
+    Str(Vec<u8>),
+    Array(Vec<Value>),
+    Object(Vec<(Vec<u8>, Value)>),
+}
+
+impl Value {
+    /// The value of a field of an object.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        let Value::Object(fields) = self else {
+            return None;
+        };
+        // The last of repeated fields wins, as in Python's json module
+        fields
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name.as_bytes())
+            .map(|(_, value)| value)
+    }
+
+    pub fn into_py(self, py: Python) -> PyResult<Py<PyAny>> {
+        Ok(match self {
+            Value::Null => py.None(),
+            Value::Bool(b) => b.into_pyobject(py)?.to_owned().into_any().unbind(),
+            Value::Int(i) => i.into_pyobject(py)?.into_any().unbind(),
+            Value::BigInt(digits) => py.get_type::<PyInt>().call1((digits,))?.unbind(),
+            Value::Float(f) => f.into_pyobject(py)?.into_any().unbind(),
+            Value::Str(s) => text(py, &s)?.unbind(),
+            Value::Array(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| item.into_py(py))
+                    .collect::<PyResult<Vec<_>>>()?;
+                PyList::new(py, items)?.into_any().unbind()
+            }
+            Value::Object(fields) => {
+                let dict = PyDict::new(py);
+                for (key, value) in fields {
+                    dict.set_item(text(py, &key)?, value.into_py(py)?)?;
+                }
+                dict.into_any().unbind()
+            }
+        })
+    }
+}
+
+/// A str from the contents of `Value::Str`.
+fn text<'py>(py: Python<'py>, bytes: &[u8]) -> PyResult<Bound<'py, PyAny>> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(PyString::new(py, text).into_any()),
+        Err(_) => PyBytes::new(py, bytes).call_method1("decode", ("utf-8", "surrogatepass")),
+    }
+}
+
+/// Parse one complete JSON document.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos < parser.bytes.len() {
+        return Err(parser.error("extra data"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at column {}", self.pos + 1)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &[u8]) -> Result<(), String> {
+        match self.bytes[self.pos..].starts_with(literal) {
+            true => {
+                self.pos += literal.len();
+                Ok(())
+            }
+            false => Err(self.error("invalid literal")),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            None => Err(self.error("expected a value")),
+            Some(b'n') => self.expect(b"null").map(|_| Value::Null),
+            Some(b't') => self.expect(b"true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect(b"false").map(|_| Value::Bool(false)),
+            // Extensions Python's json module accepts
+            Some(b'N') => self.expect(b"NaN").map(|_| Value::Float(f64::NAN)),
+            Some(b'I') => self
+                .expect(b"Infinity")
+                .map(|_| Value::Float(f64::INFINITY)),
+            Some(b'-') if self.bytes.get(self.pos + 1) == Some(&b'I') => self
+                .expect(b"-Infinity")
+                .map(|_| Value::Float(f64::NEG_INFINITY)),
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.bytes.get(self.pos) != Some(&b'"') {
+                        return Err(self.error("expected a property name"));
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.bytes.get(self.pos) != Some(&b':') {
+                        return Err(self.error("expected ':'"));
+                    }
+                    self.pos += 1;
+                    fields.push((key, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(fields));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let from = parser.pos;
+            while let Some(b'0'..=b'9') = parser.bytes.get(parser.pos) {
+                parser.pos += 1;
+            }
+            parser.pos > from
+        };
+        if self.bytes[self.pos] == b'-' {
+            self.pos += 1;
+        }
+        if self.bytes.get(self.pos) == Some(&b'0') {
+            self.pos += 1;
+        } else if !digits(self) {
+            return Err(self.error("invalid number"));
+        }
+        let mut float = false;
+        if self.bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            float = true;
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(self.pos) {
+            self.pos += 1;
+            float = true;
+            if let Some(b'+' | b'-') = self.bytes.get(self.pos) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        // Only ASCII digits and signs were consumed
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or_default();
+        if float {
+            return text
+                .parse()
+                .map(Value::Float)
+                .map_err(|_| self.error("invalid number"));
+        }
+        Ok(text
+            .parse()
+            .map_or_else(|_| Value::BigInt(text.to_string()), Value::Int))
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.bytes.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.bytes.get(self.pos) {
+                        Some(b'"') => '"' as u32,
+                        Some(b'\\') => '\\' as u32,
+                        Some(b'/') => '/' as u32,
+                        Some(b'b') => 0x8,
+                        Some(b'f') => 0xC,
+                        Some(b'n') => '\n' as u32,
+                        Some(b'r') => '\r' as u32,
+                        Some(b't') => '\t' as u32,
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.pos += 1;
+                    match char::from_u32(c) {
+                        Some(c) => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                        // A lone surrogate, in the three bytes of any other
+                        // code point in its range
+                        None => out.extend_from_slice(&[
+                            0xE0 | (c >> 12) as u8,
+                            0x80 | (c >> 6 & 0x3F) as u8,
+                            0x80 | (c & 0x3F) as u8,
+                        ]),
+                    }
+                }
+                Some(b) if *b < 0x20 => return Err(self.error("control character in string")),
+                Some(&b) => {
+                    out.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Decode `\uXXXX` (and a following low surrogate) to a code point,
+    /// leaving the position on the last hex digit. Unpaired surrogates are
+    /// kept, as Python's json module does.
+    fn unicode_escape(&mut self) -> Result<u32, String> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high)
+            || self.bytes.get(self.pos + 1..self.pos + 3) != Some(b"\\u")
+        {
+            return Ok(high);
+        }
+        let pos = self.pos;
+        self.pos += 2;
+        match self.hex4()? {
+            low @ 0xDC00..0xE000 => Ok(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)),
+            _ => {
+                self.pos = pos;
+                Ok(high)
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let hex = self
+            .bytes
+            .get(self.pos + 1..self.pos + 5)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(hex)
+    }
+}
+
+/// The line number, key and value of each record in a JSON Lines file, the
+/// value being one field or the whole record. Blank lines are skipped, and
+/// errors name the line they are on.
+pub fn read_records(
+    data: &[u8],
+    key_field: &str,
+    value_field: Option<&str>,
+) -> Result<Vec<(usize, Value, Value)>, String> {
+    let mut records = Vec::new();
+    for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+        let line_no = i + 1;
+        let line = std::str::from_utf8(line)
+            .map_err(|_| format!("line {line_no}: not valid UTF-8"))?
+            .trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = parse(line).map_err(|e| format!("line {line_no}: {e}"))?;
+        if !matches!(record, Value::Object(_)) {
+            return Err(format!("line {line_no}: record is not an object"));
+        }
+        let missing = |field: &str| format!("line {line_no}: missing field {field:?}");
+        let key = record.field(key_field).ok_or_else(|| missing(key_field))?;
+        let value = match value_field {
+            Some(field) => record.field(field).ok_or_else(|| missing(field))?.clone(),
+            None => record.clone(),
+        };
+        records.push((line_no, key.clone(), value));
+    }
+    Ok(records)
+}
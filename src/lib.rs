@@ -1,16 +1,52 @@
 use pyo3::prelude::*;
 
+mod arrays;
+mod codec;
+mod columnar;
+mod compression;
+mod concurrent;
+mod counter;
+mod cow;
+mod delimited;
+mod fuzzy;
+mod history;
+mod indexes;
 mod iterators;
+mod json;
+mod metrics;
+mod multimap;
+mod nodes;
+mod pattern;
+mod pickled;
+mod shared;
+mod snapshot;
+mod stored;
+mod transaction;
 mod treemap;
+mod trie;
+mod views;
 
-#[pymodule]
+#[pymodule(gil_used = false)]
 fn _blart(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<treemap::PyTreeMap>()?;
+    m.add_class::<concurrent::PyConcurrentTreeMap>()?;
+    m.add_class::<snapshot::PyTreeMapSnapshot>()?;
+    m.add_class::<transaction::PyTreeMapTransaction>()?;
+    m.add_class::<shared::PySharedTreeMap>()?;
+    m.add_class::<multimap::PyTreeMultiMap>()?;
+    m.add_class::<counter::PyTreeCounter>()?;
     m.add_class::<iterators::PyTreeMapIter>()?;
     m.add_class::<iterators::PyTreeMapKeys>()?;
     m.add_class::<iterators::PyTreeMapValues>()?;
     m.add_class::<iterators::PyTreeMapItems>()?;
     m.add_class::<iterators::PyPrefixIter>()?;
     m.add_class::<iterators::PyFuzzyIter>()?;
+    m.add_class::<iterators::PyRegexIter>()?;
+    m.add_class::<iterators::PyChunkIter>()?;
+    m.add_class::<trie::PyTrieChildren>()?;
+    m.add_class::<views::PyTreeMapKeysView>()?;
+    m.add_class::<views::PyTreeMapValuesView>()?;
+    m.add_class::<views::PyTreeMapItemsView>()?;
+    m.add_class::<views::PyTreeMapILoc>()?;
     Ok(())
 }
@@ -0,0 +1,75 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Operation counters for `TreeMap.metrics()`.
+///
+/// Lookups only borrow the tree, so the counters are atomics that can be
+/// bumped through a shared reference.
+#[derive(Default)]
+pub struct Metrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    removals: AtomicU64,
+    evictions: AtomicU64,
+    fuzzy_scans: AtomicU64,
+}
+
+fn bump(counter: &AtomicU64, by: usize) {
+    counter.fetch_add(by as u64, Ordering::Relaxed);
+}
+
+impl Metrics {
+    /// Record a point lookup and whether it found the key.
+    pub fn lookup(&self, hit: bool) {
+        bump(if hit { &self.hits } else { &self.misses }, 1);
+    }
+
+    /// Record values stored, for new keys and overwrites alike.
+    pub fn insert(&self, count: usize) {
+        bump(&self.inserts, count);
+    }
+
+    /// Record keys removed on request.
+    pub fn remove(&self, count: usize) {
+        bump(&self.removals, count);
+    }
+
+    /// Record keys the tree dropped by itself.
+    pub fn evict(&self, count: usize) {
+        bump(&self.evictions, count);
+    }
+
+    /// Record fuzzy searches run over the keys.
+    pub fn fuzzy_scan(&self, count: usize) {
+        bump(&self.fuzzy_scans, count);
+    }
+
+    pub fn reset(&self) {
+        for counter in [
+            &self.hits,
+            &self.misses,
+            &self.inserts,
+            &self.removals,
+            &self.evictions,
+            &self.fuzzy_scans,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let (hits, misses) = (load(&self.hits), load(&self.misses));
+        let dict = PyDict::new(py);
+        dict.set_item("lookups", hits + misses)?;
+        dict.set_item("hits", hits)?;
+        dict.set_item("misses", misses)?;
+        dict.set_item("inserts", load(&self.inserts))?;
+        dict.set_item("removals", load(&self.removals))?;
+        dict.set_item("evictions", load(&self.evictions))?;
+        dict.set_item("fuzzy_scans", load(&self.fuzzy_scans))?;
+        Ok(dict)
+    }
+}
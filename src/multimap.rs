@@ -0,0 +1,363 @@
+use crate::codec::KeyCodec;
+use crate::iterators::{PyPrefixIter, PyTreeMapIter};
+use crate::treemap::{generic_alias, key_error};
+use blart::TreeMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyType};
+use pyo3::{PyTraverseError, PyVisit};
+
+/// A sorted map holding any number of values per key.
+///
+/// Inserting a key that is already stored appends to its values instead of
+/// replacing them, which suits inverted indexes (term -> document IDs). The
+/// values of a key keep their insertion order.
+///
+/// Examples:
+///     >>> index = TreeMultiMap()
+///     >>> index.insert("apple", 1)
+///     >>> index.insert("apple", 7)
+///     >>> index.get_all("apple")
+///     [1, 7]
+///     >>> index.items()
+///     [('apple', 1), ('apple', 7)]
+#[pyclass(name = "PyTreeMultiMap", module = "blart._blart", weakref)]
+pub struct PyTreeMultiMap {
+    inner: TreeMap<Box<[u8]>, Vec<Py<PyAny>>>,
+    codec: KeyCodec,
+    /// Number of values across all keys
+    len: usize,
+}
+
+impl PyTreeMultiMap {
+    /// Append values under an encoded key.
+    fn append(&mut self, key: Box<[u8]>, values: Vec<Py<PyAny>>) {
+        if values.is_empty() {
+            return;
+        }
+        self.len += values.len();
+        if let Some(slot) = self.inner.get_mut(&key) {
+            slot.extend(values);
+            return;
+        }
+        // Keys are terminated, so no stored key is evicted for prefixing
+        // this one
+        self.inner.force_insert(key, values);
+    }
+
+    /// (key, value) pairs for every value of the given entries, in order.
+    fn flatten<'a>(
+        &self,
+        py: Python,
+        entries: impl Iterator<Item = (&'a Box<[u8]>, &'a Vec<Py<PyAny>>)>,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let mut items = Vec::new();
+        for (key, values) in entries {
+            let key = self.codec.decode_terminated(py, key)?;
+            for value in values {
+                items.push((key.clone_ref(py), value.clone_ref(py)));
+            }
+        }
+        Ok(items)
+    }
+}
+
+#[pymethods]
+impl PyTreeMultiMap {
+    /// Create a new TreeMultiMap.
+    ///
+    /// Args:
+    ///     data: Optional dict or iterable of (key, value) pairs; repeated
+    ///         keys collect all their values
+    ///     key_type: How keys are encoded, as for TreeMap
+    ///     separator: Path separator for key_type="path"
+    ///     errors: Decode policy for str keys that are not valid UTF-8
+    ///
+    /// Examples:
+    ///     >>> index = TreeMultiMap([("a", 1), ("b", 2), ("a", 3)])
+    ///     >>> index.get_all("a")
+    ///     [1, 3]
+    #[new]
+    #[pyo3(signature = (data=None, *, key_type=None, separator=None, errors=None))]
+    fn new(
+        data: Option<&Bound<'_, PyAny>>,
+        key_type: Option<&Bound<'_, PyAny>>,
+        separator: Option<&str>,
+        errors: Option<&str>,
+    ) -> PyResult<Self> {
+        let codec = KeyCodec::from_key_type(key_type)?
+            .with_separator(separator)?
+            .with_errors(errors)?;
+        let mut map = Self {
+            inner: TreeMap::new(),
+            codec,
+            len: 0,
+        };
+        if let Some(data) = data {
+            if let Ok(dict) = data.cast::<PyDict>() {
+                for (key, value) in dict.iter() {
+                    map.insert(&key, value.unbind())?;
+                }
+            } else {
+                for item in data.try_iter()? {
+                    let (key, value) = item?.extract::<(Bound<'_, PyAny>, Py<PyAny>)>()?;
+                    map.insert(&key, value)?;
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Parameterize the class for type annotations, as in
+    /// `TreeMultiMap[str, int]`.
+    ///
+    /// Returns:
+    ///     A types.GenericAlias; the parameters are not checked at runtime
+    #[classmethod]
+    fn __class_getitem__<'py>(
+        cls: &Bound<'py, PyType>,
+        params: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        generic_alias(cls, params)
+    }
+
+    /// Add a value under a key, after any values already stored there.
+    ///
+    /// Args:
+    ///     key: Key to add to
+    ///     value: Python object to store
+    fn insert(&mut self, key: &Bound<'_, PyAny>, value: Py<PyAny>) -> PyResult<()> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        self.append(key_bytes, vec![value]);
+        Ok(())
+    }
+
+    /// Add several values under a key, after any values already stored there.
+    ///
+    /// Args:
+    ///     key: Key to add to
+    ///     values: Iterable of Python objects to store
+    fn extend(&mut self, key: &Bound<'_, PyAny>, values: &Bound<'_, PyAny>) -> PyResult<()> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        let values = values
+            .try_iter()?
+            .map(|value| Ok(value?.unbind()))
+            .collect::<PyResult<Vec<_>>>()?;
+        self.append(key_bytes, values);
+        Ok(())
+    }
+
+    /// Return all values stored under a key, in insertion order.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///
+    /// Returns:
+    ///     List of values, empty if the key is missing
+    fn get_all(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<Vec<Py<PyAny>>> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        Ok(self
+            .inner
+            .get(&key_bytes)
+            .map(|values| values.iter().map(|v| v.clone_ref(py)).collect())
+            .unwrap_or_default())
+    }
+
+    /// Get the first value stored under a key, with optional default.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///     default: Value to return if key not found (defaults to None)
+    #[pyo3(signature = (key, default=None))]
+    fn get(
+        &self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        match self.inner.get(&key_bytes).and_then(|values| values.first()) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    /// Remove a key and return all of its values.
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist
+    fn remove(&mut self, key: &Bound<'_, PyAny>) -> PyResult<Vec<Py<PyAny>>> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        match self.inner.remove(&key_bytes) {
+            Some(values) => {
+                self.len -= values.len();
+                Ok(values)
+            }
+            None => Err(key_error(key)),
+        }
+    }
+
+    /// Remove the first occurrence of a value under a key. The key is
+    /// removed along with its last value.
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist
+    ///     ValueError: If the value is not stored under the key
+    fn remove_value(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        let Some(values) = self.inner.get_mut(&key_bytes) else {
+            return Err(key_error(key));
+        };
+        let mut position = None;
+        for (i, stored) in values.iter().enumerate() {
+            if stored.is(value) || stored.bind(py).eq(value)? {
+                position = Some(i);
+                break;
+            }
+        }
+        let Some(position) = position else {
+            return Err(PyValueError::new_err(format!(
+                "{} is not stored under {}",
+                value.repr()?,
+                key.repr()?
+            )));
+        };
+        let removed = values.remove(position);
+        if values.is_empty() {
+            self.inner.remove(&key_bytes);
+        }
+        self.len -= 1;
+        drop(removed);
+        Ok(())
+    }
+
+    /// Remove all keys and values.
+    fn clear(&mut self) {
+        self.inner.clear();
+        self.len = 0;
+    }
+
+    /// Return a list of the distinct keys, in key order.
+    fn keys(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .keys()
+            .map(|k| self.codec.decode_terminated(py, k))
+            .collect()
+    }
+
+    /// Return a list of every value, in key order.
+    fn values(&self, py: Python) -> Vec<Py<PyAny>> {
+        self.inner
+            .values()
+            .flatten()
+            .map(|v| v.clone_ref(py))
+            .collect()
+    }
+
+    /// Return a list of the stored items, in key order.
+    ///
+    /// Args:
+    ///     flatten: List a (key, value) pair for every value, rather than
+    ///         one (key, values) pair per key (defaults to True)
+    ///
+    /// Examples:
+    ///     >>> index = TreeMultiMap([("a", 1), ("a", 2)])
+    ///     >>> index.items()
+    ///     [('a', 1), ('a', 2)]
+    ///     >>> index.items(flatten=False)
+    ///     [('a', [1, 2])]
+    #[pyo3(signature = (flatten=true))]
+    fn items(&self, py: Python, flatten: bool) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        if flatten {
+            return self.flatten(py, self.inner.iter());
+        }
+        self.inner
+            .iter()
+            .map(|(k, values)| {
+                let values = values.iter().map(|v| v.clone_ref(py)).collect::<Vec<_>>();
+                Ok((
+                    self.codec.decode_terminated(py, k)?,
+                    values.into_pyobject(py)?.unbind(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Return an iterator over a (key, value) pair for every value whose key
+    /// starts with a prefix.
+    fn prefix_iter(&self, py: Python, prefix: &Bound<'_, PyAny>) -> PyResult<PyPrefixIter> {
+        let prefix_bytes = self.codec.encode_prefix_terminated(prefix)?;
+        let entries = self
+            .inner
+            .prefix(&prefix_bytes)
+            .filter(|(k, _)| k.starts_with(&prefix_bytes));
+        Ok(PyPrefixIter::snapshot(self.flatten(py, entries)?))
+    }
+
+    /// How keys are stored: a key_type name such as "str" or "int", or the codec object.
+    #[getter]
+    fn key_type(&self, py: Python) -> Py<PyAny> {
+        self.codec.key_type(py)
+    }
+
+    /// Return all values stored under a key.
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist
+    fn __getitem__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<Vec<Py<PyAny>>> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        match self.inner.get(&key_bytes) {
+            Some(values) => Ok(values.iter().map(|v| v.clone_ref(py)).collect()),
+            None => Err(key_error(key)),
+        }
+    }
+
+    /// Add a value under a key, like `insert`.
+    fn __setitem__(&mut self, key: &Bound<'_, PyAny>, value: Py<PyAny>) -> PyResult<()> {
+        self.insert(key, value)
+    }
+
+    fn __delitem__(&mut self, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.remove(key)?;
+        Ok(())
+    }
+
+    fn __contains__(&self, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let key_bytes = self.codec.encode_terminated(key)?;
+        Ok(self.inner.contains_key(&key_bytes))
+    }
+
+    /// Number of values across all keys.
+    fn __len__(&self) -> usize {
+        self.len
+    }
+
+    /// Iterate over a snapshot of the distinct keys, in key order.
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapIter> {
+        Ok(PyTreeMapIter::snapshot(self.keys(py)?))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "TreeMultiMap(keys={}, values={})",
+            self.inner.len(),
+            self.len
+        )
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for value in self.inner.values().flatten() {
+            visit.call(value)?;
+        }
+        self.codec.traverse(&visit)
+    }
+
+    fn __clear__(&mut self) {
+        self.clear();
+    }
+}
@@ -0,0 +1,113 @@
+use blart::raw::{InnerNode, InnerNode16, InnerNode256, InnerNode4, InnerNode48, LeafNode};
+use blart::visitor::{Visitable, Visitor};
+use blart::TreeMap;
+
+/// A node of blart's radix tree, copied out for rendering.
+pub enum Shape {
+    Inner {
+        /// Node type: "Node4", "Node16", "Node48" or "Node256"
+        kind: &'static str,
+        /// Length of the compressed prefix the keys below share
+        prefix_len: usize,
+        /// Each child with the key byte it is reached by
+        children: Vec<(u8, Shape)>,
+        /// Number of leaves below
+        keys: usize,
+    },
+    Leaf(Box<[u8]>),
+}
+
+impl Shape {
+    /// Copy the node graph of `tree`, or None when it is empty.
+    pub fn of<V>(tree: &TreeMap<Box<[u8]>, V>) -> Option<Shape> {
+        // SAFETY: `into_raw` only reads the root pointer out of this bitwise
+        // copy, which is never dropped, so the nodes stay owned by `tree`.
+        // They are only read while `tree` is borrowed.
+        let root = TreeMap::into_raw(unsafe { std::ptr::read(tree) })?;
+        Some(root.visit_with(&mut ShapeCollector))
+    }
+
+    /// Number of keys below this node.
+    pub fn keys(&self) -> usize {
+        match self {
+            Shape::Inner { keys, .. } => *keys,
+            Shape::Leaf(_) => 1,
+        }
+    }
+
+    /// The smallest key below this node, which spells out its prefix.
+    pub fn first_key(&self) -> &[u8] {
+        match self {
+            Shape::Inner { children, .. } => children[0].1.first_key(),
+            Shape::Leaf(key) => key,
+        }
+    }
+}
+
+/// Visitor copying each node into a `Shape`.
+struct ShapeCollector;
+
+impl ShapeCollector {
+    fn inner<N, V>(&mut self, kind: &'static str, node: &N) -> Shape
+    where
+        N: InnerNode<{ blart::map::DEFAULT_PREFIX_LEN }, Key = Box<[u8]>, Value = V>,
+    {
+        let children: Vec<(u8, Shape)> = node
+            .iter()
+            .map(|(byte, child)| (byte, child.visit_with(self)))
+            .collect();
+        Shape::Inner {
+            kind,
+            prefix_len: node.header().prefix_len(),
+            keys: children.iter().map(|(_, child)| child.keys()).sum(),
+            children,
+        }
+    }
+}
+
+impl<V> Visitor<Box<[u8]>, V, { blart::map::DEFAULT_PREFIX_LEN }> for ShapeCollector {
+    type Output = Shape;
+
+    fn default_output(&self) -> Shape {
+        unreachable!("every node type is visited explicitly")
+    }
+
+    fn combine_output(&self, _: Shape, _: Shape) -> Shape {
+        unreachable!("every node type is visited explicitly")
+    }
+
+    fn visit_node4(
+        &mut self,
+        node: &InnerNode4<Box<[u8]>, V, { blart::map::DEFAULT_PREFIX_LEN }>,
+    ) -> Shape {
+        self.inner("Node4", node)
+    }
+
+    fn visit_node16(
+        &mut self,
+        node: &InnerNode16<Box<[u8]>, V, { blart::map::DEFAULT_PREFIX_LEN }>,
+    ) -> Shape {
+        self.inner("Node16", node)
+    }
+
+    fn visit_node48(
+        &mut self,
+        node: &InnerNode48<Box<[u8]>, V, { blart::map::DEFAULT_PREFIX_LEN }>,
+    ) -> Shape {
+        self.inner("Node48", node)
+    }
+
+    fn visit_node256(
+        &mut self,
+        node: &InnerNode256<Box<[u8]>, V, { blart::map::DEFAULT_PREFIX_LEN }>,
+    ) -> Shape {
+        self.inner("Node256", node)
+    }
+
+    fn visit_leaf(
+        &mut self,
+        leaf: &LeafNode<Box<[u8]>, V, { blart::map::DEFAULT_PREFIX_LEN }>,
+    ) -> Shape {
+        Shape::Leaf(leaf.key_ref().clone())
+    }
+}
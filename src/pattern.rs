@@ -0,0 +1,47 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::PyResult;
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+use regex_syntax::ParserBuilder;
+
+/// A regular expression matched against whole stored keys.
+pub struct KeyPattern {
+    regex: Regex,
+    /// Literal every matching key starts with, so only that part of the
+    /// tree needs to be scanned
+    prefix: Box<[u8]>,
+}
+
+impl KeyPattern {
+    /// Compile `pattern` so that it must match the entire key, like
+    /// Python's `re.fullmatch`.
+    ///
+    /// With `unicode` off, `.` and classes match single bytes, for bytes
+    /// keys that need not be valid UTF-8.
+    pub fn new(pattern: &str, unicode: bool) -> PyResult<Self> {
+        let invalid = |err: &dyn std::fmt::Display| {
+            PyValueError::new_err(format!("invalid regular expression: {err}"))
+        };
+        let hir = ParserBuilder::new()
+            .unicode(unicode)
+            .utf8(unicode)
+            .build()
+            .parse(pattern)
+            .map_err(|err| invalid(&err))?;
+        let regex = RegexBuilder::new(&format!(r"\A(?:{pattern})\z"))
+            .unicode(unicode)
+            .build()
+            .map_err(|err| invalid(&err))?;
+        let literals = Extractor::new().kind(ExtractKind::Prefix).extract(&hir);
+        let prefix = literals.longest_common_prefix().unwrap_or_default().into();
+        Ok(Self { regex, prefix })
+    }
+
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    pub fn is_match(&self, key: &[u8]) -> bool {
+        self.regex.is_match(key)
+    }
+}
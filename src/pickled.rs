@@ -0,0 +1,128 @@
+use lru::LruCache;
+use pyo3::prelude::*;
+use pyo3::sync::PyOnceLock;
+use pyo3::types::PyBytes;
+use pyo3::{PyTraverseError, PyVisit};
+use std::sync::{Mutex, PoisonError};
+
+/// `pickle.dumps`, used to store values.
+pub fn dumps<'py>(py: Python<'py>, value: &Py<PyAny>) -> PyResult<Bound<'py, PyBytes>> {
+    static DUMPS: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+    Ok(DUMPS
+        .import(py, "pickle", "dumps")?
+        .call1((value, -1))?
+        .cast_into::<PyBytes>()?)
+}
+
+/// `pickle.loads`, used to read values back.
+pub fn loads(py: Python, data: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+    static LOADS: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+    Ok(LOADS
+        .import(py, "pickle", "loads")?
+        .call1((data,))?
+        .unbind())
+}
+
+/// A recently unpickled value, with the bytes it came from.
+struct Cached {
+    /// Held so the bytes object, whose address is the cache key, can't be
+    /// freed and its address reused while the entry exists
+    stored: Py<PyAny>,
+    value: Py<PyAny>,
+}
+
+/// Values of a `store_pickled` tree are kept as pickled bytes; this unpickles
+/// them on access and remembers the most recently used results.
+///
+/// Entries are keyed by the identity of the stored bytes object, so
+/// overwriting a key never returns the old value.
+pub struct PickleCache {
+    capacity: usize,
+    /// Cached values by the address of their stored bytes, in order of use
+    lru: Mutex<LruCache<usize, Cached>>,
+}
+
+impl PickleCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lru: Mutex::new(LruCache::unbounded()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Pickle a value into the form it is stored in.
+    pub fn dump(py: Python, value: &Py<PyAny>) -> PyResult<Py<PyAny>> {
+        Ok(dumps(py, value)?.into_any().unbind())
+    }
+
+    /// Unpickle a stored value, from the cache when it was used recently.
+    /// `pickle` produces the pickled bytes on a miss, for values that are
+    /// stored compressed.
+    pub fn load(
+        &self,
+        py: Python,
+        stored: &Py<PyAny>,
+        pickle: impl FnOnce() -> PyResult<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let id = stored.as_ptr() as usize;
+        if let Some(cached) = self.lock().get(&id) {
+            return Ok(cached.value.clone_ref(py));
+        }
+        // Unpickling runs arbitrary Python code, so it happens unlocked
+        let value = loads(py, pickle()?.bind(py))?;
+        if self.capacity == 0 {
+            return Ok(value);
+        }
+        let evicted = {
+            let mut lru = self.lock();
+            let cached = Cached {
+                stored: stored.clone_ref(py),
+                value: value.clone_ref(py),
+            };
+            let replaced = lru.push(id, cached);
+            let oldest = (lru.len() > self.capacity).then(|| lru.pop_lru()).flatten();
+            (replaced, oldest)
+        };
+        // Released after unlocking, as finalizers may touch the tree
+        drop(evicted);
+        Ok(value)
+    }
+
+    /// Bytes held by the cache, not counting the cached values.
+    pub fn memory_usage(&self) -> usize {
+        // Each entry is a node holding the key, the value and two links,
+        // plus a key and a pointer in the index
+        let entry = std::mem::size_of::<(usize, Cached)>() + 4 * std::mem::size_of::<usize>();
+        self.lock().len() * entry
+    }
+
+    /// Every cached unpickled value.
+    pub fn values(&self, py: Python) -> Vec<Py<PyAny>> {
+        self.lock()
+            .iter()
+            .map(|(_, cached)| cached.value.clone_ref(py))
+            .collect()
+    }
+
+    /// Forget every cached value.
+    pub fn clear(&self) {
+        let entries = std::mem::replace(&mut *self.lock(), LruCache::unbounded());
+        drop(entries);
+    }
+
+    pub fn traverse(&self, visit: &PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for (_, cached) in self.lock().iter() {
+            visit.call(&cached.stored)?;
+            visit.call(&cached.value)?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<usize, Cached>> {
+        self.lru.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
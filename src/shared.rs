@@ -0,0 +1,438 @@
+use crate::iterators::{PyPrefixIter, PyTreeMapIter};
+use crate::pickled::{self, dumps};
+use crate::treemap::{key_error, PyTreeMap};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::sync::PyOnceLock;
+use pyo3::types::{PyBytes, PyDict, PySlice, PyString, PyTuple};
+use std::cmp::Ordering;
+
+/// Start of every shared block, so attaching to unrelated memory fails early.
+const MAGIC: &[u8; 8] = b"BLARTSH1";
+/// Magic, entry count and settings length.
+const HEADER_LEN: usize = 24;
+/// Key offset, key length, original key length, value offset, value length.
+const ENTRY_LEN: usize = 40;
+
+/// `pickle.loads`, used to read values back.
+fn loads(py: Python, data: &[u8]) -> PyResult<Py<PyAny>> {
+    pickled::loads(py, PyBytes::new(py, data).as_any())
+}
+
+/// `multiprocessing.shared_memory.SharedMemory`.
+fn shared_memory_class(py: Python<'_>) -> PyResult<&Bound<'_, PyAny>> {
+    static CLASS: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+    CLASS.import(py, "multiprocessing.shared_memory", "SharedMemory")
+}
+
+fn corrupt() -> PyErr {
+    PyValueError::new_err("shared memory block does not hold a valid TreeMap")
+}
+
+fn read_u64(data: &[u8], at: usize) -> PyResult<usize> {
+    let bytes = data.get(at..at + 8).ok_or_else(corrupt)?;
+    let value = u64::from_le_bytes(bytes.try_into().map_err(|_| corrupt())?);
+    usize::try_from(value).map_err(|_| corrupt())
+}
+
+fn slice_at(data: &[u8], offset: usize, len: usize) -> PyResult<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(corrupt)
+}
+
+/// One stored entry, borrowed from the shared block.
+struct Entry<'a> {
+    key: &'a [u8],
+    /// Originally inserted spelling, for case-insensitive trees
+    original: Option<&'a [u8]>,
+    /// Pickled value
+    value: &'a [u8],
+}
+
+/// Serialize a tree into the shared block layout.
+///
+/// The layout is the header, the pickled constructor settings, a table of
+/// fixed-size entries in key order, and finally the key and value bytes.
+fn encode(py: Python, map: &PyTreeMap) -> PyResult<Vec<u8>> {
+    let settings = dumps(py, &map.settings(py)?.into_any().unbind())?;
+    let settings = settings.as_bytes();
+    let count = map.entry_count();
+    let table_at = HEADER_LEN + settings.len();
+    let mut table = Vec::with_capacity(count * ENTRY_LEN);
+    let mut heap = Vec::new();
+    let heap_at = table_at + count * ENTRY_LEN;
+    for (key, value) in map.tree().iter() {
+        let key_at = heap_at + heap.len();
+        heap.extend_from_slice(key);
+        // Only record spellings that differ from what the codec would decode
+        let original = map
+            .original_key(key)
+            .map(str::as_bytes)
+            .filter(|original| *original != &**key)
+            .unwrap_or_default();
+        heap.extend_from_slice(original);
+        let pickled = map.pickled_value(py, value)?;
+        let value_at = heap_at + heap.len();
+        heap.extend_from_slice(pickled.as_bytes());
+        for field in [
+            key_at,
+            key.len(),
+            original.len(),
+            value_at,
+            pickled.as_bytes().len(),
+        ] {
+            table.extend_from_slice(&(field as u64).to_le_bytes());
+        }
+    }
+    let mut data = Vec::with_capacity(heap_at + heap.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&(count as u64).to_le_bytes());
+    data.extend_from_slice(&(settings.len() as u64).to_le_bytes());
+    data.extend_from_slice(settings);
+    data.extend_from_slice(&table);
+    data.extend_from_slice(&heap);
+    Ok(data)
+}
+
+/// Copy a tree into a new shared memory block.
+pub fn share(py: Python, map: &PyTreeMap, name: Option<&str>) -> PyResult<PySharedTreeMap> {
+    let data = encode(py, map)?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("name", name)?;
+    kwargs.set_item("create", true)?;
+    kwargs.set_item("size", data.len())?;
+    let shm = shared_memory_class(py)?.call((), Some(&kwargs))?;
+    // The block can be larger than requested (it is rounded to whole pages
+    // on some platforms), so only the leading bytes are written
+    let written = PySlice::new(py, 0, data.len() as isize, 1);
+    shm.getattr("buf")?
+        .set_item(written, PyBytes::new(py, &data))?;
+    PySharedTreeMap::open(py, shm, true)
+}
+
+/// Read-only TreeMap stored in shared memory (returned by .share() and
+/// TreeMap.attach()).
+///
+/// Entries live in a `multiprocessing.shared_memory` block, so any number of
+/// processes can query one copy of the index. Keys are searched in place;
+/// values are stored pickled and unpickled on every access, so each lookup
+/// returns a fresh copy of the value.
+///
+/// The process that called share() owns the block: it should call unlink()
+/// (or use the map as a context manager) once every process is done with it.
+/// Pickling a SharedTreeMap attaches to the same block on the other side, so
+/// it can be passed straight to multiprocessing workers.
+///
+/// Examples:
+///     >>> shared = TreeMap({"apple": 1, "apricot": 2}).share()
+///     >>> worker_view = TreeMap.attach(shared.name)
+///     >>> worker_view["apple"]
+///     1
+///     >>> worker_view.close()
+///     >>> shared.unlink()
+#[pyclass(name = "PySharedTreeMap")]
+pub struct PySharedTreeMap {
+    /// Exported view of the block; None once closed. Declared before `shm`
+    /// so it is released first, letting the block close when dropped.
+    buffer: Option<PyBuffer<u8>>,
+    shm: Py<PyAny>,
+    /// Empty tree carrying the key settings, used to encode and decode keys
+    settings: Py<PyTreeMap>,
+    count: usize,
+    table_at: usize,
+    owner: bool,
+}
+
+impl PySharedTreeMap {
+    /// Attach to an existing shared memory block by name.
+    pub fn attach(py: Python, name: &str) -> PyResult<Self> {
+        let class = shared_memory_class(py)?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("track", false)?;
+        // Python 3.13 added track=False; older versions register every
+        // attachment with the resource tracker that the owner also uses
+        let shm = match class.call((name,), Some(&kwargs)) {
+            Ok(shm) => shm,
+            Err(err) if err.is_instance_of::<PyTypeError>(py) => class.call1((name,))?,
+            Err(err) => return Err(err),
+        };
+        Self::open(py, shm, false)
+    }
+
+    fn open(py: Python, shm: Bound<'_, PyAny>, owner: bool) -> PyResult<Self> {
+        let buffer = PyBuffer::<u8>::get(&shm.getattr("buf")?)?;
+        // SAFETY: the buffer stays exported until it is dropped in close()
+        let data = unsafe {
+            std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes())
+        };
+        if !data.starts_with(MAGIC) {
+            return Err(corrupt());
+        }
+        let count = read_u64(data, 8)?;
+        let settings_len = read_u64(data, 16)?;
+        let settings = loads(py, slice_at(data, HEADER_LEN, settings_len)?)?;
+        let settings = settings.bind(py).cast::<PyDict>()?;
+        let settings = py
+            .get_type::<PyTreeMap>()
+            .call((), Some(settings))?
+            .cast_into::<PyTreeMap>()?
+            .unbind();
+        let table_at = HEADER_LEN + settings_len;
+        let table_len = count.checked_mul(ENTRY_LEN).ok_or_else(corrupt)?;
+        slice_at(data, table_at, table_len)?;
+        Ok(Self {
+            buffer: Some(buffer),
+            shm: shm.unbind(),
+            settings,
+            count,
+            table_at,
+            owner,
+        })
+    }
+
+    /// The shared block, or ValueError once closed.
+    fn data(&self) -> PyResult<&[u8]> {
+        let buffer = self
+            .buffer
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("SharedTreeMap is closed"))?;
+        // SAFETY: the buffer is exported for as long as self.buffer holds it
+        Ok(
+            unsafe {
+                std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes())
+            },
+        )
+    }
+
+    fn entry<'a>(&self, data: &'a [u8], index: usize) -> PyResult<Entry<'a>> {
+        let at = self.table_at + index * ENTRY_LEN;
+        let key_at = read_u64(data, at)?;
+        let key_len = read_u64(data, at + 8)?;
+        let original_len = read_u64(data, at + 16)?;
+        let key = slice_at(data, key_at, key_len)?;
+        let original = match original_len {
+            0 => None,
+            len => Some(slice_at(data, key_at + key_len, len)?),
+        };
+        let value = slice_at(data, read_u64(data, at + 24)?, read_u64(data, at + 32)?)?;
+        Ok(Entry {
+            key,
+            original,
+            value,
+        })
+    }
+
+    /// Index of the first entry whose key is not less than `key`.
+    fn lower_bound(&self, data: &[u8], key: &[u8]) -> PyResult<usize> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.entry(data, mid)?.key.cmp(key) {
+                Ordering::Less => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        Ok(low)
+    }
+
+    /// The pickled value stored under a key, if any.
+    fn find<'a>(
+        &self,
+        py: Python,
+        data: &'a [u8],
+        key: &Bound<'_, PyAny>,
+    ) -> PyResult<Option<&'a [u8]>> {
+        let key_bytes = self.settings.borrow(py).encode_key(key)?;
+        let index = self.lower_bound(data, &key_bytes)?;
+        if index < self.count {
+            let entry = self.entry(data, index)?;
+            if entry.key == &*key_bytes {
+                return Ok(Some(entry.value));
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_key(&self, py: Python, entry: &Entry) -> PyResult<Py<PyAny>> {
+        match entry.original {
+            Some(original) => {
+                let original = std::str::from_utf8(original).map_err(|_| corrupt())?;
+                Ok(PyString::new(py, original).into_any().unbind())
+            }
+            None => self.settings.borrow(py).decode_key(py, entry.key),
+        }
+    }
+
+    fn decode_item(&self, py: Python, entry: &Entry) -> PyResult<(Py<PyAny>, Py<PyAny>)> {
+        Ok((self.decode_key(py, entry)?, loads(py, entry.value)?))
+    }
+
+    fn decode_range(
+        &self,
+        py: Python,
+        range: std::ops::Range<usize>,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let data = self.data()?;
+        range
+            .map(|index| self.decode_item(py, &self.entry(data, index)?))
+            .collect()
+    }
+}
+
+#[pymethods]
+impl PySharedTreeMap {
+    /// Get a value by key, with optional default.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///     default: Value to return if key not found (defaults to None)
+    ///
+    /// Raises:
+    ///     ValueError: If the map has been closed
+    #[pyo3(signature = (key, default=None))]
+    fn get(
+        &self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        match self.find(py, self.data()?, key)? {
+            Some(value) => loads(py, value),
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    /// Return an iterator over the (key, value) pairs whose key starts with
+    /// a prefix.
+    ///
+    /// The matching range is located by binary search over the shared keys.
+    fn prefix_iter(&self, py: Python, prefix: &Bound<'_, PyAny>) -> PyResult<PyPrefixIter> {
+        let prefix_bytes = self.settings.borrow(py).encode_prefix(prefix)?;
+        let data = self.data()?;
+        let start = self.lower_bound(data, &prefix_bytes)?;
+        let mut end = start;
+        while end < self.count && self.entry(data, end)?.key.starts_with(&prefix_bytes) {
+            end += 1;
+        }
+        Ok(PyPrefixIter::snapshot(self.decode_range(py, start..end)?))
+    }
+
+    /// Get the first (lexicographically smallest) key-value pair, or None if empty.
+    fn first(&self, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        Ok(self.decode_range(py, 0..self.count.min(1))?.pop())
+    }
+
+    /// Get the last (lexicographically largest) key-value pair, or None if empty.
+    fn last(&self, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        Ok(self
+            .decode_range(py, self.count.saturating_sub(1)..self.count)?
+            .pop())
+    }
+
+    /// Return a list of the keys, in key order.
+    fn keys(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        let data = self.data()?;
+        (0..self.count)
+            .map(|index| self.decode_key(py, &self.entry(data, index)?))
+            .collect()
+    }
+
+    /// Return a list of the values, in key order.
+    fn values(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        let data = self.data()?;
+        (0..self.count)
+            .map(|index| loads(py, self.entry(data, index)?.value))
+            .collect()
+    }
+
+    /// Return a list of the (key, value) pairs, in key order.
+    fn items(&self, py: Python) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        self.decode_range(py, 0..self.count)
+    }
+
+    /// Name of the shared memory block, for TreeMap.attach().
+    #[getter]
+    fn name(&self, py: Python) -> PyResult<Py<PyAny>> {
+        Ok(self.shm.bind(py).getattr("name")?.unbind())
+    }
+
+    /// How keys are stored: a key_type name such as "str" or "int".
+    #[getter]
+    fn key_type(&self, py: Python) -> PyResult<Py<PyAny>> {
+        Ok(self.settings.bind(py).getattr("key_type")?.unbind())
+    }
+
+    /// Detach from the shared memory block. The block itself is kept.
+    fn close(&mut self, py: Python) -> PyResult<()> {
+        // The exported buffer has to go before the block can be closed
+        self.buffer = None;
+        self.shm.bind(py).call_method0("close")?;
+        Ok(())
+    }
+
+    /// Free the shared memory block once every process has closed it.
+    fn unlink(&self, py: Python) -> PyResult<()> {
+        self.shm.bind(py).call_method0("unlink")?;
+        Ok(())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Close the map, and free the block if this process created it.
+    fn __exit__(
+        &mut self,
+        py: Python,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        if self.buffer.is_some() {
+            self.close(py)?;
+        }
+        if self.owner {
+            self.unlink(py)?;
+        }
+        Ok(())
+    }
+
+    /// Pickle as a reference to the block, so workers attach rather than copy.
+    fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyTuple>)> {
+        let attach = py.get_type::<PyTreeMap>().getattr("attach")?;
+        Ok((attach, PyTuple::new(py, [self.name(py)?])?))
+    }
+
+    fn __getitem__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        match self.find(py, self.data()?, key)? {
+            Some(value) => loads(py, value),
+            None => Err(key_error(key)),
+        }
+    }
+
+    fn __contains__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.find(py, self.data()?, key)?.is_some())
+    }
+
+    fn __len__(&self) -> usize {
+        self.count
+    }
+
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapIter> {
+        Ok(PyTreeMapIter::snapshot(self.keys(py)?))
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!(
+            "SharedTreeMap(name={}, len={})",
+            self.name(py)?.bind(py).repr()?,
+            self.count
+        ))
+    }
+}
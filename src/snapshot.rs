@@ -0,0 +1,120 @@
+use crate::iterators::{PyPrefixIter, PyTreeMapIter};
+use crate::treemap::{key_error, PyTreeMap};
+use pyo3::prelude::*;
+use pyo3::{PyTraverseError, PyVisit};
+
+/// Read-only, point-in-time copy of a TreeMap (returned by .snapshot()).
+///
+/// Writes to the original tree never show up in a snapshot, and a snapshot
+/// cannot be modified, so any thread can read or iterate it without locking
+/// while the original keeps changing.
+///
+/// Examples:
+///     >>> tree = TreeMap({"a": 1})
+///     >>> snap = tree.snapshot()
+///     >>> tree["b"] = 2
+///     >>> list(snap)
+///     ['a']
+#[pyclass(name = "PyTreeMapSnapshot", frozen, weakref)]
+pub struct PyTreeMapSnapshot {
+    map: PyTreeMap,
+}
+
+impl PyTreeMapSnapshot {
+    pub fn new(map: PyTreeMap) -> Self {
+        Self { map }
+    }
+}
+
+#[pymethods]
+impl PyTreeMapSnapshot {
+    /// Get a value by key, with optional default.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///     default: Value to return if key not found (defaults to None)
+    #[pyo3(signature = (key, default=None))]
+    fn get(
+        &self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        match self.map.lookup_value(key)? {
+            Some(value) => self.map.value_out(py, value),
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    /// Return an iterator over the (key, value) pairs whose key starts with
+    /// a prefix.
+    fn prefix_iter(&self, py: Python, prefix: &Bound<'_, PyAny>) -> PyResult<PyPrefixIter> {
+        let prefix_bytes = self.map.encode_prefix(prefix)?;
+        let items = self
+            .map
+            .prefix_entries(&prefix_bytes)
+            .map(|(k, v)| Ok((self.map.decode_key(py, k)?, self.map.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyPrefixIter::snapshot(items))
+    }
+
+    /// Get the first (lexicographically smallest) key-value pair, or None if empty.
+    fn first(&self, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        self.map
+            .tree()
+            .first_key_value()
+            .map(|(k, v)| Ok((self.map.decode_key(py, k)?, self.map.value_out(py, v)?)))
+            .transpose()
+    }
+
+    /// Get the last (lexicographically largest) key-value pair, or None if empty.
+    fn last(&self, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        self.map
+            .tree()
+            .last_key_value()
+            .map(|(k, v)| Ok((self.map.decode_key(py, k)?, self.map.value_out(py, v)?)))
+            .transpose()
+    }
+
+    /// Return a list of the keys, in key order.
+    fn keys(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.map.key_list(py)
+    }
+
+    /// Return a list of the values, in key order.
+    fn values(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.map.value_list(py)
+    }
+
+    /// Return a list of the (key, value) pairs, in key order.
+    fn items(&self, py: Python) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        self.map.item_list(py)
+    }
+
+    fn __getitem__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        match self.map.lookup_value(key)? {
+            Some(value) => self.map.value_out(py, value),
+            None => Err(key_error(key)),
+        }
+    }
+
+    fn __contains__(&self, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.map.lookup_value(key)?.is_some())
+    }
+
+    fn __len__(&self) -> usize {
+        self.map.entry_count()
+    }
+
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapIter> {
+        Ok(PyTreeMapIter::snapshot(self.map.key_list(py)?))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TreeMapSnapshot(len={})", self.map.entry_count())
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        self.map.traverse(&visit)
+    }
+}
@@ -0,0 +1,154 @@
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyFloat, PyInt};
+
+/// A value as a tree stores it.
+///
+/// Trees with a `value_type` keep their values as native Rust values, which
+/// take a fraction of the memory of the Python objects and can be read
+/// without the GIL. Every other value is a Python object: the value itself,
+/// or its pickled or compressed form.
+pub enum Stored {
+    Object(Py<PyAny>),
+    Int(i64),
+    Float(f64),
+    Bytes(Box<[u8]>),
+}
+
+impl Stored {
+    pub fn clone_ref(&self, py: Python) -> Self {
+        match self {
+            Self::Object(value) => Self::Object(value.clone_ref(py)),
+            Self::Int(int) => Self::Int(*int),
+            Self::Float(float) => Self::Float(*float),
+            Self::Bytes(bytes) => Self::Bytes(bytes.clone()),
+        }
+    }
+
+    /// The Python object stored, or None for a native value.
+    pub fn object(&self) -> Option<&Py<PyAny>> {
+        match self {
+            Self::Object(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value as a Python object: the stored object itself, or a new
+    /// one for a native value.
+    pub fn to_object(&self, py: Python) -> PyResult<Py<PyAny>> {
+        Ok(match self {
+            Self::Object(value) => value.clone_ref(py),
+            Self::Int(int) => int.into_pyobject(py)?.into_any().unbind(),
+            Self::Float(float) => PyFloat::new(py, *float).into_any().unbind(),
+            Self::Bytes(bytes) => PyBytes::new(py, bytes).into_any().unbind(),
+        })
+    }
+
+    /// Whether both hold the same object, or equal native values.
+    pub fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Object(a), Self::Object(b)) => a.is(b),
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a == b,
+            (Self::Bytes(a), Self::Bytes(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Bytes held outside the tree's own slot for the value, not counting
+    /// Python objects.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Self::Bytes(bytes) => bytes.len(),
+            _ => 0,
+        }
+    }
+}
+
+impl From<Py<PyAny>> for Stored {
+    fn from(value: Py<PyAny>) -> Self {
+        Self::Object(value)
+    }
+}
+
+/// Native types values can be restricted to with `value_type`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// Signed 64-bit integers
+    Int,
+    /// Double-precision floats
+    Float,
+    /// Byte strings
+    Bytes,
+}
+
+impl ValueType {
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "int" => Ok(Self::Int),
+            "float" => Ok(Self::Float),
+            "bytes" => Ok(Self::Bytes),
+            _ => Err(PyValueError::new_err(format!(
+                "value_type must be 'int', 'float', 'bytes' or None, got '{name}'"
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Bytes => "bytes",
+        }
+    }
+
+    /// Convert a value to the native type, raising TypeError (or
+    /// OverflowError for ints outside 64 bits) if it can't be.
+    pub fn native(self, value: &Bound<'_, PyAny>) -> PyResult<Stored> {
+        let py = value.py();
+        let mismatch = |err: PyErr| {
+            if !err.is_instance_of::<PyTypeError>(py) {
+                return err;
+            }
+            let type_name = value
+                .get_type()
+                .name()
+                .map_or_else(|_| "?".to_string(), |name| name.to_string());
+            PyTypeError::new_err(format!(
+                "values must be of type {} (value_type={:?}), got '{type_name}'",
+                self.name(),
+                self.name()
+            ))
+        };
+        Ok(match self {
+            Self::Int => Stored::Int(value.extract::<i64>().map_err(mismatch)?),
+            Self::Float => Stored::Float(value.extract::<f64>().map_err(mismatch)?),
+            Self::Bytes => match value.cast::<PyBytes>() {
+                Ok(bytes) => Stored::Bytes(bytes.as_bytes().into()),
+                Err(_) => {
+                    let buffer = PyBuffer::<u8>::get(value).map_err(mismatch)?;
+                    Stored::Bytes(buffer.to_vec(py)?.into())
+                }
+            },
+        })
+    }
+
+    /// Convert a value to the type, as a Python object. Values already of
+    /// the exact type (and range) are returned as they are.
+    pub fn check(value_type: Option<Self>, py: Python, value: Py<PyAny>) -> PyResult<Py<PyAny>> {
+        let Some(value_type) = value_type else {
+            return Ok(value);
+        };
+        let bound = value.bind(py);
+        let exact = match value_type {
+            Self::Int => bound.is_exact_instance_of::<PyInt>() && bound.extract::<i64>().is_ok(),
+            Self::Float => bound.is_exact_instance_of::<PyFloat>(),
+            Self::Bytes => bound.is_exact_instance_of::<PyBytes>(),
+        };
+        match exact {
+            true => Ok(value),
+            false => value_type.native(bound)?.to_object(py),
+        }
+    }
+}
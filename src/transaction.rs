@@ -0,0 +1,128 @@
+use crate::stored::Stored;
+use crate::treemap::PyTreeMap;
+use pyo3::prelude::*;
+use pyo3::{PyTraverseError, PyVisit};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// State of a key before its first change in a transaction.
+pub struct Saved {
+    pub value: Option<Stored>,
+    /// Original spelling, in case-insensitive trees
+    pub spelling: Option<String>,
+    pub deadline: Option<Instant>,
+}
+
+/// Undo log of an open transaction: the prior state of every key changed
+/// since it began.
+#[derive(Default)]
+pub struct Journal {
+    pub saved: HashMap<Box<[u8]>, Saved>,
+}
+
+impl Journal {
+    /// Bytes held by the undo log, not counting saved Python objects.
+    pub fn memory_usage(&self) -> usize {
+        self.saved.capacity() * std::mem::size_of::<(Box<[u8]>, Saved)>()
+            + self
+                .saved
+                .iter()
+                .map(|(key, saved)| {
+                    key.len()
+                        + saved.spelling.as_ref().map_or(0, String::capacity)
+                        + saved.value.as_ref().map_or(0, Stored::heap_size)
+                })
+                .sum::<usize>()
+    }
+
+    /// Python objects saved, as they were before the transaction.
+    pub fn values(&self) -> impl Iterator<Item = &Py<PyAny>> {
+        self.saved
+            .values()
+            .filter_map(|saved| saved.value.as_ref()?.object())
+    }
+
+    /// Save the state of a key, unless it was already changed earlier in
+    /// the transaction.
+    pub fn record(
+        &mut self,
+        py: Python,
+        key: &[u8],
+        value: Option<&Stored>,
+        spelling: Option<&String>,
+        deadline: Option<&Instant>,
+    ) {
+        if self.saved.contains_key(key) {
+            return;
+        }
+        self.saved.insert(
+            key.into(),
+            Saved {
+                value: value.map(|v| v.clone_ref(py)),
+                spelling: spelling.cloned(),
+                deadline: deadline.copied(),
+            },
+        );
+    }
+}
+
+/// Context manager returned by `TreeMap.transaction()`.
+///
+/// Changes made to the tree inside the `with` block take effect as usual,
+/// and the prior state of every changed key is logged. If an exception
+/// escapes the block, those keys are restored and the exception propagates;
+/// otherwise the log is dropped.
+///
+/// Examples:
+///     >>> tree = TreeMap({"a": 1})
+///     >>> with tree.transaction():
+///     ...     tree["b"] = 2
+///     ...     del tree["a"]
+///     ...     raise ValueError("abort")
+///     Traceback (most recent call last):
+///     ValueError: abort
+///     >>> dict(tree)
+///     {'a': 1}
+#[pyclass(name = "PyTreeMapTransaction", module = "blart._blart")]
+pub struct PyTreeMapTransaction {
+    map: Py<PyTreeMap>,
+}
+
+impl PyTreeMapTransaction {
+    pub fn new(map: Py<PyTreeMap>) -> Self {
+        Self { map }
+    }
+}
+
+#[pymethods]
+impl PyTreeMapTransaction {
+    /// Start logging changes and return the tree.
+    ///
+    /// Raises:
+    ///     RuntimeError: If a transaction is already open on the tree
+    fn __enter__(&self, py: Python) -> PyResult<Py<PyTreeMap>> {
+        self.map.borrow_mut(py).begin()?;
+        Ok(self.map.clone_ref(py))
+    }
+
+    /// Keep the changes, or roll them back if the block raised.
+    #[pyo3(signature = (exc_type, _exc_value=None, _traceback=None))]
+    fn __exit__(
+        &self,
+        py: Python,
+        exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        let mut map = self.map.borrow_mut(py);
+        match exc_type {
+            None => map.commit(),
+            Some(_) => map.rollback(py)?,
+        }
+        Ok(false)
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.map)
+    }
+}
@@ -1,56 +1,204 @@
 #![allow(clippy::useless_conversion)]
 
+use crate::arrays::{array_keys, arrow_array, arrow_keys, RawKeys};
+use crate::codec::{normalize_path, KeyCodec};
+use crate::columnar;
+use crate::compression::{Compressed, Compression};
+use crate::cow::CopyOnWrite;
+use crate::delimited::{read_columns, Column};
+use crate::fuzzy::{successor, Costs, FuzzyCursor, Metric};
+use crate::history::History;
+use crate::indexes::{CountIndex, InsertionOrder, KeyIndexes, SubstringIndex, SuffixIndex};
 use crate::iterators::{
-    PyFuzzyIter, PyPrefixIter, PyTreeMapItems, PyTreeMapIter, PyTreeMapKeys, PyTreeMapValues,
+    fuzzy_distance, FuzzyMatch, IterGuard, PyChunkIter, PyFuzzyIter, PyPrefixIter, PyRegexIter,
+    PyTreeMapItems, PyTreeMapIter, PyTreeMapKeys, PyTreeMapValues,
 };
+use crate::json;
+use crate::metrics::Metrics;
+use crate::nodes::Shape;
+use crate::pattern::KeyPattern;
+use crate::pickled::{self, PickleCache};
+use crate::shared::{self, PySharedTreeMap};
+use crate::snapshot::PyTreeMapSnapshot;
+use crate::stored::{Stored, ValueType};
+use crate::transaction::{Journal, PyTreeMapTransaction};
+use crate::trie;
+use crate::views::{PyTreeMapILoc, PyTreeMapItemsView, PyTreeMapKeysView, PyTreeMapValuesView};
+use blart::map::Entry;
+use blart::visitor::{TreeStatsCollector, WellFormedChecker};
 use blart::TreeMap;
-use pyo3::exceptions::PyKeyError;
+use caseless::{default_case_fold_str, Caseless};
+use pyo3::basic::CompareOp;
+use pyo3::exceptions::{
+    PyIndexError, PyKeyError, PyOverflowError, PyRuntimeError, PyTypeError, PyValueError,
+};
+use pyo3::ffi;
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
-
-/// Calculate Levenshtein distance between two strings
-#[allow(clippy::needless_range_loop)]
-fn levenshtein_distance(s1: &str, s2: &str) -> usize {
-    let len1 = s1.chars().count();
-    let len2 = s2.chars().count();
+use pyo3::sync::PyOnceLock;
+use pyo3::types::{
+    PyBool, PyByteArray, PyBytes, PyCFunction, PyDict, PyFloat, PyInt, PyList, PyMapping, PySlice,
+    PyString, PyTuple, PyType,
+};
+use pyo3::{PyTraverseError, PyVisit};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+use unicode_normalization::{
+    is_nfc_quick, is_nfd_quick, is_nfkc_quick, is_nfkd_quick, IsNormalized, UnicodeNormalization,
+};
 
-    if len1 == 0 {
-        return len2;
+/// Number of inner nodes above each leaf of the radix tree holding `keys`.
+///
+/// `keys` must be sorted with no key a prefix of another, which makes the
+/// tree a path-compressed trie: inner nodes sit exactly where keys diverge.
+/// A leaf's ancestors are then the distinct common prefix lengths it shares
+/// with the other keys, taken as running minima of the adjacent LCPs on
+/// either side of it.
+fn leaf_depths(keys: &[&[u8]]) -> Vec<usize> {
+    let adjacent: Vec<usize> = keys
+        .windows(2)
+        .map(|w| common_prefix_len(w[0], w[1]))
+        .collect();
+    // Strictly increasing stack of running minima, as seen from one side
+    let push = |stack: &mut Vec<usize>, l: usize| {
+        while stack.last().is_some_and(|&top| top > l) {
+            stack.pop();
+        }
+        if stack.last() != Some(&l) {
+            stack.push(l);
+        }
+    };
+    let mut right = vec![Vec::new(); keys.len()];
+    let mut stack = Vec::new();
+    for i in (0..adjacent.len()).rev() {
+        push(&mut stack, adjacent[i]);
+        right[i] = stack.clone();
     }
-    if len2 == 0 {
-        return len1;
+    let mut left = Vec::new();
+    let mut depths = Vec::with_capacity(keys.len());
+    for (i, right) in right.iter().enumerate() {
+        if i > 0 {
+            push(&mut left, adjacent[i - 1]);
+        }
+        let shared = left
+            .iter()
+            .filter(|l| right.binary_search(l).is_ok())
+            .count();
+        depths.push(left.len() + right.len() - shared);
     }
+    depths
+}
 
-    let mut matrix = vec![vec![0; len2 + 1]; len1 + 1];
+/// Autocomplete score, ordered with `f64::total_cmp`.
+#[derive(PartialEq)]
+struct Score(f64);
 
-    for i in 0..=len1 {
-        matrix[i][0] = i;
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
-    for j in 0..=len2 {
-        matrix[0][j] = j;
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
     }
+}
 
-    let s1_chars: Vec<char> = s1.chars().collect();
-    let s2_chars: Vec<char> = s2.chars().collect();
+/// Length of the common prefix of two byte strings.
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
 
-    for i in 1..=len1 {
-        for j in 1..=len2 {
-            let cost = if s1_chars[i - 1] == s2_chars[j - 1] {
-                0
-            } else {
-                1
-            };
-            matrix[i][j] = std::cmp::min(
-                std::cmp::min(
-                    matrix[i - 1][j] + 1, // deletion
-                    matrix[i][j - 1] + 1, // insertion
-                ),
-                matrix[i - 1][j - 1] + cost, // substitution
-            );
-        }
-    }
+/// Render key bytes for a DOT label: printable ASCII as-is, the rest as \xNN.
+fn dot_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'"' | b'\\' => format!("\\{}", b as char),
+            0x20..=0x7e => (b as char).to_string(),
+            _ => format!("\\\\x{b:02x}"),
+        })
+        .collect()
+}
+
+/// Escape arbitrary text for use inside a quoted DOT label.
+fn dot_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Render key bytes as plain ASCII: printable characters as-is, the rest
+/// (and backslashes) escaped as in a bytes literal.
+fn ascii_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'\\' => "\\\\".to_string(),
+            0x20..=0x7e => (b as char).to_string(),
+            _ => format!("\\x{b:02x}"),
+        })
+        .collect()
+}
 
-    matrix[len1][len2]
+/// The items of a mapping (anything with `keys()`) or of an iterable of
+/// pairs, read as `dict()` reads them.
+fn mapping_pairs<'py>(
+    data: &Bound<'py, PyAny>,
+) -> PyResult<Vec<(Bound<'py, PyAny>, Bound<'py, PyAny>)>> {
+    if let Ok(dict) = data.cast_exact::<PyDict>() {
+        return Ok(dict.iter().collect());
+    }
+    if data.hasattr("keys")? {
+        return data
+            .call_method0("keys")?
+            .try_iter()?
+            .map(|key| {
+                let key = key?;
+                let value = data.get_item(&key)?;
+                Ok((key, value))
+            })
+            .collect();
+    }
+    let items = data.try_iter().map_err(|_| {
+        let type_name = data
+            .get_type()
+            .name()
+            .map_or_else(|_| "?".to_string(), |name| name.to_string());
+        PyTypeError::new_err(format!(
+            "expected a mapping or an iterable of (key, value) pairs, got '{type_name}'"
+        ))
+    })?;
+    items
+        .enumerate()
+        .map(|(i, item)| {
+            let item = item?;
+            if let Ok(pair) = item.extract::<(Bound<'py, PyAny>, Bound<'py, PyAny>)>() {
+                return Ok(pair);
+            }
+            let fields = item
+                .try_iter()
+                .map_err(|_| {
+                    PyTypeError::new_err(format!(
+                        "cannot convert element #{i} of the sequence to a (key, value) pair"
+                    ))
+                })?
+                .collect::<PyResult<Vec<_>>>()?;
+            match <[_; 2]>::try_from(fields) {
+                Ok([key, value]) => Ok((key, value)),
+                Err(fields) => Err(PyValueError::new_err(format!(
+                    "element #{i} of the sequence has length {}; 2 is required",
+                    fields.len()
+                ))),
+            }
+        })
+        .collect()
 }
 
 /// A high-performance adaptive radix tree (ART) implementation.
@@ -83,369 +231,7461 @@ fn levenshtein_distance(s1: &str, s2: &str) -> usize {
 /// for key, value in tree.prefix_iter("app"):
 ///     print(key, value)
 /// ```
-#[pyclass(name = "PyTreeMap")]
+#[pyclass(name = "PyTreeMap", module = "blart._blart", weakref)]
 pub struct PyTreeMap {
-    inner: TreeMap<Box<[u8]>, Py<PyAny>>,
+    /// Shared with snapshots until either side writes
+    inner: CopyOnWrite<TreeMap<Box<[u8]>, Stored>>,
+    default_factory: Option<Py<PyAny>>,
+    codec: KeyCodec,
+    case_insensitive: bool,
+    /// Unicode normalization form applied to keys ("NFC", "NFKC", ...)
+    normalize: Option<String>,
+    /// Originally inserted spelling of each key, only kept in case-insensitive mode
+    original_keys: CopyOnWrite<HashMap<Box<[u8]>, String>>,
+    /// Bumped whenever keys are added or removed, so iterators can detect
+    /// mutation during iteration
+    generation: u64,
+    /// Add the closest stored keys to the message of KeyErrors
+    did_you_mean: bool,
+    /// Secondary indexes for substring and suffix search, when enabled
+    indexes: CopyOnWrite<KeyIndexes>,
+    /// When entries inserted with a ttl expire
+    deadlines: CopyOnWrite<HashMap<Box<[u8]>, Instant>>,
+    /// No entry expires before this; reads sweep the tree once it has passed
+    next_deadline: Option<Instant>,
+    /// Most entries the tree may hold, and what happens to a new key beyond it
+    max_len: Option<usize>,
+    eviction: Eviction,
+    /// Type every value is checked against and converted to, or None for any
+    value_type: Option<ValueType>,
+    /// Recently unpickled values, when values are stored pickled
+    pickled: Option<PickleCache>,
+    /// Compresses large str and bytes values, when `compress_values` is set
+    compression: Option<Compression>,
+    /// One stored object for each distinct immutable value, when values are
+    /// interned
+    interned: Option<Py<PyDict>>,
+    /// Callable that produces the value of a key missing on lookup
+    loader: Option<Py<PyAny>>,
+    /// Callables registered with `on_change()`
+    observers: Vec<Py<PyAny>>,
+    /// Operation counters reported by `metrics()`
+    metrics: Metrics,
+    /// Undo log of the open `transaction()`, if any
+    journal: Option<Journal>,
+    /// Log of every change, in versioned trees
+    history: Option<History>,
 }
 
-#[pymethods]
 impl PyTreeMap {
-    /// Create a new TreeMap.
-    ///
-    /// Args:
-    ///     data: Optional initial data. Can be:
-    ///         - None: Creates an empty TreeMap
-    ///         - dict: Creates TreeMap from dictionary
-    ///         - list of tuples: Creates TreeMap from [(key, value), ...] pairs
-    ///
-    /// Returns:
-    ///     A new TreeMap instance
+    /// Convert a user-facing key into the bytes stored in the tree.
+    pub(crate) fn encode_key(&self, key: &Bound<'_, PyAny>) -> PyResult<Box<[u8]>> {
+        if !self.codec.is_str() || (!self.case_insensitive && self.normalize.is_none()) {
+            return self.codec.encode(key);
+        }
+        // Keys that aren't valid text (bytes or escaped surrogates) are stored
+        // as-is by the codec
+        let Ok(key) = key
+            .cast::<PyString>()
+            .map_err(PyErr::from)
+            .and_then(|k| k.to_str())
+        else {
+            return self.codec.encode(key);
+        };
+        let normalized = match &self.normalize {
+            Some(form) => normalize_key(form, key),
+            None => None,
+        };
+        let key = normalized.as_deref().unwrap_or(key);
+        if self.case_insensitive {
+            Ok(default_case_fold_str(key).into_bytes().into_boxed_slice())
+        } else {
+            Ok(key.as_bytes().into())
+        }
+    }
+
+    /// Convert a prefix query into the bytes matched against stored keys.
+    pub(crate) fn encode_prefix(&self, prefix: &Bound<'_, PyAny>) -> PyResult<Box<[u8]>> {
+        if self.codec.is_str() {
+            self.encode_key(prefix)
+        } else {
+            self.codec.encode_prefix(prefix)
+        }
+    }
+
+    /// Counter that changes whenever keys are added or removed.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The underlying radix tree.
+    pub(crate) fn tree(&self) -> &TreeMap<Box<[u8]>, Stored> {
+        &self.inner
+    }
+
+    /// Copy the tree and its key settings. Values are shared, not copied,
+    /// and the keys are shared until either tree is written to.
+    pub(crate) fn duplicate(&self, py: Python) -> Self {
+        Self {
+            inner: self.inner.share(),
+            default_factory: None,
+            codec: self.codec.clone_ref(py),
+            case_insensitive: self.case_insensitive,
+            normalize: self.normalize.clone(),
+            original_keys: self.original_keys.share(),
+            generation: 0,
+            did_you_mean: self.did_you_mean,
+            indexes: self.indexes.share(),
+            deadlines: self.deadlines.share(),
+            next_deadline: self.next_deadline,
+            max_len: self.max_len,
+            eviction: self.eviction,
+            value_type: self.value_type,
+            pickled: self
+                .pickled
+                .as_ref()
+                .map(|p| PickleCache::new(p.capacity())),
+            compression: self.compression,
+            interned: self.interned.as_ref().map(|table| {
+                let table = table.bind(py);
+                table.copy().unwrap_or_else(|_| PyDict::new(py)).unbind()
+            }),
+            loader: None,
+            observers: Vec::new(),
+            metrics: Metrics::default(),
+            journal: None,
+            history: None,
+        }
+    }
+
+    /// Report every Python object this tree holds to the garbage collector.
     ///
-    /// Raises:
-    ///     ValueError: If data format is invalid
-    ///     TypeError: If keys are not strings
+    /// Values shared with a snapshot or copy hold one reference between
+    /// them, so neither reports them: reporting from both would count the
+    /// reference twice and let the collector clear values still in use.
+    /// Cycles through shared values are collected once the sharing ends.
+    pub(crate) fn traverse(&self, visit: &PyVisit<'_>) -> Result<(), PyTraverseError> {
+        if !self.inner.is_shared() {
+            for (_, value) in self.inner.iter() {
+                visit.call(value.object())?;
+            }
+        }
+        visit.call(&self.default_factory)?;
+        visit.call(&self.loader)?;
+        for observer in &self.observers {
+            visit.call(observer)?;
+        }
+        if let Some(journal) = &self.journal {
+            for saved in journal.saved.values() {
+                visit.call(saved.value.as_ref().and_then(Stored::object))?;
+            }
+        }
+        if let Some(history) = &self.history {
+            history.traverse(visit)?;
+        }
+        if let Some(cache) = &self.pickled {
+            cache.traverse(visit)?;
+        }
+        visit.call(&self.interned)?;
+        self.codec.traverse(visit)
+    }
+
+    /// Constructor keyword arguments that recreate this tree's key settings.
     ///
-    /// Examples:
-    ///     >>> tree = TreeMap()
-    ///     >>> tree = TreeMap({"a": 1, "b": 2})
-    ///     >>> tree = TreeMap([("a", 1), ("b", 2)])
-    #[new]
-    #[pyo3(signature = (data=None))]
-    fn new(py: Python, data: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
-        let mut tree = Self {
-            inner: TreeMap::new(),
+    /// Raises TypeError for custom codec objects, which cannot be described
+    /// by name.
+    pub(crate) fn settings<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        if let KeyCodec::Custom(_) = self.codec {
+            return Err(PyTypeError::new_err(
+                "trees with a custom key_type codec cannot be shared",
+            ));
+        }
+        let settings = PyDict::new(py);
+        settings.set_item("key_type", self.codec.key_type(py))?;
+        if let Some(separator) = self.codec.path_separator() {
+            settings.set_item("separator", (separator as char).to_string())?;
+        }
+        settings.set_item("errors", self.codec.errors())?;
+        settings.set_item("case_insensitive", self.case_insensitive)?;
+        settings.set_item("normalize", &self.normalize)?;
+        settings.set_item("value_type", self.value_type.map(ValueType::name))?;
+        if let Some(cache) = &self.pickled {
+            settings.set_item("store_pickled", true)?;
+            settings.set_item("pickle_cache_size", cache.capacity())?;
+        }
+        if self.interned.is_some() {
+            settings.set_item("intern_values", true)?;
+        }
+        if let Some(compression) = &self.compression {
+            settings.set_item("compress_values", compression.codec())?;
+            settings.set_item("compress_threshold", compression.threshold())?;
+        }
+        Ok(settings)
+    }
+
+    /// Originally inserted spelling of a stored key, in case-insensitive mode.
+    pub(crate) fn original_key(&self, key: &[u8]) -> Option<&str> {
+        self.original_keys.get(key).map(String::as_str)
+    }
+
+    /// Number of entries in the tree.
+    pub(crate) fn entry_count(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// The stored key at a position in key order, negative positions
+    /// counting from the end as for lists.
+    pub(crate) fn key_at_position(&self, py: Python, index: isize) -> Option<&[u8]> {
+        let len = self.inner.len();
+        let position = if index < 0 {
+            len.checked_sub(index.unsigned_abs())
+        } else {
+            Some(index as usize).filter(|position| *position < len)
+        }?;
+        py.detach(|| match &self.indexes.counts {
+            Some(counts) => counts.select(position),
+            None => self.inner.keys().nth(position).map(|k| &**k),
+        })
+    }
+
+    /// The stored keys at the positions a slice selects, in slice order.
+    pub(crate) fn keys_in_slice(&self, slice: &Bound<'_, PySlice>) -> PyResult<Vec<Box<[u8]>>> {
+        let indices = slice.indices(self.inner.len() as isize)?;
+        let keys: Vec<&Box<[u8]>> = self.inner.keys().collect();
+        Ok((0..indices.slicelength as isize)
+            .map(|i| keys[(indices.start + i * indices.step) as usize].clone())
+            .collect())
+    }
+
+    /// Remove an encoded key, if stored, and report the removal.
+    pub(crate) fn remove_encoded(&mut self, py: Python, key: &[u8]) -> PyResult<()> {
+        match self.inner.remove(key) {
+            Some(value) => self.discard(py, key, &value, Change::Remove),
+            None => Ok(()),
+        }
+    }
+
+    /// Look up the value stored for a key.
+    pub(crate) fn lookup_value(&self, key: &Bound<'_, PyAny>) -> PyResult<Option<&Stored>> {
+        let key_bytes = self.encode_key(key)?;
+        let value = match self.is_expired(&key_bytes) {
+            true => None,
+            false => self.inner.get(&key_bytes),
         };
+        self.metrics.lookup(value.is_some());
+        Ok(value)
+    }
 
-        if let Some(data) = data {
-            // Try to interpret as dict
-            if let Ok(dict) = data.cast_exact::<PyDict>() {
-                for (key, value) in dict.iter() {
-                    let key_str: String = key.extract()?;
-                    tree.insert(py, key_str, value.clone().unbind())?;
-                }
-            }
-            // Try to interpret as list of tuples
-            else if let Ok(list) = data.cast_exact::<PyList>() {
-                for item in list.iter() {
-                    let tuple = item.cast_exact::<pyo3::types::PyTuple>()?;
-                    if tuple.len() != 2 {
-                        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-                            "Items must be (key, value) tuples",
-                        ));
-                    }
-                    let key_str: String = tuple.get_item(0)?.extract()?;
-                    let value = tuple.get_item(1)?.clone().unbind();
-                    tree.insert(py, key_str, value)?;
-                }
+    /// Convert a value to the form it is stored in: checked against
+    /// `value_type`, pickled in `store_pickled` trees, compressed in
+    /// `compress_values` trees and interned in `intern_values` trees.
+    fn value_in(&self, py: Python, value: Py<PyAny>) -> PyResult<Stored> {
+        let interned = self.interned.as_ref().map(|table| table.bind(py));
+        let compression = self.compression.as_ref();
+        let pickled = self.pickled.is_some();
+        stored_value(self.value_type, pickled, compression, interned, py, value)
+    }
+
+    /// The value to hand out for a stored one: decompressed and unpickled
+    /// as needed, a new object for a native value, otherwise the stored
+    /// object itself.
+    pub(crate) fn value_out(&self, py: Python, stored: &Stored) -> PyResult<Py<PyAny>> {
+        original_value(self.pickled.as_ref(), self.compression.as_ref(), py, stored)
+    }
+
+    /// A stored value as pickled bytes.
+    pub(crate) fn pickled_value<'py>(
+        &self,
+        py: Python<'py>,
+        stored: &Stored,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        match (&self.pickled, &self.compression, stored) {
+            (Some(_), None, Stored::Object(stored)) => {
+                Ok(stored.bind(py).cast::<PyBytes>()?.clone())
             }
+            (Some(_), Some(compression), Stored::Object(stored)) => Ok(compression
+                .decompress(py, stored)?
+                .into_bound(py)
+                .cast_into::<PyBytes>()?),
+            _ => pickled::dumps(py, &self.value_out(py, stored)?),
         }
+    }
 
-        Ok(tree)
+    /// Fetch a missing key through the loader and store the result.
+    ///
+    /// Returns None when the tree has no loader. An exception raised by the
+    /// loader becomes the cause of a KeyError for the key. The loader runs
+    /// with the tree unborrowed, so it may read the tree itself; only
+    /// storing the result borrows it mutably.
+    fn load(slf: &Bound<'_, Self>, key: &Bound<'_, PyAny>) -> PyResult<Option<Py<PyAny>>> {
+        let py = slf.py();
+        let Some(loader) = slf.try_borrow()?.loader.as_ref().map(|l| l.clone_ref(py)) else {
+            return Ok(None);
+        };
+        let value = match loader.bind(py).call1((key,)) {
+            Ok(value) => value.unbind(),
+            Err(err) => {
+                let error = key_error(key);
+                error.set_cause(py, Some(err));
+                return Err(error);
+            }
+        };
+        let mut tree = slf.try_borrow_mut()?;
+        let value = ValueType::check(tree.value_type, py, value)?;
+        let key_bytes = tree.encode_key(key)?;
+        tree.store(key_bytes, key, value.clone_ref(py))?;
+        Ok(Some(value))
     }
 
-    /// Insert a key-value pair into the TreeMap.
+    /// Whether an entry inserted with a ttl has outlived it. Expired entries
+    /// read as missing even before the next sweep removes them.
+    fn is_expired(&self, key_bytes: &[u8]) -> bool {
+        !self.deadlines.is_empty()
+            && self
+                .deadlines
+                .get(key_bytes)
+                .is_some_and(|deadline| *deadline <= Instant::now())
+    }
+
+    /// Give a key a deadline after which it expires.
+    fn set_deadline(&mut self, key_bytes: Box<[u8]>, deadline: Instant) {
+        self.next_deadline = Some(
+            self.next_deadline
+                .map_or(deadline, |next| next.min(deadline)),
+        );
+        self.deadlines.insert(key_bytes, deadline);
+    }
+
+    /// Remove every entry whose ttl has run out, reporting each to the
+    /// observers as evicted. Returns how many were removed.
     ///
-    /// If the key already exists, its value is updated.
-    /// Note: Due to the adaptive radix tree structure, inserting a key may
-    /// remove existing keys that are prefixes of the new key.
+    /// Open iterators stay valid: entries running out of time is not a
+    /// change made during iteration.
+    pub(crate) fn purge_expired(&mut self, py: Python) -> PyResult<usize> {
+        let now = Instant::now();
+        if self.next_deadline.is_none_or(|next| next > now) {
+            return Ok(0);
+        }
+        let mut expired = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        // Report expirations in key order
+        expired.sort_unstable();
+        let generation = self.generation;
+        let mut removed = 0;
+        for key in &expired {
+            match self.inner.remove(key) {
+                Some(value) => {
+                    self.discard(py, key, &value, Change::Evict)?;
+                    removed += 1;
+                }
+                None => self.forget(key),
+            }
+        }
+        self.generation = generation;
+        self.next_deadline = self.deadlines.values().min().copied();
+        Ok(removed)
+    }
+
+    /// Borrow a tree for reading, sweeping out its expired entries first.
     ///
-    /// Args:
-    ///     key: String key to insert
-    ///     value: Python object to store
+    /// Every read goes through here, so expired entries never show up in
+    /// `len()`, iteration or queries. While another thread is reading the
+    /// tree the sweep is left to the next read; lookups by key still skip
+    /// expired entries.
+    pub(crate) fn live<'py>(slf: &Bound<'py, Self>) -> PyResult<PyRef<'py, Self>> {
+        let tree = slf.try_borrow()?;
+        if tree.next_deadline.is_none_or(|next| next > Instant::now()) {
+            return Ok(tree);
+        }
+        drop(tree);
+        if let Ok(mut tree) = slf.try_borrow_mut() {
+            tree.purge_expired(slf.py())?;
+        }
+        Ok(slf.try_borrow()?)
+    }
+
+    /// All keys, in key order, as a pyarrow array.
     ///
-    /// Examples:
-    ///     >>> tree = TreeMap()
-    ///     >>> tree.insert("hello", "world")
-    ///     >>> tree.insert("hello", "universe")  # Updates value
-    fn insert(&mut self, _py: Python, key: String, value: Py<PyAny>) -> PyResult<()> {
-        let key_bytes = key.into_bytes().into_boxed_slice();
-        self.inner.force_insert(key_bytes, value);
+    /// Plain str and bytes keys (in their original spelling) are copied into
+    /// Arrow buffers directly; other key types are decoded one by one.
+    fn arrow_keys<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let text = self.codec.is_str();
+        if self.codec.is_verbatim() {
+            let keys: Vec<&[u8]> = self
+                .inner
+                .iter()
+                .map(|(key, _)| self.original_key(key).map_or(&**key, str::as_bytes))
+                .collect();
+            // str trees may hold bytes that aren't UTF-8, under a lenient
+            // decode policy
+            if !text || keys.iter().all(|key| std::str::from_utf8(key).is_ok()) {
+                return arrow_array(py, &keys, text);
+            }
+        }
+        py.import("pyarrow")?
+            .call_method1("array", (self.key_list(py)?,))
+    }
+
+    /// All keys, in key order.
+    pub(crate) fn key_list(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .iter()
+            .map(|(k, _)| self.decode_key(py, k))
+            .collect()
+    }
+
+    /// All values, in key order.
+    pub(crate) fn value_list(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .iter()
+            .map(|(_, v)| self.value_out(py, v))
+            .collect()
+    }
+
+    /// All (key, value) pairs, in key order.
+    pub(crate) fn item_list(&self, py: Python) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        self.inner
+            .iter()
+            .map(|(k, v)| Ok((self.decode_key(py, k)?, self.value_out(py, v)?)))
+            .collect()
+    }
+
+    /// Append the lines of `pretty_print()` for `node`, whose compressed
+    /// prefix starts at byte `depth`. The first line continues the branch
+    /// already written; the rest start with `indent`.
+    fn render_node(
+        &self,
+        py: Python,
+        node: &Shape,
+        depth: usize,
+        max_depth: Option<usize>,
+        indent: &str,
+        out: &mut String,
+    ) -> PyResult<()> {
+        let (kind, prefix_len, children) = match node {
+            Shape::Leaf(key) => {
+                let key = self.decode_key(py, key)?;
+                out.push_str(&format!("{}\n", key.bind(py).repr()?));
+                return Ok(());
+            }
+            Shape::Inner {
+                kind,
+                prefix_len,
+                children,
+                ..
+            } => (kind, *prefix_len, children),
+        };
+        let split = depth + prefix_len;
+        out.push_str(kind);
+        if prefix_len > 0 {
+            out.push_str(&format!(
+                " prefix=\"{}\"",
+                ascii_bytes(&node.first_key()[depth..split])
+            ));
+        }
+        out.push('\n');
+        if max_depth == Some(0) {
+            out.push_str(&format!("{indent}`-- ... {} keys\n", node.keys()));
+            return Ok(());
+        }
+        let max_depth = max_depth.map(|max| max - 1);
+        for (i, (byte, child)) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, below) = if last {
+                ("`-- ", "    ")
+            } else {
+                ("|-- ", "|   ")
+            };
+            out.push_str(&format!("{indent}{branch}{}: ", ascii_bytes(&[*byte])));
+            let indent = format!("{indent}{below}");
+            self.render_node(py, child, split + 1, max_depth, &indent, out)?;
+        }
         Ok(())
     }
 
-    /// Get a value by key, with optional default.
-    ///
-    /// Args:
-    ///     key: String key to look up
-    ///     default: Value to return if key not found (defaults to None)
+    /// The first `limit` entries as "key: value" reprs separated by commas,
+    /// ending in "..." if any were left out.
+    fn preview(&self, py: Python, limit: usize) -> PyResult<String> {
+        let mut parts = self
+            .inner
+            .iter()
+            .take(limit)
+            .map(|(key, value)| {
+                let key = self.decode_key(py, key)?.into_bound(py).repr()?;
+                let value = self.value_out(py, value)?.into_bound(py).repr()?;
+                Ok(format!("{key}: {value}"))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        if self.inner.len() > limit {
+            parts.push("...".to_string());
+        }
+        Ok(parts.join(", "))
+    }
+
+    /// Entries whose key starts with `prefix`, in key order.
     ///
-    /// Returns:
-    ///     The value associated with the key, or default if not found
+    /// blart's prefix search trusts compressed node prefixes without checking
+    /// them, so it can return keys that share only part of the prefix;
+    /// filtering keeps the results exact.
+    pub(crate) fn prefix_entries<'a, 'p>(
+        &'a self,
+        prefix: &'p [u8],
+    ) -> impl DoubleEndedIterator<Item = (&'a Box<[u8]>, &'a Stored)> + use<'a, 'p> {
+        self.inner
+            .prefix(prefix)
+            .filter(move |(k, _)| k.starts_with(prefix))
+    }
+
+    /// Find the first stored key satisfying a lower bound.
     ///
-    /// Examples:
-    ///     >>> tree = TreeMap({"hello": "world"})
-    ///     >>> tree.get("hello")
-    ///     'world'
-    ///     >>> tree.get("missing")
-    ///     None
-    ///     >>> tree.get("missing", "default")
-    ///     'default'
-    #[pyo3(signature = (key, default=None))]
-    fn get(
+    /// blart's range search comes back empty when the bound falls after the
+    /// last child of an inner node, so the start key is located with prefix
+    /// probes instead: first among keys extending the bound, then among keys
+    /// that branch off it at a higher byte, from the deepest position up.
+    pub(crate) fn seek(&self, lower: std::ops::Bound<&[u8]>) -> Option<&[u8]> {
+        let (bound, inclusive) = match lower {
+            Unbounded => return self.inner.first_key_value().map(|(k, _)| &**k),
+            Included(bound) => (bound, true),
+            Excluded(bound) => (bound, false),
+        };
+        if let Some((key, _)) = self
+            .prefix_entries(bound)
+            .find(|(k, _)| inclusive || &***k != bound)
+        {
+            return Some(key);
+        }
+        for depth in (0..bound.len()).rev() {
+            let Some(next_byte) = bound[depth].checked_add(1) else {
+                continue;
+            };
+            let mut probe = bound[..depth].to_vec();
+            probe.push(next_byte);
+            let found = self
+                .inner
+                .range::<[u8], _>((Included(probe.as_slice()), Unbounded))
+                .next();
+            if let Some((key, _)) = found {
+                // Iterating from a stored key is exact, so step forward from
+                // the reported key in case its search overshot backwards
+                let key = self
+                    .inner
+                    .range::<[u8], _>((Included(&**key), Unbounded))
+                    .map(|(k, _)| k)
+                    .find(|k| ***k >= *probe);
+                if let Some(key) = key.filter(|k| k.starts_with(&bound[..depth])) {
+                    return Some(key);
+                }
+            }
+        }
+        None
+    }
+
+    /// Iterate in key order starting from the first key satisfying `lower`.
+    fn iter_from(
+        &self,
+        lower: std::ops::Bound<&[u8]>,
+    ) -> impl Iterator<Item = (&Box<[u8]>, &Stored)> {
+        self.seek(lower)
+            .map(|start| self.inner.range::<[u8], _>((Included(start), Unbounded)))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Separator and encoded directory prefix (with trailing separator) of a
+    /// "path" tree directory. The top level has an empty prefix.
+    fn path_directory(&self, path: Option<&Bound<'_, PyAny>>) -> PyResult<(u8, Vec<u8>)> {
+        let separator = self
+            .codec
+            .path_separator()
+            .ok_or_else(|| PyTypeError::new_err("directory helpers require key_type=\"path\""))?;
+        let mut dir = match path {
+            Some(path) => normalize_path(path, separator)?.into_bytes(),
+            None => Vec::new(),
+        };
+        if !dir.is_empty() && !dir.ends_with(&[separator]) {
+            dir.push(separator);
+        }
+        Ok((separator, dir))
+    }
+
+    /// Convert stored key bytes back into the user-facing key.
+    pub(crate) fn decode_key(&self, py: Python, key: &[u8]) -> PyResult<Py<PyAny>> {
+        decode_key(py, &self.codec, &self.original_keys, key)
+    }
+
+    /// Whether every key of `other` is stored here, optionally with an equal
+    /// value. Walks both trees together and stops at the first miss.
+    fn contains_tree(&self, py: Python, other: &Self, values: bool) -> PyResult<bool> {
+        self.check_key_type(py, other, "compare")?;
+        if other.inner.len() > self.inner.len() {
+            return Ok(false);
+        }
+        let mut mine = self.inner.iter().peekable();
+        for (key, value) in other.inner.iter() {
+            while mine.next_if(|(k, _)| *k < key).is_some() {}
+            let Some((_, ours)) = mine.next_if(|(k, _)| *k == key) else {
+                return Ok(false);
+            };
+            if values && !ours.same(value) {
+                let (ours, value) = (self.value_out(py, ours)?, other.value_out(py, value)?);
+                if !ours.bind(py).eq(value)? {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Keys of this tree and of another TreeMap or iterable of keys, in key
+    /// order, walking both sides together. `keep` selects the keys found
+    /// only here, in both, and only in other.
+    fn combine_keys(
         &self,
         py: Python,
-        key: String,
-        default: Option<Py<PyAny>>,
-    ) -> PyResult<Option<Py<PyAny>>> {
-        let key_bytes = key.as_bytes();
-        match self.inner.get(key_bytes) {
-            Some(value) => Ok(Some(value.clone_ref(py))),
-            None => Ok(default.or_else(|| Some(py.None()))),
+        other: &Bound<'_, PyAny>,
+        [ours_only, both, theirs_only]: [bool; 3],
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let tree = other.cast::<PyTreeMap>().ok().map(Self::live).transpose()?;
+        let mut keys = Vec::new();
+        match &tree {
+            Some(tree) => self.check_key_type(py, tree, "combine keys of")?,
+            None => {
+                for key in other.try_iter()? {
+                    let key = key?;
+                    keys.push((self.encode_key(&key)?, key));
+                }
+                keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+                keys.dedup_by(|(a, _), (b, _)| a == b);
+            }
+        }
+        // Only one side of the chain has keys
+        let theirs = tree
+            .iter()
+            .flat_map(|tree| tree.inner.keys())
+            .map(|k| (&**k, None))
+            .chain(keys.iter().map(|(k, key)| (&**k, Some(key))));
+        let mut result = Vec::new();
+        let mut mine = self.inner.keys().peekable();
+        for (key, original) in theirs {
+            while let Some(ours) = mine.next_if(|k| &***k < key) {
+                if ours_only {
+                    result.push(self.decode_key(py, ours)?);
+                }
+            }
+            if mine.next_if(|k| &***k == key).is_some() {
+                if both {
+                    result.push(self.decode_key(py, key)?);
+                }
+            } else if theirs_only {
+                result.push(match (original, &tree) {
+                    (Some(original), _) => original.clone().unbind(),
+                    (None, Some(tree)) => tree.decode_key(py, key)?,
+                    (None, None) => unreachable!("keys without a tree keep their original"),
+                });
+            }
+        }
+        if ours_only {
+            for ours in mine {
+                result.push(self.decode_key(py, ours)?);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Fail unless `other` has the same key type, so that the encoded keys
+    /// of both trees can be compared directly.
+    fn check_key_type(&self, py: Python, other: &Self, action: &str) -> PyResult<()> {
+        if self
+            .codec
+            .key_type(py)
+            .bind(py)
+            .eq(other.codec.key_type(py))?
+        {
+            Ok(())
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "cannot {action} TreeMaps with different key types"
+            )))
+        }
+    }
+
+    /// Bytes used by this tree and every auxiliary structure kept beside it,
+    /// optionally adding the size of every distinct value, including values
+    /// only held by the history, the transaction journal and the unpickling
+    /// cache. Nested TreeMaps are measured recursively; `seen` guards against
+    /// counting a value (or a cycle back to a tree) twice.
+    fn footprint(&self, py: Python, seen: Option<&mut HashSet<usize>>) -> PyResult<usize> {
+        let stats = TreeStatsCollector::collect(&self.inner);
+        let nodes = stats.as_ref().map_or(0, |s| s.total_memory_usage());
+        let key_bytes = stats.as_ref().map_or(0, |s| s.leaf.sum_key_bytes);
+        let original_keys = self.original_keys.capacity()
+            * std::mem::size_of::<(Box<[u8]>, String)>()
+            + self
+                .original_keys
+                .iter()
+                .map(|(k, v)| k.len() + v.capacity())
+                .sum::<usize>();
+        let deadlines = self.deadlines.capacity() * std::mem::size_of::<(Box<[u8]>, Instant)>()
+            + self.deadlines.keys().map(|k| k.len()).sum::<usize>();
+        static GETSIZEOF: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+        let getsizeof = GETSIZEOF.import(py, "sys", "getsizeof")?;
+        let interned = match &self.interned {
+            Some(table) => getsizeof.call1((table,))?.extract::<usize>()?,
+            None => 0,
+        };
+        let mut total = nodes
+            + key_bytes
+            + original_keys
+            + deadlines
+            + self.indexes.memory_usage()
+            + self.history.as_ref().map_or(0, History::memory_usage)
+            + self.journal.as_ref().map_or(0, Journal::memory_usage)
+            + self.pickled.as_ref().map_or(0, PickleCache::memory_usage)
+            + interned
+            + self.observers.capacity() * std::mem::size_of::<Py<PyAny>>();
+        let Some(seen) = seen else {
+            return Ok(total);
+        };
+        let cached = self
+            .pickled
+            .as_ref()
+            .map_or_else(Vec::new, |cache| cache.values(py));
+        let values = self
+            .inner
+            .values()
+            .filter_map(Stored::object)
+            .chain(self.history.iter().flat_map(History::values))
+            .chain(self.journal.iter().flat_map(Journal::values))
+            .chain(&cached);
+        for value in values {
+            if !seen.insert(value.as_ptr() as usize) {
+                continue;
+            }
+            total += getsizeof.call1((value,))?.extract::<usize>()?;
+            if let Ok(tree) = value.bind(py).cast::<PyTreeMap>() {
+                if let Ok(tree) = tree.try_borrow() {
+                    total += tree.footprint(py, Some(seen))?;
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Stored keys that inserting `key_bytes` would evict, because one is a
+    /// prefix of the other. Stored keys never prefix one another, so this
+    /// is either one shorter key or any number of longer ones.
+    fn displaced(&self, key_bytes: &[u8]) -> Vec<Box<[u8]>> {
+        let mut evicted: Vec<Box<[u8]>> = self
+            .prefix_entries(key_bytes)
+            .filter(|(k, _)| ***k != *key_bytes)
+            .map(|(k, _)| k.clone())
+            .collect();
+        if let Some((k, _)) = self.inner.get_prefix_key_value(key_bytes) {
+            if key_bytes.starts_with(k) && **k != *key_bytes {
+                evicted.push(k.clone());
+            }
+        }
+        evicted
+    }
+
+    /// Force-insert an encoded key, keeping the original key bookkeeping in sync
+    /// with any prefix-conflicting keys that blart removes.
+    ///
+    /// Fails only when the tree is full and its eviction policy is "reject".
+    fn store(
+        &mut self,
+        key_bytes: Box<[u8]>,
+        original: &Bound<'_, PyAny>,
+        value: Py<PyAny>,
+    ) -> PyResult<()> {
+        let py = original.py();
+        self.purge_expired(py)?;
+        let value = self.value_in(py, value)?;
+        self.metrics.insert(1);
+        if let Some(slot) = self.inner.get_mut(&key_bytes) {
+            let old = std::mem::replace(slot, value);
+            self.record(py, &key_bytes, Some(&old));
+            if !self.deadlines.is_empty() {
+                // A new value lives until it is given a ttl of its own
+                self.deadlines.remove(&key_bytes);
+            }
+            if self.observers.is_empty() {
+                return Ok(());
+            }
+            let new = self.inner.get(&key_bytes);
+            let notice = self.notice(py, Change::Overwrite, &key_bytes, Some(&old), new);
+            return self.notify(py, notice?);
+        }
+        let mut notices: Vec<Notice> = self.make_room(py, &key_bytes)?.into_iter().collect();
+        self.record(py, &key_bytes, None);
+        if !self.deadlines.is_empty() {
+            self.deadlines.remove(&key_bytes);
+        }
+        self.generation += 1;
+        if self.case_insensitive
+            || self.indexes.is_active()
+            || !self.deadlines.is_empty()
+            || !self.observers.is_empty()
+            || self.journal.is_some()
+            || self.history.is_some()
+        {
+            for k in self.displaced(&key_bytes) {
+                notices.extend(self.notice(py, Change::Evict, &k, self.inner.get(&k), None)?);
+                self.record(py, &k, None);
+                self.original_keys.remove(&k);
+                self.indexes.remove(&k);
+                self.deadlines.remove(&k);
+            }
+            self.indexes.add(&key_bytes);
+        }
+        if self.case_insensitive && !self.original_keys.contains_key(&key_bytes) {
+            if let Ok(original) = original.extract::<String>() {
+                self.original_keys.insert(key_bytes.clone(), original);
+            }
+        }
+        let notice = self.notice(py, Change::Insert, &key_bytes, None, Some(&value));
+        let len = self.inner.len();
+        self.inner.force_insert(key_bytes, value);
+        self.metrics.evict(len + 1 - self.inner.len());
+        notices.extend(notice?);
+        self.notify(py, notices)
+    }
+
+    /// Evict an entry (or refuse) if adding `key_bytes` would grow the tree
+    /// beyond `max_len`. The eviction is returned for the caller to report.
+    fn make_room(&mut self, py: Python, key_bytes: &[u8]) -> PyResult<Option<Notice>> {
+        let Some(max_len) = self.max_len else {
+            return Ok(None);
+        };
+        // Keys the new one prefixes or extends make way for it anyway
+        if self.inner.len() < max_len || !self.displaced(key_bytes).is_empty() {
+            return Ok(None);
+        }
+        let victim: Option<Box<[u8]>> = match self.eviction {
+            Eviction::Reject => {
+                return Err(PyOverflowError::new_err(format!(
+                    "TreeMap is full (max_len={max_len})"
+                )))
+            }
+            Eviction::EvictFirst => self.inner.first_key_value().map(|(k, _)| k.clone()),
+            Eviction::EvictLast => self.inner.last_key_value().map(|(k, _)| k.clone()),
+            Eviction::EvictOldest => self
+                .indexes
+                .order
+                .as_ref()
+                .and_then(InsertionOrder::oldest)
+                .map(Box::from),
+        };
+        let Some(victim) = victim else {
+            return Ok(None);
+        };
+        let value = self.inner.remove(&victim);
+        let notice = self.notice(py, Change::Evict, &victim, value.as_ref(), None);
+        self.record(py, &victim, value.as_ref());
+        self.metrics.evict(1);
+        self.forget(&victim);
+        notice
+    }
+
+    /// Insert many pairs, walking the tree with the GIL released.
+    ///
+    /// All keys are encoded first, so a key that fails to encode leaves the
+    /// tree unchanged. Case-insensitive trees record original spellings as
+    /// they go and take the regular insert path instead, as do trees with a
+    /// substring index.
+    fn insert_all(
+        &mut self,
+        py: Python,
+        pairs: Vec<(Bound<'_, PyAny>, Py<PyAny>)>,
+    ) -> PyResult<()> {
+        self.insert_all_from(py, pairs, |_, err| err)
+    }
+
+    /// `insert_all()`, passing an error about the pair at an index through
+    /// `locate` so it can say where the pair came from.
+    fn insert_all_from(
+        &mut self,
+        py: Python,
+        pairs: Vec<(Bound<'_, PyAny>, Py<PyAny>)>,
+        locate: impl Fn(usize, PyErr) -> PyErr,
+    ) -> PyResult<()> {
+        if self.case_insensitive || self.tracks_changes() {
+            for (i, (key, value)) in pairs.into_iter().enumerate() {
+                self.insert(&key, value, None)
+                    .map_err(|err| locate(i, err))?;
+            }
+            return Ok(());
+        }
+        let entries = pairs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (key, value))| {
+                let key = self.encode_key(&key).map_err(|err| locate(i, err))?;
+                Ok((key, value))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        self.insert_encoded(py, entries)
+    }
+
+    /// Whether inserting a key involves more than the tree itself: indexes,
+    /// ttls, a size limit, observers, a transaction or a version history.
+    fn tracks_changes(&self) -> bool {
+        self.indexes.is_active()
+            || !self.deadlines.is_empty()
+            || self.max_len.is_some()
+            || !self.observers.is_empty()
+            || self.journal.is_some()
+            || self.history.is_some()
+    }
+
+    /// Build a tree of class `cls` from an Arrow array of keys and their
+    /// values.
+    fn from_key_column<'py>(
+        cls: &Bound<'py, PyType>,
+        keys: &Bound<'py, PyAny>,
+        values: Vec<Py<PyAny>>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let tree = cls.call((), kwargs)?;
+        let mut map = tree.cast::<PyTreeMap>()?.borrow_mut();
+        match arrow_keys(keys)? {
+            Some(raw) => map.insert_raw(py, raw, values)?,
+            None => {
+                let keys = keys.call_method0("to_pylist")?;
+                let keys = keys.try_iter()?.collect::<PyResult<Vec<_>>>()?;
+                map.insert_all(py, keys.into_iter().zip(values).collect())?;
+            }
+        }
+        drop(map);
+        Ok(tree)
+    }
+
+    /// Insert keys read from an array, one value per key.
+    fn insert_raw(&mut self, py: Python, raw: RawKeys, values: Vec<Py<PyAny>>) -> PyResult<()> {
+        let text = raw.text || self.codec.is_str();
+        if text && !self.codec.is_str() {
+            return Err(PyTypeError::new_err(
+                "text keys can only be inserted into TreeMaps with str keys",
+            ));
+        }
+        if text && self.codec.errors() == Some("strict") {
+            if let Some(i) = raw
+                .keys
+                .iter()
+                .position(|k| std::str::from_utf8(k).is_err())
+            {
+                return Err(PyValueError::new_err(format!(
+                    "key at index {i} is not valid UTF-8"
+                )));
+            }
+        }
+        if self.codec.is_verbatim() && !self.case_insensitive && self.normalize.is_none() {
+            return self.insert_encoded(py, raw.keys.into_iter().zip(values).collect());
+        }
+        // Keys have to go through the codec, or be case-folded or normalized
+        let keys = raw.keys.iter().map(|key| match text {
+            true => PyString::new(py, &String::from_utf8_lossy(key)).into_any(),
+            false => PyBytes::new(py, key).into_any(),
+        });
+        self.insert_all(py, keys.zip(values).collect())
+    }
+
+    /// Insert pairs of already encoded keys and values, with the GIL
+    /// released unless the tree tracks its changes.
+    ///
+    /// Not for case-insensitive trees, which need the original spelling of
+    /// each key.
+    fn insert_encoded(&mut self, py: Python, entries: Vec<(Box<[u8]>, Py<PyAny>)>) -> PyResult<()> {
+        if self.tracks_changes() {
+            let spelling = py.None().into_bound(py);
+            for (key, value) in entries {
+                self.store(key, &spelling, value)?;
+            }
+            return Ok(());
+        }
+        let converts = self.value_type.is_some()
+            || self.pickled.is_some()
+            || self.compression.is_some()
+            || self.interned.is_some();
+        let entries: Vec<(Box<[u8]>, Stored)> = match converts {
+            true => entries
+                .into_iter()
+                .map(|(key, value)| Ok((key, self.value_in(py, value)?)))
+                .collect::<PyResult<_>>()?,
+            false => entries
+                .into_iter()
+                .map(|(key, value)| (key, value.into()))
+                .collect(),
+        };
+        let len = self.inner.len();
+        let inner = &mut self.inner;
+        let (added, replaced) = py.detach(move || {
+            let mut added = 0;
+            let mut replaced = Vec::new();
+            for (key, value) in entries {
+                match inner.get_mut(&key) {
+                    Some(slot) => replaced.push(std::mem::replace(slot, value)),
+                    None => {
+                        inner.force_insert(key, value);
+                        added += 1;
+                    }
+                }
+            }
+            (added, replaced)
+        });
+        self.generation += added;
+        self.metrics.insert(added as usize + replaced.len());
+        // Keys that prefix or extend an inserted one were displaced
+        self.metrics.evict(len + added as usize - self.inner.len());
+        // Release the overwritten values while attached
+        drop(replaced);
+        Ok(())
+    }
+
+    /// Encode optional range bounds, each inclusive or exclusive.
+    fn range_bounds(
+        &self,
+        start: Option<&Bound<'_, PyAny>>,
+        stop: Option<&Bound<'_, PyAny>>,
+        (start_inclusive, stop_inclusive): (bool, bool),
+    ) -> PyResult<(KeyBound, KeyBound)> {
+        let encode = |key: Option<&Bound<'_, PyAny>>, inclusive: bool| {
+            Ok::<_, PyErr>(match key {
+                None => Unbounded,
+                Some(key) if inclusive => Included(self.encode_key(key)?),
+                Some(key) => Excluded(self.encode_key(key)?),
+            })
+        };
+        Ok((
+            encode(start, start_inclusive)?,
+            encode(stop, stop_inclusive)?,
+        ))
+    }
+
+    /// Number of keys between two bounds, from the order statistics when
+    /// they are kept and by walking the range otherwise.
+    fn count_between(&self, lower: std::ops::Bound<&[u8]>, upper: std::ops::Bound<&[u8]>) -> usize {
+        match &self.indexes.counts {
+            Some(index) => index.count(lower, upper),
+            None => self.bounded(lower, upper).count(),
+        }
+    }
+
+    /// Add the values of some entries to a running aggregate, naming the
+    /// key of any value that is not a number.
+    fn aggregate_into<'a, 'py>(
+        &'a self,
+        py: Python<'py>,
+        total: &mut Aggregate<'py>,
+        entries: impl Iterator<Item = (&'a Box<[u8]>, &'a Stored)>,
+    ) -> PyResult<()> {
+        for (key, value) in entries {
+            let value = self.value_out(py, value)?.into_bound(py);
+            if total.add(&value).is_err() {
+                return Err(PyTypeError::new_err(format!(
+                    "cannot aggregate value of {}: {} is not a number",
+                    self.decode_key(py, key)?.bind(py).repr()?,
+                    value.get_type().name()?
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Distinct segments that follow `dir` in the stored keys, up to the
+    /// next `separator`, in key order.
+    ///
+    /// Once a key is seen to continue past a separator, every other key in
+    /// that child is skipped with a single seek.
+    fn next_segments(&self, dir: &[u8], separator: &[u8]) -> Vec<Box<[u8]>> {
+        let mut segments = BTreeSet::new();
+        let mut lower = Included(dir.to_vec());
+        while let Some(key) = self.seek(lower.as_ref().map(Vec::as_slice)) {
+            let Some(rest) = key.strip_prefix(dir) else {
+                break;
+            };
+            let end = rest
+                .windows(separator.len())
+                .position(|window| window == separator);
+            let segment = &rest[..end.unwrap_or(rest.len())];
+            if !rest.is_empty() {
+                segments.insert(Box::<[u8]>::from(segment));
+            }
+            lower = match end {
+                Some(end) => {
+                    let child = &key[..dir.len() + end];
+                    match successor(separator) {
+                        Some(next) => Included([child, &next].concat()),
+                        None => break,
+                    }
+                }
+                None => Excluded(key.to_vec()),
+            };
+        }
+        segments.into_iter().collect()
+    }
+
+    /// Iterate in key order over the entries between two bounds.
+    fn bounded<'a>(
+        &'a self,
+        lower: std::ops::Bound<&[u8]>,
+        upper: std::ops::Bound<&'a [u8]>,
+    ) -> impl Iterator<Item = (&'a Box<[u8]>, &'a Stored)> {
+        self.iter_from(lower).take_while(move |(k, _)| match upper {
+            Included(upper) => &***k <= upper,
+            Excluded(upper) => &***k < upper,
+            Unbounded => true,
+        })
+    }
+
+    /// Start logging changes for a transaction.
+    pub(crate) fn begin(&mut self) -> PyResult<()> {
+        if self.journal.is_some() {
+            return Err(PyRuntimeError::new_err(
+                "a transaction is already open on this TreeMap",
+            ));
+        }
+        self.journal = Some(Journal::default());
+        Ok(())
+    }
+
+    /// Keep the changes made in the open transaction.
+    pub(crate) fn commit(&mut self) {
+        self.journal = None;
+    }
+
+    /// Restore every key changed in the open transaction to its prior
+    /// state, and report the restoring changes to the observers.
+    pub(crate) fn rollback(&mut self, py: Python) -> PyResult<()> {
+        let Some(journal) = self.journal.take() else {
+            return Ok(());
+        };
+        let mut saved = journal.saved.into_iter().collect::<Vec<_>>();
+        saved.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        // Take out the current entries first: the saved ones were stored
+        // together before, so they can't displace each other going back in
+        let mut notices = Vec::new();
+        let mut current = Vec::with_capacity(saved.len());
+        for (key, before) in &saved {
+            let value = self.inner.remove(key);
+            if let Some(history) = &mut self.history {
+                history.record(py, key, value.as_ref(), self.original_keys.get(key));
+            }
+            if before.value.is_none() {
+                notices.extend(self.notice(py, Change::Remove, key, value.as_ref(), None)?);
+            }
+            if self.case_insensitive {
+                self.original_keys.remove(key);
+            }
+            self.indexes.remove(key);
+            self.deadlines.remove(key);
+            current.push(value);
+        }
+        for ((key, before), value) in saved.into_iter().zip(current) {
+            let Some(restored) = before.value else {
+                continue;
+            };
+            if let Some(spelling) = before.spelling {
+                self.original_keys.insert(key.clone(), spelling);
+            }
+            if let Some(deadline) = before.deadline {
+                self.set_deadline(key.clone(), deadline);
+            }
+            self.indexes.add(&key);
+            let change = match value {
+                Some(_) => Change::Overwrite,
+                None => Change::Insert,
+            };
+            notices.extend(self.notice(py, change, &key, value.as_ref(), Some(&restored))?);
+            self.inner.force_insert(key, restored);
+        }
+        self.generation += 1;
+        self.notify(py, notices)
+    }
+
+    /// Log the state of a key before a change to it, for the open
+    /// transaction and the version history. `prior` is its value if it was
+    /// just taken out of the tree or replaced; otherwise the stored value is
+    /// looked up.
+    fn record(&mut self, py: Python, key_bytes: &[u8], prior: Option<&Stored>) {
+        if self.journal.is_none() && self.history.is_none() {
+            return;
+        }
+        let value = prior.or_else(|| self.inner.get(key_bytes));
+        let spelling = self.original_keys.get(key_bytes);
+        if let Some(journal) = &mut self.journal {
+            let deadline = self.deadlines.get(key_bytes);
+            journal.record(py, key_bytes, value, spelling, deadline);
+        }
+        if let Some(history) = &mut self.history {
+            history.record(py, key_bytes, value, spelling);
+        }
+    }
+
+    /// Describe a change for the observers, or None if there are none.
+    ///
+    /// Must be called while the key's bookkeeping is still in place, so
+    /// case-insensitive keys decode to their original spelling.
+    fn notice(
+        &self,
+        py: Python,
+        change: Change,
+        key_bytes: &[u8],
+        old: Option<&Stored>,
+        new: Option<&Stored>,
+    ) -> PyResult<Option<Notice>> {
+        if self.observers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Notice {
+            change,
+            key: self.decode_key(py, key_bytes)?,
+            old: old.map(|v| self.value_out(py, v)).transpose()?,
+            new: new.map(|v| self.value_out(py, v)).transpose()?,
+        }))
+    }
+
+    /// Call every observer with each change, in order.
+    fn notify(&self, py: Python, notices: impl IntoIterator<Item = Notice>) -> PyResult<()> {
+        for notice in notices {
+            for observer in &self.observers {
+                observer.bind(py).call1((
+                    notice.change.name(),
+                    notice.key.clone_ref(py),
+                    notice.old.as_ref().map(|v| v.clone_ref(py)),
+                    notice.new.as_ref().map(|v| v.clone_ref(py)),
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the bookkeeping for a key whose `value` was just removed from
+    /// the tree, and report the removal.
+    fn discard(
+        &mut self,
+        py: Python,
+        key_bytes: &[u8],
+        value: &Stored,
+        change: Change,
+    ) -> PyResult<()> {
+        let notice = self.notice(py, change, key_bytes, Some(value), None);
+        self.record(py, key_bytes, Some(value));
+        match change {
+            Change::Evict => self.metrics.evict(1),
+            _ => self.metrics.remove(1),
+        }
+        self.forget(key_bytes);
+        self.notify(py, notice?)
+    }
+
+    /// Drop the bookkeeping for an encoded key that was removed from the tree.
+    fn forget(&mut self, key_bytes: &[u8]) {
+        self.generation += 1;
+        if self.case_insensitive {
+            self.original_keys.remove(key_bytes);
+        }
+        self.indexes.remove(key_bytes);
+        self.deadlines.remove(key_bytes);
+    }
+
+    /// Update a key in a single traversal of the tree.
+    ///
+    /// `update` receives the current value (if any) and returns the value to
+    /// store, or None to leave the entry untouched. Returns the value stored
+    /// under the key afterwards, or None if the key is still absent.
+    fn upsert<F>(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        update: F,
+    ) -> PyResult<Option<Py<PyAny>>>
+    where
+        F: FnOnce(Option<&Py<PyAny>>) -> PyResult<Option<Py<PyAny>>>,
+    {
+        self.purge_expired(py)?;
+        let key_bytes = self.encode_key(key)?;
+        if !self.observers.is_empty()
+            || self.journal.is_some()
+            || self.history.is_some()
+            || self.pickled.is_some()
+            || self.compression.is_some()
+            || (self.max_len.is_some() && !self.inner.contains_key(&key_bytes))
+        {
+            // Storing may have to evict another entry first, and reports
+            // the change to observers and the open transaction. Pickled
+            // values are unpickled for `update` and pickled again by store()
+            let current = self
+                .inner
+                .get(&key_bytes)
+                .map(|v| self.value_out(py, v))
+                .transpose()?;
+            return match update(current.as_ref())? {
+                Some(value) => {
+                    let value = ValueType::check(self.value_type, py, value)?;
+                    self.store(key_bytes, key, value.clone_ref(py))?;
+                    Ok(Some(value))
+                }
+                None => Ok(current),
+            };
+        }
+        // Without pickling or compression, a stored value is the value
+        // itself or its native form
+        let value_type = self.value_type;
+        let interned = self.interned.as_ref().map(|table| table.bind(py));
+        match self.inner.try_entry(key_bytes) {
+            Ok(Entry::Occupied(mut entry)) => {
+                let current = entry.get().to_object(py)?;
+                match update(Some(&current))? {
+                    Some(value) => {
+                        let stored = stored_value(value_type, false, None, interned, py, value)?;
+                        let value = stored.to_object(py)?;
+                        entry.insert(stored);
+                        self.metrics.insert(1);
+                        Ok(Some(value))
+                    }
+                    None => Ok(Some(current)),
+                }
+            }
+            Ok(Entry::Vacant(entry)) => match update(None)? {
+                Some(value) => {
+                    let stored = stored_value(value_type, false, None, interned, py, value)?;
+                    let value = stored.to_object(py)?;
+                    let key_bytes = entry.key().clone();
+                    entry.insert(stored);
+                    self.generation += 1;
+                    self.metrics.insert(1);
+                    self.indexes.add(&key_bytes);
+                    if self.case_insensitive {
+                        self.original_keys.insert(key_bytes, key.extract()?);
+                    }
+                    Ok(Some(value))
+                }
+                None => Ok(None),
+            },
+            // The key conflicts with a stored prefix, fall back to the same
+            // replacement semantics as insert()
+            Err(err) => match update(None)? {
+                Some(value) => {
+                    let value = ValueType::check(value_type, py, value)?;
+                    self.store(err.byte_repr, key, value.clone_ref(py))?;
+                    Ok(Some(value))
+                }
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// Build the search for `fuzzy_search()` and `fuzzy_prefix()`, and
+    /// whether its distances are weighted costs rather than edit counts.
+    fn fuzzy_cursor(
+        &self,
+        query: &[u8],
+        max_distance: &Bound<'_, PyAny>,
+        metric: &str,
+        [insert_cost, delete_cost, substitute_cost]: [Option<f64>; 3],
+        substitution_costs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<(FuzzyCursor, bool)> {
+        let metric = Metric::from_name(metric)?;
+        let chars = self.codec.is_str();
+        let weighted = insert_cost.is_some()
+            || delete_cost.is_some()
+            || substitute_cost.is_some()
+            || substitution_costs.is_some();
+        let max_distance = if weighted {
+            let max_distance: f64 = max_distance.extract()?;
+            if max_distance.is_nan() || max_distance < 0.0 {
+                return Err(PyValueError::new_err(format!(
+                    "max_distance must be non-negative, got {max_distance}"
+                )));
+            }
+            max_distance
+        } else {
+            max_distance.extract::<usize>()? as f64
+        };
+        let costs = Costs::from_args(
+            insert_cost,
+            delete_cost,
+            substitute_cost,
+            substitution_costs,
+            chars,
+        )?;
+        Ok((
+            FuzzyCursor::new(query, max_distance, chars, metric, costs),
+            weighted,
+        ))
+    }
+
+    /// Closest stored keys to `query`, ranked by distance and then by
+    /// value as a frequency, as returned by `suggest()`.
+    fn suggestions(
+        &self,
+        py: Python,
+        query: &[u8],
+        n: usize,
+        max_distance: usize,
+        metric: Metric,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        self.metrics.fuzzy_scan(1);
+        let cursor = FuzzyCursor::new(
+            query,
+            max_distance as f64,
+            self.codec.is_str(),
+            metric,
+            Costs::default(),
+        );
+        let ranked = py.detach(|| cursor.ranked(|bound| self.seek(bound), None));
+        let mut candidates: Vec<_> = ranked
+            .into_iter()
+            .filter_map(|(distance, key)| {
+                let value = self.value_out(py, self.inner.get(key)?);
+                let frequency = value
+                    .and_then(|value| value.bind(py).extract::<f64>())
+                    .unwrap_or(f64::NEG_INFINITY);
+                Some((distance, frequency, key))
+            })
+            .collect();
+        // Stable, so keys with the same distance and frequency stay in order
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(b.1.total_cmp(&a.1)));
+        candidates
+            .into_iter()
+            .take(n)
+            .map(|(_, _, key)| self.decode_key(py, key))
+            .collect()
+    }
+
+    /// Build the KeyError for a missing key, naming the closest stored keys
+    /// when `did_you_mean` is enabled.
+    fn missing_key(&self, py: Python, key: &Bound<'_, PyAny>, key_bytes: &[u8]) -> PyErr {
+        if !self.did_you_mean || !self.codec.is_str() {
+            return key_error(key);
+        }
+        // Allow one typo in short keys and two in longer ones
+        let max_distance = if key_bytes.len() <= 4 { 1 } else { 2 };
+        let candidates = self
+            .suggestions(py, key_bytes, MAX_CANDIDATES, max_distance, Metric::Damerau)
+            .and_then(|keys| {
+                keys.iter()
+                    .map(|k| Ok(k.bind(py).repr()?.to_string()))
+                    .collect::<PyResult<Vec<_>>>()
+            });
+        let (Ok(candidates), Ok(repr)) = (candidates, key.repr()) else {
+            return key_error(key);
+        };
+        match candidates.split_last() {
+            None => key_error(key),
+            Some((last, [])) => PyKeyError::new_err(format!("{repr} (did you mean {last}?)")),
+            Some((last, rest)) => PyKeyError::new_err(format!(
+                "{repr} (did you mean {} or {last}?)",
+                rest.join(", ")
+            )),
+        }
+    }
+
+    /// Bytes of a text to match keys against, with the text index of every
+    /// position where a match may start or end.
+    ///
+    /// Str text is indexed by character. In case-insensitive trees it is
+    /// case folded one character at a time, so positions inside a character
+    /// that folds to several (such as "ß" to "ss") are not boundaries.
+    fn haystack(&self, text: &Bound<'_, PyAny>) -> PyResult<Haystack> {
+        if !self.codec.is_str() {
+            let bytes = text.cast::<PyBytes>()?.as_bytes().to_vec();
+            let offsets = (0..=bytes.len()).map(Some).collect();
+            return Ok(Haystack { bytes, offsets });
+        }
+        let text = text.cast::<PyString>()?.to_cow()?;
+        let mut bytes = Vec::with_capacity(text.len());
+        let mut offsets = Vec::with_capacity(text.len() + 1);
+        let mut buf = [0; 4];
+        for (index, c) in text.chars().enumerate() {
+            if self.case_insensitive {
+                for folded in std::iter::once(c).default_case_fold() {
+                    bytes.extend_from_slice(folded.encode_utf8(&mut buf).as_bytes());
+                }
+            } else {
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            offsets.push(Some(index));
+            offsets.resize(bytes.len(), None);
+        }
+        offsets.push(Some(text.chars().count()));
+        Ok(Haystack { bytes, offsets })
+    }
+
+    /// Walk the entries under an optional prefix for `traverse`.
+    fn traverse_nodes(
+        slf: PyRef<'_, Self>,
+        py: Python,
+        node_factory: Py<PyAny>,
+        prefix: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        if !slf.codec.is_verbatim() {
+            return Err(PyTypeError::new_err("traverse requires str or bytes keys"));
+        }
+        let prefix_bytes = match prefix {
+            Some(prefix) => slf.encode_prefix(prefix)?,
+            None => Box::default(),
+        };
+        let entries = slf
+            .trie_entries(prefix)?
+            .map(|(k, v)| Ok((k.clone(), slf.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        let text = slf.codec.is_str();
+        // The node factory may modify the tree
+        drop(slf);
+        trie::traverse(py, entries, text, node_factory, &prefix_bytes)
+    }
+
+    /// The entries under an optional prefix, for the pygtrie-style
+    /// methods, which reject a prefix no stored key starts with.
+    fn trie_entries<'a>(
+        &'a self,
+        prefix: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<impl Iterator<Item = (&'a Box<[u8]>, &'a Stored)>> {
+        let prefix_bytes = match prefix {
+            Some(prefix) => self.encode_prefix(prefix)?,
+            None => Box::default(),
+        };
+        let entries: Vec<_> = self.prefix_entries(&prefix_bytes).collect();
+        match prefix {
+            Some(prefix) if entries.is_empty() => Err(key_error(prefix)),
+            _ => Ok(entries.into_iter()),
+        }
+    }
+
+    /// The stored key that `text` starts with, if any.
+    ///
+    /// Stored keys never prefix one another, so there is at most one.
+    fn key_at<'a>(&'a self, text: &[u8]) -> Option<&'a [u8]> {
+        let (key, _) = self.inner.get_prefix_key_value(text)?;
+        (!key.is_empty() && text.starts_with(key)).then_some(&**key)
+    }
+
+    /// The stored key starting at a byte position of a haystack, if it also
+    /// ends on a character boundary.
+    fn key_in<'a>(&'a self, haystack: &Haystack, i: usize) -> Option<&'a [u8]> {
+        haystack.offsets[i]?;
+        let key = self.key_at(&haystack.bytes[i..])?;
+        haystack.offsets[i + key.len()].map(|_| key)
+    }
+
+    /// Split a haystack into (start, end, key) byte ranges, taking the key
+    /// chosen at each position and merging the unmatched bytes between keys
+    /// into ranges with a key of None.
+    fn segment_with<'a, F>(
+        &self,
+        len: usize,
+        mut choose: F,
+    ) -> Vec<(usize, usize, Option<&'a [u8]>)>
+    where
+        F: FnMut(usize) -> Option<&'a [u8]>,
+    {
+        let mut tokens = Vec::new();
+        let mut unmatched = None;
+        let mut i = 0;
+        while i < len {
+            match choose(i) {
+                Some(key) => {
+                    if let Some(start) = unmatched.take() {
+                        tokens.push((start, i, None));
+                    }
+                    tokens.push((i, i + key.len(), Some(key)));
+                    i += key.len();
+                }
+                None => {
+                    unmatched.get_or_insert(i);
+                    i += 1;
+                }
+            }
+        }
+        if let Some(start) = unmatched {
+            tokens.push((start, len, None));
+        }
+        tokens
+    }
+
+    /// Choose, by dynamic programming from the end of the text, the key to
+    /// take at each position so that the fewest characters are left
+    /// unmatched, then the fewest keys are used.
+    fn optimal_keys<'a>(&'a self, haystack: &Haystack) -> Vec<Option<&'a [u8]>> {
+        let n = haystack.bytes.len();
+        // (unmatched characters, keys used) for the rest of the text
+        let mut cost = vec![(0usize, 0usize); n + 1];
+        let mut choice = vec![None; n + 1];
+        for i in (0..n).rev() {
+            let skipped = usize::from(haystack.offsets[i].is_some());
+            cost[i] = (cost[i + 1].0 + skipped, cost[i + 1].1);
+            if let Some(key) = self.key_in(haystack, i) {
+                let (unmatched, keys) = cost[i + key.len()];
+                if (unmatched, keys + 1) <= cost[i] {
+                    cost[i] = (unmatched, keys + 1);
+                    choice[i] = Some(key);
+                }
+            }
+        }
+        choice
+    }
+
+    /// Run a fuzzy search lazily, or rank its best matches up front when
+    /// sorted by distance.
+    fn fuzzy_results(
+        slf: PyRef<'_, Self>,
+        py: Python,
+        cursor: FuzzyCursor,
+        weighted: bool,
+        max_results: Option<usize>,
+        sort_by_distance: bool,
+    ) -> PyResult<PyFuzzyIter> {
+        slf.metrics.fuzzy_scan(1);
+        if !sort_by_distance {
+            return Ok(PyFuzzyIter::lazy(
+                cursor,
+                IterGuard::new(&slf),
+                max_results,
+                weighted,
+            ));
+        }
+        let map = &*slf;
+        let ranked = py.detach(|| cursor.ranked(|bound| map.seek(bound), max_results));
+        let items = ranked
+            .into_iter()
+            .filter_map(|(distance, k)| map.inner.get(k).map(|v| (k, v, distance)))
+            .map(|(k, v, distance)| {
+                Ok((
+                    map.decode_key(py, k)?,
+                    map.value_out(py, v)?,
+                    fuzzy_distance(py, distance, weighted)?,
+                ))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyFuzzyIter::new(items, IterGuard::new(&slf)))
+    }
+}
+
+/// Text prepared for matching stored keys inside it.
+struct Haystack {
+    bytes: Vec<u8>,
+    /// Index into the original text for each byte position (and the end),
+    /// or None inside a character
+    offsets: Vec<Option<usize>>,
+}
+
+/// What a TreeMap at its `max_len` does with a new key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Eviction {
+    /// Raise OverflowError
+    Reject,
+    /// Remove the smallest key
+    EvictFirst,
+    /// Remove the largest key
+    EvictLast,
+    /// Remove the key inserted longest ago
+    EvictOldest,
+}
+
+impl Eviction {
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "reject" => Ok(Self::Reject),
+            "evict_first" => Ok(Self::EvictFirst),
+            "evict_last" => Ok(Self::EvictLast),
+            "evict_oldest" => Ok(Self::EvictOldest),
+            _ => Err(PyValueError::new_err(format!(
+                "eviction must be 'reject', 'evict_first', 'evict_last' or 'evict_oldest', \
+                 got '{name}'"
+            ))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Reject => "reject",
+            Self::EvictFirst => "evict_first",
+            Self::EvictLast => "evict_last",
+            Self::EvictOldest => "evict_oldest",
+        }
+    }
+}
+
+/// The value a tree hands out for a stored one: decompressed if it was
+/// compressed, and unpickled through the tree's cache if it stores values
+/// pickled.
+fn original_value(
+    pickled: Option<&PickleCache>,
+    compression: Option<&Compression>,
+    py: Python,
+    stored: &Stored,
+) -> PyResult<Py<PyAny>> {
+    let Stored::Object(stored) = stored else {
+        return stored.to_object(py);
+    };
+    let decompressed = || match compression {
+        Some(compression) => compression.decompress(py, stored),
+        None => Ok(stored.clone_ref(py)),
+    };
+    match pickled {
+        Some(cache) => cache.load(py, stored, decompressed),
+        None => decompressed(),
+    }
+}
+
+/// Convert a value to the form a tree stores it in: a native value of its
+/// `value_type`, or else pickled if the tree stores values pickled,
+/// compressed if it is large enough, and replaced by an equal value already
+/// in its intern table.
+fn stored_value(
+    value_type: Option<ValueType>,
+    pickled: bool,
+    compression: Option<&Compression>,
+    interned: Option<&Bound<'_, PyDict>>,
+    py: Python,
+    value: Py<PyAny>,
+) -> PyResult<Stored> {
+    // Pickling, compressing and interning work on Python objects, so typed
+    // values combined with them stay objects of their type
+    if let (Some(value_type), false, None, None) = (value_type, pickled, compression, interned) {
+        return value_type.native(value.bind(py));
+    }
+    let value = ValueType::check(value_type, py, value)?;
+    let value = match pickled {
+        true => PickleCache::dump(py, &value)?,
+        false => value,
+    };
+    let value = match compression {
+        Some(compression) => compression.compress(py, value)?,
+        None => value,
+    };
+    let Some(table) = interned else {
+        return Ok(value.into());
+    };
+    let Some(key) = intern_key(value.bind(py))? else {
+        return Ok(value.into());
+    };
+    if let Some(existing) = table.get_item(&key)? {
+        return Ok(existing.unbind().into());
+    }
+    table.set_item(key, &value)?;
+    Ok(value.into())
+}
+
+/// Key of an immutable value in an intern table, or None for values that
+/// aren't interned. The type is part of the key so that equal values of
+/// different types, such as 1, 1.0 and True, stay distinct; floats are keyed
+/// by their bits so 0.0 and -0.0 do too.
+fn intern_key<'py>(value: &Bound<'py, PyAny>) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let py = value.py();
+    let of_type = |key: Bound<'py, PyAny>| PyTuple::new(py, [value.get_type().into_any(), key]);
+    if value.is_exact_instance_of::<PyString>()
+        || value.is_exact_instance_of::<PyBytes>()
+        || value.is_exact_instance_of::<PyInt>()
+        || value.is_exact_instance_of::<PyBool>()
+        || value.is_none()
+    {
+        return Ok(Some(of_type(value.clone())?.into_any()));
+    }
+    if let Ok(compressed) = value.cast_exact::<Compressed>() {
+        // Compression is deterministic, so equal values compress alike
+        return Ok(Some(of_type(compressed.get().contents(py)?)?.into_any()));
+    }
+    if value.is_exact_instance_of::<PyFloat>() {
+        let bits = value.extract::<f64>()?.to_bits();
+        return Ok(Some(
+            of_type(bits.into_pyobject(py)?.into_any())?.into_any(),
+        ));
+    }
+    if let Ok(tuple) = value.cast_exact::<PyTuple>() {
+        let mut keys = Vec::with_capacity(tuple.len());
+        for item in tuple.iter() {
+            match intern_key(&item)? {
+                Some(key) => keys.push(key),
+                None => return Ok(None),
+            }
+        }
+        return Ok(Some(
+            of_type(PyTuple::new(py, keys)?.into_any())?.into_any(),
+        ));
+    }
+    Ok(None)
+}
+
+/// Kinds of change reported to `on_change()` callbacks.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Change {
+    /// A new key was stored
+    Insert,
+    /// The value of a stored key was replaced
+    Overwrite,
+    /// A key was removed on request
+    Remove,
+    /// A key was removed by the tree itself: displaced by a key it
+    /// prefixes or extends, evicted by `max_len`, or expired
+    Evict,
+}
+
+impl Change {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Overwrite => "overwrite",
+            Self::Remove => "remove",
+            Self::Evict => "evict",
+        }
+    }
+}
+
+/// A change waiting to be reported to the observers, with its key decoded.
+struct Notice {
+    change: Change,
+    key: Py<PyAny>,
+    old: Option<Py<PyAny>>,
+    new: Option<Py<PyAny>>,
+}
+
+/// Operations accepted by `aggregate()`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AggregateOp {
+    Sum,
+    Min,
+    Max,
+    Mean,
+    Count,
+}
+
+impl AggregateOp {
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "sum" => Ok(Self::Sum),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            "mean" => Ok(Self::Mean),
+            "count" => Ok(Self::Count),
+            _ => Err(PyValueError::new_err(format!(
+                "op must be 'sum', 'min', 'max', 'mean' or 'count', got '{name}'"
+            ))),
+        }
+    }
+}
+
+/// Running totals of the numeric values seen by `aggregate()`.
+struct Aggregate<'py> {
+    op: AggregateOp,
+    count: usize,
+    /// Exact sum while every value is an integer that fits
+    int_sum: Option<i128>,
+    float_sum: f64,
+    /// Whether a value that is not an integer was seen
+    inexact: bool,
+    min: Option<(f64, Bound<'py, PyAny>)>,
+    max: Option<(f64, Bound<'py, PyAny>)>,
+}
+
+impl<'py> Aggregate<'py> {
+    fn new(op: AggregateOp) -> Self {
+        Self {
+            op,
+            count: 0,
+            int_sum: Some(0),
+            float_sum: 0.0,
+            inexact: false,
+            min: None,
+            max: None,
+        }
+    }
+
+    fn add(&mut self, value: &Bound<'py, PyAny>) -> PyResult<()> {
+        if self.op == AggregateOp::Count {
+            self.count += 1;
+            return Ok(());
+        }
+        let number: f64 = value.extract()?;
+        self.count += 1;
+        self.float_sum += number;
+        match value.extract::<i64>() {
+            Ok(int) if !self.inexact => {
+                self.int_sum = self
+                    .int_sum
+                    .and_then(|sum| sum.checked_add(i128::from(int)));
+            }
+            _ => self.inexact = true,
+        }
+        if self.min.as_ref().is_none_or(|(min, _)| number < *min) {
+            self.min = Some((number, value.clone()));
+        }
+        if self.max.as_ref().is_none_or(|(max, _)| number > *max) {
+            self.max = Some((number, value.clone()));
+        }
+        Ok(())
+    }
+
+    fn finish(self, py: Python<'py>) -> PyResult<Py<PyAny>> {
+        let object = |value: Option<(f64, Bound<'py, PyAny>)>| match value {
+            Some((_, value)) => value.unbind(),
+            None => py.None(),
+        };
+        Ok(match self.op {
+            AggregateOp::Count => self.count.into_pyobject(py)?.into_any().unbind(),
+            AggregateOp::Sum => match self.int_sum {
+                Some(sum) if !self.inexact => sum.into_pyobject(py)?.into_any().unbind(),
+                _ => self.float_sum.into_pyobject(py)?.into_any().unbind(),
+            },
+            AggregateOp::Mean if self.count == 0 => py.None(),
+            AggregateOp::Mean => (self.float_sum / self.count as f64)
+                .into_pyobject(py)?
+                .into_any()
+                .unbind(),
+            AggregateOp::Min => object(self.min),
+            AggregateOp::Max => object(self.max),
+        })
+    }
+}
+
+/// Range bound on an encoded key.
+type KeyBound = std::ops::Bound<Box<[u8]>>;
+
+/// Added, removed and changed keys reported by `diff`. Added and changed
+/// entries are keys, or (key, value) pairs when values are requested.
+type KeyDiff = (Vec<Py<PyAny>>, Vec<Py<PyAny>>, Vec<Py<PyAny>>);
+
+/// Entries of a page from `prefix_page`, and the token for the next page.
+type Page = (Vec<(Py<PyAny>, Py<PyAny>)>, Option<Py<PyAny>>);
+
+/// Borrow the key of an owned range bound.
+fn bound_ref(bound: &KeyBound) -> std::ops::Bound<&[u8]> {
+    match bound {
+        Included(key) => Included(key),
+        Excluded(key) => Excluded(key),
+        Unbounded => Unbounded,
+    }
+}
+
+/// Unicode normalization forms accepted by the `normalize` option.
+const NORMALIZATION_FORMS: [&str; 4] = ["NFC", "NFD", "NFKC", "NFKD"];
+
+/// Apply a Unicode normalization form to a key.
+///
+/// Returns None when the key is unchanged. ASCII strings are invariant under
+/// every normalization form, and most other keys pass the quick check, so
+/// only keys that really change are copied.
+fn normalize_key(form: &str, key: &str) -> Option<String> {
+    if key.is_ascii() {
+        return None;
+    }
+    let quick = match form {
+        "NFD" => is_nfd_quick(key.chars()),
+        "NFKC" => is_nfkc_quick(key.chars()),
+        "NFKD" => is_nfkd_quick(key.chars()),
+        _ => is_nfc_quick(key.chars()),
+    };
+    if quick == IsNormalized::Yes {
+        return None;
+    }
+    let normalized: String = match form {
+        "NFD" => key.nfd().collect(),
+        "NFKC" => key.nfkc().collect(),
+        "NFKD" => key.nfkd().collect(),
+        _ => key.nfc().collect(),
+    };
+    (normalized != key).then_some(normalized)
+}
+
+/// Decode a stored key, preferring the original spelling when one was recorded.
+fn decode_key(
+    py: Python,
+    codec: &KeyCodec,
+    original_keys: &HashMap<Box<[u8]>, String>,
+    key: &[u8],
+) -> PyResult<Py<PyAny>> {
+    match original_keys.get(key) {
+        Some(original) => Ok(PyString::new(py, original).into_any().unbind()),
+        None => codec.decode(py, key),
+    }
+}
+
+/// `types.GenericAlias(cls, params)`, for the `__class_getitem__` of the
+/// generic collection classes.
+pub(crate) fn generic_alias<'py>(
+    cls: &Bound<'py, PyType>,
+    params: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    static GENERIC_ALIAS: PyOnceLock<Py<PyType>> = PyOnceLock::new();
+    GENERIC_ALIAS
+        .import(cls.py(), "types", "GenericAlias")?
+        .call1((cls, params))
+}
+
+/// Number of stored keys listed by a did-you-mean KeyError.
+const MAX_CANDIDATES: usize = 3;
+
+/// Number of entries `repr()` shows, set with `TreeMap.set_repr_limit()`.
+static REPR_LIMIT: AtomicUsize = AtomicUsize::new(5);
+
+/// Build the KeyError raised for a missing key.
+/// Raise ValueError unless there is one value per key.
+fn check_lengths(keys: usize, values: usize) -> PyResult<()> {
+    if keys != values {
+        return Err(PyValueError::new_err(format!(
+            "got {keys} keys but {values} values"
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn key_error(key: &Bound<'_, PyAny>) -> PyErr {
+    PyKeyError::new_err(key.clone().unbind())
+}
+
+#[pymethods]
+impl PyTreeMap {
+    /// Create a new TreeMap.
+    ///
+    /// Args:
+    ///     data: Optional initial data. Can be:
+    ///         - None: Creates an empty TreeMap
+    ///         - dict or other mapping (including a TreeMap): Creates TreeMap
+    ///           from its items
+    ///         - iterable of pairs: Creates TreeMap from [(key, value), ...]
+    ///     default_factory: Optional callable used by tree[key] to create values
+    ///         for missing keys, like collections.defaultdict
+    ///     key_type: How keys are stored. Either "str" (the default), "bytes",
+    ///         "int" (signed 64-bit), "uint" (unsigned 64-bit), "tuple",
+    ///         "datetime", "uuid", "ip", or a codec object with
+    ///         `encode(key) -> bytes` and `decode(data) -> key` methods.
+    ///         Codecs must preserve ordering. Ints and floats in "tuple"
+    ///         keys compare by value, so (1,) and (1.0,) are the same key
+    ///         and integral floats come back as ints.
+    ///     separator: Separator used to normalize "path" keys (default "/")
+    ///     errors: How str keys that are not valid UTF-8 are returned:
+    ///         "strict" (raise UnicodeDecodeError, the default), "replace",
+    ///         "surrogateescape" (lossless round-trip), or "bytes" (return
+    ///         them as bytes; bytes keys are then accepted on input too)
+    ///     case_insensitive: If True, keys are compared case-insensitively,
+    ///         using full Unicode case folding ("Straße" and "STRASSE" are the
+    ///         same key). Iteration returns keys as they were first inserted.
+    ///     normalize: Optional Unicode normalization form ("NFC", "NFD", "NFKC"
+    ///         or "NFKD") applied to keys on insert and lookup, so canonically
+    ///         equivalent strings map to the same key.
+    ///     did_you_mean: If True, KeyErrors for missing str keys list the
+    ///         closest stored keys, e.g. `KeyError: "'colr' (did you mean
+    ///         'color'?)"`. Can be changed later through the attribute.
+    ///     substring_index: If True, also index every suffix of every key so
+    ///         `contains_substring()` can find keys containing a fragment.
+    ///         Costs memory proportional to the total key length. Requires
+    ///         str or bytes keys.
+    ///     order_statistics: If True, also keep every key in a tree that
+    ///         records subtree sizes, so `count_range()`, `count_prefix()`,
+    ///         `rank()` and `select()` take O(log n) time instead of walking
+    ///         the keys. Costs a second copy of the keys and slower inserts.
+    ///     max_len: Optional limit on the number of entries, enforced on
+    ///         every insert
+    ///     eviction: What inserting a new key into a full tree does:
+    ///         "reject" (raise OverflowError, the default), "evict_first" or
+    ///         "evict_last" (remove the smallest or largest key), or
+    ///         "evict_oldest" (remove the key inserted longest ago)
+    ///     loader: Optional callable consulted when `get()` or tree[key] miss.
+    ///         `loader(key)` is stored under the key and returned, turning the
+    ///         tree into a read-through cache. Exceptions it raises surface as
+    ///         a KeyError for the key.
+    ///     versioned: If True, every change to a key bumps `version` and is
+    ///         logged, so `at_version()` can return the tree as it was at any
+    ///         earlier version. The log keeps the key and prior value of
+    ///         every change, so it grows with every change unless bounded
+    ///         by max_versions or trimmed with `prune_versions()`.
+    ///     max_versions: Optional number of most recent changes a versioned
+    ///         tree keeps; versions older than that can no longer be read
+    ///     value_type: Optional type every value must have: "int" (signed
+    ///         64-bit), "float" or "bytes". Values are converted on insert
+    ///         (an int to a float, a bytearray to bytes, a NumPy scalar to
+    ///         int or float), and anything else raises TypeError at once.
+    ///         Values are kept as native 64-bit ints, floats and byte
+    ///         strings rather than Python objects, unless the tree also has
+    ///         store_pickled, compress_values or intern_values. Reads return
+    ///         a new object each time.
+    ///     store_pickled: If True, values are pickled to bytes on insert and
+    ///         unpickled on access, so rarely read values cost one bytes
+    ///         object each instead of a graph of Python objects. Every read
+    ///         returns a fresh copy unless the value was read recently, so
+    ///         stored values should be treated as immutable; changing a
+    ///         value means storing it again.
+    ///     pickle_cache_size: How many recently unpickled values
+    ///         store_pickled trees keep, so repeated reads of a hot key don't
+    ///         unpickle it every time (defaults to 128; 0 disables the cache)
+    ///     intern_values: If True, equal immutable values (str, bytes, int,
+    ///         float and tuples of them) are stored as one shared object, so
+    ///         millions of keys with a handful of distinct labels hold a
+    ///         handful of objects. Every distinct value is kept in an intern
+    ///         table until `clear()`, so this suits values with few
+    ///         distinct values.
+    ///     compress_values: Optional codec, "zstd" or "zlib", that str and
+    ///         bytes values of at least compress_threshold bytes are
+    ///         compressed with on insert and decompressed on access, in
+    ///         Rust with the GIL released. With store_pickled, the pickled
+    ///         bytes are compressed.
+    ///     compress_threshold: Size in bytes from which values are
+    ///         compressed (defaults to 1024)
+    ///
+    /// Returns:
+    ///     A new TreeMap instance
+    ///
+    /// Raises:
+    ///     ValueError: If data format is invalid, normalize is not a known form,
+    ///         eviction is not a known policy, max_len or max_versions is 0,
+    ///         max_versions is given without versioned, loader is combined
+    ///         with default_factory, a string-only option is combined with a
+    ///         non-str key_type, or compress_values is not a known codec
+    ///     TypeError: If keys don't match the key_type
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> tree = TreeMap({"a": 1, "b": 2})
+    ///     >>> tree = TreeMap([("a", 1), ("b", 2)])
+    ///     >>> tree = TreeMap(default_factory=list)
+    ///     >>> tree = TreeMap(case_insensitive=True)
+    ///     >>> tree = TreeMap(key_type="bytes")
+    ///     >>> tree = TreeMap(key_type="int")
+    ///     >>> tree = TreeMap(key_type="tuple")
+    ///     >>> tree = TreeMap(key_type="path")
+    ///     >>> tree = TreeMap(did_you_mean=True)
+    ///     >>> tree = TreeMap(substring_index=True)
+    ///     >>> tree = TreeMap(order_statistics=True)
+    ///     >>> tree = TreeMap(max_len=10_000, eviction="evict_oldest")
+    ///     >>> tree = TreeMap(loader=lambda key: key.upper(), max_len=1000)
+    ///     >>> tree = TreeMap(versioned=True, max_versions=10_000)
+    ///     >>> tree = TreeMap(value_type="float")
+    ///     >>> tree = TreeMap(store_pickled=True, pickle_cache_size=1024)
+    ///     >>> tree = TreeMap(intern_values=True)
+    ///     >>> tree = TreeMap(compress_values="zlib", compress_threshold=4096)
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (
+        data=None,
+        *,
+        default_factory=None,
+        key_type=None,
+        separator=None,
+        errors=None,
+        case_insensitive=false,
+        normalize=None,
+        did_you_mean=false,
+        substring_index=false,
+        order_statistics=false,
+        max_len=None,
+        eviction="reject",
+        loader=None,
+        versioned=false,
+        max_versions=None,
+        value_type=None,
+        store_pickled=false,
+        pickle_cache_size=128,
+        intern_values=false,
+        compress_values=None,
+        compress_threshold=1024,
+    ))]
+    fn new(
+        py: Python,
+        data: Option<&Bound<'_, PyAny>>,
+        default_factory: Option<Py<PyAny>>,
+        key_type: Option<&Bound<'_, PyAny>>,
+        separator: Option<&str>,
+        errors: Option<&str>,
+        case_insensitive: bool,
+        normalize: Option<String>,
+        did_you_mean: bool,
+        substring_index: bool,
+        order_statistics: bool,
+        max_len: Option<usize>,
+        eviction: &str,
+        loader: Option<Py<PyAny>>,
+        versioned: bool,
+        max_versions: Option<usize>,
+        value_type: Option<&str>,
+        store_pickled: bool,
+        pickle_cache_size: usize,
+        intern_values: bool,
+        compress_values: Option<&str>,
+        compress_threshold: usize,
+    ) -> PyResult<Self> {
+        let codec = KeyCodec::from_key_type(key_type)?
+            .with_separator(separator)?
+            .with_errors(errors)?;
+        if !codec.is_str() && (case_insensitive || normalize.is_some()) {
+            return Err(PyValueError::new_err(
+                "case_insensitive and normalize require str keys",
+            ));
+        }
+        if substring_index && !codec.is_verbatim() {
+            return Err(PyValueError::new_err(
+                "substring_index requires str or bytes keys",
+            ));
+        }
+        if max_len == Some(0) {
+            return Err(PyValueError::new_err("max_len must be at least 1"));
+        }
+        if max_versions == Some(0) {
+            return Err(PyValueError::new_err("max_versions must be at least 1"));
+        }
+        if max_versions.is_some() && !versioned {
+            return Err(PyValueError::new_err(
+                "max_versions requires versioned=True",
+            ));
+        }
+        if loader.is_some() && default_factory.is_some() {
+            return Err(PyValueError::new_err(
+                "loader and default_factory cannot be combined",
+            ));
+        }
+        let eviction = Eviction::from_name(eviction)?;
+        let value_type = value_type.map(ValueType::from_name).transpose()?;
+        let compression = compress_values
+            .map(|codec| Compression::new(codec, compress_threshold))
+            .transpose()?;
+        let codec_is_str = codec.is_str();
+        if let Some(form) = &normalize {
+            if !NORMALIZATION_FORMS.contains(&form.as_str()) {
+                return Err(PyValueError::new_err(format!(
+                    "normalize must be one of {:?} or None, got '{}'",
+                    NORMALIZATION_FORMS, form
+                )));
+            }
+        }
+        let mut tree = Self {
+            inner: TreeMap::new().into(),
+            default_factory,
+            codec,
+            case_insensitive,
+            normalize,
+            original_keys: HashMap::new().into(),
+            generation: 0,
+            did_you_mean,
+            indexes: KeyIndexes {
+                substrings: substring_index.then(|| SubstringIndex::new(codec_is_str)),
+                suffixes: None,
+                counts: order_statistics.then(CountIndex::default),
+                order: (eviction == Eviction::EvictOldest).then(InsertionOrder::default),
+            }
+            .into(),
+            deadlines: HashMap::new().into(),
+            next_deadline: None,
+            max_len,
+            eviction,
+            value_type,
+            pickled: store_pickled.then(|| PickleCache::new(pickle_cache_size)),
+            compression,
+            interned: intern_values.then(|| PyDict::new(py).unbind()),
+            loader,
+            observers: Vec::new(),
+            metrics: Metrics::default(),
+            journal: None,
+            history: versioned.then(|| History::new(max_versions)),
+        };
+
+        if let Some(data) = data {
+            let pairs = mapping_pairs(data)?
+                .into_iter()
+                .map(|(key, value)| (key, value.unbind()))
+                .collect();
+            tree.insert_all(py, pairs)?;
+        }
+
+        Ok(tree)
+    }
+
+    /// Parameterize the class for type annotations, as in
+    /// `TreeMap[str, int]`.
+    ///
+    /// Returns:
+    ///     A types.GenericAlias; the parameters are not checked at runtime
+    #[classmethod]
+    fn __class_getitem__<'py>(
+        cls: &Bound<'py, PyType>,
+        params: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        generic_alias(cls, params)
+    }
+
+    /// Insert a key-value pair into the TreeMap.
+    ///
+    /// If the key already exists, its value is updated.
+    /// Note: Due to the adaptive radix tree structure, inserting a key may
+    /// remove existing keys that are prefixes of the new key.
+    ///
+    /// With a `ttl`, the entry expires that many seconds later: the next
+    /// read or write of the tree removes it, reporting it to observers as
+    /// evicted. Storing a new value under the key without a ttl makes it
+    /// permanent again.
+    ///
+    /// Args:
+    ///     key: Key to insert
+    ///     value: Python object to store
+    ///     ttl: Optional lifetime of the entry in seconds
+    ///
+    /// Raises:
+    ///     ValueError: If ttl is not a positive number of seconds
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.insert("hello", "world")
+    ///     >>> tree.insert("hello", "universe")  # Updates value
+    ///     >>> tree.insert("session:42", "token", ttl=30)
+    #[pyo3(signature = (key, value, ttl=None))]
+    fn insert(
+        &mut self,
+        key: &Bound<'_, PyAny>,
+        value: Py<PyAny>,
+        ttl: Option<f64>,
+    ) -> PyResult<()> {
+        let deadline = ttl
+            .map(|ttl| {
+                Duration::try_from_secs_f64(ttl)
+                    .ok()
+                    .filter(|ttl| !ttl.is_zero())
+                    .and_then(|ttl| Instant::now().checked_add(ttl))
+                    .ok_or_else(|| {
+                        PyValueError::new_err("ttl must be a positive number of seconds")
+                    })
+            })
+            .transpose()?;
+        let key_bytes = self.encode_key(key)?;
+        if let Some(deadline) = deadline {
+            self.store(key_bytes.clone(), key, value)?;
+            self.set_deadline(key_bytes, deadline);
+        } else {
+            self.store(key_bytes, key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Insert a key-value pair only if the key is not already present.
+    ///
+    /// The lookup and the insertion share a single traversal of the tree, which
+    /// makes this the primitive to use for caches and deduplication.
+    ///
+    /// Args:
+    ///     key: Key to insert
+    ///     value: Python object to store
+    ///
+    /// Returns:
+    ///     True if the value was inserted, False if the key already existed
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.try_insert("hello", "world")
+    ///     True
+    ///     >>> tree.try_insert("hello", "universe")  # Existing value is kept
+    ///     False
+    ///     >>> tree["hello"]
+    ///     'world'
+    fn try_insert(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        value: Py<PyAny>,
+    ) -> PyResult<bool> {
+        let mut inserted = false;
+        self.upsert(py, key, |current| {
+            inserted = current.is_none();
+            Ok(inserted.then_some(value))
+        })?;
+        Ok(inserted)
+    }
+
+    /// Get the value for a key, inserting the result of a factory if missing.
+    ///
+    /// The factory is called at most once, and only when the key is absent.
+    /// The lookup and the insertion share a single traversal of the tree.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///     factory: Callable taking no arguments that produces the value to insert
+    ///
+    /// Returns:
+    ///     The stored value, or the newly inserted value if the key was missing
+    ///
+    /// Raises:
+    ///     Any exception raised by the factory (nothing is inserted in that case)
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.get_or_insert("hello", lambda: "world")
+    ///     'world'
+    ///     >>> tree.get_or_insert("hello", lambda: "universe")  # Factory not called
+    ///     'world'
+    fn get_or_insert(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        factory: &Bound<'_, PyAny>,
+    ) -> PyResult<Py<PyAny>> {
+        let stored = self.upsert(py, key, |current| match current {
+            Some(_) => Ok(None),
+            None => Ok(Some(factory.call0()?.unbind())),
+        })?;
+        Ok(stored.unwrap_or_else(|| py.None()))
+    }
+
+    /// Replace the value for a key with the result of calling a function on it.
+    ///
+    /// The stored value is passed to `func` and the return value is stored back,
+    /// saving the get/modify/set round-trip for counters and accumulators.
+    ///
+    /// Args:
+    ///     key: Key to update
+    ///     func: Callable taking the current value and returning the new one
+    ///     default: Value passed to `func` when the key is missing. If None,
+    ///         a missing key raises KeyError instead.
+    ///
+    /// Returns:
+    ///     The new value stored under the key
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist and no default is given
+    ///     Any exception raised by func (the stored value is left unchanged)
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"hits": 1})
+    ///     >>> tree.apply("hits", lambda v: v + 1)
+    ///     2
+    ///     >>> tree.apply("seen", lambda v: v + ["a"], default=[])
+    ///     ['a']
+    #[pyo3(signature = (key, func, default=None))]
+    fn apply(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        func: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let stored = self.upsert(py, key, |current| {
+            let current = match current {
+                Some(value) => value.clone_ref(py),
+                None => default.ok_or_else(|| key_error(key))?,
+            };
+            Ok(Some(func.call1((current,))?.unbind()))
+        })?;
+        Ok(stored.unwrap_or_else(|| py.None()))
+    }
+
+    /// Add a number to the value stored under a key.
+    ///
+    /// Missing keys are treated as 0, so the TreeMap can be used directly as a
+    /// counter without a separate lookup and store per event.
+    ///
+    /// Args:
+    ///     key: Key to increment
+    ///     delta: Amount to add (defaults to 1)
+    ///
+    /// Returns:
+    ///     The new value stored under the key
+    ///
+    /// Raises:
+    ///     TypeError: If the stored value and delta cannot be added
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.increment("page:/home")
+    ///     1
+    ///     >>> tree.increment("page:/home", 5)
+    ///     6
+    #[pyo3(signature = (key, delta=None))]
+    fn increment(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        delta: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let one = 1i64.into_pyobject(py)?.into_any();
+        let delta = delta.unwrap_or(&one);
+        let stored = self.upsert(py, key, |current| {
+            let value = match current {
+                Some(value) => value.bind(py).add(delta)?,
+                None => 0i64.into_pyobject(py)?.add(delta)?,
+            };
+            Ok(Some(value.unbind()))
+        })?;
+        Ok(stored.unwrap_or_else(|| py.None()))
+    }
+
+    /// Get a value by key, with optional default.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///     default: Value to return if key not found (defaults to None)
+    ///
+    /// Returns:
+    ///     The value associated with the key, or default if not found. With a
+    ///     loader, a missing key is loaded and stored instead; if the loader
+    ///     raises, the key counts as not found.
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"hello": "world"})
+    ///     >>> tree.get("hello")
+    ///     'world'
+    ///     >>> tree.get("missing")
+    ///     None
+    ///     >>> tree.get("missing", "default")
+    ///     'default'
+    #[pyo3(signature = (key, default=None))]
+    fn get(
+        slf: &Bound<'_, Self>,
+        key: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Option<Py<PyAny>>> {
+        let py = slf.py();
+        {
+            // Hits only read the tree, so threads can look up in parallel
+            let tree = Self::live(slf)?;
+            if let Some(value) = tree.lookup_value(key)? {
+                return Ok(Some(tree.value_out(py, value)?));
+            }
+        }
+        match Self::load(slf, key) {
+            Ok(Some(value)) => Ok(Some(value)),
+            Ok(None) => Ok(default.or_else(|| Some(py.None()))),
+            // load() turns an exception raised by the loader into a KeyError
+            Err(err) if err.is_instance_of::<PyKeyError>(py) => {
+                Ok(default.or_else(|| Some(py.None())))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Remove a key and return its value.
+    ///
+    /// Args:
+    ///     key: Key to remove
+    ///
+    /// Returns:
+    ///     The value that was associated with the key
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"hello": "world"})
+    ///     >>> tree.remove("hello")
+    ///     'world'
+    ///     >>> tree.remove("missing")  # Raises KeyError
+    fn remove(&mut self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        self.purge_expired(py)?;
+        let key_bytes = self.encode_key(key)?;
+        match self.inner.remove(&key_bytes) {
+            Some(value) => {
+                self.discard(py, &key_bytes, &value, Change::Remove)?;
+                self.value_out(py, &value)
+            }
+            None => Err(self.missing_key(py, key, &key_bytes)),
+        }
+    }
+
+    /// Move a value to a new key in one call.
+    ///
+    /// The value is never lost: either the old key is removed and the new
+    /// one inserted, or, when an error is raised, the tree is unchanged.
+    /// Without `overwrite`, a destination that is already stored is an
+    /// error, as is one that would evict a stored key it prefixes or
+    /// extends.
+    ///
+    /// Args:
+    ///     old_key: Key to move
+    ///     new_key: Key to move the value to
+    ///     overwrite: Replace (or evict) whatever is stored at the
+    ///         destination (defaults to False)
+    ///
+    /// Raises:
+    ///     KeyError: If old_key does not exist, or new_key is taken and
+    ///         overwrite is False
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"draft": 1, "final": 2})
+    ///     >>> tree.rename("draft", "published")
+    ///     >>> list(tree.items())
+    ///     [('final', 2), ('published', 1)]
+    ///     >>> tree.rename("published", "final", overwrite=True)
+    ///     >>> list(tree.items())
+    ///     [('final', 1)]
+    #[pyo3(signature = (old_key, new_key, overwrite=false))]
+    fn rename(
+        &mut self,
+        py: Python,
+        old_key: &Bound<'_, PyAny>,
+        new_key: &Bound<'_, PyAny>,
+        overwrite: bool,
+    ) -> PyResult<()> {
+        self.purge_expired(py)?;
+        let old = self.encode_key(old_key)?;
+        let new = self.encode_key(new_key)?;
+        if !self.inner.contains_key(&old) {
+            return Err(self.missing_key(py, old_key, &old));
+        }
+        if old == new {
+            // Only the spelling can change, in a case-insensitive tree
+            if let (true, Ok(spelling)) = (self.case_insensitive, new_key.extract::<String>()) {
+                self.original_keys.insert(new, spelling);
+            }
+            return Ok(());
+        }
+        if !overwrite {
+            if self.inner.contains_key(&new) {
+                return Err(PyKeyError::new_err(format!(
+                    "{} already exists",
+                    new_key.repr()?
+                )));
+            }
+            if let Some(taken) = self.displaced(&new).into_iter().find(|k| *k != old) {
+                return Err(PyKeyError::new_err(format!(
+                    "{} would evict the stored key {}",
+                    new_key.repr()?,
+                    self.decode_key(py, &taken)?.bind(py).repr()?
+                )));
+            }
+        }
+        let value = self.inner.remove(&old).expect("checked above");
+        self.discard(py, &old, &value, Change::Remove)?;
+        let value = self.value_out(py, &value)?;
+        self.store(new, new_key, value)?;
+        Ok(())
+    }
+
+    /// Move every key under one prefix to another prefix.
+    ///
+    /// Renames a tenant or namespace in one call: the subtree is collected
+    /// and reinserted under the new prefix in Rust, keeping the rest of
+    /// each key. Without `overwrite`, nothing is moved if a new key is
+    /// already stored, or would evict a stored key it prefixes or extends.
+    ///
+    /// Args:
+    ///     old_prefix: Prefix of the keys to move
+    ///     new_prefix: Prefix to put in its place
+    ///     overwrite: Replace (or evict) stored keys in the way (defaults
+    ///         to False)
+    ///
+    /// Returns:
+    ///     Number of keys moved
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///     KeyError: If a new key is taken and overwrite is False
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"acme:users:1": "ann", "acme:users:2": "bob", "other:1": 0})
+    ///     >>> tree.rename_prefix("acme:", "initech:")
+    ///     2
+    ///     >>> list(tree)
+    ///     ['initech:users:1', 'initech:users:2', 'other:1']
+    #[pyo3(signature = (old_prefix, new_prefix, overwrite=false))]
+    fn rename_prefix(
+        &mut self,
+        py: Python,
+        old_prefix: &Bound<'_, PyAny>,
+        new_prefix: &Bound<'_, PyAny>,
+        overwrite: bool,
+    ) -> PyResult<usize> {
+        self.purge_expired(py)?;
+        if !self.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "rename_prefix requires str or bytes keys",
+            ));
+        }
+        let old = self.encode_prefix(old_prefix)?;
+        let new = self.encode_prefix(new_prefix)?;
+        if old == new {
+            return Ok(0);
+        }
+        let moved = self
+            .prefix_entries(&old)
+            .map(|(k, _)| {
+                let renamed: Box<[u8]> = [&*new, &k[old.len()..]].concat().into();
+                (k.clone(), renamed)
+            })
+            .collect::<Vec<_>>();
+        if !overwrite {
+            for (_, key) in &moved {
+                let mut taken = self.displaced(key);
+                if self.inner.contains_key(key) {
+                    taken.push(key.clone());
+                }
+                // Keys under the old prefix move out of the way
+                if let Some(taken) = taken.into_iter().find(|taken| !taken.starts_with(&old)) {
+                    return Err(PyKeyError::new_err(format!(
+                        "moving {} to {} would replace the stored key {}",
+                        old_prefix.repr()?,
+                        new_prefix.repr()?,
+                        self.decode_key(py, &taken)?.bind(py).repr()?
+                    )));
+                }
+            }
+        }
+        // Keep the original spelling of the rest of each key
+        let prefix_len = old_prefix.len()?;
+        let mut entries = Vec::with_capacity(moved.len());
+        for (old_key, new_key) in moved {
+            let spelling = self.decode_key(py, &old_key)?.into_bound(py);
+            let spelling = new_prefix.add(spelling.get_item(PySlice::new(
+                py,
+                prefix_len as isize,
+                isize::MAX,
+                1,
+            ))?)?;
+            let value = self.inner.remove(&old_key).expect("collected above");
+            self.discard(py, &old_key, &value, Change::Remove)?;
+            entries.push((new_key, spelling, self.value_out(py, &value)?));
+        }
+        let count = entries.len();
+        for (key, spelling, value) in entries {
+            self.store(key, &spelling, value)?;
+        }
+        Ok(count)
+    }
+
+    /// Remove a key and return its value, or a default if it is missing.
+    ///
+    /// Args:
+    ///     key: Key to remove
+    ///     default: Optional value returned when the key is missing
+    ///
+    /// Returns:
+    ///     The removed value, or default
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist and no default was given
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"hello": "world"})
+    ///     >>> tree.pop("hello")
+    ///     'world'
+    ///     >>> tree.pop("hello", None) is None
+    ///     True
+    #[pyo3(signature = (key, *default))]
+    fn pop(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        default: &Bound<'_, PyTuple>,
+    ) -> PyResult<Py<PyAny>> {
+        self.purge_expired(py)?;
+        if default.len() > 1 {
+            return Err(PyTypeError::new_err(format!(
+                "pop expected at most 2 arguments, got {}",
+                default.len() + 1
+            )));
+        }
+        let key_bytes = self.encode_key(key)?;
+        match self.inner.remove(&key_bytes) {
+            Some(value) => {
+                self.discard(py, &key_bytes, &value, Change::Remove)?;
+                self.value_out(py, &value)
+            }
+            None if !default.is_empty() => Ok(default.get_item(0)?.unbind()),
+            None => Err(self.missing_key(py, key, &key_bytes)),
+        }
+    }
+
+    /// Remove and return the last (key, value) pair.
+    ///
+    /// Returns:
+    ///     (key, value) tuple for the last entry
+    ///
+    /// Raises:
+    ///     KeyError: If the TreeMap is empty
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2})
+    ///     >>> tree.popitem()
+    ///     ('b', 2)
+    fn popitem(&mut self, py: Python) -> PyResult<(Py<PyAny>, Py<PyAny>)> {
+        self.pop_last(py)?
+            .ok_or_else(|| PyKeyError::new_err("popitem(): TreeMap is empty"))
+    }
+
+    /// Get the value for a key, inserting default if the key is missing.
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///     default: Value to insert when the key is missing
+    ///
+    /// Returns:
+    ///     The stored value, or default after inserting it
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.setdefault("tags", []).append("new")
+    ///     >>> tree["tags"]
+    ///     ['new']
+    #[pyo3(signature = (key, default=None))]
+    fn setdefault(
+        &mut self,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let default = default.unwrap_or_else(|| py.None());
+        let stored = self.upsert(py, key, |current| match current {
+            Some(_) => Ok(None),
+            None => Ok(Some(default)),
+        })?;
+        Ok(stored.unwrap_or_else(|| py.None()))
+    }
+
+    /// Update the TreeMap from a mapping or iterable of pairs, and keyword arguments.
+    ///
+    /// All keys are encoded before the tree is touched, so a key of the wrong
+    /// type leaves the tree unchanged; the insertions then run with the GIL
+    /// released.
+    ///
+    /// Args:
+    ///     other: Mapping, or iterable of (key, value) pairs
+    ///     **kwargs: Additional string keys and values
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1})
+    ///     >>> tree.update({"b": 2}, c=3)
+    ///     >>> list(tree.items())
+    ///     [('a', 1), ('b', 2), ('c', 3)]
+    #[pyo3(signature = (other=None, **kwargs))]
+    fn update(
+        slf: &Bound<'_, Self>,
+        other: Option<&Bound<'_, PyAny>>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        let py = slf.py();
+        // Gather pairs before borrowing, since other may be this TreeMap
+        let mut pairs = match other {
+            Some(other) => mapping_pairs(other)?,
+            None => Vec::new(),
+        };
+        if let Some(kwargs) = kwargs {
+            pairs.extend(kwargs.iter());
+        }
+        let pairs = pairs.into_iter().map(|(k, v)| (k, v.unbind())).collect();
+        slf.borrow_mut().insert_all(py, pairs)
+    }
+
+    /// Insert keys from a NumPy array and values from a matching sequence.
+    ///
+    /// Bytes ("S") and unicode ("U") arrays are read straight from the
+    /// array's memory, so no Python object is created per key. Alternatively
+    /// `keys` can be a flat bytes-like buffer of concatenated keys, with
+    /// `offsets` an integer array where key `i` spans
+    /// `keys[offsets[i]:offsets[i + 1]]` (the layout of Arrow string
+    /// arrays). Arrays of other dtypes, and trees whose keys are not plain
+    /// str or bytes, are converted element by element instead. Values are
+    /// taken from `values.tolist()` when available.
+    ///
+    /// Args:
+    ///     keys: NumPy array of keys, or a buffer of concatenated keys
+    ///     values: Sequence or array of values, one per key
+    ///     offsets: Integer array of len(values) + 1 key boundaries in `keys`
+    ///
+    /// Raises:
+    ///     ValueError: If keys and values differ in length, the offsets are
+    ///         out of range, or str keys are not valid UTF-8
+    ///     TypeError: If the keys don't match the key_type
+    ///
+    /// Examples:
+    ///     >>> import numpy as np
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.insert_from_numpy(np.array(["b", "a"]), np.array([2, 1]))
+    ///     >>> list(tree.items())
+    ///     [('a', 1), ('b', 2)]
+    ///     >>> tree.insert_from_numpy(b"carrotdate", [3, 4], offsets=[0, 6, 10])
+    ///     >>> tree["date"]
+    ///     4
+    #[pyo3(signature = (keys, values, *, offsets=None))]
+    fn insert_from_numpy(
+        &mut self,
+        py: Python,
+        keys: &Bound<'_, PyAny>,
+        values: &Bound<'_, PyAny>,
+        offsets: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        self.purge_expired(py)?;
+        let values = match values.hasattr("tolist")? {
+            true => values.call_method0("tolist")?,
+            false => values.clone(),
+        };
+        let values = values
+            .try_iter()?
+            .map(|value| Ok(value?.unbind()))
+            .collect::<PyResult<Vec<_>>>()?;
+        let Some(raw) = array_keys(keys, offsets)? else {
+            let keys = match keys.hasattr("tolist")? {
+                true => keys.call_method0("tolist")?,
+                false => keys.clone(),
+            };
+            let keys = keys.try_iter()?.collect::<PyResult<Vec<_>>>()?;
+            check_lengths(keys.len(), values.len())?;
+            return self.insert_all(py, keys.into_iter().zip(values).collect());
+        };
+        check_lengths(raw.keys.len(), values.len())?;
+        self.insert_raw(py, raw, values)
+    }
+
+    /// Look up many keys at once into NumPy arrays of numbers.
+    ///
+    /// Keys are read like `insert_from_numpy()`, and the tree is searched
+    /// with the GIL released. Values are converted to `dtype` without
+    /// creating Python objects for the results; missing and expired keys get
+    /// `fill` and are marked False in the mask. In a tree with an "int" or
+    /// "float" `value_type` the values are read with the GIL released too.
+    ///
+    /// Args:
+    ///     keys: NumPy array of keys, or a buffer of concatenated keys
+    ///     dtype: "float64" or "int64"
+    ///     fill: Value for missing keys (default NaN for float64, 0 for int64)
+    ///     offsets: Integer array of key boundaries in `keys`
+    ///
+    /// Returns:
+    ///     Tuple of (values, found) NumPy arrays, with found a bool mask
+    ///
+    /// Raises:
+    ///     ValueError: If dtype is not supported
+    ///     TypeError: If a value found can't be converted to dtype
+    ///
+    /// Examples:
+    ///     >>> import numpy as np
+    ///     >>> tree = TreeMap({"a": 1.5, "b": 2})
+    ///     >>> values, found = tree.get_many_numpy(np.array(["a", "x", "b"]))
+    ///     >>> values
+    ///     array([1.5, nan, 2. ])
+    ///     >>> found
+    ///     array([ True, False,  True])
+    #[pyo3(signature = (keys, *, dtype="float64", fill=None, offsets=None))]
+    fn get_many_numpy<'py>(
+        slf: &Bound<'py, Self>,
+        py: Python<'py>,
+        keys: &Bound<'py, PyAny>,
+        dtype: &str,
+        fill: Option<&Bound<'py, PyAny>>,
+        offsets: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyAny>)> {
+        let this = &*Self::live(slf)?;
+        let float = match dtype {
+            "float64" => true,
+            "int64" => false,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "dtype must be \"float64\" or \"int64\", not {dtype:?}"
+                )))
+            }
+        };
+        let raw = match array_keys(keys, offsets)? {
+            Some(raw)
+                if this.codec.is_verbatim()
+                    && !this.case_insensitive
+                    && this.normalize.is_none() =>
+            {
+                if raw.text && !this.codec.is_str() {
+                    return Err(PyTypeError::new_err(
+                        "unicode arrays can only be looked up in TreeMaps with str keys",
+                    ));
+                }
+                raw.keys
+            }
+            Some(raw) => raw
+                .keys
+                .iter()
+                .map(|key| match raw.text || this.codec.is_str() {
+                    true => PyString::new(py, &String::from_utf8_lossy(key)).into_any(),
+                    false => PyBytes::new(py, key).into_any(),
+                })
+                .map(|key| this.encode_key(&key))
+                .collect::<PyResult<_>>()?,
+            None => {
+                let keys = match keys.hasattr("tolist")? {
+                    true => keys.call_method0("tolist")?,
+                    false => keys.clone(),
+                };
+                keys.try_iter()?
+                    .map(|key| this.encode_key(&key?))
+                    .collect::<PyResult<_>>()?
+            }
+        };
+        let fill = match float {
+            true => fill
+                .map(|v| v.extract::<f64>())
+                .transpose()?
+                .unwrap_or(f64::NAN)
+                .to_ne_bytes(),
+            false => fill
+                .map(|v| v.extract::<i64>())
+                .transpose()?
+                .unwrap_or(0)
+                .to_ne_bytes(),
+        };
+        // Native numbers go straight into the buffer with the GIL released;
+        // the positions of Python objects are filled in afterwards
+        let (mut data, mask, objects) = py.detach(|| {
+            let mut data = Vec::with_capacity(raw.len() * 8);
+            let mut mask = Vec::with_capacity(raw.len());
+            let mut objects = Vec::new();
+            for (i, key) in raw.iter().enumerate() {
+                let value = match this.is_expired(key) {
+                    true => None,
+                    false => this.inner.get(key),
+                };
+                mask.push(value.is_some() as u8);
+                data.extend(match (value, float) {
+                    (None, _) => fill,
+                    (Some(Stored::Float(value)), true) => value.to_ne_bytes(),
+                    (Some(Stored::Int(value)), true) => (*value as f64).to_ne_bytes(),
+                    (Some(Stored::Int(value)), false) => value.to_ne_bytes(),
+                    (Some(value), _) => {
+                        objects.push((i, value));
+                        fill
+                    }
+                });
+            }
+            (data, mask, objects)
+        });
+        for found in &mask {
+            this.metrics.lookup(*found == 1);
+        }
+        for (i, value) in objects {
+            let value = this.value_out(py, value)?.into_bound(py);
+            let value = match float {
+                true => value.extract::<f64>()?.to_ne_bytes(),
+                false => value.extract::<i64>()?.to_ne_bytes(),
+            };
+            data[i * 8..(i + 1) * 8].copy_from_slice(&value);
+        }
+        let numpy = py.import("numpy")?;
+        let values = numpy.call_method1("frombuffer", (PyByteArray::new(py, &data), dtype))?;
+        let mask = numpy.call_method1("frombuffer", (PyByteArray::new(py, &mask), "bool"))?;
+        Ok((values, mask))
+    }
+
+    /// Compare with another mapping; equal when both hold the same items.
+    fn __richcmp__(
+        slf: &Bound<'_, Self>,
+        other: &Bound<'_, PyAny>,
+        op: CompareOp,
+    ) -> PyResult<Py<PyAny>> {
+        static MAPPING: PyOnceLock<Py<PyType>> = PyOnceLock::new();
+        let py = slf.py();
+        let equal = match op {
+            CompareOp::Eq => true,
+            CompareOp::Ne => false,
+            _ => return Ok(py.NotImplemented()),
+        };
+        if !other.is_instance(MAPPING.import(py, "collections.abc", "Mapping")?)? {
+            return Ok(py.NotImplemented());
+        }
+        let same = slf.is(other) || {
+            let items = Self::live(slf)?.item_list(py)?;
+            items.len() == other.len()? && {
+                let mut same = true;
+                for (key, value) in items {
+                    if !other.contains(&key)? || !other.get_item(&key)?.eq(value)? {
+                        same = false;
+                        break;
+                    }
+                }
+                same
+            }
+        };
+        Ok(PyBool::new(py, same == equal)
+            .to_owned()
+            .into_any()
+            .unbind())
+    }
+
+    /// Compare the keys and values of two TreeMaps.
+    ///
+    /// Both trees are walked side by side in key order, so the comparison
+    /// costs one pass over each tree and no hashing. Values of shared keys
+    /// are compared with `==`.
+    ///
+    /// Args:
+    ///     other: TreeMap to compare against, with the same key type
+    ///     values: Report added and changed entries as (key, value) pairs
+    ///         holding other's values, so the result is a patch that
+    ///         `apply_patch()` applies without a `source` (defaults to False)
+    ///
+    /// Returns:
+    ///     Tuple of (added, removed, changed) key lists in key order: keys
+    ///     only in other, keys only in this tree, and shared keys whose
+    ///     values differ
+    ///
+    /// Raises:
+    ///     TypeError: If the trees have different key types
+    ///
+    /// Examples:
+    ///     >>> old = TreeMap({"a": 1, "b": 2, "c": 3})
+    ///     >>> new = TreeMap({"b": 2, "c": 30, "d": 4})
+    ///     >>> old.diff(new)
+    ///     (['d'], ['a'], ['c'])
+    ///     >>> old.diff(new, values=True)
+    ///     ([('d', 4)], ['a'], [('c', 30)])
+    #[pyo3(signature = (other, values=false))]
+    fn diff(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        other: &Bound<'_, Self>,
+        values: bool,
+    ) -> PyResult<KeyDiff> {
+        let this = &*Self::live(slf)?;
+        let other = &*Self::live(other)?;
+        this.check_key_type(py, other, "diff")?;
+        // A key of other, paired with its value when values are requested
+        let entry = |key: &[u8], value: &Stored| -> PyResult<Py<PyAny>> {
+            let key = other.decode_key(py, key)?;
+            if !values {
+                return Ok(key);
+            }
+            let value = other.value_out(py, value)?;
+            Ok(PyTuple::new(py, [key, value])?.into_any().unbind())
+        };
+        let (mut added, mut removed, mut changed) = (Vec::new(), Vec::new(), Vec::new());
+        let mut ours = this.inner.iter().peekable();
+        let mut theirs = other.inner.iter().peekable();
+        loop {
+            match (ours.peek(), theirs.peek()) {
+                (None, None) => break,
+                (Some((key, _)), None) => {
+                    removed.push(this.decode_key(py, key)?);
+                    ours.next();
+                }
+                (None, Some((key, value))) => {
+                    added.push(entry(key, value)?);
+                    theirs.next();
+                }
+                (Some((a, value)), Some((b, other_value))) => match a.cmp(b) {
+                    Ordering::Less => {
+                        removed.push(this.decode_key(py, a)?);
+                        ours.next();
+                    }
+                    Ordering::Greater => {
+                        added.push(entry(b, other_value)?);
+                        theirs.next();
+                    }
+                    Ordering::Equal => {
+                        let mine = this.value_out(py, value)?.into_bound(py);
+                        let yours = other.value_out(py, other_value)?.into_bound(py);
+                        if !mine.is(&yours) && !mine.eq(&yours)? {
+                            changed.push(entry(b, other_value)?);
+                        }
+                        ours.next();
+                        theirs.next();
+                    }
+                },
+            }
+        }
+        Ok((added, removed, changed))
+    }
+
+    /// Keys stored in this tree, in other, or in both.
+    ///
+    /// This tree and other are walked side by side in key order. Other may
+    /// be a TreeMap with the same key type or any iterable of keys.
+    ///
+    /// Args:
+    ///     other: TreeMap or iterable of keys
+    ///
+    /// Returns:
+    ///     Keys in either, in key order
+    ///
+    /// Raises:
+    ///     TypeError: If other is a TreeMap with a different key type
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"a": 1, "b": 2}).union_keys(TreeMap({"b": 3, "c": 4}))
+    ///     ['a', 'b', 'c']
+    fn union_keys(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        other: &Bound<'_, PyAny>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let this = &*Self::live(slf)?;
+        this.combine_keys(py, other, [true, true, true])
+    }
+
+    /// Keys stored both in this tree and in other.
+    ///
+    /// Other may be a TreeMap with the same key type or any iterable of
+    /// keys; see `union_keys`.
+    ///
+    /// Args:
+    ///     other: TreeMap or iterable of keys
+    ///
+    /// Returns:
+    ///     Keys in both, in key order
+    ///
+    /// Raises:
+    ///     TypeError: If other is a TreeMap with a different key type
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"a": 1, "b": 2}).intersection_keys(TreeMap({"b": 3, "c": 4}))
+    ///     ['b']
+    fn intersection_keys(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        other: &Bound<'_, PyAny>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let this = &*Self::live(slf)?;
+        this.combine_keys(py, other, [false, true, false])
+    }
+
+    /// Keys stored in this tree but not in other.
+    ///
+    /// Other may be a TreeMap with the same key type or any iterable of
+    /// keys; see `union_keys`.
+    ///
+    /// Args:
+    ///     other: TreeMap or iterable of keys
+    ///
+    /// Returns:
+    ///     Keys only in this tree, in key order
+    ///
+    /// Raises:
+    ///     TypeError: If other is a TreeMap with a different key type
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"a": 1, "b": 2}).difference_keys(TreeMap({"b": 3, "c": 4}))
+    ///     ['a']
+    fn difference_keys(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        other: &Bound<'_, PyAny>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let this = &*Self::live(slf)?;
+        this.combine_keys(py, other, [true, false, false])
+    }
+
+    /// Keys stored in exactly one of this tree and other.
+    ///
+    /// Other may be a TreeMap with the same key type or any iterable of
+    /// keys; see `union_keys`.
+    ///
+    /// Args:
+    ///     other: TreeMap or iterable of keys
+    ///
+    /// Returns:
+    ///     Keys in only one of them, in key order
+    ///
+    /// Raises:
+    ///     TypeError: If other is a TreeMap with a different key type
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"a": 1, "b": 2}).symmetric_difference_keys(TreeMap({"b": 3, "c": 4}))
+    ///     ['a', 'c']
+    fn symmetric_difference_keys(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        other: &Bound<'_, PyAny>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let this = &*Self::live(slf)?;
+        this.combine_keys(py, other, [true, false, true])
+    }
+
+    /// Whether every key of this tree is also stored in other.
+    ///
+    /// Both trees are walked side by side in key order, stopping at the
+    /// first key other is missing.
+    ///
+    /// Args:
+    ///     other: TreeMap with the same key type
+    ///     values: Also require the values to be equal (defaults to False)
+    ///
+    /// Returns:
+    ///     True if this tree's keys (and values) are contained in other
+    ///
+    /// Raises:
+    ///     TypeError: If the trees have different key types
+    ///
+    /// Examples:
+    ///     >>> small = TreeMap({"a": 1})
+    ///     >>> small.issubset(TreeMap({"a": 2, "b": 3}))
+    ///     True
+    ///     >>> small.issubset(TreeMap({"a": 2, "b": 3}), values=True)
+    ///     False
+    #[pyo3(signature = (other, values=false))]
+    fn issubset(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        other: &Bound<'_, Self>,
+        values: bool,
+    ) -> PyResult<bool> {
+        let this = &*Self::live(slf)?;
+        let other = &*Self::live(other)?;
+        other.contains_tree(py, this, values)
+    }
+
+    /// Whether every key of other is also stored in this tree.
+    ///
+    /// The mirror image of `issubset`.
+    ///
+    /// Args:
+    ///     other: TreeMap with the same key type
+    ///     values: Also require the values to be equal (defaults to False)
+    ///
+    /// Returns:
+    ///     True if other's keys (and values) are contained in this tree
+    ///
+    /// Raises:
+    ///     TypeError: If the trees have different key types
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"a": 1, "b": 2}).issuperset(TreeMap({"b": 2}), values=True)
+    ///     True
+    #[pyo3(signature = (other, values=false))]
+    fn issuperset(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        other: &Bound<'_, Self>,
+        values: bool,
+    ) -> PyResult<bool> {
+        let this = &*Self::live(slf)?;
+        let other = &*Self::live(other)?;
+        this.contains_tree(py, other, values)
+    }
+
+    /// Apply a patch of inserts, deletes and updates.
+    ///
+    /// The patch has the shape returned by `diff`: a tuple of (added,
+    /// removed, changed). Added and changed entries are mappings or
+    /// (key, value) pairs, as `diff(values=True)` returns, or plain keys
+    /// whose values are read from `source`. So both
+    /// `old.apply_patch(old.diff(new, values=True))` and
+    /// `old.apply_patch(old.diff(new), new)` make `old` equal to `new`.
+    /// Deletes are applied first, then updates, then inserts.
+    ///
+    /// With `atomic`, every operation is checked before any is applied: an
+    /// insert needs an absent key that evicts no stored key (other than
+    /// one being deleted), and deletes and updates need a stored key.
+    /// Without it, operations that fail these checks are skipped.
+    ///
+    /// Args:
+    ///     patch: Tuple of (added, removed, changed)
+    ///     source: Mapping the values of plain added and changed keys are
+    ///         read from
+    ///     atomic: Apply all operations or none (defaults to True)
+    ///
+    /// Returns:
+    ///     Number of operations applied
+    ///
+    /// Raises:
+    ///     KeyError: If atomic is True and an operation does not apply
+    ///     ValueError: If the patch is not a tuple of three parts
+    ///     TypeError: If added or changed holds plain keys and no source is
+    ///         given
+    ///
+    /// Examples:
+    ///     >>> old = TreeMap({"a": 1, "b": 2, "c": 3})
+    ///     >>> new = TreeMap({"b": 2, "c": 30, "d": 4})
+    ///     >>> old.apply_patch(old.diff(new, values=True))
+    ///     3
+    ///     >>> old == new
+    ///     True
+    ///     >>> old = TreeMap({"a": 1, "b": 2, "c": 3})
+    ///     >>> old.apply_patch(old.diff(new), new)
+    ///     3
+    ///     >>> old == new
+    ///     True
+    ///     >>> old.apply_patch(({"e": 5}, ["a"], {}), atomic=False)
+    ///     1
+    #[pyo3(signature = (patch, source=None, atomic=true))]
+    fn apply_patch<'py>(
+        slf: &Bound<'py, Self>,
+        patch: &Bound<'py, PyAny>,
+        source: Option<&Bound<'py, PyAny>>,
+        atomic: bool,
+    ) -> PyResult<usize> {
+        let py = slf.py();
+        let Ok((added, removed, changed)) =
+            patch.extract::<(Bound<'py, PyAny>, Bound<'py, PyAny>, Bound<'py, PyAny>)>()
+        else {
+            return Err(PyValueError::new_err(
+                "patch must be a tuple of (added, removed, changed)",
+            ));
+        };
+        // Gather values before borrowing, since source may be this TreeMap
+        let entries = |part: &Bound<'py, PyAny>| {
+            let mut pairs = Vec::new();
+            if part.hasattr("keys")? {
+                for key in part.call_method0("keys")?.try_iter()? {
+                    let key = key?;
+                    let value = part.get_item(&key)?;
+                    pairs.push((key, value.unbind()));
+                }
+            } else {
+                for item in part.try_iter()? {
+                    let item = item?;
+                    pairs.push(match source {
+                        Some(source) => {
+                            let value = source.get_item(&item)?.unbind();
+                            (item, value)
+                        }
+                        None => item
+                            .extract::<(Bound<'_, PyAny>, Py<PyAny>)>()
+                            .map_err(|_| {
+                                PyTypeError::new_err(format!(
+                                    "patch entry {} is not a (key, value) pair; pass \
+                                     source= to read the values of plain keys, or \
+                                     build the patch with diff(values=True)",
+                                    item.repr().map_or_else(|_| "?".into(), |r| r.to_string())
+                                ))
+                            })?,
+                    });
+                }
+            }
+            Ok::<_, PyErr>(pairs)
+        };
+        let inserts = entries(&added)?;
+        let updates = entries(&changed)?;
+        let deletes = removed.try_iter()?.collect::<PyResult<Vec<_>>>()?;
+
+        let mut tree = slf.borrow_mut();
+        let encode = |pairs: Vec<(Bound<'py, PyAny>, Py<PyAny>)>| {
+            pairs
+                .into_iter()
+                .map(|(key, value)| Ok((tree.encode_key(&key)?, key, value)))
+                .collect::<PyResult<Vec<_>>>()
+        };
+        let inserts = encode(inserts)?;
+        let updates = encode(updates)?;
+        let deletes = deletes
+            .into_iter()
+            .map(|key| Ok((tree.encode_key(&key)?, key)))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let deleted: HashSet<&[u8]> = deletes
+            .iter()
+            .filter(|(bytes, _)| tree.inner.contains_key(bytes))
+            .map(|(bytes, _)| &**bytes)
+            .collect();
+        // Whether to apply an operation, given the error it would raise
+        let check = |error: Option<PyErr>| match error {
+            Some(error) if atomic => Err(error),
+            error => Ok(error.is_none()),
+        };
+        let mut keep_deletes = Vec::with_capacity(deletes.len());
+        for (bytes, key) in &deletes {
+            let error = (!tree.inner.contains_key(bytes)).then(|| key_error(key));
+            keep_deletes.push(check(error)?);
+        }
+        let mut keep_updates = Vec::with_capacity(updates.len());
+        for (bytes, key, _) in &updates {
+            let missing = !tree.inner.contains_key(bytes) && !deleted.contains(&**bytes);
+            keep_updates.push(check(missing.then(|| key_error(key)))?);
+        }
+        let mut keep_inserts = Vec::with_capacity(inserts.len());
+        for (bytes, key, _) in &inserts {
+            let error = if tree.inner.contains_key(bytes) && !deleted.contains(&**bytes) {
+                Some(PyKeyError::new_err(format!(
+                    "{} already exists",
+                    key.repr()?
+                )))
+            } else if let Some(taken) = tree
+                .displaced(bytes)
+                .into_iter()
+                .find(|k| !deleted.contains(&**k))
+            {
+                Some(PyKeyError::new_err(format!(
+                    "{} would evict the stored key {}",
+                    key.repr()?,
+                    tree.decode_key(py, &taken)?.bind(py).repr()?
+                )))
+            } else {
+                None
+            };
+            keep_inserts.push(check(error)?);
+        }
+        drop(deleted);
+
+        let mut applied = 0;
+        let mut released = Vec::new();
+        for ((bytes, _), keep) in deletes.into_iter().zip(keep_deletes) {
+            if let Some(value) = keep.then(|| tree.inner.remove(&bytes)).flatten() {
+                tree.discard(py, &bytes, &value, Change::Remove)?;
+                released.push(value);
+                applied += 1;
+            }
+        }
+        let upserts = updates
+            .into_iter()
+            .zip(keep_updates)
+            .chain(inserts.into_iter().zip(keep_inserts));
+        for ((bytes, key, value), keep) in upserts {
+            if keep {
+                tree.store(bytes, &key, value)?;
+                applied += 1;
+            }
+        }
+        drop(tree);
+        // Release the removed values after the tree is unborrowed
+        drop(released);
+        Ok(applied)
+    }
+
+    /// Combine this tree with another, resolving keys stored in both.
+    ///
+    /// Both trees are walked side by side in key order. Keys stored in only
+    /// one tree are copied in Rust; `resolve` is called only for keys
+    /// stored in both, and its return value is kept. Without `resolve`,
+    /// the value from other wins, as with `update`.
+    ///
+    /// Args:
+    ///     other: TreeMap to merge in, with the same key type
+    ///     resolve: Called as resolve(key, ours, theirs) for each key stored
+    ///         in both trees; returns the value to keep
+    ///     inplace: Update this tree instead of returning a new one
+    ///         (defaults to False)
+    ///
+    /// Returns:
+    ///     The merged TreeMap, or None when inplace is True
+    ///
+    /// Raises:
+    ///     TypeError: If the trees have different key types
+    ///
+    /// Examples:
+    ///     >>> a = TreeMap({"x": 1, "y": 2})
+    ///     >>> b = TreeMap({"y": 10, "z": 3})
+    ///     >>> dict(a.merge(b, lambda k, ours, theirs: ours + theirs))
+    ///     {'x': 1, 'y': 12, 'z': 3}
+    ///     >>> dict(a.merge(b))
+    ///     {'x': 1, 'y': 10, 'z': 3}
+    #[pyo3(signature = (other, resolve=None, *, inplace=false))]
+    fn merge(
+        slf: &Bound<'_, Self>,
+        other: &Bound<'_, Self>,
+        resolve: Option<&Bound<'_, PyAny>>,
+        inplace: bool,
+    ) -> PyResult<Option<Self>> {
+        let py = slf.py();
+        // Entries of other to store, and the values of shared keys to resolve
+        let (mut entries, shared) = {
+            let (ours, theirs) = (Self::live(slf)?, Self::live(other)?);
+            ours.check_key_type(py, &theirs, "merge")?;
+            let mut entries = Vec::new();
+            let mut shared = Vec::new();
+            let mut mine = ours.inner.iter().peekable();
+            for (key, value) in theirs.inner.iter() {
+                while mine.next_if(|(k, _)| *k < key).is_some() {}
+                let spelling = theirs.decode_key(py, key)?;
+                match mine.next_if(|(k, _)| *k == key) {
+                    Some((_, our_value)) if resolve.is_some() => shared.push((
+                        key.clone(),
+                        spelling,
+                        ours.value_out(py, our_value)?,
+                        theirs.value_out(py, value)?,
+                    )),
+                    _ => entries.push((key.clone(), spelling, theirs.value_out(py, value)?)),
+                }
+            }
+            (entries, shared)
+        };
+        if let Some(resolve) = resolve {
+            for (key, spelling, ours, theirs) in shared {
+                let value = resolve.call1((&spelling, ours, theirs))?.unbind();
+                entries.push((key, spelling, value));
+            }
+        }
+        let store_all = |tree: &mut Self| {
+            for (key, spelling, value) in entries {
+                tree.store(key, spelling.bind(py), value)?;
+            }
+            Ok::<_, PyErr>(())
+        };
+        if inplace {
+            store_all(&mut slf.borrow_mut())?;
+            return Ok(None);
+        }
+        let mut merged = slf.borrow().duplicate(py);
+        merged.default_factory = slf
+            .borrow()
+            .default_factory
+            .as_ref()
+            .map(|f| f.clone_ref(py));
+        merged.loader = slf.borrow().loader.as_ref().map(|f| f.clone_ref(py));
+        store_all(&mut merged)?;
+        Ok(Some(merged))
+    }
+
+    /// Merge the changes another tree made to a common ancestor.
+    ///
+    /// This tree holds "our" changes to `base`, and `theirs` holds another
+    /// set of changes to it. The three trees are walked side by side in key
+    /// order: a key changed (or added, or removed) on only one side takes
+    /// that side's value, and a key both sides changed the same way is kept.
+    /// Only keys both sides changed differently are passed to `on_conflict`,
+    /// whose return value is stored. The merge is computed before this tree
+    /// is changed, so an error leaves it untouched.
+    ///
+    /// Args:
+    ///     base: Common ancestor of this tree and theirs
+    ///     theirs: Tree holding the other changes to base
+    ///     on_conflict: Called as on_conflict(key, base, ours, theirs) for
+    ///         each conflicting key, with None for a side the key is missing
+    ///         from; returns the value to store
+    ///
+    /// Returns:
+    ///     List of the conflicting keys, in key order
+    ///
+    /// Raises:
+    ///     ValueError: If a key conflicts and on_conflict is None
+    ///     TypeError: If the trees have different key types
+    ///
+    /// Examples:
+    ///     >>> base = TreeMap({"a": 1, "b": 2, "c": 3})
+    ///     >>> ours = TreeMap({"a": 10, "b": 2, "c": 30})
+    ///     >>> theirs = TreeMap({"a": 1, "c": 300, "d": 4})
+    ///     >>> ours.merge3(base, theirs, on_conflict=lambda k, b, o, t: o + t)
+    ///     ['c']
+    ///     >>> dict(ours)
+    ///     {'a': 10, 'c': 330, 'd': 4}
+    #[pyo3(signature = (base, theirs, on_conflict=None))]
+    fn merge3(
+        slf: &Bound<'_, Self>,
+        base: &Bound<'_, Self>,
+        theirs: &Bound<'_, Self>,
+        on_conflict: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let py = slf.py();
+        let (mut takes, conflicts) = {
+            let trees = [Self::live(base)?, Self::live(slf)?, Self::live(theirs)?];
+            for tree in [&trees[0], &trees[2]] {
+                trees[1].check_key_type(py, tree, "merge")?;
+            }
+            let same = |a: Option<&Py<PyAny>>, b: Option<&Py<PyAny>>| match (a, b) {
+                (None, None) => Ok(true),
+                (Some(a), Some(b)) => Ok(a.is(b) || a.bind(py).eq(b)?),
+                _ => Ok::<_, PyErr>(false),
+            };
+            // Keys taking their value (or removal) from theirs, and conflicts
+            // with the base, ours and theirs values
+            let mut takes = Vec::new();
+            let mut conflicts = Vec::new();
+            let mut iters = trees.each_ref().map(|tree| tree.inner.iter().peekable());
+            while let Some(key) = iters
+                .iter_mut()
+                .filter_map(|it| it.peek().map(|(k, _)| *k))
+                .min()
+            {
+                let key = key.clone();
+                let mut values = [None, None, None];
+                for (i, it) in iters.iter_mut().enumerate() {
+                    if let Some((_, v)) = it.next_if(|(k, _)| **k == key) {
+                        values[i] = Some(trees[i].value_out(py, v)?);
+                    }
+                }
+                let [b, o, t] = values;
+                if same(o.as_ref(), t.as_ref())? || same(t.as_ref(), b.as_ref())? {
+                    continue;
+                }
+                let spelling = match t.is_some() {
+                    true => trees[2].decode_key(py, &key)?,
+                    false => trees[1].decode_key(py, &key)?,
+                };
+                if same(o.as_ref(), b.as_ref())? {
+                    takes.push((key, spelling, t));
+                } else {
+                    conflicts.push((key, spelling, [b, o, t]));
+                }
+            }
+            (takes, conflicts)
+        };
+        if !conflicts.is_empty() && on_conflict.is_none() {
+            return Err(PyValueError::new_err(format!(
+                "conflicting changes to {}",
+                conflicts[0].1.bind(py).repr()?
+            )));
+        }
+        let mut keys = Vec::with_capacity(conflicts.len());
+        if let Some(on_conflict) = on_conflict {
+            for (key, spelling, [b, o, t]) in conflicts {
+                let value = on_conflict.call1((&spelling, b, o, t))?;
+                takes.push((key, spelling.clone_ref(py), Some(value.unbind())));
+                keys.push(spelling);
+            }
+        }
+        // Removals first, so stores cannot evict keys that are kept
+        takes.sort_by_key(|(_, _, value)| value.is_some());
+        let mut tree = slf.borrow_mut();
+        let mut released = Vec::new();
+        for (key, spelling, value) in takes {
+            match value {
+                Some(value) => tree.store(key, spelling.bind(py), value)?,
+                None => {
+                    if let Some(value) = tree.inner.remove(&key) {
+                        tree.discard(py, &key, &value, Change::Remove)?;
+                        released.push(value);
+                    }
+                }
+            }
+        }
+        drop(tree);
+        drop(released);
+        Ok(keys)
+    }
+
+    /// Register a callable to be told about every change to the tree.
+    ///
+    /// The callback is called as `callback(event, key, old_value, new_value)`
+    /// after the change is made, with `event` one of "insert", "overwrite",
+    /// "remove" or "evict" and None for a missing value. "evict" covers keys
+    /// the tree drops by itself: keys displaced by an inserted key that
+    /// prefixes or extends them, `max_len` evictions and expired entries.
+    /// Bulk operations such as `update()` and `clear()` report one event per
+    /// key. The tree is locked while callbacks run, and an exception raised
+    /// by one propagates to the caller of the mutating method.
+    ///
+    /// Args:
+    ///     callback: Callable taking (event, key, old_value, new_value)
+    ///
+    /// Returns:
+    ///     The callback, so `on_change` can be used as a decorator
+    ///
+    /// Raises:
+    ///     TypeError: If callback is not callable
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> events = []
+    ///     >>> @tree.on_change
+    ///     ... def log(event, key, old_value, new_value):
+    ///     ...     events.append((event, key, old_value, new_value))
+    ///     >>> tree["a"] = 1
+    ///     >>> tree["a"] = 2
+    ///     >>> del tree["a"]
+    ///     >>> events
+    ///     [('insert', 'a', None, 1), ('overwrite', 'a', 1, 2), ('remove', 'a', 2, None)]
+    fn on_change(&mut self, callback: Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        if !callback.is_callable() {
+            return Err(PyTypeError::new_err(
+                "on_change() callback must be callable",
+            ));
+        }
+        self.observers.push(callback.clone().unbind());
+        Ok(callback.unbind())
+    }
+
+    /// Unregister a callback added with `on_change()`.
+    ///
+    /// Raises:
+    ///     ValueError: If the callback is not registered
+    fn off_change(&mut self, callback: &Bound<'_, PyAny>) -> PyResult<()> {
+        let position = self.observers.iter().position(|o| o.is(callback));
+        match position {
+            Some(position) => {
+                self.observers.remove(position);
+                Ok(())
+            }
+            None => Err(PyValueError::new_err("callback is not registered")),
+        }
+    }
+
+    /// Return counters of the operations run on the tree since it was
+    /// created or `reset_metrics()` was last called.
+    ///
+    /// Lookups are `get()`, `tree[key]` and `in` checks, each counted as a
+    /// hit or a miss; expired entries count as misses. Inserts count every
+    /// value stored, for new keys and overwrites alike. Removals count keys
+    /// removed on request, evictions keys the tree dropped by itself (keys
+    /// displaced by a key that prefixes or extends them, `max_len`
+    /// evictions and expired entries), and fuzzy_scans the fuzzy searches
+    /// and suggestions run over the keys.
+    ///
+    /// Returns:
+    ///     Dict with "lookups", "hits", "misses", "inserts", "removals",
+    ///     "evictions" and "fuzzy_scans" counts
+    ///
+    /// Examples:
+    ///     >>> cache = TreeMap({"a": 1})
+    ///     >>> cache.get("a"), cache.get("b")
+    ///     (1, None)
+    ///     >>> stats = cache.metrics()
+    ///     >>> stats["hits"] / stats["lookups"]
+    ///     0.5
+    fn metrics<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.metrics.to_dict(py)
+    }
+
+    /// Set every counter reported by `metrics()` back to zero.
+    fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+
+    /// Remove all entries from the TreeMap.
+    ///
+    /// The tree's nodes are freed with the GIL released, so other threads
+    /// keep running while a large tree is dropped.
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2})
+    ///     >>> tree.clear()
+    ///     >>> len(tree)
+    ///     0
+    fn clear(&mut self, py: Python) -> PyResult<()> {
+        let mut notices = Vec::new();
+        if !self.observers.is_empty() {
+            for (key, value) in self.inner.iter() {
+                notices.extend(self.notice(py, Change::Remove, key, Some(value), None)?);
+            }
+        }
+        self.metrics.remove(self.inner.len());
+        if let Some(journal) = &mut self.journal {
+            for (key, value) in self.inner.iter() {
+                let spelling = self.original_keys.get(key);
+                journal.record(py, key, Some(value), spelling, self.deadlines.get(key));
+            }
+        }
+        if let Some(history) = &mut self.history {
+            for (key, value) in self.inner.iter() {
+                history.record(py, key, Some(value), self.original_keys.get(key));
+            }
+        }
+        // Free the nodes and keys with the GIL released, unless a snapshot
+        // still shares them; the values come back so their reference counts
+        // are dropped while attached
+        if let Some(old) = std::mem::take(&mut self.inner).try_into_inner() {
+            let values: Vec<Stored> = py.detach(move || old.into_values().collect());
+            drop(values);
+        }
+        if let Some(cache) = &self.pickled {
+            cache.clear();
+        }
+        if let Some(table) = &self.interned {
+            table.bind(py).clear();
+        }
+        self.original_keys = Default::default();
+        self.indexes.clear();
+        self.deadlines = Default::default();
+        self.next_deadline = None;
+        self.generation += 1;
+        self.notify(py, notices)
+    }
+
+    /// Remove every entry whose ttl has run out.
+    ///
+    /// Reads and writes sweep expired entries out before they look at the
+    /// tree, so they never show up; calling this frees their memory in a
+    /// tree that is not otherwise being used.
+    ///
+    /// Returns:
+    ///     Number of entries removed
+    ///
+    /// Examples:
+    ///     >>> import time
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.insert("session:1", "token", ttl=0.01)
+    ///     >>> time.sleep(0.02)
+    ///     >>> tree.expire()
+    ///     1
+    fn expire(&mut self, py: Python) -> PyResult<usize> {
+        self.purge_expired(py)
+    }
+
+    /// Keep only the entries for which a predicate returns a truthy value.
+    ///
+    /// The tree is walked once and failing entries are removed in place, without
+    /// collecting keys into a temporary list first.
+    ///
+    /// Args:
+    ///     predicate: Callable taking (key, value) and returning a truthy value
+    ///         for entries that should be kept
+    ///
+    /// Raises:
+    ///     Any exception raised by the predicate. Entries visited before the
+    ///     exception have already been filtered; the rest are kept.
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    ///     >>> tree.retain(lambda key, value: value % 2 == 0)
+    ///     >>> list(tree.items())
+    ///     [('b', 2), ('d', 4)]
+    fn retain(&mut self, py: Python, predicate: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.purge_expired(py)?;
+        let mut error: Option<PyErr> = None;
+        let mut notices = Vec::new();
+        let observed = !self.observers.is_empty();
+        let codec = &self.codec;
+        let original_keys = &mut self.original_keys;
+        let indexes = &mut self.indexes;
+        let generation = &mut self.generation;
+        let metrics = &self.metrics;
+        let journal = &mut self.journal;
+        let history = &mut self.history;
+        let deadlines = &self.deadlines;
+        let pickled = self.pickled.as_ref();
+        let compression = self.compression.as_ref();
+        self.inner.retain(|key, value| {
+            if error.is_some() {
+                return true;
+            }
+            match decode_key(py, codec, original_keys, key).and_then(|key_obj| {
+                let value_obj = original_value(pickled, compression, py, value)?;
+                let keep = predicate
+                    .call1((key_obj.clone_ref(py), value_obj.clone_ref(py)))?
+                    .is_truthy()?;
+                Ok((key_obj, value_obj, keep))
+            }) {
+                Ok((_, _, true)) => true,
+                Ok((key_obj, value_obj, false)) => {
+                    if observed {
+                        notices.push(Notice {
+                            change: Change::Remove,
+                            key: key_obj,
+                            old: Some(value_obj),
+                            new: None,
+                        });
+                    }
+                    let spelling = original_keys.get(key);
+                    if let Some(journal) = journal.as_mut() {
+                        journal.record(py, key, Some(value), spelling, deadlines.get(key));
+                    }
+                    if let Some(history) = history.as_mut() {
+                        history.record(py, key, Some(value), spelling);
+                    }
+                    original_keys.remove(key);
+                    indexes.remove(key);
+                    *generation += 1;
+                    metrics.remove(1);
+                    false
+                }
+                Err(err) => {
+                    error = Some(err);
+                    true
+                }
+            }
+        });
+        self.notify(py, notices)?;
+        match error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Return a new TreeMap with the same keys and `func(value)` as values.
+    ///
+    /// The copy is built in a single traversal of this tree; the original is
+    /// left unchanged.
+    ///
+    /// Args:
+    ///     func: Callable taking a value and returning the transformed value
+    ///
+    /// Returns:
+    ///     A new TreeMap with transformed values
+    ///
+    /// Raises:
+    ///     Any exception raised by func
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2})
+    ///     >>> doubled = tree.map_values(lambda v: v * 2)
+    ///     >>> list(doubled.items())
+    ///     [('a', 2), ('b', 4)]
+    fn map_values(slf: &Bound<'_, Self>, py: Python, func: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let this = &*Self::live(slf)?;
+        let mut inner = TreeMap::new();
+        let interned = this.interned.as_ref().map(|_| PyDict::new(py));
+        for (key, value) in this.inner.iter() {
+            let mapped = func.call1((this.value_out(py, value)?,))?.unbind();
+            let pickled = this.pickled.is_some();
+            let compression = this.compression.as_ref();
+            let mapped = stored_value(
+                this.value_type,
+                pickled,
+                compression,
+                interned.as_ref(),
+                py,
+                mapped,
+            )?;
+            inner.force_insert(key.clone(), mapped);
+        }
+        Ok(Self {
+            inner: inner.into(),
+            default_factory: this.default_factory.as_ref().map(|f| f.clone_ref(py)),
+            codec: this.codec.clone_ref(py),
+            case_insensitive: this.case_insensitive,
+            normalize: this.normalize.clone(),
+            original_keys: this.original_keys.share(),
+            generation: 0,
+            did_you_mean: this.did_you_mean,
+            indexes: this.indexes.share(),
+            deadlines: this.deadlines.share(),
+            next_deadline: this.next_deadline,
+            max_len: this.max_len,
+            eviction: this.eviction,
+            value_type: this.value_type,
+            pickled: this
+                .pickled
+                .as_ref()
+                .map(|p| PickleCache::new(p.capacity())),
+            compression: this.compression,
+            interned: interned.map(Bound::unbind),
+            loader: this.loader.as_ref().map(|f| f.clone_ref(py)),
+            observers: Vec::new(),
+            metrics: Metrics::default(),
+            journal: None,
+            history: None,
+        })
+    }
+
+    /// Explode the keys at a separator into nested dicts.
+    ///
+    /// Turns a flat key space such as {"db.host": ..., "db.port": ...} into
+    /// the structure of a configuration file. The inverse of
+    /// `from_nested_dict()`. Stored keys never prefix one another, so a
+    /// segment never holds both a value and nested keys.
+    ///
+    /// Args:
+    ///     separator: Separator between the levels of a key (defaults
+    ///         to ".")
+    ///
+    /// Returns:
+    ///     Dict of dicts, with the stored values at the leaves
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///     ValueError: If separator is empty
+    ///
+    /// Examples:
+    ///     >>> config = TreeMap({"db.host": "localhost", "db.port": 5432, "debug": True})
+    ///     >>> config.to_nested_dict()
+    ///     {'db': {'host': 'localhost', 'port': 5432}, 'debug': True}
+    #[pyo3(signature = (separator=None))]
+    fn to_nested_dict<'py>(
+        slf: &Bound<'py, Self>,
+        py: Python<'py>,
+        separator: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let this = &*Self::live(slf)?;
+        if !this.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "to_nested_dict requires str or bytes keys",
+            ));
+        }
+        let separator = match separator {
+            Some(separator) => separator.clone(),
+            None if this.codec.is_str() => PyString::new(py, ".").into_any(),
+            None => PyBytes::new(py, b".").into_any(),
+        };
+        if separator.len()? == 0 {
+            return Err(PyValueError::new_err("separator must not be empty"));
+        }
+        let root = PyDict::new(py);
+        for (key, value) in this.inner.iter() {
+            let key = this.decode_key(py, key)?.into_bound(py);
+            let parts: Vec<Bound<'py, PyAny>> =
+                key.call_method1("split", (&separator,))?.extract()?;
+            let Some((last, parents)) = parts.split_last() else {
+                continue;
+            };
+            let mut level = root.clone();
+            for part in parents {
+                level = match level.get_item(part)? {
+                    Some(child) => child.cast_into::<PyDict>().map_err(|_| {
+                        PyValueError::new_err(format!("key {} conflicts with a value", key))
+                    })?,
+                    None => {
+                        let child = PyDict::new(py);
+                        level.set_item(part, &child)?;
+                        child
+                    }
+                };
+            }
+            level.set_item(last, this.value_out(py, value)?)?;
+        }
+        Ok(root)
+    }
+
+    /// Build a TreeMap from nested dicts, joining the keys at each level.
+    ///
+    /// The inverse of `to_nested_dict()`: every value that is not a dict is
+    /// stored under the keys leading to it, joined with `separator`. Empty
+    /// dicts are stored as values, so they survive a round trip.
+    ///
+    /// Args:
+    ///     data: Dict whose values may be dicts themselves
+    ///     separator: Separator to join the levels of a key (defaults
+    ///         to ".")
+    ///     **kwargs: Options passed on to the TreeMap constructor
+    ///
+    /// Returns:
+    ///     A new TreeMap with one entry per leaf value
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap.from_nested_dict({"db": {"host": "localhost", "port": 5432}})
+    ///     >>> list(tree.items())
+    ///     [('db.host', 'localhost'), ('db.port', 5432)]
+    #[classmethod]
+    #[pyo3(signature = (data, separator=None, **kwargs))]
+    fn from_nested_dict<'py>(
+        cls: &Bound<'py, PyType>,
+        data: &Bound<'py, PyDict>,
+        separator: Option<&Bound<'py, PyAny>>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        fn flatten<'py>(
+            prefix: Option<&Bound<'py, PyAny>>,
+            level: &Bound<'py, PyDict>,
+            separator: &Bound<'py, PyAny>,
+            pairs: &Bound<'py, PyList>,
+        ) -> PyResult<()> {
+            for (key, value) in level.iter() {
+                let key = match prefix {
+                    Some(prefix) => prefix.add(separator)?.add(key)?,
+                    None => key,
+                };
+                match value.cast::<PyDict>() {
+                    Ok(nested) if !nested.is_empty() => {
+                        flatten(Some(&key), nested, separator, pairs)?
+                    }
+                    _ => pairs.append((key, value))?,
+                }
+            }
+            Ok(())
+        }
+        let py = cls.py();
+        let separator = match separator {
+            Some(separator) => separator.clone(),
+            None => PyString::new(py, ".").into_any(),
+        };
+        let pairs = PyList::empty(py);
+        flatten(None, data, &separator, &pairs)?;
+        cls.call((pairs,), kwargs)
+    }
+
+    /// Build a TreeMap from two columns of Arrow data.
+    ///
+    /// String and binary key columns are read straight from their Arrow
+    /// buffers, so no Python object is created per key. `data` is a pyarrow
+    /// Table or RecordBatch, or anything `pyarrow.table()` accepts through
+    /// the Arrow PyCapsule interface (such as a polars or DuckDB result).
+    ///
+    /// Args:
+    ///     data: Arrow table or record batch
+    ///     key_column: Name of the column holding the keys
+    ///     value_column: Name of the column holding the values
+    ///     **kwargs: Options passed on to the TreeMap constructor
+    ///
+    /// Returns:
+    ///     A new TreeMap with one entry per row
+    ///
+    /// Raises:
+    ///     ValueError: If the key column contains nulls
+    ///     TypeError: If the keys don't match the key_type
+    ///
+    /// Examples:
+    ///     >>> import pyarrow as pa
+    ///     >>> table = pa.table({"word": ["b", "a"], "count": [2, 1]})
+    ///     >>> tree = TreeMap.from_arrow(table, "word", "count")
+    ///     >>> list(tree.items())
+    ///     [('a', 1), ('b', 2)]
+    #[classmethod]
+    #[pyo3(signature = (data, key_column, value_column, **kwargs))]
+    fn from_arrow<'py>(
+        cls: &Bound<'py, PyType>,
+        data: &Bound<'py, PyAny>,
+        key_column: &str,
+        value_column: &str,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let data = match data.hasattr("column")? {
+            true => data.clone(),
+            false => py.import("pyarrow")?.call_method1("table", (data,))?,
+        };
+        let keys = data.call_method1("column", (key_column,))?;
+        let values = data
+            .call_method1("column", (value_column,))?
+            .call_method0("to_pylist")?
+            .try_iter()?
+            .map(|value| Ok(value?.unbind()))
+            .collect::<PyResult<Vec<_>>>()?;
+        Self::from_key_column(cls, &keys, values, kwargs)
+    }
+
+    /// Build a TreeMap from two columns of a CSV or TSV file.
+    ///
+    /// The file is read and parsed in Rust with the GIL released, with
+    /// records read as `csv.reader()` reads them. Fields may be quoted with
+    /// `"` (doubling quotes inside), which lets them hold delimiters and
+    /// newlines; blank lines are skipped. Keys and values are stored as str.
+    ///
+    /// Args:
+    ///     path: Path of the file, which must be UTF-8
+    ///     key_column: Column holding the keys, by header name or position
+    ///     value_column: Column holding the values, by header name or position
+    ///     delimiter: Single-character field separator ("\t" for TSV)
+    ///     header: Whether the first line names the columns (and is skipped)
+    ///     **kwargs: Options passed on to the TreeMap constructor
+    ///
+    /// Returns:
+    ///     A new TreeMap with one entry per record
+    ///
+    /// Raises:
+    ///     OSError: If the file cannot be read
+    ///     ValueError: If a column is unknown, or a record is malformed (the
+    ///         message gives its line number)
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap.from_csv("words.csv", "word", "definition")  # doctest: +SKIP
+    ///     >>> tree = TreeMap.from_csv("words.tsv", 0, 1, delimiter="\t", header=False)  # doctest: +SKIP
+    #[classmethod]
+    #[pyo3(signature = (path, key_column, value_column, delimiter=",", header=true, **kwargs))]
+    fn from_csv<'py>(
+        cls: &Bound<'py, PyType>,
+        path: std::path::PathBuf,
+        key_column: Column,
+        value_column: Column,
+        delimiter: &str,
+        header: bool,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let invalid = || {
+            PyValueError::new_err(
+                "delimiter must be a single ASCII character other than a quote or newline",
+            )
+        };
+        let &[delimiter] = delimiter.as_bytes() else {
+            return Err(invalid());
+        };
+        if !delimiter.is_ascii() || matches!(delimiter, b'"' | b'\n' | b'\r') {
+            return Err(invalid());
+        }
+        let columns = py.detach(|| {
+            let data = std::fs::read(&path)?;
+            read_columns(&data, delimiter, header, &key_column, &value_column)
+        })?;
+        let values = columns
+            .values
+            .iter()
+            .map(|value| PyString::new(py, value).into_any().unbind())
+            .collect();
+        let tree = cls.call((), kwargs)?;
+        let raw = RawKeys {
+            keys: columns.keys,
+            text: true,
+        };
+        tree.cast::<PyTreeMap>()?
+            .borrow_mut()
+            .insert_raw(py, raw, values)?;
+        Ok(tree)
+    }
+
+    /// Build a TreeMap from a JSON Lines file, one record per line.
+    ///
+    /// The file is read and parsed in Rust with the GIL released. Each line
+    /// holds a JSON object; its `key_field` becomes the key, and the value is
+    /// its `value_field`, or the whole record (as a dict, key included) when
+    /// no value field is given. Blank lines are skipped. Values are the
+    /// same as `json.loads()` gives: the last of repeated fields wins, and
+    /// lone surrogate escapes, NaN and Infinity are accepted.
+    ///
+    /// Args:
+    ///     path: Path of the file, which must be UTF-8
+    ///     key_field: Name of the field holding the key
+    ///     value_field: Name of the field holding the value (defaults to the
+    ///         whole record)
+    ///     **kwargs: Options passed on to the TreeMap constructor
+    ///
+    /// Returns:
+    ///     A new TreeMap with one entry per record
+    ///
+    /// Raises:
+    ///     OSError: If the file cannot be read
+    ///     ValueError: If a line is not a JSON object or lacks a field (the
+    ///         message gives its line number)
+    ///     TypeError: If a key is not of the tree's key type (the message
+    ///         gives its line number)
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap.from_jsonl("events.jsonl", "id")  # doctest: +SKIP
+    ///     >>> tree["evt-1"]  # doctest: +SKIP
+    ///     {'id': 'evt-1', 'type': 'click', 'ts': 1700000000}
+    #[classmethod]
+    #[pyo3(signature = (path, key_field, value_field=None, **kwargs))]
+    fn from_jsonl<'py>(
+        cls: &Bound<'py, PyType>,
+        path: std::path::PathBuf,
+        key_field: &str,
+        value_field: Option<&str>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let records = py.detach(|| {
+            let data = std::fs::read(&path)?;
+            json::read_records(&data, key_field, value_field).map_err(PyValueError::new_err)
+        })?;
+        let lines = records.iter().map(|(line, _, _)| *line).collect::<Vec<_>>();
+        let pairs = records
+            .into_iter()
+            .map(|(_, key, value)| Ok((key.into_py(py)?.into_bound(py), value.into_py(py)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        let tree = cls.call((), kwargs)?;
+        tree.cast::<PyTreeMap>()?
+            .borrow_mut()
+            .insert_all_from(py, pairs, |i, err| {
+                let located = PyErr::from_type(
+                    err.get_type(py),
+                    format!("line {}: {}", lines[i], err.value(py)),
+                );
+                located.set_cause(py, Some(err));
+                located
+            })?;
+        Ok(tree)
+    }
+
+    /// Build a TreeMap from two columns of a Parquet file.
+    ///
+    /// The file is read and decoded in Rust with the GIL released, without
+    /// pyarrow, and only the two columns are decoded. String and binary key
+    /// columns are inserted without creating a Python object per key.
+    /// Columns may hold booleans, integers, floats, strings or binary
+    /// values, with nulls read as None.
+    ///
+    /// Args:
+    ///     path: Path of the file
+    ///     key_column: Name of the column holding the keys
+    ///     value_column: Name of the column holding the values
+    ///     **kwargs: Options passed on to the TreeMap constructor
+    ///
+    /// Returns:
+    ///     A new TreeMap with one entry per row
+    ///
+    /// Raises:
+    ///     OSError: If the file cannot be read
+    ///     ValueError: If the file is not valid Parquet, a column is
+    ///         unknown, or the key column contains nulls
+    ///     TypeError: If a column holds another type (dates, decimals,
+    ///         nested values)
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap.from_parquet("words.parquet", "word", "count")  # doctest: +SKIP
+    #[classmethod]
+    #[pyo3(signature = (path, key_column, value_column, **kwargs))]
+    fn from_parquet<'py>(
+        cls: &Bound<'py, PyType>,
+        path: std::path::PathBuf,
+        key_column: &str,
+        value_column: &str,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let (keys, values) =
+            py.detach(|| columnar::read_columns(&path, key_column, value_column))?;
+        let values = values
+            .into_iter()
+            .map(|value| value.into_py(py))
+            .collect::<PyResult<Vec<_>>>()?;
+        let tree = cls.call((), kwargs)?;
+        let mut map = tree.cast::<PyTreeMap>()?.borrow_mut();
+        match columnar::raw_keys(keys) {
+            Ok(raw) => map.insert_raw(py, raw, values)?,
+            Err(keys) => {
+                let keys = keys
+                    .into_iter()
+                    .map(|key| Ok(key.into_py(py)?.into_bound(py)))
+                    .collect::<PyResult<Vec<_>>>()?;
+                map.insert_all(py, keys.into_iter().zip(values).collect())?;
+            }
+        }
+        drop(map);
+        Ok(tree)
+    }
+
+    /// Build a TreeMap from two columns of a polars DataFrame.
+    ///
+    /// The DataFrame is handed over as Arrow data with `to_arrow()`, which
+    /// shares its buffers rather than copying them, and is then loaded like
+    /// `from_arrow()`.
+    ///
+    /// Args:
+    ///     df: polars DataFrame
+    ///     key_column: Name of the column holding the keys
+    ///     value_column: Name of the column holding the values
+    ///     **kwargs: Options passed on to the TreeMap constructor
+    ///
+    /// Returns:
+    ///     A new TreeMap with one entry per row
+    ///
+    /// Raises:
+    ///     ValueError: If the key column contains nulls
+    ///
+    /// Examples:
+    ///     >>> import polars as pl
+    ///     >>> df = pl.DataFrame({"word": ["b", "a"], "count": [2, 1]})
+    ///     >>> list(TreeMap.from_polars(df, "word", "count").items())
+    ///     [('a', 1), ('b', 2)]
+    #[classmethod]
+    #[pyo3(signature = (df, key_column, value_column, **kwargs))]
+    fn from_polars<'py>(
+        cls: &Bound<'py, PyType>,
+        df: &Bound<'py, PyAny>,
+        key_column: &str,
+        value_column: &str,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let table = df.call_method0("to_arrow")?;
+        Self::from_arrow(cls, &table, key_column, value_column, kwargs)
+    }
+
+    /// Build a TreeMap from a pandas Series or DataFrame, keyed by its index.
+    ///
+    /// The index is converted to an Arrow array, so string keys are read
+    /// from its buffers like `from_arrow()` does. With a DataFrame, the
+    /// values come from `column`, which may be left out when there is only
+    /// one.
+    ///
+    /// Args:
+    ///     data: pandas Series or DataFrame
+    ///     column: Name of the DataFrame column holding the values
+    ///     **kwargs: Options passed on to the TreeMap constructor
+    ///
+    /// Returns:
+    ///     A new TreeMap with one entry per row
+    ///
+    /// Raises:
+    ///     ValueError: If a DataFrame has several columns and none is given,
+    ///         or the index contains nulls
+    ///
+    /// Examples:
+    ///     >>> import pandas as pd
+    ///     >>> tree = TreeMap.from_pandas(pd.Series([2, 1], index=["b", "a"]))
+    ///     >>> list(tree.items())
+    ///     [('a', 1), ('b', 2)]
+    #[classmethod]
+    #[pyo3(signature = (data, column=None, **kwargs))]
+    fn from_pandas<'py>(
+        cls: &Bound<'py, PyType>,
+        data: &Bound<'py, PyAny>,
+        column: Option<&Bound<'py, PyAny>>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let series = match (data.hasattr("columns")?, column) {
+            (false, _) => data.clone(),
+            (true, Some(column)) => data.get_item(column)?,
+            (true, None) if data.getattr("columns")?.len()? == 1 => {
+                data.getattr("iloc")?.get_item((PySlice::full(py), 0))?
+            }
+            (true, None) => {
+                return Err(PyValueError::new_err(
+                    "column is required for DataFrames with several columns",
+                ))
+            }
+        };
+        let keys = py
+            .import("pyarrow")?
+            .call_method1("array", (series.getattr("index")?,))?;
+        let values = series
+            .call_method0("tolist")?
+            .try_iter()?
+            .map(|value| Ok(value?.unbind()))
+            .collect::<PyResult<Vec<_>>>()?;
+        Self::from_key_column(cls, &keys, values, kwargs)
+    }
+
+    /// Return the keys, in key order, as a pyarrow array.
+    ///
+    /// str and bytes keys are copied straight into the Arrow buffers of a
+    /// string or binary array, without creating a Python object per key.
+    ///
+    /// Returns:
+    ///     pyarrow.Array of the keys
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"b": 2, "a": 1}).keys_to_arrow().to_pylist()
+    ///     ['a', 'b']
+    fn keys_to_arrow<'py>(slf: &Bound<'py, Self>, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let this = &*Self::live(slf)?;
+        this.arrow_keys(py)
+    }
+
+    /// Return the entries, in key order, as a pyarrow RecordBatch.
+    ///
+    /// The "key" column is built like `keys_to_arrow()`; the "value" column
+    /// is converted by `pyarrow.array()`, so values should be of one simple
+    /// type such as int, float, str or bytes.
+    ///
+    /// Returns:
+    ///     pyarrow.RecordBatch with "key" and "value" columns
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"b": 2, "a": 1}).to_arrow().to_pydict()
+    ///     {'key': ['a', 'b'], 'value': [1, 2]}
+    fn to_arrow<'py>(slf: &Bound<'py, Self>, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let this = &*Self::live(slf)?;
+        let pa = py.import("pyarrow")?;
+        let keys = this.arrow_keys(py)?;
+        let values = this.value_list(py)?;
+        let values = pa.call_method1("array", (values,))?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("names", ["key", "value"])?;
+        pa.getattr("RecordBatch")?
+            .call_method("from_arrays", ([keys, values],), Some(&kwargs))
+    }
+
+    /// Return the entries, in key order, as a polars DataFrame.
+    ///
+    /// Built from `to_arrow()`, so the same value types are supported.
+    ///
+    /// Returns:
+    ///     polars.DataFrame with "key" and "value" columns
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"b": 2, "a": 1}).to_polars()["key"].to_list()
+    ///     ['a', 'b']
+    fn to_polars<'py>(slf: &Bound<'py, Self>, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let batch = Self::to_arrow(slf, py)?;
+        py.import("polars")?.call_method1("from_arrow", (batch,))
+    }
+
+    /// Return the entries as a pandas Series indexed by key.
+    ///
+    /// The index is built from `keys_to_arrow()`, so it is in key order.
+    ///
+    /// Returns:
+    ///     pandas.Series of the values, with the keys as its index
+    ///
+    /// Examples:
+    ///     >>> series = TreeMap({"b": 2, "a": 1}).to_pandas()
+    ///     >>> series["a"]
+    ///     1
+    fn to_pandas<'py>(slf: &Bound<'py, Self>, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let this = &*Self::live(slf)?;
+        let pd = py.import("pandas")?;
+        let index = this.arrow_keys(py)?.call_method0("to_pandas")?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("index", pd.call_method1("Index", (index,))?)?;
+        let values = this.value_list(py)?;
+        pd.call_method("Series", (values,), Some(&kwargs))
+    }
+
+    /// Return a read-only snapshot of the tree as it is now.
+    ///
+    /// Taking a snapshot is O(1): it shares the tree's nodes, keys and
+    /// indexes instead of copying them. The first write to this tree while
+    /// the snapshot is alive copies them (O(n), once), so later writes don't
+    /// affect the snapshot and a background thread can iterate a consistent
+    /// state while this tree keeps changing. Values are never copied.
+    ///
+    /// Returns:
+    ///     TreeMapSnapshot supporting lookups, len(), iteration, prefix_iter(),
+    ///     first()/last() and keys()/values()/items()
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2})
+    ///     >>> snap = tree.snapshot()
+    ///     >>> del tree["a"]
+    ///     >>> list(snap.items())
+    ///     [('a', 1), ('b', 2)]
+    fn snapshot(slf: &Bound<'_, Self>, py: Python) -> PyResult<PyTreeMapSnapshot> {
+        let this = &*Self::live(slf)?;
+        Ok(PyTreeMapSnapshot::new(this.duplicate(py)))
+    }
+
+    /// Group changes so they are all kept or all undone.
+    ///
+    /// Inside `with tree.transaction():` the tree is read and written as
+    /// usual, while the state of each key before its first change is
+    /// logged. If an exception escapes the block, every changed key
+    /// (including keys evicted along the way) is restored and the exception
+    /// propagates; otherwise the changes stand. Restoring costs time
+    /// proportional to the number of changed keys, not the size of the tree.
+    ///
+    /// Returns:
+    ///     Context manager whose `__enter__` returns this tree
+    ///
+    /// Raises:
+    ///     RuntimeError: On entering, if a transaction is already open
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1})
+    ///     >>> with tree.transaction():
+    ///     ...     tree["b"] = 2
+    ///     ...     raise ValueError("abort")
+    ///     Traceback (most recent call last):
+    ///     ValueError: abort
+    ///     >>> "b" in tree
+    ///     False
+    fn transaction(slf: &Bound<'_, Self>) -> PyTreeMapTransaction {
+        PyTreeMapTransaction::new(slf.clone().unbind())
+    }
+
+    /// Return a read-only snapshot of the tree as it was at an earlier version.
+    ///
+    /// Only available on trees created with `versioned=True`. Every change
+    /// to a key (an insert, overwrite, removal or eviction, as reported to
+    /// `on_change()`) bumps the version by one, starting from 0 for the
+    /// empty tree.
+    ///
+    /// The snapshot is rebuilt on every call: the current tree is shared
+    /// with it, and undoing the changes logged since `version` copies the
+    /// tree's nodes and keys once (values are shared, not copied) and then
+    /// takes one step per change. Reading the current version costs
+    /// nothing; reading an old version of a large tree costs a full copy.
+    ///
+    /// Args:
+    ///     version: Version to read, between `tree.oldest_version` and
+    ///         `tree.version`
+    ///
+    /// Returns:
+    ///     TreeMapSnapshot of the tree at that version
+    ///
+    /// Raises:
+    ///     ValueError: If the tree is not versioned
+    ///     IndexError: If version is greater than the current version, or
+    ///         older than the oldest version kept
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap(versioned=True)
+    ///     >>> tree["a"] = 1
+    ///     >>> v = tree.version
+    ///     >>> tree["a"] = 2
+    ///     >>> tree.at_version(v)["a"]
+    ///     1
+    fn at_version(&self, py: Python, version: usize) -> PyResult<PyTreeMapSnapshot> {
+        let Some(history) = &self.history else {
+            return Err(PyValueError::new_err(
+                "at_version() requires a TreeMap created with versioned=True",
+            ));
+        };
+        if version > history.version() {
+            return Err(PyIndexError::new_err(format!(
+                "version {version} is newer than the current version {}",
+                history.version()
+            )));
+        }
+        if version < history.oldest() {
+            return Err(PyIndexError::new_err(format!(
+                "version {version} is older than the oldest version kept, {}",
+                history.oldest()
+            )));
+        }
+        let mut past = self.duplicate(py);
+        past.deadlines = Default::default();
+        let state = history.since(version);
+        for key in state.keys() {
+            if past.inner.remove(*key).is_some() {
+                past.forget(key);
+            }
+        }
+        for (key, prior) in state {
+            let Some(value) = &prior.value else {
+                continue;
+            };
+            if let Some(spelling) = &prior.spelling {
+                past.original_keys.insert(key.into(), spelling.clone());
+            }
+            past.indexes.add(key);
+            past.inner.force_insert(key.into(), value.clone_ref(py));
+        }
+        Ok(PyTreeMapSnapshot::new(past))
+    }
+
+    /// Number of changes made to a versioned tree, or None if it is not
+    /// versioned.
+    #[getter]
+    fn version(&self) -> Option<usize> {
+        self.history.as_ref().map(History::version)
+    }
+
+    /// Earliest version `at_version()` can still read in a versioned tree,
+    /// or None if it is not versioned.
+    #[getter]
+    fn oldest_version(&self) -> Option<usize> {
+        self.history.as_ref().map(History::oldest)
+    }
+
+    /// Forget the changes made before a version, freeing the keys and
+    /// values the log kept for them.
+    ///
+    /// Versions before `version` can no longer be read afterwards.
+    ///
+    /// Args:
+    ///     version: Oldest version to keep; at most `tree.version`
+    ///
+    /// Returns:
+    ///     Number of logged changes dropped
+    ///
+    /// Raises:
+    ///     ValueError: If the tree is not versioned
+    ///     IndexError: If version is greater than the current version
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap(versioned=True)
+    ///     >>> tree["a"] = 1
+    ///     >>> tree["a"] = 2
+    ///     >>> tree.prune_versions(tree.version)
+    ///     2
+    ///     >>> tree.oldest_version
+    ///     2
+    fn prune_versions(&mut self, version: usize) -> PyResult<usize> {
+        let Some(history) = &mut self.history else {
+            return Err(PyValueError::new_err(
+                "prune_versions() requires a TreeMap created with versioned=True",
+            ));
+        };
+        if version > history.version() {
+            return Err(PyIndexError::new_err(format!(
+                "version {version} is newer than the current version {}",
+                history.version()
+            )));
+        }
+        Ok(history.prune(version))
+    }
+
+    /// Copy the tree into shared memory that other processes can attach to.
+    ///
+    /// Worker processes attach with `TreeMap.attach(name)` (or receive the
+    /// returned map through pickling) and query the one shared copy instead
+    /// of each holding their own. Values are pickled into the block, and the
+    /// shared map is read-only.
+    ///
+    /// Args:
+    ///     name: Name for the shared memory block (defaults to a random name)
+    ///
+    /// Returns:
+    ///     SharedTreeMap owning the block; call unlink() when every process
+    ///     is done, or use it as a context manager
+    ///
+    /// Raises:
+    ///     TypeError: If the tree uses a custom key_type codec
+    ///     PicklingError: If a value cannot be pickled
+    ///
+    /// Examples:
+    ///     >>> with TreeMap({"apple": 1}).share() as shared:
+    ///     ...     with multiprocessing.Pool() as pool:
+    ///     ...         pool.map(lookup, [(shared, "apple")] * 4)
+    #[pyo3(signature = (name=None))]
+    fn share(slf: &Bound<'_, Self>, py: Python, name: Option<&str>) -> PyResult<PySharedTreeMap> {
+        let this = &*Self::live(slf)?;
+        shared::share(py, this, name)
+    }
+
+    /// Attach to a TreeMap shared by another process with share().
+    ///
+    /// Args:
+    ///     name: Name of the shared memory block (SharedTreeMap.name)
+    ///
+    /// Returns:
+    ///     Read-only SharedTreeMap; call close() when done
+    ///
+    /// Raises:
+    ///     FileNotFoundError: If no block with that name exists
+    ///     ValueError: If the block does not hold a shared TreeMap
+    ///
+    /// Examples:
+    ///     >>> index = TreeMap.attach("my_index")
+    ///     >>> index.get("apple")
+    ///     1
+    #[staticmethod]
+    fn attach(py: Python, name: &str) -> PyResult<PySharedTreeMap> {
+        PySharedTreeMap::attach(py, name)
+    }
+
+    /// Report the memory used by the tree, in bytes.
+    ///
+    /// Counts the radix tree nodes and the stored key bytes, as measured by
+    /// blart, and everything kept beside the tree: original key spellings,
+    /// the substring, suffix, order statistic and insertion order indexes,
+    /// ttl deadlines, the change history of a versioned tree, the journal of
+    /// an open transaction, the unpickling cache and the intern table. With
+    /// `deep=True`, the `sys.getsizeof()` of every distinct value is added as
+    /// well, including values only kept by the history, the journal or the
+    /// cache, recursing into values that are themselves TreeMaps.
+    ///
+    /// Args:
+    ///     deep: Also count the values (defaults to False)
+    ///
+    /// Returns:
+    ///     Number of bytes
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({f"key_{i}": i for i in range(1000)})
+    ///     >>> tree.memory_usage() < tree.memory_usage(deep=True)
+    ///     True
+    #[pyo3(signature = (deep=false))]
+    fn memory_usage(slf: &Bound<'_, Self>, deep: bool) -> PyResult<usize> {
+        let mut seen = HashSet::from([slf.as_ptr() as usize]);
+        slf.borrow().footprint(slf.py(), deep.then_some(&mut seen))
+    }
+
+    /// How well the values of a `compress_values` tree compress.
+    ///
+    /// Returns:
+    ///     None if values aren't compressed, otherwise a dict with the
+    ///     "codec" and "threshold", the number of "compressed_values", their
+    ///     "raw_bytes" and "compressed_bytes", and the "ratio" of the two
+    ///     (1.0 when nothing is compressed)
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": "x" * 10_000}, compress_values="zlib")
+    ///     >>> stats = tree.compression_stats()
+    ///     >>> stats["compressed_values"], stats["raw_bytes"]
+    ///     (1, 10000)
+    ///     >>> stats["ratio"] > 10
+    ///     True
+    fn compression_stats<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.compression
+            .as_ref()
+            .map(|compression| {
+                compression.stats(py, self.inner.values().filter_map(Stored::object))
+            })
+            .transpose()
+    }
+
+    /// Rebuild the tree to release memory left over from removed keys.
+    ///
+    /// After a large wave of deletions a long-lived tree can keep memory
+    /// sized for its high-water mark. This reinserts every entry into a
+    /// fresh tree with the GIL released, rebuilds the order statistics
+    /// without the slots of removed keys, shrinks the internal tables, drops
+    /// values no longer stored from the intern table, and empties the cache
+    /// of unpickled values. Keys, values and settings are unchanged.
+    ///
+    /// Returns:
+    ///     Number of bytes reclaimed, as measured by `memory_usage()`
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({f"user:{i:06}": i for i in range(100_000)}, case_insensitive=True)
+    ///     >>> tree.delete_range("user:000000", "user:099000")
+    ///     99000
+    ///     >>> tree.compact() > 0
+    ///     True
+    fn compact(&mut self, py: Python) -> PyResult<usize> {
+        self.purge_expired(py)?;
+        let before = self.footprint(py, None)?;
+        let old = std::mem::take(&mut self.inner).into_inner();
+        let indexes = &mut self.indexes;
+        self.inner = py
+            .detach(move || {
+                indexes.compact();
+                let mut inner = TreeMap::new();
+                for (key, value) in old {
+                    inner.force_insert(key, value);
+                }
+                inner
+            })
+            .into();
+        self.original_keys.shrink_to_fit();
+        self.deadlines.shrink_to_fit();
+        if let Some(table) = &self.interned {
+            let stored: HashSet<usize> = self
+                .inner
+                .values()
+                .filter_map(Stored::object)
+                .map(|v| v.as_ptr() as usize)
+                .collect();
+            let kept = PyDict::new(py);
+            for (key, value) in table.bind(py).iter() {
+                if stored.contains(&(value.as_ptr() as usize)) {
+                    kept.set_item(key, value)?;
+                }
+            }
+            self.interned = Some(kept.unbind());
+        }
+        if let Some(cache) = &self.pickled {
+            cache.clear();
+        }
+        Ok(before.saturating_sub(self.footprint(py, None)?))
+    }
+
+    /// Size of the TreeMap in bytes, including its Rust-side tree but not the values.
+    fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        static OBJECT: PyOnceLock<Py<PyType>> = PyOnceLock::new();
+        let base: usize = OBJECT
+            .import(slf.py(), "builtins", "object")?
+            .call_method1("__sizeof__", (slf,))?
+            .extract()?;
+        Ok(base + slf.borrow().footprint(slf.py(), None)?)
+    }
+
+    /// Describe the shape of the radix tree.
+    ///
+    /// Useful for understanding how well a dataset compresses: long shared
+    /// prefixes are stored once in inner node headers rather than as chains
+    /// of single-child nodes.
+    ///
+    /// Returns:
+    ///     Dict with the keys:
+    ///         node4, node16, node48, node256: Inner node counts by type
+    ///         inner_nodes: Total number of inner nodes
+    ///         leaves: Number of leaves (equal to len(tree))
+    ///         max_depth, avg_depth: Inner nodes on the path to a leaf
+    ///         key_bytes: Total bytes of all stored keys
+    ///         prefix_bytes: Key bytes folded into compressed node prefixes
+    ///         prefix_savings: Estimated bytes saved by prefix compression,
+    ///             compared with one Node4 per compressed byte
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "apricot": 2, "banana": 3})
+    ///     >>> stats = tree.stats()
+    ///     >>> stats["leaves"], stats["node4"], stats["max_depth"]
+    ///     (3, 2, 2)
+    fn stats<'py>(slf: &Bound<'py, Self>, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let this = &*Self::live(slf)?;
+        let inner = &this.inner;
+        let (stats, depths) = py.detach(|| {
+            let stats = TreeStatsCollector::collect(inner).unwrap_or_default();
+            let keys: Vec<&[u8]> = inner.iter().map(|(k, _)| &**k).collect();
+            (stats, leaf_depths(&keys))
+        });
+        let max_depth = depths.iter().copied().max().unwrap_or(0);
+        let avg_depth = if depths.is_empty() {
+            0.0
+        } else {
+            depths.iter().sum::<usize>() as f64 / depths.len() as f64
+        };
+        let node4_size = std::mem::size_of::<
+            blart::raw::InnerNode4<Box<[u8]>, Py<PyAny>, { blart::map::DEFAULT_PREFIX_LEN }>,
+        >();
+        let result = PyDict::new(py);
+        result.set_item("node4", stats.node4.count)?;
+        result.set_item("node16", stats.node16.count)?;
+        result.set_item("node48", stats.node48.count)?;
+        result.set_item("node256", stats.node256.count)?;
+        result.set_item("inner_nodes", stats.tree.count)?;
+        result.set_item("leaves", this.inner.len())?;
+        result.set_item("max_depth", max_depth)?;
+        result.set_item("avg_depth", avg_depth)?;
+        result.set_item("key_bytes", stats.leaf.sum_key_bytes)?;
+        result.set_item("prefix_bytes", stats.tree.sum_prefix_len_bytes)?;
+        result.set_item(
+            "prefix_savings",
+            stats.tree.sum_prefix_len_bytes * node4_size,
+        )?;
+        Ok(result)
+    }
+
+    /// Render the radix tree structure as a Graphviz DOT graph.
+    ///
+    /// Walks blart's actual nodes, so the graph shows the node types and
+    /// compressed prefixes the tree really uses. Inner nodes show their type
+    /// and compressed prefix, edges are labelled
+    /// with the key byte they branch on, and leaves show their key. Useful for
+    /// debugging unexpected prefix behavior.
+    ///
+    /// Args:
+    ///     max_nodes: Stop after this many nodes; the keys below unexpanded
+    ///         nodes are summarized as "... N more keys" (defaults to no limit)
+    ///     path: If given, write the graph to this file instead of returning it
+    ///
+    /// Returns:
+    ///     The DOT source, or None when written to a file
+    ///
+    /// Raises:
+    ///     OSError: If the file cannot be written
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "apricot": 2})
+    ///     >>> print(tree.to_dot())  # doctest: +SKIP
+    ///     >>> tree.to_dot(path="tree.dot")  # then: dot -Tsvg tree.dot
+    #[pyo3(signature = (max_nodes=None, path=None))]
+    fn to_dot(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        max_nodes: Option<usize>,
+        path: Option<std::path::PathBuf>,
+    ) -> PyResult<Option<String>> {
+        let this = &*Self::live(slf)?;
+        let shape = Shape::of(&this.inner);
+        let max_nodes = max_nodes.unwrap_or(usize::MAX);
+        let mut out = String::from("digraph TreeMap {\n    node [fontname=\"monospace\"];\n");
+        // (node, depth of its prefix, parent and edge byte)
+        let mut queue = std::collections::VecDeque::new();
+        if let Some(root) = &shape {
+            queue.push_back((root, 0, None));
+        }
+        let mut next_id = 0usize;
+        while let Some((node, depth, parent)) = queue.pop_front() {
+            let id = next_id;
+            if id >= max_nodes {
+                queue.push_front((node, depth, parent));
+                break;
+            }
+            next_id += 1;
+            match node {
+                Shape::Leaf(key) => {
+                    let key = this.decode_key(py, key)?;
+                    let label = key.bind(py).repr()?.to_string();
+                    out.push_str(&format!(
+                        "    n{id} [shape=ellipse, label=\"{}\"];\n",
+                        dot_text(&label)
+                    ));
+                }
+                Shape::Inner {
+                    kind,
+                    prefix_len,
+                    children,
+                    ..
+                } => {
+                    let split = depth + prefix_len;
+                    let mut label = kind.to_string();
+                    if *prefix_len > 0 {
+                        label.push_str(&format!(
+                            "\\nprefix: {}",
+                            dot_bytes(&node.first_key()[depth..split])
+                        ));
+                    }
+                    out.push_str(&format!("    n{id} [shape=box, label=\"{label}\"];\n"));
+                    for (byte, child) in children {
+                        queue.push_back((child, split + 1, Some((id, *byte))));
+                    }
+                }
+            }
+            if let Some((parent, byte)) = parent {
+                out.push_str(&format!(
+                    "    n{parent} -> n{id} [label=\"{}\"];\n",
+                    dot_bytes(&[byte])
+                ));
+            }
+        }
+        // Summarize whatever was left unexpanded, one node per parent
+        let mut remaining: Vec<(Option<usize>, usize)> = Vec::new();
+        for (node, _, parent) in queue {
+            let parent = parent.map(|(p, _)| p);
+            match remaining.last_mut() {
+                Some((p, count)) if *p == parent => *count += node.keys(),
+                _ => remaining.push((parent, node.keys())),
+            }
+        }
+        for (parent, count) in remaining {
+            let id = next_id;
+            next_id += 1;
+            out.push_str(&format!(
+                "    n{id} [shape=plaintext, label=\"... {count} more keys\"];\n"
+            ));
+            if let Some(parent) = parent {
+                out.push_str(&format!("    n{parent} -> n{id} [style=dashed];\n"));
+            }
+        }
+        out.push_str("}\n");
+        match path {
+            Some(path) => {
+                std::fs::write(path, out)?;
+                Ok(None)
+            }
+            None => Ok(Some(out)),
+        }
+    }
+
+    /// Print the radix tree structure as an indented ASCII tree.
+    ///
+    /// Each inner node shows its type and the compressed prefix its keys
+    /// share, each branch the key byte it is taken on, and each leaf its
+    /// key. Useful for seeing how keys compress and for debugging
+    /// unexpected `prefix_iter()` results.
+    ///
+    /// Args:
+    ///     max_depth: Only expand this many levels of inner nodes; the keys
+    ///         below are summarized as "... N keys" (defaults to no limit)
+    ///     file: Stream to write to, as for print() (defaults to sys.stdout)
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"apple": 1, "apricot": 2, "banana": 3}).pretty_print()
+    ///     Node4
+    ///     |-- a: Node4 prefix="p"
+    ///     |   |-- p: 'apple'
+    ///     |   `-- r: 'apricot'
+    ///     `-- b: 'banana'
+    #[pyo3(signature = (max_depth=None, file=None))]
+    fn pretty_print(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        max_depth: Option<usize>,
+        file: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let this = &*Self::live(slf)?;
+        static PRINT: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+        let mut out = String::new();
+        match Shape::of(&this.inner) {
+            None => out.push_str("(empty)\n"),
+            Some(root) => this.render_node(py, &root, 0, max_depth, "", &mut out)?,
+        }
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("end", "")?;
+        kwargs.set_item("file", file)?;
+        PRINT
+            .import(py, "builtins", "print")?
+            .call((out,), Some(&kwargs))?;
+        Ok(())
+    }
+
+    /// Check the tree's structural integrity.
+    ///
+    /// Walks the radix tree verifying its invariants (child counts per node
+    /// type, node prefixes matching leaf keys, every leaf reachable and linked
+    /// in order), then checks that keys are sorted, that no key is a prefix of
+    /// another, and that every key survives a decode/encode round trip through
+    /// the key codec. Useful when testing custom codecs and persistence.
+    ///
+    /// Raises:
+    ///     RuntimeError: Describing the first problem found
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "banana": 2})
+    ///     >>> tree.validate()
+    fn validate(slf: &Bound<'_, Self>, py: Python) -> PyResult<()> {
+        let this = &*Self::live(slf)?;
+        let invalid =
+            |detail: String| PyRuntimeError::new_err(format!("TreeMap is invalid: {detail}"));
+        let inner = &this.inner;
+        let stats = py
+            .detach(|| WellFormedChecker::check(inner).map_err(|err| err.to_string()))
+            .map_err(invalid)?;
+        if stats.num_leaf != this.inner.len() {
+            return Err(invalid(format!(
+                "found {} leaves but the length is {}",
+                stats.num_leaf,
+                this.inner.len()
+            )));
+        }
+        let mut previous: Option<&[u8]> = None;
+        for (key, _) in this.inner.iter() {
+            if let Some(previous) = previous {
+                if previous >= &**key {
+                    return Err(invalid(format!(
+                        "keys out of order: {previous:?} before {key:?}"
+                    )));
+                }
+                if key.starts_with(previous) {
+                    return Err(invalid(format!("key {previous:?} is a prefix of {key:?}")));
+                }
+            }
+            let decoded = this.decode_key(py, key)?;
+            let encoded = this.encode_key(decoded.bind(py))?;
+            if encoded != *key {
+                return Err(invalid(format!(
+                    "key {key:?} decodes to {} which encodes to {encoded:?}",
+                    decoded.bind(py).repr()?
+                )));
+            }
+            previous = Some(key);
+        }
+        if let Some(key) = this
+            .original_keys
+            .keys()
+            .find(|k| !this.inner.contains_key(&***k))
+        {
+            return Err(invalid(format!(
+                "original spelling recorded for missing key {key:?}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check if the TreeMap contains no entries.
+    ///
+    /// Returns:
+    ///     True if the TreeMap is empty, False otherwise
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap()
+    ///     >>> tree.is_empty()
+    ///     True
+    ///     >>> tree["key"] = "value"
+    ///     >>> tree.is_empty()
+    ///     False
+    fn is_empty(slf: &Bound<'_, Self>) -> PyResult<bool> {
+        let this = &*Self::live(slf)?;
+        Ok(this.inner.is_empty())
+    }
+
+    /// Get item using subscript notation (tree[key]).
+    ///
+    /// Args:
+    ///     key: Key to look up
+    ///
+    /// If the TreeMap was created with a default_factory, a missing key is
+    /// inserted with the value `default_factory()` and that value is returned.
+    /// With a loader, it is inserted with `loader(key)` instead.
+    ///
+    /// Returns:
+    ///     The value associated with the key
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist and there is no default_factory
+    ///         or loader, or if the loader raised
+    fn __getitem__(slf: &Bound<'_, Self>, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        let py = slf.py();
+        let key_bytes = {
+            // Hits only read the tree, so threads can look up in parallel
+            let tree = Self::live(slf)?;
+            let key_bytes = tree.encode_key(key)?;
+            if let Some(value) = tree.lookup_value(key)? {
+                return tree.value_out(py, value);
+            }
+            let fills = tree.loader.is_some() || tree.default_factory.is_some();
+            if !fills && !tree.is_expired(&key_bytes) {
+                return Err(tree.missing_key(py, key, &key_bytes));
+            }
+            key_bytes
+        };
+        {
+            let mut tree = slf.try_borrow_mut()?;
+            if tree.is_expired(&key_bytes) {
+                if let Some(value) = tree.inner.remove(&key_bytes) {
+                    tree.discard(py, &key_bytes, &value, Change::Evict)?;
+                }
+            }
+        }
+        if let Some(value) = Self::load(slf, key)? {
+            return Ok(value);
+        }
+        let mut tree = slf.try_borrow_mut()?;
+        match &tree.default_factory {
+            Some(factory) => {
+                let factory = factory.clone_ref(py);
+                tree.get_or_insert(py, key, factory.bind(py))
+            }
+            None => Err(tree.missing_key(py, key, &key_bytes)),
+        }
+    }
+
+    /// Let the garbage collector see stored values, so cycles through them
+    /// (e.g. a value referring back to the TreeMap) can be collected.
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        self.traverse(&visit)
+    }
+
+    fn __clear__(&mut self) {
+        self.inner = Default::default();
+        if let Some(cache) = &self.pickled {
+            cache.clear();
+        }
+        self.interned = None;
+        self.original_keys = Default::default();
+        self.indexes.clear();
+        self.deadlines = Default::default();
+        self.default_factory = None;
+        self.loader = None;
+        self.observers.clear();
+        self.journal = None;
+        self.history = None;
+        self.generation += 1;
+    }
+
+    /// The callable used to create values for missing keys, or None.
+    #[getter]
+    fn default_factory(&self, py: Python) -> Option<Py<PyAny>> {
+        self.default_factory.as_ref().map(|f| f.clone_ref(py))
+    }
+
+    /// The callable used to load values for missing keys, or None.
+    #[getter]
+    fn loader(&self, py: Python) -> Option<Py<PyAny>> {
+        self.loader.as_ref().map(|f| f.clone_ref(py))
+    }
+
+    /// How keys are stored: a key_type name such as "str" or "int", or the codec object.
+    #[getter]
+    fn key_type(&self, py: Python) -> Py<PyAny> {
+        self.codec.key_type(py)
+    }
+
+    /// How str keys that are not valid UTF-8 are decoded, or None for other key types.
+    #[getter]
+    fn errors(&self) -> Option<&'static str> {
+        self.codec.errors()
+    }
+
+    /// Whether keys are compared case-insensitively.
+    #[getter]
+    fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    /// The Unicode normalization form applied to keys, or None.
+    #[getter]
+    fn normalize(&self) -> Option<String> {
+        self.normalize.clone()
+    }
+
+    /// Whether the tree keeps a suffix index for `contains_substring()`.
+    #[getter]
+    fn substring_index(&self) -> bool {
+        self.indexes.substrings.is_some()
+    }
+
+    /// Whether `enable_suffix_index()` has been called.
+    #[getter]
+    fn suffix_index(&self) -> bool {
+        self.indexes.suffixes.is_some()
+    }
+
+    /// Whether the tree keeps order statistics for counting and ranking.
+    #[getter]
+    fn order_statistics(&self) -> bool {
+        self.indexes.counts.is_some()
+    }
+
+    /// The most entries the tree may hold, or None.
+    #[getter]
+    fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// What inserting a new key into a full tree does.
+    #[getter]
+    fn eviction(&self) -> &'static str {
+        self.eviction.name()
+    }
+
+    /// The type values are restricted to: "int", "float", "bytes" or None.
+    #[getter]
+    fn value_type(&self) -> Option<&'static str> {
+        self.value_type.map(ValueType::name)
+    }
+
+    /// Whether values are stored pickled and unpickled on access.
+    #[getter]
+    fn store_pickled(&self) -> bool {
+        self.pickled.is_some()
+    }
+
+    /// Whether equal immutable values are stored as one shared object.
+    #[getter]
+    fn intern_values(&self) -> bool {
+        self.interned.is_some()
+    }
+
+    /// The codec large values are compressed with: "zstd", "zlib" or None.
+    #[getter]
+    fn compress_values(&self) -> Option<&'static str> {
+        self.compression.as_ref().map(Compression::codec)
+    }
+
+    /// Whether KeyErrors for missing str keys suggest the closest stored keys.
+    #[getter]
+    fn did_you_mean(&self) -> bool {
+        self.did_you_mean
+    }
+
+    #[setter]
+    fn set_did_you_mean(&mut self, enabled: bool) {
+        self.did_you_mean = enabled;
+    }
+
+    /// Set item using subscript notation (tree[key] = value).
+    ///
+    /// Args:
+    ///     key: Key
+    ///     value: Python object to store
+    fn __setitem__(&mut self, key: &Bound<'_, PyAny>, value: Py<PyAny>) -> PyResult<()> {
+        self.insert(key, value, None)
+    }
+
+    /// Delete item using del statement (del tree[key]).
+    ///
+    /// Args:
+    ///     key: Key to delete
+    ///
+    /// Raises:
+    ///     KeyError: If the key does not exist
+    fn __delitem__(&mut self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.remove(py, key)?;
+        Ok(())
+    }
+
+    /// Check if key exists using 'in' operator (key in tree).
+    ///
+    /// Args:
+    ///     key: Key to check
+    ///
+    /// Returns:
+    ///     True if key exists, False otherwise
+    fn __contains__(slf: &Bound<'_, Self>, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let this = &*Self::live(slf)?;
+        Ok(this.lookup_value(key)?.is_some())
+    }
+
+    /// Get the number of entries in the TreeMap.
+    ///
+    /// Returns:
+    ///     Number of key-value pairs
+    fn __len__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        let this = &*Self::live(slf)?;
+        Ok(this.inner.len())
+    }
+
+    /// Return a developer-friendly string representation.
+    ///
+    /// The first few entries are shown, as many as
+    /// `TreeMap.set_repr_limit()` allows (5 by default), followed by "..."
+    /// when there are more. A tree that contains itself is shown as
+    /// "TreeMap(...)" inside its own repr.
+    ///
+    /// Returns:
+    ///     String like "TreeMap({'a': 1, 'ab': 2, ...}, len=10423)"
+    fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let py = slf.py();
+        // SAFETY: slf is a live object; every successful Py_ReprEnter is
+        // paired with a Py_ReprLeave below
+        match unsafe { ffi::Py_ReprEnter(slf.as_ptr()) } {
+            0 => {}
+            -1 => return Err(PyErr::fetch(py)),
+            _ => return Ok("TreeMap(...)".to_string()),
+        }
+        let preview = Self::live(slf).and_then(|tree| {
+            let limit = REPR_LIMIT.load(AtomicOrdering::Relaxed);
+            Ok((tree.preview(py, limit)?, tree.inner.len()))
+        });
+        unsafe { ffi::Py_ReprLeave(slf.as_ptr()) };
+        let (preview, len) = preview?;
+        Ok(format!("TreeMap({{{preview}}}, len={len})"))
+    }
+
+    /// Set how many entries `repr()` shows for every TreeMap.
+    ///
+    /// Args:
+    ///     limit: Number of entries to show; 0 shows only the length
+    ///
+    /// Returns:
+    ///     The previous limit
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "ab": 2, "b": 3})
+    ///     >>> previous = TreeMap.set_repr_limit(2)
+    ///     >>> tree
+    ///     TreeMap({'a': 1, 'ab': 2, ...}, len=3)
+    ///     >>> TreeMap.set_repr_limit(previous)
+    ///     2
+    #[staticmethod]
+    fn set_repr_limit(limit: usize) -> usize {
+        REPR_LIMIT.swap(limit, AtomicOrdering::Relaxed)
+    }
+
+    /// Return a user-friendly string representation.
+    ///
+    /// Returns:
+    ///     String like "TreeMap with 5 entries"
+    fn __str__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let this = &*Self::live(slf)?;
+        Ok(format!("TreeMap with {} entries", this.inner.len()))
+    }
+
+    /// Return an iterator over keys in lexicographic order.
+    ///
+    /// Returns:
+    ///     Iterator that yields keys
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
+    ///     >>> list(tree)
+    ///     ['a', 'b', 'c']
+    fn __iter__(slf: &Bound<'_, Self>, py: Python) -> PyResult<PyTreeMapIter> {
+        let slf = Self::live(slf)?;
+        Ok(PyTreeMapIter::new(slf.key_list(py)?, IterGuard::new(&slf)))
+    }
+
+    /// Return a view of the keys, in lexicographic order.
+    ///
+    /// Like dict.keys(), the view reflects later changes to the tree and
+    /// supports len(), membership tests and set operations.
+    ///
+    /// Returns:
+    ///     Live view of the keys
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
+    ///     >>> list(tree.keys())
+    ///     ['a', 'b', 'c']
+    ///     >>> tree.keys() & {"a", "z"}
+    ///     {'a'}
+    fn keys(slf: &Bound<'_, Self>) -> PyTreeMapKeysView {
+        PyTreeMapKeysView::new(slf.clone().unbind())
+    }
+
+    /// Return a view of the values, in key order.
+    ///
+    /// Returns:
+    ///     Live view of the values
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
+    ///     >>> list(tree.values())
+    ///     [1, 2, 3]
+    fn values(slf: &Bound<'_, Self>) -> PyTreeMapValuesView {
+        PyTreeMapValuesView::new(slf.clone().unbind())
+    }
+
+    /// Return a view of the (key, value) pairs, in lexicographic order.
+    ///
+    /// With `offset` or `limit`, returns an iterator over one page of the
+    /// pairs instead, e.g. for paginated APIs. Skipped entries are passed
+    /// over without being converted to Python objects.
+    ///
+    /// Args:
+    ///     offset: Number of leading pairs to skip (defaults to 0)
+    ///     limit: Maximum number of pairs to return (defaults to no limit)
+    ///
+    /// Returns:
+    ///     Live view of the items, supporting set operations, or an iterator
+    ///     yielding (key, value) tuples when paginating
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
+    ///     >>> list(tree.items())
+    ///     [('a', 1), ('b', 2), ('c', 3)]
+    ///     >>> list(tree.items(offset=1, limit=1))
+    ///     [('b', 2)]
+    #[pyo3(signature = (*, offset=0, limit=None))]
+    fn items(slf: &Bound<'_, Self>, offset: usize, limit: Option<usize>) -> PyResult<Py<PyAny>> {
+        let py = slf.py();
+        if offset == 0 && limit.is_none() {
+            let view = PyTreeMapItemsView::new(slf.clone().unbind());
+            return Ok(Bound::new(py, view)?.into_any().unbind());
+        }
+        let this = Self::live(slf)?;
+        let items = this
+            .inner
+            .iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(k, v)| Ok((this.decode_key(py, k)?, this.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        let iter = PyTreeMapItems::new(items, IterGuard::new(&this));
+        Ok(Bound::new(py, iter)?.into_any().unbind())
+    }
+
+    /// Copy the entries into a new dict, in key order.
+    ///
+    /// Built in one pass over the tree into a dict sized for every entry up
+    /// front, which is much faster than `dict(tree.items())`.
+    ///
+    /// Returns:
+    ///     dict of every key and value
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"b": 2, "a": 1}).to_dict()
+    ///     {'a': 1, 'b': 2}
+    fn to_dict<'py>(slf: &Bound<'py, Self>, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let this = &*Self::live(slf)?;
+        // SAFETY: _PyDict_NewPresized returns a new reference to a dict, or
+        // NULL with an exception set
+        let dict = unsafe {
+            Bound::from_owned_ptr_or_err(py, ffi::_PyDict_NewPresized(this.inner.len() as isize))?
+                .cast_into_unchecked::<PyDict>()
+        };
+        for (key, value) in this.inner.iter() {
+            dict.set_item(this.decode_key(py, key)?, this.value_out(py, value)?)?;
+        }
+        Ok(dict)
+    }
+
+    /// Copy the entries into a new list of (key, value) pairs, in key order.
+    ///
+    /// Returns:
+    ///     list of (key, value) tuples
+    ///
+    /// Examples:
+    ///     >>> TreeMap({"b": 2, "a": 1}).to_list()
+    ///     [('a', 1), ('b', 2)]
+    fn to_list<'py>(slf: &Bound<'py, Self>, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let this = &*Self::live(slf)?;
+        let items = this
+            .inner
+            .iter()
+            .map(|(key, value)| Ok((this.decode_key(py, key)?, this.value_out(py, value)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyList::new(py, items)
+    }
+
+    /// Get the first key-value pair matching a prefix.
+    ///
+    /// This is useful for quickly checking if any keys start with a given prefix,
+    /// or for getting a representative value for a prefix.
+    ///
+    /// Args:
+    ///     prefix: Prefix to search for
+    ///
+    /// Returns:
+    ///     (key, value) tuple for first match, or None if no match
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "application": 2, "banana": 3})
+    ///     >>> tree.get_prefix("app")
+    ///     ('apple', 1)
+    ///     >>> tree.get_prefix("ban")
+    ///     ('banana', 3)
+    ///     >>> tree.get_prefix("xyz")
+    ///     None
+    fn get_prefix(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        let prefix_bytes = this.encode_prefix(prefix)?;
+        // Use prefix iterator to get the first matching key-value pair
+        let mut iter = this.prefix_entries(&prefix_bytes);
+        match iter.next() {
+            Some((key, val)) => {
+                let key_obj = this.decode_key(py, key)?;
+                Ok(Some((key_obj, this.value_out(py, val)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Check whether any stored key extends a prefix.
+    ///
+    /// As pygtrie's `Trie.has_subtrie()`: the prefix itself being stored
+    /// does not count, only longer keys starting with it.
+    ///
+    /// Args:
+    ///     prefix: Prefix to look under
+    ///
+    /// Returns:
+    ///     True if a stored key is longer than `prefix` and starts with it
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "banana": 2})
+    ///     >>> tree.has_subtrie("app")
+    ///     True
+    ///     >>> tree.has_subtrie("apple")
+    ///     False
+    fn has_subtrie(slf: &Bound<'_, Self>, prefix: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let this = &*Self::live(slf)?;
+        let prefix_bytes = this.encode_prefix(prefix)?;
+        let extended = this
+            .prefix_entries(&prefix_bytes)
+            .any(|(k, _)| k.len() > prefix_bytes.len());
+        Ok(extended)
+    }
+
+    /// Check whether a key is stored, as pygtrie's `Trie.has_key()`.
+    ///
+    /// Args:
+    ///     key: Key to check
+    ///
+    /// Returns:
+    ///     True if the key is stored
+    fn has_key(slf: &Bound<'_, Self>, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let this = &*Self::live(slf)?;
+        Ok(this.lookup_value(key)?.is_some())
+    }
+
+    /// Find the stored key that a key starts with.
+    ///
+    /// The stored key may be the key itself. For pygtrie's
+    /// `Trie.shortest_prefix()`; stored keys never prefix one another, so
+    /// this always finds the same entry as `longest_prefix()`.
+    ///
+    /// Args:
+    ///     key: Key to find a prefix of (str or bytes, like the stored keys)
+    ///
+    /// Returns:
+    ///     (key, value) tuple of the stored prefix, or None if there is none
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///
+    /// Examples:
+    ///     >>> routes = TreeMap({"/api/": "api", "/static/": "files"})
+    ///     >>> routes.shortest_prefix("/api/users")
+    ///     ('/api/', 'api')
+    ///     >>> routes.shortest_prefix("/admin") is None
+    ///     True
+    fn shortest_prefix(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        Self::longest_prefix(slf, py, key)
+    }
+
+    /// Find the stored key that a key starts with.
+    ///
+    /// The stored key may be the key itself. For pygtrie's
+    /// `Trie.longest_prefix()`, e.g. to route a path to its handler.
+    ///
+    /// Args:
+    ///     key: Key to find a prefix of (str or bytes, like the stored keys)
+    ///
+    /// Returns:
+    ///     (key, value) tuple of the stored prefix, or None if there is none
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///
+    /// Examples:
+    ///     >>> routes = TreeMap({"/api/": "api", "/static/": "files"})
+    ///     >>> routes.longest_prefix("/static/app.js")
+    ///     ('/static/', 'files')
+    fn longest_prefix(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        if !this.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "longest_prefix requires str or bytes keys",
+            ));
+        }
+        let key_bytes = this.encode_key(key)?;
+        this.key_at(&key_bytes)
+            .and_then(|k| Some((k, this.inner.get(k)?)))
+            .map(|(k, v)| Ok((this.decode_key(py, k)?, this.value_out(py, v)?)))
+            .transpose()
+    }
+
+    /// Return an iterator over the entries under a prefix, as pygtrie's
+    /// `Trie.iteritems()`.
+    ///
+    /// Unlike `prefix_iter()`, a prefix that no stored key starts with is
+    /// an error, as it is in pygtrie.
+    ///
+    /// Args:
+    ///     prefix: Prefix to iterate under, or None for every entry
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value) tuples in key order
+    ///
+    /// Raises:
+    ///     KeyError: If no stored key starts with the prefix
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"foo/bar": 1, "foo/baz": 2, "qux": 3})
+    ///     >>> list(tree.iteritems("foo/"))
+    ///     [('foo/bar', 1), ('foo/baz', 2)]
+    #[pyo3(signature = (prefix=None))]
+    fn iteritems(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<PyPrefixIter> {
+        let slf = Self::live(slf)?;
+        let items = slf
+            .trie_entries(prefix)?
+            .map(|(k, v)| Ok((slf.decode_key(py, k)?, slf.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyPrefixIter::new(items, IterGuard::new(&slf)))
+    }
+
+    /// Return an iterator over the keys under a prefix, as pygtrie's
+    /// `Trie.iterkeys()`.
+    ///
+    /// Args:
+    ///     prefix: Prefix to iterate under, or None for every key
+    ///
+    /// Returns:
+    ///     Iterator yielding the keys in key order
+    ///
+    /// Raises:
+    ///     KeyError: If no stored key starts with the prefix
+    #[pyo3(signature = (prefix=None))]
+    fn iterkeys(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<PyTreeMapKeys> {
+        let slf = Self::live(slf)?;
+        let keys = slf
+            .trie_entries(prefix)?
+            .map(|(k, _)| slf.decode_key(py, k))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyTreeMapKeys::new(keys, IterGuard::new(&slf)))
+    }
+
+    /// Return an iterator over the values under a prefix, as pygtrie's
+    /// `Trie.itervalues()`.
+    ///
+    /// Args:
+    ///     prefix: Prefix to iterate under, or None for every value
+    ///
+    /// Returns:
+    ///     Iterator yielding the values in key order
+    ///
+    /// Raises:
+    ///     KeyError: If no stored key starts with the prefix
+    #[pyo3(signature = (prefix=None))]
+    fn itervalues(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<PyTreeMapValues> {
+        let slf = Self::live(slf)?;
+        let values = slf
+            .trie_entries(prefix)?
+            .map(|(_, v)| slf.value_out(py, v))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyTreeMapValues::new(values, IterGuard::new(&slf)))
+    }
+
+    /// Walk the keys node by node, as pygtrie's `Trie.traverse()`.
+    ///
+    /// The keys are treated as a character trie (a byte trie for bytes
+    /// keys), whatever shape the radix tree has. `node_factory` is called
+    /// for every node as `node_factory(path_conv, path, children)`, with a
+    /// fourth `value` argument when a key ends at the node. `path` is a
+    /// tuple of characters (ints for bytes keys) that `path_conv` turns
+    /// back into a key, and `children` is an iterator over the results for
+    /// the child nodes; a child is only visited once the iterator reaches
+    /// it, so a factory can prune the walk by not consuming it. The entries
+    /// are captured when the walk starts.
+    ///
+    /// Args:
+    ///     node_factory: Callable building the result for one node
+    ///     prefix: Prefix of the node to start from, or None for the root
+    ///
+    /// Returns:
+    ///     What `node_factory` returned for the starting node
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///     KeyError: If no stored key starts with the prefix
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"ab": 1, "ac": 2})
+    ///     >>> def count(path_conv, path, children, value=None):
+    ///     ...     return (value is not None) + sum(children)
+    ///     >>> tree.traverse(count)
+    ///     2
+    // A getter returning the function, since a method named `traverse`
+    // clashes with the wrapper PyO3 generates for `__traverse__`
+    #[getter(traverse)]
+    fn traverse_function<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyCFunction>> {
+        let map = slf.clone().unbind();
+        let traverse = move |args: &Bound<'_, PyTuple>, kwargs: Option<&Bound<'_, PyDict>>| {
+            let py = args.py();
+            let arg = |position: usize, name: &str| match args.get_item(position) {
+                Ok(arg) => Ok(Some(arg)),
+                Err(_) => kwargs
+                    .map(|kwargs| kwargs.get_item(name))
+                    .transpose()
+                    .map(Option::flatten),
+            };
+            if args.len() > 2 {
+                return Err(PyTypeError::new_err(format!(
+                    "traverse() takes at most 2 arguments ({} given)",
+                    args.len()
+                )));
+            }
+            let Some(node_factory) = arg(0, "node_factory")? else {
+                return Err(PyTypeError::new_err(
+                    "traverse() missing required argument 'node_factory'",
+                ));
+            };
+            let prefix = arg(1, "prefix")?.filter(|prefix| !prefix.is_none());
+            Self::traverse_nodes(
+                Self::live(map.bind(py))?,
+                py,
+                node_factory.unbind(),
+                prefix.as_ref(),
+            )
+        };
+        PyCFunction::new_closure(
+            slf.py(),
+            Some(c"traverse"),
+            Some(c"traverse(node_factory, prefix=None)\n--\n\nWalk the keys node by node, as pygtrie's Trie.traverse()."),
+            traverse,
+        )
+    }
+
+    /// Return an iterator over all key-value pairs with a given prefix.
+    ///
+    /// This is one of the key features of the adaptive radix tree - efficient
+    /// prefix queries that don't require scanning all keys.
+    ///
+    /// Args:
+    ///     prefix: Prefix to search for
+    ///     offset: Number of leading matches to skip (defaults to 0)
+    ///     limit: Maximum number of matches to return (defaults to no limit)
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value) tuples for matching keys
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "application": 2, "apply": 3, "banana": 4})
+    ///     >>> list(tree.prefix_iter("app"))
+    ///     [('apple', 1), ('application', 2), ('apply', 3)]
+    ///     >>> list(tree.prefix_iter(""))  # Empty prefix matches all
+    ///     [('apple', 1), ('application', 2), ('apply', 3), ('banana', 4)]
+    ///     >>> list(tree.prefix_iter("app", offset=1, limit=1))
+    ///     [('application', 2)]
+    #[pyo3(signature = (prefix, *, offset=0, limit=None))]
+    fn prefix_iter(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> PyResult<PyPrefixIter> {
+        let slf = Self::live(slf)?;
+        let prefix_bytes = slf.encode_prefix(prefix)?;
+        let items = slf
+            .prefix_entries(&prefix_bytes)
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(k, v)| Ok((slf.decode_key(py, k)?, slf.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyPrefixIter::new(items, IterGuard::new(&slf)))
+    }
+
+    /// Return an iterator over the keys with a given prefix.
+    ///
+    /// Like `prefix_iter()` without building the (key, value) tuples or
+    /// touching the values, for callers such as autocomplete that only need
+    /// the keys.
+    ///
+    /// Args:
+    ///     prefix: Prefix to search for
+    ///     offset: Number of leading matches to skip (defaults to 0)
+    ///     limit: Maximum number of keys to return (defaults to no limit)
+    ///
+    /// Returns:
+    ///     Iterator yielding the matching keys in key order
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "application": 2, "banana": 3})
+    ///     >>> list(tree.prefix_keys("app"))
+    ///     ['apple', 'application']
+    ///     >>> list(tree.prefix_keys("a", limit=1))
+    ///     ['apple']
+    #[pyo3(signature = (prefix, *, offset=0, limit=None))]
+    fn prefix_keys(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> PyResult<PyTreeMapKeys> {
+        let slf = Self::live(slf)?;
+        let prefix_bytes = slf.encode_prefix(prefix)?;
+        let keys = slf
+            .prefix_entries(&prefix_bytes)
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(k, _)| slf.decode_key(py, k))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyTreeMapKeys::new(keys, IterGuard::new(&slf)))
+    }
+
+    /// Return an iterator over the values of the keys with a given prefix.
+    ///
+    /// Like `prefix_iter()` without decoding the keys or building tuples.
+    ///
+    /// Args:
+    ///     prefix: Prefix to search for
+    ///     offset: Number of leading matches to skip (defaults to 0)
+    ///     limit: Maximum number of values to return (defaults to no limit)
+    ///
+    /// Returns:
+    ///     Iterator yielding the values of matching keys in key order
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "application": 2, "banana": 3})
+    ///     >>> list(tree.prefix_values("app"))
+    ///     [1, 2]
+    #[pyo3(signature = (prefix, *, offset=0, limit=None))]
+    fn prefix_values(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> PyResult<PyTreeMapValues> {
+        let slf = Self::live(slf)?;
+        let prefix_bytes = slf.encode_prefix(prefix)?;
+        let values = slf
+            .prefix_entries(&prefix_bytes)
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(_, v)| slf.value_out(py, v))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyTreeMapValues::new(values, IterGuard::new(&slf)))
+    }
+
+    /// Return one page of the entries with a given prefix, and a token to
+    /// fetch the next page with.
+    ///
+    /// The token is the last key of the page, so a stateless service can
+    /// hand it to a client and resume from it in a later request without
+    /// holding an iterator open. Each page starts with a seek to the token,
+    /// however deep into the prefix it is, and keys inserted or removed
+    /// between requests are picked up or skipped consistently.
+    ///
+    /// Args:
+    ///     prefix: Prefix to search for
+    ///     limit: Maximum number of entries in the page
+    ///     after_key: Token returned with the previous page, or None for the
+    ///         first page
+    ///
+    /// Returns:
+    ///     Tuple of a list of (key, value) tuples and the token for the next
+    ///     page, which is None once the last page has been returned
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"user:1": "a", "user:2": "b", "user:3": "c"})
+    ///     >>> tree.prefix_page("user:", 2)
+    ///     ([('user:1', 'a'), ('user:2', 'b')], 'user:2')
+    ///     >>> tree.prefix_page("user:", 2, after_key="user:2")
+    ///     ([('user:3', 'c')], None)
+    #[pyo3(signature = (prefix, limit, after_key=None))]
+    fn prefix_page(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+        limit: usize,
+        after_key: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Page> {
+        let this = &*Self::live(slf)?;
+        let prefix_bytes = this.encode_prefix(prefix)?;
+        let after = after_key.map(|key| this.encode_key(key)).transpose()?;
+        let lower = match &after {
+            Some(after) if **after >= *prefix_bytes => Excluded(&**after),
+            _ => Included(&*prefix_bytes),
+        };
+        let mut entries = this
+            .iter_from(lower)
+            .take_while(|(k, _)| k.starts_with(&prefix_bytes));
+        let items = entries
+            .by_ref()
+            .take(limit)
+            .map(|(k, v)| Ok((this.decode_key(py, k)?, this.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        let token = match entries.next() {
+            Some(_) => items.last().map(|(key, _)| key.clone_ref(py)),
+            None => None,
+        };
+        Ok((items, token))
+    }
+
+    /// Return an iterator over the entries whose key contains a fragment.
+    ///
+    /// With `substring_index=True` the matching keys are looked up in the
+    /// suffix index, in time proportional to the fragment length and the
+    /// number of matches. Without it every key is scanned, which gives the
+    /// same results more slowly.
+    ///
+    /// Args:
+    ///     fragment: Text (or bytes, for bytes keys) to look for
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value) tuples in key order
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"book": 1, "cook": 2, "cake": 3}, substring_index=True)
+    ///     >>> list(tree.contains_substring("oo"))
+    ///     [('book', 1), ('cook', 2)]
+    fn contains_substring(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        fragment: &Bound<'_, PyAny>,
+    ) -> PyResult<PyPrefixIter> {
+        let slf = Self::live(slf)?;
+        if !slf.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "contains_substring requires str or bytes keys",
+            ));
+        }
+        let fragment = slf.encode_key(fragment)?;
+        let keys: Vec<&[u8]> = match &slf.indexes.substrings {
+            _ if fragment.is_empty() => slf.inner.keys().map(|k| &**k).collect(),
+            Some(index) => index.find(&fragment).into_iter().collect(),
+            None => slf
+                .inner
+                .keys()
+                .filter(|k| k.windows(fragment.len()).any(|w| w == &*fragment))
+                .map(|k| &**k)
+                .collect(),
+        };
+        let items = keys
+            .into_iter()
+            .filter_map(|k| Some((k, slf.inner.get(k)?)))
+            .map(|(k, v)| Ok((slf.decode_key(py, k)?, slf.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyPrefixIter::new(items, IterGuard::new(&slf)))
+    }
+
+    /// Return an iterator over the entries whose key matches a regular
+    /// expression.
+    ///
+    /// The pattern must match the whole key, like `re.fullmatch`; add `.*`
+    /// to allow trailing text. It is compiled with Rust's `regex` crate, so
+    /// the syntax is close to Python's `re` without lookaround or
+    /// backreferences. Any literal text every match has to start with is
+    /// extracted first, and only keys under that prefix are visited; matches
+    /// are found lazily as the iterator advances.
+    ///
+    /// For bytes keys the pattern is matched byte by byte, so `.` matches
+    /// any byte. Case-insensitive trees match against the case folded keys.
+    ///
+    /// Args:
+    ///     pattern: Regular expression the keys must match
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value) tuples in key order
+    ///
+    /// Raises:
+    ///     ValueError: If the pattern is not a valid regular expression
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///
+    /// Examples:
+    ///     >>> logs = TreeMap({"log/2024-01-02": 1, "log/2024-02-10": 2, "tmp/x": 3})
+    ///     >>> list(logs.regex_iter(r"log/2024-01-\d+"))
+    ///     [('log/2024-01-02', 1)]
+    ///     >>> [k for k, _ in logs.regex_iter(r".*-\d0")]
+    ///     ['log/2024-02-10']
+    fn regex_iter(slf: &Bound<'_, Self>, pattern: &str) -> PyResult<PyRegexIter> {
+        let slf = Self::live(slf)?;
+        if !slf.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "regex_iter requires str or bytes keys",
+            ));
+        }
+        let pattern = KeyPattern::new(pattern, slf.codec.is_str())?;
+        Ok(PyRegexIter::new(pattern, IterGuard::new(&slf)))
+    }
+
+    /// Return an iterator over the entries in lists of `size` pairs.
+    ///
+    /// Batch consumers such as ETL jobs get one list per step instead of one
+    /// tuple, cutting the per-item overhead of the Python loop. Chunks are
+    /// collected lazily, so only one is held at a time; the last one may be
+    /// shorter.
+    ///
+    /// Args:
+    ///     size: Number of (key, value) pairs per chunk
+    ///     prefix: Only visit keys starting with this prefix (defaults to
+    ///         every key)
+    ///
+    /// Returns:
+    ///     Iterator yielding lists of (key, value) tuples in key order
+    ///
+    /// Raises:
+    ///     ValueError: If size is 0
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    ///     >>> list(tree.iter_chunks(2))
+    ///     [[('a', 1), ('b', 2)], [('c', 3)]]
+    ///     >>> list(TreeMap({"x1": 1, "x2": 2, "y": 3}).iter_chunks(5, prefix="x"))
+    ///     [[('x1', 1), ('x2', 2)]]
+    #[pyo3(signature = (size, prefix=None))]
+    fn iter_chunks(
+        slf: &Bound<'_, Self>,
+        size: usize,
+        prefix: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<PyChunkIter> {
+        let slf = Self::live(slf)?;
+        if size == 0 {
+            return Err(PyValueError::new_err("size must be at least 1"));
+        }
+        let prefix = match prefix {
+            Some(prefix) => slf.encode_prefix(prefix)?,
+            None => Box::default(),
+        };
+        Ok(PyChunkIter::new(prefix, size, IterGuard::new(&slf)))
+    }
+
+    /// Call a function on each entry in key order until it returns False.
+    ///
+    /// Entries are converted one at a time as they are visited, so a bounded
+    /// search or a side-effect traversal stops without materializing the
+    /// rest of a large tree. Any return value other than False, including
+    /// None, continues the scan. The tree can be read but not changed from
+    /// the callback.
+    ///
+    /// Args:
+    ///     callback: Function called as `callback(key, value)`
+    ///     prefix: Only visit keys starting with this prefix (defaults to
+    ///         every key)
+    ///     reverse: If True, visit the keys from largest to smallest
+    ///
+    /// Returns:
+    ///     Number of entries the callback was called on
+    ///
+    /// Raises:
+    ///     Any exception raised by callback, which ends the scan
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    ///     >>> seen = []
+    ///     >>> tree.scan(lambda key, value: seen.append(key) or value < 2)
+    ///     2
+    ///     >>> seen
+    ///     ['a', 'b']
+    ///     >>> tree.scan(lambda key, value: print(key), reverse=True)
+    ///     d
+    ///     c
+    ///     b
+    ///     a
+    ///     4
+    #[pyo3(signature = (callback, prefix=None, reverse=false))]
+    fn scan(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        callback: &Bound<'_, PyAny>,
+        prefix: Option<&Bound<'_, PyAny>>,
+        reverse: bool,
+    ) -> PyResult<usize> {
+        let this = &*Self::live(slf)?;
+        let prefix = match prefix {
+            Some(prefix) => this.encode_prefix(prefix)?,
+            None => Box::default(),
+        };
+        let entries = this.prefix_entries(&prefix);
+        let entries: Box<dyn Iterator<Item = _>> = match reverse {
+            true => Box::new(entries.rev()),
+            false => Box::new(entries),
+        };
+        let mut visited = 0;
+        for (key, value) in entries {
+            let result = callback.call1((this.decode_key(py, key)?, this.value_out(py, value)?))?;
+            visited += 1;
+            if result.is_instance_of::<PyBool>() && !result.is_truthy()? {
+                break;
+            }
+        }
+        Ok(visited)
+    }
+
+    /// Find every occurrence of any stored key inside a text.
+    ///
+    /// Turns the tree into a keyword or entity gazetteer: each position of
+    /// the text is matched against the tree in a single walk, in Rust with
+    /// the GIL released. Occurrences may overlap. Since stored keys never
+    /// prefix one another, at most one key matches at each position.
+    ///
+    /// Args:
+    ///     text: Text to scan (bytes for bytes keys)
+    ///
+    /// Returns:
+    ///     List of (start, end, key, value) tuples ordered by start, where
+    ///     `text[start:end]` is the occurrence. Str offsets count characters
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys, or the
+    ///         text is of the other type
+    ///
+    /// Examples:
+    ///     >>> places = TreeMap({"New York": "US", "York": "UK", "Paris": "FR"})
+    ///     >>> places.scan_text("From New York to Paris")
+    ///     [(5, 13, 'New York', 'US'), (9, 13, 'York', 'UK'), (17, 22, 'Paris', 'FR')]
+    #[allow(clippy::type_complexity)]
+    fn scan_text(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        text: &Bound<'_, PyAny>,
+    ) -> PyResult<Vec<(usize, usize, Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        if !this.codec.is_verbatim() {
+            return Err(PyTypeError::new_err("scan_text requires str or bytes keys"));
+        }
+        let haystack = this.haystack(text)?;
+        let found: Vec<(usize, usize, &[u8])> = py.detach(|| {
+            let Haystack { bytes, offsets } = &haystack;
+            (0..bytes.len())
+                .filter_map(|i| {
+                    let key = this.key_in(&haystack, i)?;
+                    Some((offsets[i]?, offsets[i + key.len()]?, key))
+                })
+                .collect()
+        });
+        found
+            .into_iter()
+            .filter_map(|(start, end, key)| Some((start, end, key, this.inner.get(key)?)))
+            .map(|(start, end, key, value)| {
+                Ok((
+                    start,
+                    end,
+                    this.decode_key(py, key)?,
+                    this.value_out(py, value)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Split a text into stored keys and the runs of text between them.
+    ///
+    /// A dictionary-based tokenizer: with the default "greedy" strategy the
+    /// text is read left to right, taking a stored key wherever one starts.
+    /// Since stored keys never prefix one another, that key is also the
+    /// longest match. The "optimal" strategy instead picks, by dynamic
+    /// programming, the segmentation that leaves the fewest characters
+    /// unmatched, then the one with the fewest tokens. Matching runs in Rust
+    /// with the GIL released.
+    ///
+    /// Args:
+    ///     text: Text to split (bytes for bytes keys)
+    ///     strategy: "greedy" (default) or "optimal"
+    ///
+    /// Returns:
+    ///     List of (token, value) tuples that concatenate back to the text.
+    ///     Tokens are slices of the text; text that matches no key comes
+    ///     back as one token per run, with value None
+    ///
+    /// Raises:
+    ///     ValueError: If strategy is not "greedy" or "optimal"
+    ///     TypeError: If the tree does not have str or bytes keys, or the
+    ///         text is of the other type
+    ///
+    /// Examples:
+    ///     >>> units = TreeMap({"kg": 1000, "g": 1, "mg": 0.001})
+    ///     >>> units.segment("12kg")
+    ///     [('12', None), ('kg', 1000)]
+    ///     >>> parts = TreeMap({"ab": 1, "bcd": 2})
+    ///     >>> parts.segment("abcd")
+    ///     [('ab', 1), ('cd', None)]
+    ///     >>> parts.segment("abcd", strategy="optimal")
+    ///     [('a', None), ('bcd', 2)]
+    #[pyo3(signature = (text, *, strategy="greedy"))]
+    fn segment(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        text: &Bound<'_, PyAny>,
+        strategy: &str,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        if !this.codec.is_verbatim() {
+            return Err(PyTypeError::new_err("segment requires str or bytes keys"));
+        }
+        let optimal = match strategy {
+            "greedy" => false,
+            "optimal" => true,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "strategy must be 'greedy' or 'optimal', got '{strategy}'"
+                )))
+            }
+        };
+        let haystack = this.haystack(text)?;
+        let tokens = py.detach(|| {
+            let len = haystack.bytes.len();
+            if optimal {
+                let choice = this.optimal_keys(&haystack);
+                this.segment_with(len, |i| choice[i])
+            } else {
+                this.segment_with(len, |i| this.key_in(&haystack, i))
+            }
+        });
+        tokens
+            .into_iter()
+            .map(|(start, end, key)| {
+                let start = haystack.offsets[start].unwrap_or_default() as isize;
+                let end = haystack.offsets[end].unwrap_or_default() as isize;
+                let token = text.get_item(PySlice::new(py, start, end, 1))?.unbind();
+                let value = match key.and_then(|key| this.inner.get(key)) {
+                    Some(value) => this.value_out(py, value)?,
+                    None => py.None(),
+                };
+                Ok((token, value))
+            })
+            .collect()
+    }
+
+    /// Replace every occurrence of a stored key in a text with its value.
+    ///
+    /// The text is read left to right in a single pass, like `segment()`:
+    /// wherever a stored key starts it is replaced and matching resumes
+    /// after it, so replacements never overlap and are never rescanned.
+    /// This avoids building a regular expression alternation of all keys.
+    ///
+    /// Args:
+    ///     text: Text to rewrite (bytes for bytes keys)
+    ///     formatter: Optional callable taking (key, value) and returning
+    ///         the replacement. Without it the value itself is used
+    ///
+    /// Returns:
+    ///     The rewritten text, of the same type as `text`
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys, the text
+    ///         is of the other type, or a replacement is not of the text's
+    ///         type
+    ///
+    /// Examples:
+    ///     >>> slang = TreeMap({"brb": "be right back", "imo": "in my opinion"})
+    ///     >>> slang.replace_all("imo it's fine, brb")
+    ///     "in my opinion it's fine, be right back"
+    ///     >>> slang.replace_all("brb", formatter=lambda k, v: f"{k} ({v})")
+    ///     'brb (be right back)'
+    #[pyo3(signature = (text, formatter=None))]
+    fn replace_all(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        text: &Bound<'_, PyAny>,
+        formatter: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let this = &*Self::live(slf)?;
+        if !this.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "replace_all requires str or bytes keys",
+            ));
+        }
+        let haystack = this.haystack(text)?;
+        let tokens =
+            py.detach(|| this.segment_with(haystack.bytes.len(), |i| this.key_in(&haystack, i)));
+        let is_str = this.codec.is_str();
+        let mut out = Vec::with_capacity(haystack.bytes.len());
+        for (start, end, key) in tokens {
+            let start = haystack.offsets[start].unwrap_or_default() as isize;
+            let end = haystack.offsets[end].unwrap_or_default() as isize;
+            let token = text.get_item(PySlice::new(py, start, end, 1))?;
+            let piece = match key.and_then(|key| Some((key, this.inner.get(key)?))) {
+                None => token.clone(),
+                Some((key, value)) => {
+                    let value = this.value_out(py, value)?;
+                    match formatter {
+                        Some(formatter) => formatter.call1((this.decode_key(py, key)?, value))?,
+                        None => value.into_bound(py),
+                    }
+                }
+            };
+            if is_str {
+                let Ok(piece) = piece.cast::<PyString>() else {
+                    return Err(PyTypeError::new_err(format!(
+                        "replacement for {} must be str, not {}",
+                        token.repr()?,
+                        piece.get_type().name()?
+                    )));
+                };
+                out.extend_from_slice(piece.to_cow()?.as_bytes());
+            } else {
+                let Ok(piece) = piece.cast::<PyBytes>() else {
+                    return Err(PyTypeError::new_err(format!(
+                        "replacement for {} must be bytes, not {}",
+                        token.repr()?,
+                        piece.get_type().name()?
+                    )));
+                };
+                out.extend_from_slice(piece.as_bytes());
+            }
+        }
+        if is_str {
+            let out = String::from_utf8(out).expect("concatenated str pieces");
+            Ok(PyString::new(py, &out).into_any().unbind())
+        } else {
+            Ok(PyBytes::new(py, &out).into_any().unbind())
+        }
+    }
+
+    /// Start maintaining an index of reversed keys for `endswith_iter()`.
+    ///
+    /// The index is built from the current keys and then kept up to date
+    /// on every insert and removal. Calling this again does nothing.
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///
+    /// Examples:
+    ///     >>> files = TreeMap({"a.json": 1, "b.txt": 2})
+    ///     >>> files.enable_suffix_index()
+    ///     >>> files.suffix_index
+    ///     True
+    fn enable_suffix_index(&mut self, py: Python) -> PyResult<()> {
+        if !self.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "suffix indexes require str or bytes keys",
+            ));
+        }
+        if self.indexes.suffixes.is_none() {
+            let inner = &self.inner;
+            let index = py.detach(|| {
+                let mut index = SuffixIndex::default();
+                for key in inner.keys() {
+                    index.add(key);
+                }
+                index
+            });
+            self.indexes.suffixes = Some(index);
+        }
+        Ok(())
+    }
+
+    /// Return an iterator over the entries whose key ends with a suffix.
+    ///
+    /// After `enable_suffix_index()` the matches are found with a prefix
+    /// scan of the reversed keys; otherwise every key is checked.
+    ///
+    /// Args:
+    ///     suffix: Suffix to search for
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value) tuples in key order
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys
+    ///
+    /// Examples:
+    ///     >>> files = TreeMap({"a.json": 1, "b.txt": 2, "c/d.json": 3})
+    ///     >>> files.enable_suffix_index()
+    ///     >>> list(files.endswith_iter(".json"))
+    ///     [('a.json', 1), ('c/d.json', 3)]
+    fn endswith_iter(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        suffix: &Bound<'_, PyAny>,
+    ) -> PyResult<PyPrefixIter> {
+        let slf = Self::live(slf)?;
+        if !slf.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "endswith_iter requires str or bytes keys",
+            ));
+        }
+        let suffix = slf.encode_key(suffix)?;
+        let keys: Vec<Box<[u8]>> = match &slf.indexes.suffixes {
+            Some(index) => index.find(&suffix),
+            None => slf
+                .inner
+                .keys()
+                .filter(|k| k.ends_with(&suffix))
+                .cloned()
+                .collect(),
+        };
+        let items = keys
+            .iter()
+            .filter_map(|k| Some((k, slf.inner.get(k)?)))
+            .map(|(k, v)| Ok((slf.decode_key(py, k)?, slf.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyPrefixIter::new(items, IterGuard::new(&slf)))
+    }
+
+    /// Return the k best completions of a prefix, highest score first.
+    ///
+    /// Every key under the prefix is scored while walking its subtree, and
+    /// only the k best are kept in a bounded heap, so no list of all the
+    /// completions is built. By default the value itself is the score, which
+    /// suits trees mapping words to frequencies or popularity counts.
+    ///
+    /// Args:
+    ///     prefix: Prefix typed so far
+    ///     k: Maximum number of completions (defaults to 10)
+    ///     score: How to score an entry. None (the default) uses the value,
+    ///         a str names a field of the value (`value[score]` for mappings,
+    ///         otherwise `getattr(value, score)`), and a callable is called
+    ///         with the value. Scores must be numbers.
+    ///
+    /// Returns:
+    ///     List of up to k (key, value) tuples, best first; ties keep key
+    ///     order
+    ///
+    /// Raises:
+    ///     TypeError: If a score is not a number
+    ///     Any exception raised while computing a score
+    ///
+    /// Examples:
+    ///     >>> words = TreeMap({"help": 40, "hello": 120, "helm": 3, "world": 500})
+    ///     >>> words.autocomplete("hel", 2)
+    ///     [('hello', 120), ('help', 40)]
+    ///     >>> pages = TreeMap({"home": {"views": 9}, "how-to": {"views": 30}})
+    ///     >>> pages.autocomplete("ho", 1, score="views")
+    ///     [('how-to', {'views': 30})]
+    ///     >>> words.autocomplete("hel", 1, score=lambda count: -count)
+    ///     [('helm', 3)]
+    #[pyo3(signature = (prefix, k=10, score=None))]
+    fn autocomplete(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+        k: usize,
+        score: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        let prefix_bytes = this.encode_prefix(prefix)?;
+        let field = score.and_then(|score| score.cast::<PyString>().ok());
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+        // Min-heap on (score, later key), so the root is the entry to drop
+        let mut best = BinaryHeap::with_capacity(k.min(1024));
+        for (order, (key, value)) in this.prefix_entries(&prefix_bytes).enumerate() {
+            let value = this.value_out(py, value)?.into_bound(py);
+            let value = &value;
+            let rank = match (score, field) {
+                (None, _) => value.clone(),
+                (Some(_), Some(field)) => match value.cast::<PyMapping>() {
+                    Ok(mapping) => mapping.get_item(field)?,
+                    Err(_) => value.getattr(field)?,
+                },
+                (Some(score), None) => score.call1((value,))?,
+            };
+            let rank: f64 = rank.extract().map_err(|_| {
+                PyTypeError::new_err(format!(
+                    "autocomplete scores must be numbers, got {}",
+                    rank.get_type()
+                        .name()
+                        .map(|n| n.to_string())
+                        .unwrap_or_default()
+                ))
+            })?;
+            let entry = Reverse((Score(rank), Reverse(order), &**key));
+            if best.len() < k {
+                best.push(entry);
+            } else if best.peek().is_some_and(|worst| entry < *worst) {
+                best.pop();
+                best.push(entry);
+            }
+        }
+        best.into_sorted_vec()
+            .into_iter()
+            .filter_map(|Reverse((_, _, key))| Some((key, this.inner.get(key)?)))
+            .map(|(key, value)| Ok((this.decode_key(py, key)?, this.value_out(py, value)?)))
+            .collect()
+    }
+
+    /// List the distinct segments directly below a prefix.
+    ///
+    /// For hierarchical keys such as "user:42:name", `children("user")`
+    /// lists the segments that follow "user:" up to the next separator,
+    /// without visiting every descendant: once a key is found inside a
+    /// child, the rest of that child is skipped in Rust. Children are
+    /// implied by deeper keys, so "b" is listed under "a" when only
+    /// "a:b:c" is stored.
+    ///
+    /// In a "path" tree the prefix is a directory, normalized like a path,
+    /// and the tree's own separator is used. Absolute keys appear under the
+    /// root name "/" at the top level.
+    ///
+    /// Args:
+    ///     path: Prefix (or directory) to list, or None for the top level.
+    ///         A trailing separator is optional
+    ///     separator: Separator between segments for str and bytes keys
+    ///         (defaults to ":")
+    ///
+    /// Returns:
+    ///     Sorted list of unique child names
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str, bytes or path keys
+    ///     ValueError: If separator is empty, or given for a "path" tree
+    ///
+    /// Examples:
+    ///     >>> fs = TreeMap({"src/lib.rs": 1, "src/codec/mod.rs": 2}, key_type="path")
+    ///     >>> fs.children("src")
+    ///     ['codec', 'lib.rs']
+    ///     >>> users = TreeMap({"user:1:name": "ann", "user:2:name": "bob", "team:7": 0})
+    ///     >>> users.children()
+    ///     ['team', 'user']
+    ///     >>> users.children("user")
+    ///     ['1', '2']
+    #[pyo3(signature = (path=None, separator=None))]
+    fn children(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        path: Option<&Bound<'_, PyAny>>,
+        separator: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let this = &*Self::live(slf)?;
+        if this.codec.path_separator().is_none() {
+            if !this.codec.is_verbatim() {
+                return Err(PyTypeError::new_err(
+                    "children requires str, bytes or path keys",
+                ));
+            }
+            let separator = match separator {
+                Some(separator) => this.encode_prefix(separator)?,
+                None => Box::from(&b":"[..]),
+            };
+            if separator.is_empty() {
+                return Err(PyValueError::new_err("separator must not be empty"));
+            }
+            let mut dir = match path {
+                Some(path) => this.encode_prefix(path)?.into_vec(),
+                None => Vec::new(),
+            };
+            if !dir.is_empty() && !dir.ends_with(&separator) {
+                dir.extend_from_slice(&separator);
+            }
+            return this
+                .next_segments(&dir, &separator)
+                .iter()
+                .map(|segment| this.codec.decode(py, segment))
+                .collect();
+        }
+        if separator.is_some() {
+            return Err(PyValueError::new_err(
+                "path trees use the separator they were created with",
+            ));
+        }
+        let (separator, dir) = this.path_directory(path)?;
+        let mut names = Vec::new();
+        let mut lower = Included(dir.clone());
+        while let Some(key) = this.seek(lower.as_ref().map(Vec::as_slice)) {
+            let Some(rest) = key.strip_prefix(dir.as_slice()) else {
+                break;
+            };
+            let end = rest
+                .iter()
+                .position(|&b| b == separator || b == 0)
+                .unwrap_or(rest.len());
+            if end > 0 {
+                names.push(String::from_utf8_lossy(&rest[..end]).into_owned());
+            } else if rest.first() == Some(&separator) {
+                // Absolute keys all live below the root directory
+                names.push(char::from(separator).to_string());
+            }
+            lower = if rest.get(end) == Some(&separator) {
+                // Jump past every key inside this child directory
+                let mut next = key[..dir.len() + end].to_vec();
+                next.push(separator + 1);
+                Included(next)
+            } else {
+                Excluded(key.to_vec())
+            };
+        }
+        names.sort();
+        names.dedup();
+        Ok(names
+            .into_iter()
+            .map(|name| PyString::new(py, &name).into_any().unbind())
+            .collect())
+    }
+
+    /// Return an iterator over every entry below a directory of a "path" tree.
+    ///
+    /// Args:
+    ///     path: Directory to walk, or None for the whole tree
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value) tuples in key order
+    ///
+    /// Raises:
+    ///     TypeError: If the tree was not created with key_type="path"
+    ///
+    /// Examples:
+    ///     >>> fs = TreeMap({"src/lib.rs": 1, "src/codec/mod.rs": 2}, key_type="path")
+    ///     >>> list(fs.descendants("src/codec"))
+    ///     [('src/codec/mod.rs', 2)]
+    #[pyo3(signature = (path=None))]
+    fn descendants(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        path: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<PyPrefixIter> {
+        let slf = Self::live(slf)?;
+        let (_, dir) = slf.path_directory(path)?;
+        let items = slf
+            .prefix_entries(&dir)
+            .map(|(k, v)| Ok((slf.decode_key(py, k)?, slf.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyPrefixIter::new(items, IterGuard::new(&slf)))
+    }
+
+    /// Return an iterator over key-value pairs with start <= key < end.
+    ///
+    /// Keys are compared in stored order, so this works for every key_type:
+    /// lexicographic for strings, numeric for "int", chronological for
+    /// "datetime", and field by field for "tuple".
+    ///
+    /// Args:
+    ///     start: Inclusive lower bound, or None for no lower bound
+    ///     end: Exclusive upper bound, or None for no upper bound
+    ///     offset: Number of leading entries in the range to skip (defaults
+    ///         to 0)
+    ///     limit: Maximum number of entries to return (defaults to no limit)
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value) tuples in key order
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    ///     >>> list(tree.range_between("b", "d"))
+    ///     [('b', 2), ('c', 3)]
+    ///     >>> list(tree.range_between(None, "b"))
+    ///     [('a', 1)]
+    ///     >>> list(tree.range_between("a", None, offset=2, limit=1))
+    ///     [('c', 3)]
+    #[pyo3(signature = (start=None, end=None, *, offset=0, limit=None))]
+    fn range_between(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        start: Option<&Bound<'_, PyAny>>,
+        end: Option<&Bound<'_, PyAny>>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> PyResult<PyTreeMapItems> {
+        let slf = Self::live(slf)?;
+        let start = start.map(|key| slf.encode_key(key)).transpose()?;
+        let end = end.map(|key| slf.encode_key(key)).transpose()?;
+        if let (Some(start), Some(end)) = (&start, &end) {
+            if start >= end {
+                return Ok(PyTreeMapItems::new(Vec::new(), IterGuard::new(&slf)));
+            }
+        }
+        let lower = start.as_deref().map_or(Unbounded, Included);
+        let items = slf
+            .iter_from(lower)
+            .take_while(|(k, _)| end.as_deref().is_none_or(|end| &***k < end))
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(k, v)| Ok((slf.decode_key(py, k)?, slf.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyTreeMapItems::new(items, IterGuard::new(&slf)))
+    }
+
+    /// Remove every entry whose key lies between two bounds.
+    ///
+    /// All matching keys are found in one walk from `start` and removed in
+    /// a single call, e.g. to prune data stored under encoded timestamps
+    /// once it falls out of a time window.
+    ///
+    /// Args:
+    ///     start: Lower bound, or None for no lower bound
+    ///     stop: Upper bound, or None for no upper bound
+    ///     inclusive: Pair of flags telling whether `start` and `stop`
+    ///         themselves are removed (defaults to (True, False), like
+    ///         `range_between()`)
+    ///
+    /// Returns:
+    ///     Number of entries removed
+    ///
+    /// Examples:
+    ///     >>> log = TreeMap({"2024-01": 1, "2024-02": 2, "2024-03": 3, "2024-04": 4})
+    ///     >>> log.delete_range("2024-01", "2024-03")
+    ///     2
+    ///     >>> list(log.keys())
+    ///     ['2024-03', '2024-04']
+    ///     >>> log.delete_range("2024-03", None, inclusive=(False, True))
+    ///     1
+    #[pyo3(signature = (start, stop, inclusive=(true, false)))]
+    fn delete_range(
+        &mut self,
+        py: Python,
+        start: Option<&Bound<'_, PyAny>>,
+        stop: Option<&Bound<'_, PyAny>>,
+        inclusive: (bool, bool),
+    ) -> PyResult<usize> {
+        self.purge_expired(py)?;
+        let (lower, upper) = self.range_bounds(start, stop, inclusive)?;
+        let keys: Vec<Box<[u8]>> = self
+            .bounded(bound_ref(&lower), bound_ref(&upper))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &keys {
+            if let Some(value) = self.inner.remove(key) {
+                self.discard(py, key, &value, Change::Remove)?;
+            }
+        }
+        Ok(keys.len())
+    }
+
+    /// Count the entries whose key lies between two bounds.
+    ///
+    /// The keys are counted in Rust without being decoded or yielded, which
+    /// makes histogram and cardinality queries over key ranges cheap. With
+    /// `order_statistics=True` the count takes two O(log n) descents
+    /// instead of a walk over the range.
+    ///
+    /// Args:
+    ///     start: Lower bound, or None for no lower bound
+    ///     stop: Upper bound, or None for no upper bound
+    ///     inclusive: Pair of flags telling whether `start` and `stop`
+    ///         themselves are counted (defaults to (True, False))
+    ///
+    /// Returns:
+    ///     Number of keys in the range
+    ///
+    /// Examples:
+    ///     >>> log = TreeMap({"2024-01": 1, "2024-02": 2, "2024-03": 3})
+    ///     >>> log.count_range("2024-01", "2024-03")
+    ///     2
+    ///     >>> log.count_range("2024-02", None)
+    ///     2
+    #[pyo3(signature = (start, stop, inclusive=(true, false)))]
+    fn count_range(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        start: Option<&Bound<'_, PyAny>>,
+        stop: Option<&Bound<'_, PyAny>>,
+        inclusive: (bool, bool),
+    ) -> PyResult<usize> {
+        let this = &*Self::live(slf)?;
+        let (lower, upper) = this.range_bounds(start, stop, inclusive)?;
+        Ok(py.detach(|| this.count_between(bound_ref(&lower), bound_ref(&upper))))
+    }
+
+    /// Count the entries whose key starts with a prefix.
+    ///
+    /// Equivalent to `len(list(tree.prefix_iter(prefix)))` without building
+    /// the entries. With `order_statistics=True` it takes O(log n) time
+    /// whatever the number of matches.
+    ///
+    /// Args:
+    ///     prefix: Prefix to count
+    ///
+    /// Returns:
+    ///     Number of keys starting with `prefix`
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "apply": 2, "banana": 3})
+    ///     >>> tree.count_prefix("app")
+    ///     2
+    ///     >>> tree.count_prefix("")
+    ///     3
+    fn count_prefix(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+    ) -> PyResult<usize> {
+        let this = &*Self::live(slf)?;
+        let prefix = this.encode_prefix(prefix)?;
+        if let Some(index) = &this.indexes.counts {
+            let end = successor(&prefix);
+            let upper = end.as_deref().map_or(Unbounded, Excluded);
+            return Ok(index.count(Included(&prefix), upper));
+        }
+        Ok(py.detach(|| this.prefix_entries(&prefix).count()))
+    }
+
+    /// Aggregate the numeric values under a prefix or in a key range.
+    ///
+    /// Turns prefix-structured keys such as "region:store:sku" into a small
+    /// rollup engine: the subtree is walked in Rust and only the result is
+    /// returned. A `slice` selects the half-open key range `start <= key <
+    /// stop` instead of a prefix.
+    ///
+    /// Sums of integers stay exact integers; any float makes the sum a
+    /// float. "min" and "max" return the original value object.
+    ///
+    /// Args:
+    ///     prefix_or_range: Key prefix, `slice(start, stop)` of keys, or None
+    ///         for the whole tree (defaults to None)
+    ///     op: "sum" (default), "min", "max", "mean" or "count"
+    ///
+    /// Returns:
+    ///     The aggregate. An empty selection gives 0 for "sum" and "count",
+    ///     and None for "min", "max" and "mean"
+    ///
+    /// Raises:
+    ///     ValueError: If op is not a known operation, or the slice has a
+    ///         step
+    ///     TypeError: If a selected value is not a number
+    ///
+    /// Examples:
+    ///     >>> sales = TreeMap({"eu:paris:a": 3, "eu:paris:b": 4, "us:nyc:a": 10})
+    ///     >>> sales.aggregate("eu:")
+    ///     7
+    ///     >>> sales.aggregate("eu:paris:", op="mean")
+    ///     3.5
+    ///     >>> sales.aggregate(slice("eu:paris:b", "us:"), op="max")
+    ///     4
+    ///     >>> sales.aggregate(op="count")
+    ///     3
+    #[pyo3(signature = (prefix_or_range=None, op="sum"))]
+    fn aggregate(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix_or_range: Option<&Bound<'_, PyAny>>,
+        op: &str,
+    ) -> PyResult<Py<PyAny>> {
+        let this = &*Self::live(slf)?;
+        let mut total = Aggregate::new(AggregateOp::from_name(op)?);
+        match prefix_or_range {
+            Some(range) if range.is_instance_of::<PySlice>() => {
+                if !range.getattr("step")?.is_none() {
+                    return Err(PyValueError::new_err("aggregate ranges cannot have a step"));
+                }
+                let start = range.getattr("start")?;
+                let stop = range.getattr("stop")?;
+                let start = (!start.is_none()).then_some(&start);
+                let stop = (!stop.is_none()).then_some(&stop);
+                let (lower, upper) = this.range_bounds(start, stop, (true, false))?;
+                let entries = this.bounded(bound_ref(&lower), bound_ref(&upper));
+                this.aggregate_into(py, &mut total, entries)?;
+            }
+            Some(prefix) => {
+                let prefix = this.encode_prefix(prefix)?;
+                this.aggregate_into(py, &mut total, this.prefix_entries(&prefix))?;
+            }
+            None => this.aggregate_into(py, &mut total, this.inner.iter())?,
+        }
+        total.finish(py)
+    }
+
+    /// Summarize the keys under a prefix by their next segment.
+    ///
+    /// The "directory listing with counts" query for hierarchical keys such
+    /// as "region:store:sku": every distinct segment that follows `prefix`
+    /// up to the next `separator` is reported with the number of keys under
+    /// it, including a key equal to the prefix plus the segment itself. The
+    /// segments are found by skipping whole subtrees, and with
+    /// `order_statistics=True` the counts take O(log n) time per segment.
+    ///
+    /// Args:
+    ///     prefix: Prefix to group under (defaults to "", the whole tree)
+    ///     separator: Separator between segments (defaults to "/")
+    ///     op: Aggregate computed over the values of each group: "count"
+    ///         (default), "sum", "min", "max" or "mean", as for `aggregate()`
+    ///
+    /// Returns:
+    ///     Dict mapping each segment to its aggregate, in key order
+    ///
+    /// Raises:
+    ///     TypeError: If the tree does not have str or bytes keys, or op
+    ///         needs a value that is not a number
+    ///     ValueError: If separator is empty or op is not a known operation
+    ///
+    /// Examples:
+    ///     >>> files = TreeMap({"src/a.rs": 10, "src/b.rs": 20, "docs/x.md": 5, "README": 1})
+    ///     >>> files.group_by_prefix()
+    ///     {'README': 1, 'docs': 1, 'src': 2}
+    ///     >>> files.group_by_prefix("src/", op="sum")
+    ///     {'a.rs': 10, 'b.rs': 20}
+    #[pyo3(signature = (prefix=None, separator=None, *, op="count"))]
+    fn group_by_prefix<'py>(
+        slf: &Bound<'py, Self>,
+        py: Python<'py>,
+        prefix: Option<&Bound<'py, PyAny>>,
+        separator: Option<&Bound<'py, PyAny>>,
+        op: &str,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let this = &*Self::live(slf)?;
+        if !this.codec.is_verbatim() {
+            return Err(PyTypeError::new_err(
+                "group_by_prefix requires str or bytes keys",
+            ));
+        }
+        let op = AggregateOp::from_name(op)?;
+        let dir = match prefix {
+            Some(prefix) => this.encode_prefix(prefix)?,
+            None => Box::default(),
+        };
+        let separator = match separator {
+            Some(separator) => this.encode_prefix(separator)?,
+            None => Box::from(&b"/"[..]),
+        };
+        if separator.is_empty() {
+            return Err(PyValueError::new_err("separator must not be empty"));
+        }
+        let groups = PyDict::new(py);
+        for segment in this.next_segments(&dir, &separator) {
+            let child = [&*dir, &*segment].concat();
+            let below = [&*child, &*separator].concat();
+            // A key ending at the segment belongs to it, but the prefix
+            // itself belongs to no segment
+            let leaf = this
+                .inner
+                .get_key_value(&*child)
+                .filter(|_| !segment.is_empty());
+            let result = match &this.indexes.counts {
+                Some(counts) if op == AggregateOp::Count => {
+                    let end = successor(&below);
+                    let upper = end.as_deref().map_or(Unbounded, Excluded);
+                    let count = counts.count(Included(&below), upper) + usize::from(leaf.is_some());
+                    count.into_pyobject(py)?.into_any().unbind()
+                }
+                _ => {
+                    let mut total = Aggregate::new(op);
+                    let entries = leaf.into_iter().chain(this.prefix_entries(&below));
+                    this.aggregate_into(py, &mut total, entries)?;
+                    total.finish(py)?
+                }
+            };
+            groups.set_item(this.codec.decode(py, &segment)?, result)?;
         }
+        Ok(groups)
     }
 
-    /// Remove a key and return its value.
+    /// Find the most specific network containing an address.
+    ///
+    /// Performs a longest-prefix match over the networks stored in a tree
+    /// created with key_type="ip", as used for routing tables.
     ///
     /// Args:
-    ///     key: String key to remove
+    ///     address: IP address (ipaddress object or string) to look up
     ///
     /// Returns:
-    ///     The value that was associated with the key
+    ///     (network, value) tuple for the longest matching network, or None
     ///
     /// Raises:
-    ///     KeyError: If the key does not exist
+    ///     TypeError: If the tree was not created with key_type="ip"
+    ///     ValueError: If address is not a valid IP address
     ///
     /// Examples:
-    ///     >>> tree = TreeMap({"hello": "world"})
-    ///     >>> tree.remove("hello")
-    ///     'world'
-    ///     >>> tree.remove("missing")  # Raises KeyError
-    fn remove(&mut self, _py: Python, key: String) -> PyResult<Py<PyAny>> {
-        let key_bytes = key.as_bytes();
-        match self.inner.remove(key_bytes) {
-            Some(value) => Ok(value),
-            None => Err(PyErr::new::<PyKeyError, _>(format!("'{}'", key))),
+    ///     >>> routes = TreeMap({"10.0.0.0/8": "core", "10.1.0.0/16": "lab"}, key_type="ip")
+    ///     >>> routes.lookup("10.1.2.3")
+    ///     (IPv4Network('10.1.0.0/16'), 'lab')
+    ///     >>> routes.lookup("10.2.0.1")
+    ///     (IPv4Network('10.0.0.0/8'), 'core')
+    fn lookup(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        address: &Bound<'_, PyAny>,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        for key in this.codec.covering_networks(address)? {
+            if let Some(value) = this.inner.get(&key) {
+                return Ok(Some((
+                    this.decode_key(py, &key)?,
+                    this.value_out(py, value)?,
+                )));
+            }
         }
+        Ok(None)
     }
 
-    /// Remove all entries from the TreeMap.
+    /// Find the stored key next to a key in sort order.
     ///
-    /// Examples:
-    ///     >>> tree = TreeMap({"a": 1, "b": 2})
-    ///     >>> tree.clear()
-    ///     >>> len(tree)
-    ///     0
-    fn clear(&mut self) -> PyResult<()> {
-        self.inner.clear();
-        Ok(())
-    }
-
-    /// Check if the TreeMap contains no entries.
+    /// Useful to snap a query to the nearest existing ID. The candidates are
+    /// the floor (the greatest stored key not after `key`) and the ceiling
+    /// (the smallest stored key not before it); the one sharing the longer
+    /// prefix with `key` wins, and the floor on a tie. An exact match is
+    /// returned as is.
+    ///
+    /// Args:
+    ///     key: Key to look around
     ///
     /// Returns:
-    ///     True if the TreeMap is empty, False otherwise
+    ///     (key, value) tuple for the closest stored key, or None if the
+    ///     tree is empty
     ///
     /// Examples:
-    ///     >>> tree = TreeMap()
-    ///     >>> tree.is_empty()
-    ///     True
-    ///     >>> tree["key"] = "value"
-    ///     >>> tree.is_empty()
-    ///     False
-    fn is_empty(&self) -> PyResult<bool> {
-        Ok(self.inner.is_empty())
+    ///     >>> ids = TreeMap({"user-0100": 1, "user-0200": 2, "zone-1": 3})
+    ///     >>> ids.closest("user-0150")
+    ///     ('user-0100', 1)
+    ///     >>> ids.closest("user-0199")
+    ///     ('user-0100', 1)
+    ///     >>> ids.closest("user-02")
+    ///     ('user-0200', 2)
+    ///     >>> ids.closest("zz")
+    ///     ('zone-1', 3)
+    fn closest(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+    ) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        let key_bytes = this.encode_key(key)?;
+        let ceiling = this.seek(Included(&key_bytes));
+        let floor = match ceiling {
+            Some(ceiling) if ceiling == &*key_bytes => Some(ceiling),
+            Some(ceiling) => this
+                .inner
+                .range::<[u8], _>((Unbounded, Excluded(ceiling)))
+                .next_back()
+                .map(|(k, _)| &**k),
+            None => this.inner.last_key_value().map(|(k, _)| &**k),
+        };
+        let shared = |other: &[u8]| {
+            other
+                .iter()
+                .zip(key_bytes.iter())
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+        let closest = match (floor, ceiling) {
+            (Some(floor), Some(ceiling)) if shared(ceiling) > shared(floor) => Some(ceiling),
+            (floor, ceiling) => floor.or(ceiling),
+        };
+        closest
+            .and_then(|k| Some((k, this.inner.get(k)?)))
+            .map(|(k, v)| Ok((this.decode_key(py, k)?, this.value_out(py, v)?)))
+            .transpose()
     }
 
-    /// Get item using subscript notation (tree[key]).
+    /// Return the number of stored keys that sort before a key.
+    ///
+    /// The key itself need not be stored; its rank is where it would be
+    /// inserted. Takes O(log n) time with `order_statistics=True`, and a
+    /// walk over the smaller keys otherwise.
     ///
     /// Args:
-    ///     key: String key to look up
+    ///     key: Key to rank
     ///
     /// Returns:
-    ///     The value associated with the key
+    ///     Number of keys less than `key`
     ///
-    /// Raises:
-    ///     KeyError: If the key does not exist
-    fn __getitem__(&self, py: Python, key: String) -> PyResult<Py<PyAny>> {
-        let key_bytes = key.as_bytes();
-        match self.inner.get(key_bytes) {
-            Some(value) => Ok(value.clone_ref(py)),
-            None => Err(PyErr::new::<PyKeyError, _>(format!("'{}'", key))),
-        }
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "c": 3, "e": 5}, order_statistics=True)
+    ///     >>> tree.rank("c")
+    ///     1
+    ///     >>> tree.rank("d")
+    ///     2
+    fn rank(slf: &Bound<'_, Self>, py: Python, key: &Bound<'_, PyAny>) -> PyResult<usize> {
+        let this = &*Self::live(slf)?;
+        let key = this.encode_key(key)?;
+        Ok(py.detach(|| this.count_between(Unbounded, Excluded(&key))))
     }
 
-    /// Set item using subscript notation (tree[key] = value).
+    /// Return the entry at a position in key order.
     ///
-    /// Args:
-    ///     key: String key
-    ///     value: Python object to store
-    fn __setitem__(&mut self, py: Python, key: String, value: Py<PyAny>) -> PyResult<()> {
-        self.insert(py, key, value)
-    }
-
-    /// Delete item using del statement (del tree[key]).
+    /// The inverse of `rank()`. Negative positions count from the end, as
+    /// for lists. Takes O(log n) time with `order_statistics=True`, and a
+    /// walk over the preceding keys otherwise.
     ///
     /// Args:
-    ///     key: String key to delete
+    ///     index: Position of the entry
+    ///
+    /// Returns:
+    ///     (key, value) tuple of the entry at that position
     ///
     /// Raises:
-    ///     KeyError: If the key does not exist
-    fn __delitem__(&mut self, py: Python, key: String) -> PyResult<()> {
-        self.remove(py, key)?;
-        Ok(())
+    ///     IndexError: If the position is out of range
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "c": 3, "e": 5}, order_statistics=True)
+    ///     >>> tree.select(1)
+    ///     ('c', 3)
+    ///     >>> tree.select(-1)
+    ///     ('e', 5)
+    fn select(slf: &Bound<'_, Self>, py: Python, index: isize) -> PyResult<(Py<PyAny>, Py<PyAny>)> {
+        let this = &*Self::live(slf)?;
+        let key = this.key_at_position(py, index);
+        let Some((key, value)) = key.and_then(|k| Some((k, this.inner.get(k)?))) else {
+            return Err(PyIndexError::new_err("TreeMap index out of range"));
+        };
+        Ok((this.decode_key(py, key)?, this.value_out(py, value)?))
     }
 
-    /// Check if key exists using 'in' operator (key in tree).
+    /// Return the position where a key would be inserted, before any equal
+    /// stored key.
     ///
-    /// Args:
-    ///     key: String key to check
+    /// The same as `rank()`, named as in `sortedcontainers.SortedDict` so
+    /// code written against it can switch to a TreeMap unchanged.
     ///
-    /// Returns:
-    ///     True if key exists, False otherwise
-    fn __contains__(&self, key: String) -> PyResult<bool> {
-        let key_bytes = key.as_bytes();
-        Ok(self.inner.contains_key(key_bytes))
-    }
-
-    /// Get the number of entries in the TreeMap.
+    /// Args:
+    ///     key: Key to look for
     ///
     /// Returns:
-    ///     Number of key-value pairs
-    fn __len__(&self) -> PyResult<usize> {
-        Ok(self.inner.len())
-    }
-
-    /// Return a developer-friendly string representation.
+    ///     Number of keys less than `key`
     ///
-    /// Returns:
-    ///     String like "TreeMap(len=5)"
-    fn __repr__(&self) -> PyResult<String> {
-        Ok(format!("TreeMap(len={})", self.inner.len()))
+    /// Examples:
+    ///     >>> tree = TreeMap({"a": 1, "c": 3, "e": 5})
+    ///     >>> tree.bisect_left("c")
+    ///     1
+    ///     >>> tree.bisect_left("d")
+    ///     2
+    fn bisect_left(slf: &Bound<'_, Self>, py: Python, key: &Bound<'_, PyAny>) -> PyResult<usize> {
+        Self::rank(slf, py, key)
     }
 
-    /// Return a user-friendly string representation.
+    /// Return the position where a key would be inserted, after any equal
+    /// stored key.
     ///
-    /// Returns:
-    ///     String like "TreeMap with 5 entries"
-    fn __str__(&self) -> PyResult<String> {
-        Ok(format!("TreeMap with {} entries", self.inner.len()))
-    }
-
-    /// Return an iterator over keys in lexicographic order.
+    /// Args:
+    ///     key: Key to look for
     ///
     /// Returns:
-    ///     Iterator that yields keys as strings
+    ///     Number of keys less than or equal to `key`
     ///
     /// Examples:
-    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
-    ///     >>> list(tree)
-    ///     ['a', 'b', 'c']
-    fn __iter__(&self, _py: Python) -> PyResult<PyTreeMapIter> {
-        let keys: Vec<String> = self
-            .inner
-            .iter()
-            .map(|(k, _)| String::from_utf8_lossy(k).into_owned())
-            .collect();
-        Ok(PyTreeMapIter::new(keys))
+    ///     >>> tree = TreeMap({"a": 1, "c": 3, "e": 5})
+    ///     >>> tree.bisect_right("c")
+    ///     2
+    ///     >>> tree.bisect_right("d")
+    ///     2
+    fn bisect_right(slf: &Bound<'_, Self>, py: Python, key: &Bound<'_, PyAny>) -> PyResult<usize> {
+        let this = &*Self::live(slf)?;
+        let key = this.encode_key(key)?;
+        Ok(py.detach(|| this.count_between(Unbounded, Included(&key))))
     }
 
-    /// Return an iterator over all keys in lexicographic order.
+    /// Return the position of a stored key in key order.
+    ///
+    /// As `SortedDict.index()`, `start` and `stop` limit the positions
+    /// searched, with the same meaning as for `list.index()`.
+    ///
+    /// Args:
+    ///     key: Key to look for
+    ///     start: First position to consider (defaults to 0)
+    ///     stop: Position to stop before (defaults to the end)
     ///
     /// Returns:
-    ///     Iterator that yields keys as strings
+    ///     Position of the key
+    ///
+    /// Raises:
+    ///     ValueError: If the key is not stored between start and stop
     ///
     /// Examples:
-    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
-    ///     >>> list(tree.keys())
-    ///     ['a', 'b', 'c']
-    fn keys(&self, _py: Python) -> PyResult<PyTreeMapKeys> {
-        let keys: Vec<String> = self
-            .inner
-            .iter()
-            .map(|(k, _)| String::from_utf8_lossy(k).into_owned())
-            .collect();
-        Ok(PyTreeMapKeys::new(keys))
+    ///     >>> tree = TreeMap({"a": 1, "c": 3, "e": 5})
+    ///     >>> tree.index("e")
+    ///     2
+    ///     >>> tree.index("e", 0, 2)
+    ///     Traceback (most recent call last):
+    ///         ...
+    ///     ValueError: 'e' is not in TreeMap
+    #[pyo3(signature = (key, start=None, stop=None))]
+    fn index(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        start: Option<isize>,
+        stop: Option<isize>,
+    ) -> PyResult<usize> {
+        let this = &*Self::live(slf)?;
+        let len = this.inner.len() as isize;
+        let clamp = |position: isize| {
+            let position = if position < 0 {
+                position + len
+            } else {
+                position
+            };
+            position.clamp(0, len) as usize
+        };
+        let start = start.map_or(0, clamp);
+        let stop = stop.map_or(len as usize, clamp);
+        let key_bytes = this.encode_key(key)?;
+        if this.inner.contains_key(&key_bytes) {
+            let position = py.detach(|| this.count_between(Unbounded, Excluded(&key_bytes)));
+            if (start..stop).contains(&position) {
+                return Ok(position);
+            }
+        }
+        Err(PyValueError::new_err(format!(
+            "{} is not in TreeMap",
+            key.repr()?
+        )))
     }
 
-    /// Return an iterator over all values in key order.
+    /// Return the entry at a position in key order, the last by default.
+    ///
+    /// `SortedDict.peekitem()` under its own name; see `select()`.
+    ///
+    /// Args:
+    ///     index: Position of the entry (defaults to -1)
     ///
     /// Returns:
-    ///     Iterator that yields values
+    ///     (key, value) tuple of the entry at that position
+    ///
+    /// Raises:
+    ///     IndexError: If the tree is empty or the position is out of range
     ///
     /// Examples:
-    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
-    ///     >>> list(tree.values())
-    ///     [1, 2, 3]
-    fn values(&self, py: Python) -> PyResult<PyTreeMapValues> {
-        let values: Vec<Py<PyAny>> = self.inner.iter().map(|(_, v)| v.clone_ref(py)).collect();
-        Ok(PyTreeMapValues::new(values))
+    ///     >>> tree = TreeMap({"a": 1, "c": 3, "e": 5})
+    ///     >>> tree.peekitem()
+    ///     ('e', 5)
+    ///     >>> tree.peekitem(0)
+    ///     ('a', 1)
+    #[pyo3(signature = (index=-1))]
+    fn peekitem(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        index: isize,
+    ) -> PyResult<(Py<PyAny>, Py<PyAny>)> {
+        Self::select(slf, py, index)
     }
 
-    /// Return an iterator over all (key, value) pairs in lexicographic order.
+    /// Return an iterator over the keys between a minimum and a maximum.
+    ///
+    /// Mirrors `SortedDict.irange()`: both bounds are included by default,
+    /// and either can be None to leave that side open.
+    ///
+    /// Args:
+    ///     minimum: Lower bound, or None for no lower bound
+    ///     maximum: Upper bound, or None for no upper bound
+    ///     inclusive: Pair of flags telling whether `minimum` and `maximum`
+    ///         themselves are included (defaults to (True, True))
+    ///     reverse: Yield the keys in descending order (defaults to False)
     ///
     /// Returns:
-    ///     Iterator that yields (key, value) tuples
+    ///     Iterator over the keys in the range
     ///
     /// Examples:
-    ///     >>> tree = TreeMap({"c": 3, "a": 1})
-    ///     >>> list(tree.items())
-    ///     [('a', 1), ('c', 3)]
-    fn items(&self, py: Python) -> PyResult<PyTreeMapItems> {
-        let items: Vec<(String, Py<PyAny>)> = self
-            .inner
-            .iter()
-            .map(|(k, v)| (String::from_utf8_lossy(k).into_owned(), v.clone_ref(py)))
-            .collect();
-        Ok(PyTreeMapItems::new(items))
+    ///     >>> tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    ///     >>> list(tree.irange("b", "c"))
+    ///     ['b', 'c']
+    ///     >>> list(tree.irange("b", None, inclusive=(False, True), reverse=True))
+    ///     ['d', 'c']
+    #[pyo3(signature = (minimum=None, maximum=None, inclusive=(true, true), reverse=false))]
+    fn irange(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        minimum: Option<&Bound<'_, PyAny>>,
+        maximum: Option<&Bound<'_, PyAny>>,
+        inclusive: (bool, bool),
+        reverse: bool,
+    ) -> PyResult<PyTreeMapKeys> {
+        let slf = Self::live(slf)?;
+        let (lower, upper) = slf.range_bounds(minimum, maximum, inclusive)?;
+        let mut keys = slf
+            .bounded(bound_ref(&lower), bound_ref(&upper))
+            .map(|(k, _)| slf.decode_key(py, k))
+            .collect::<PyResult<Vec<_>>>()?;
+        if reverse {
+            keys.reverse();
+        }
+        Ok(PyTreeMapKeys::new(keys, IterGuard::new(&slf)))
     }
 
-    /// Get the first key-value pair matching a prefix.
+    /// Positional access to the keys, as the `iloc` of older SortedDict
+    /// versions.
     ///
-    /// This is useful for quickly checking if any keys start with a given prefix,
-    /// or for getting a representative value for a prefix.
-    ///
-    /// Args:
-    ///     prefix: String prefix to search for
+    /// `tree.iloc[i]` is the key at position i and `tree.iloc[i:j]` a list
+    /// of keys; `del tree.iloc[i]` removes the entry at a position.
     ///
     /// Returns:
-    ///     (key, value) tuple for first match, or None if no match
+    ///     Live positional view of the keys
     ///
     /// Examples:
-    ///     >>> tree = TreeMap({"apple": 1, "application": 2, "banana": 3})
-    ///     >>> tree.get_prefix("app")
-    ///     ('apple', 1)
-    ///     >>> tree.get_prefix("ban")
-    ///     ('banana', 3)
-    ///     >>> tree.get_prefix("xyz")
-    ///     None
-    fn get_prefix(&self, py: Python, prefix: String) -> PyResult<Option<(String, Py<PyAny>)>> {
-        let prefix_bytes = prefix.as_bytes();
-        // Use prefix iterator to get the first matching key-value pair
-        let mut iter = self.inner.prefix(prefix_bytes);
-        match iter.next() {
-            Some((key, val)) => {
-                let key_str = String::from_utf8_lossy(key).into_owned();
-                Ok(Some((key_str, val.clone_ref(py))))
-            }
-            None => Ok(None),
-        }
+    ///     >>> tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    ///     >>> tree.iloc[-1]
+    ///     'c'
+    ///     >>> tree.iloc[:2]
+    ///     ['a', 'b']
+    #[getter]
+    fn iloc(slf: &Bound<'_, Self>) -> PyTreeMapILoc {
+        PyTreeMapILoc::new(slf.clone().unbind())
     }
 
-    /// Return an iterator over all key-value pairs with a given prefix.
+    /// Return entries chosen uniformly at random, without replacement.
     ///
-    /// This is one of the key features of the adaptive radix tree - efficient
-    /// prefix queries that don't require scanning all keys.
+    /// The positions to keep are drawn first, then picked up in a single
+    /// walk over the tree in Rust, so only the sampled entries are turned
+    /// into Python objects.
     ///
     /// Args:
-    ///     prefix: String prefix to search for
+    ///     n: Number of entries to return (defaults to 1)
+    ///     seed: Optional integer seed, for a reproducible sample
     ///
     /// Returns:
-    ///     Iterator yielding (key, value) tuples for matching keys
+    ///     List of n distinct (key, value) tuples, in random order
+    ///
+    /// Raises:
+    ///     ValueError: If n is larger than the number of entries
     ///
     /// Examples:
-    ///     >>> tree = TreeMap({"apple": 1, "application": 2, "apply": 3, "banana": 4})
-    ///     >>> list(tree.prefix_iter("app"))
-    ///     [('apple', 1), ('application', 2), ('apply', 3)]
-    ///     >>> list(tree.prefix_iter(""))  # Empty prefix matches all
-    ///     [('apple', 1), ('application', 2), ('apply', 3), ('banana', 4)]
-    fn prefix_iter(&self, py: Python, prefix: String) -> PyResult<PyPrefixIter> {
-        let prefix_bytes = prefix.as_bytes();
-        let items: Vec<(String, Py<PyAny>)> = self
-            .inner
-            .prefix(prefix_bytes)
-            .map(|(k, v)| (String::from_utf8_lossy(k).into_owned(), v.clone_ref(py)))
+    ///     >>> tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    ///     >>> len(tree.sample(2))
+    ///     2
+    ///     >>> tree.sample(2, seed=7) == tree.sample(2, seed=7)
+    ///     True
+    #[pyo3(signature = (n=1, seed=None))]
+    fn sample(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        n: usize,
+        seed: Option<u64>,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        let len = this.inner.len();
+        if n > len {
+            return Err(PyValueError::new_err(format!(
+                "sample larger than the tree ({n} > {len})"
+            )));
+        }
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        // (position in the tree, position in the sample), in tree order
+        let mut wanted: Vec<(usize, usize)> = rand::seq::index::sample(&mut rng, len, n)
+            .into_iter()
+            .enumerate()
+            .map(|(slot, position)| (position, slot))
             .collect();
-        Ok(PyPrefixIter::new(items))
+        wanted.sort_unstable();
+        let mut picked = vec![None; n];
+        let mut wanted = wanted.into_iter().peekable();
+        for (position, (key, value)) in this.inner.iter().enumerate() {
+            let Some(&(next, slot)) = wanted.peek() else {
+                break;
+            };
+            if position == next {
+                picked[slot] = Some((key, value));
+                wanted.next();
+            }
+        }
+        picked
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| Ok((this.decode_key(py, k)?, this.value_out(py, v)?)))
+            .collect()
     }
 
     /// Get the first (lexicographically smallest) key-value pair.
@@ -462,11 +7702,12 @@ impl PyTreeMap {
     ///     ('a', 1)
     ///     >>> TreeMap().first()
     ///     None
-    fn first(&self, py: Python) -> PyResult<Option<(String, Py<PyAny>)>> {
-        match self.inner.first_key_value() {
+    fn first(slf: &Bound<'_, Self>, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        match this.inner.first_key_value() {
             Some((key, value)) => {
-                let key_str = String::from_utf8_lossy(key).into_owned();
-                Ok(Some((key_str, value.clone_ref(py))))
+                let key_obj = this.decode_key(py, key)?;
+                Ok(Some((key_obj, this.value_out(py, value)?)))
             }
             None => Ok(None),
         }
@@ -486,16 +7727,76 @@ impl PyTreeMap {
     ///     ('c', 3)
     ///     >>> TreeMap().last()
     ///     None
-    fn last(&self, py: Python) -> PyResult<Option<(String, Py<PyAny>)>> {
-        match self.inner.last_key_value() {
+    fn last(slf: &Bound<'_, Self>, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        match this.inner.last_key_value() {
             Some((key, value)) => {
-                let key_str = String::from_utf8_lossy(key).into_owned();
-                Ok(Some((key_str, value.clone_ref(py))))
+                let key_obj = this.decode_key(py, key)?;
+                Ok(Some((key_obj, this.value_out(py, value)?)))
             }
             None => Ok(None),
         }
     }
 
+    /// Get the n smallest key-value pairs.
+    ///
+    /// Only the first n entries are visited, unlike slicing `list(tree.items())`.
+    ///
+    /// Args:
+    ///     n: Number of pairs to return
+    ///
+    /// Returns:
+    ///     List of up to n (key, value) tuples in key order
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
+    ///     >>> tree.first_n(2)
+    ///     [('a', 1), ('b', 2)]
+    fn first_n(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        n: usize,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        this.inner
+            .iter()
+            .take(n)
+            .map(|(k, v)| Ok((this.decode_key(py, k)?, this.value_out(py, v)?)))
+            .collect()
+    }
+
+    /// Get the n largest key-value pairs.
+    ///
+    /// The entries are found walking back from the last key, visiting only
+    /// n of them, and returned in ascending key order like `items()[-n:]`.
+    ///
+    /// Args:
+    ///     n: Number of pairs to return
+    ///
+    /// Returns:
+    ///     List of up to n (key, value) tuples in key order
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"c": 3, "a": 1, "b": 2})
+    ///     >>> tree.last_n(2)
+    ///     [('b', 2), ('c', 3)]
+    fn last_n(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        n: usize,
+    ) -> PyResult<Vec<(Py<PyAny>, Py<PyAny>)>> {
+        let this = &*Self::live(slf)?;
+        let mut items = this
+            .inner
+            .iter()
+            .rev()
+            .take(n)
+            .map(|(k, v)| Ok((this.decode_key(py, k)?, this.value_out(py, v)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        items.reverse();
+        Ok(items)
+    }
+
     /// Remove and return the first (lexicographically smallest) key-value pair.
     ///
     /// This is useful for implementing queue-like behavior or for iteratively
@@ -515,11 +7816,13 @@ impl PyTreeMap {
     ///     ('b', 2)
     ///     >>> len(tree)
     ///     1
-    fn pop_first(&mut self, _py: Python) -> PyResult<Option<(String, Py<PyAny>)>> {
+    fn pop_first(&mut self, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        self.purge_expired(py)?;
         match self.inner.pop_first() {
             Some((key, value)) => {
-                let key_str = String::from_utf8_lossy(&key).into_owned();
-                Ok(Some((key_str, value)))
+                let key_obj = self.decode_key(py, &key)?;
+                self.discard(py, &key, &value, Change::Remove)?;
+                Ok(Some((key_obj, self.value_out(py, &value)?)))
             }
             None => Ok(None),
         }
@@ -544,11 +7847,13 @@ impl PyTreeMap {
     ///     ('b', 2)
     ///     >>> len(tree)
     ///     1
-    fn pop_last(&mut self, _py: Python) -> PyResult<Option<(String, Py<PyAny>)>> {
+    fn pop_last(&mut self, py: Python) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        self.purge_expired(py)?;
         match self.inner.pop_last() {
             Some((key, value)) => {
-                let key_str = String::from_utf8_lossy(&key).into_owned();
-                Ok(Some((key_str, value)))
+                let key_obj = self.decode_key(py, &key)?;
+                self.discard(py, &key, &value, Change::Remove)?;
+                Ok(Some((key_obj, self.value_out(py, &value)?)))
             }
             None => Ok(None),
         }
@@ -559,17 +7864,49 @@ impl PyTreeMap {
     /// This is useful for fuzzy matching, typo tolerance, and approximate
     /// string searching. The Levenshtein distance counts the minimum number
     /// of single-character edits (insertions, deletions, substitutions)
-    /// needed to transform one string into another.
+    /// needed to transform one string into another. The "damerau" metric
+    /// also counts swapping two adjacent characters as a single edit, which
+    /// ranks common typos such as "teh" for "the" correctly; "hamming" only
+    /// allows substitutions, for fixed-length keys such as codes or hashes.
+    ///
+    /// Matches are streamed lazily in key order: each `next()` walks the tree
+    /// only until the following match, skipping subtrees whose shared prefix
+    /// is already too far from the key. String keys are compared by
+    /// character, other keys by byte.
+    ///
+    /// With `sort_by_distance=True` the closest matches come first (ties in
+    /// key order). They are ranked in Rust with a heap of `max_results`
+    /// entries, and the search radius narrows as better matches are found,
+    /// so asking for the k best candidates is cheaper than sorting them all.
     ///
     /// Args:
-    ///     key: String to search for
+    ///     key: Key to search for
     ///     max_distance: Maximum edit distance allowed (must be non-negative)
+    ///     max_results: Stop after this many matches (defaults to no limit)
+    ///     sort_by_distance: Yield the closest matches first instead of in
+    ///         key order (defaults to False)
+    ///     metric: "levenshtein" (default), "damerau" or "hamming"
+    ///     insert_cost: Cost of a character in the stored key that is not
+    ///         in `key` (defaults to 1)
+    ///     delete_cost: Cost of a character of `key` missing from the
+    ///         stored key (defaults to 1)
+    ///     substitute_cost: Cost of replacing one character with another,
+    ///         and of swapping two with the "damerau" metric (defaults to 1)
+    ///     substitution_costs: Dict mapping `(key_char, stored_char)` pairs
+    ///         to their own substitution cost, e.g. to make neighbouring
+    ///         keyboard keys cheap typos. Characters are 1-character strings
+    ///         for str keys and byte values (ints) otherwise
     ///
     /// Returns:
-    ///     Iterator yielding (key, value, distance) tuples for all matches
+    ///     Iterator yielding (key, value, distance) tuples for all matches.
+    ///     The distance is the total cost, a float when any cost argument
+    ///     is given and the integer edit count otherwise
     ///
     /// Raises:
     ///     OverflowError: If max_distance is negative
+    ///     ValueError: If metric is not a known metric name, or a cost or a
+    ///         weighted max_distance is negative
+    ///     TypeError: If a substitution_costs key is not a pair of units
     ///
     /// Examples:
     ///     >>> tree = TreeMap({"hello": 1, "hallo": 2, "world": 3})
@@ -579,17 +7916,354 @@ impl PyTreeMap {
     ///     >>> # Returns both "hello" (distance 0) and "hallo" (distance 1)
     ///     >>> len(results)
     ///     2
-    fn fuzzy_search(&self, py: Python, key: String, max_distance: usize) -> PyResult<PyFuzzyIter> {
-        let key_bytes = key.as_bytes();
-        let items: Vec<(String, Py<PyAny>, usize)> = self
-            .inner
-            .fuzzy(key_bytes, max_distance)
-            .map(|(k, v)| {
-                let key_str = String::from_utf8_lossy(k).into_owned();
-                let distance = levenshtein_distance(&key, &key_str);
-                (key_str, v.clone_ref(py), distance)
+    ///     >>> words = TreeMap({"hallo": 1, "hello": 2, "help": 3, "hullo": 4})
+    ///     >>> list(words.fuzzy_search("hello", 2, max_results=2, sort_by_distance=True))
+    ///     [('hello', 2, 0), ('hallo', 1, 1)]
+    ///     >>> list(TreeMap({"the": 1}).fuzzy_search("teh", 1, metric="damerau"))
+    ///     [('the', 1, 1)]
+    ///     >>> typos = {("s", "a"): 0.5, ("a", "s"): 0.5}
+    ///     >>> list(TreeMap({"cat": 1, "cut": 2}).fuzzy_search(
+    ///     ...     "cst", 0.5, substitution_costs=typos))
+    ///     [('cat', 1, 0.5)]
+    #[pyo3(signature = (
+        key,
+        max_distance,
+        *,
+        max_results=None,
+        sort_by_distance=false,
+        metric="levenshtein",
+        insert_cost=None,
+        delete_cost=None,
+        substitute_cost=None,
+        substitution_costs=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn fuzzy_search(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        max_distance: &Bound<'_, PyAny>,
+        max_results: Option<usize>,
+        sort_by_distance: bool,
+        metric: &str,
+        insert_cost: Option<f64>,
+        delete_cost: Option<f64>,
+        substitute_cost: Option<f64>,
+        substitution_costs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<PyFuzzyIter> {
+        let slf = Self::live(slf)?;
+        let key_bytes = slf.encode_key(key)?;
+        let (cursor, weighted) = slf.fuzzy_cursor(
+            &key_bytes,
+            max_distance,
+            metric,
+            [insert_cost, delete_cost, substitute_cost],
+            substitution_costs,
+        )?;
+        Self::fuzzy_results(slf, py, cursor, weighted, max_results, sort_by_distance)
+    }
+
+    /// Find keys that start with something close to a prefix.
+    ///
+    /// Unlike `fuzzy_search()`, which compares whole keys, a key matches
+    /// when any of its prefixes is within `max_distance` of `prefix`, and
+    /// the distance reported is that of its closest prefix. This is what a
+    /// search-as-you-type box needs: "helo" already matches "hello world"
+    /// at distance 1, although the whole key is much further away.
+    ///
+    /// Matches are streamed lazily in key order, and take the same keyword
+    /// arguments as `fuzzy_search()`: `max_results`, `sort_by_distance`,
+    /// `metric` and the edit costs.
+    ///
+    /// Args:
+    ///     prefix: Prefix typed so far
+    ///     max_distance: Maximum edit distance allowed between `prefix` and
+    ///         the start of a key (must be non-negative)
+    ///     max_results: Stop after this many matches (defaults to no limit)
+    ///     sort_by_distance: Yield the closest matches first instead of in
+    ///         key order (defaults to False)
+    ///     metric: "levenshtein" (default), "damerau" or "hamming"
+    ///     insert_cost: Cost of an extra character in the key (defaults to 1)
+    ///     delete_cost: Cost of a character of `prefix` missing from the key
+    ///         (defaults to 1)
+    ///     substitute_cost: Cost of replacing one character with another
+    ///         (defaults to 1)
+    ///     substitution_costs: Dict mapping `(prefix_char, stored_char)`
+    ///         pairs to their own substitution cost
+    ///
+    /// Returns:
+    ///     Iterator yielding (key, value, distance) tuples for all matches
+    ///
+    /// Raises:
+    ///     OverflowError: If max_distance is negative
+    ///     ValueError: If metric is not a known metric name, or a cost or a
+    ///         weighted max_distance is negative
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"hello world": 1, "help": 2, "yellow": 3})
+    ///     >>> list(tree.fuzzy_prefix("helo", 1))
+    ///     [('hello world', 1, 1), ('help', 2, 1)]
+    ///     >>> list(tree.fuzzy_prefix("yel", 0))
+    ///     [('yellow', 3, 0)]
+    #[pyo3(signature = (
+        prefix,
+        max_distance,
+        *,
+        max_results=None,
+        sort_by_distance=false,
+        metric="levenshtein",
+        insert_cost=None,
+        delete_cost=None,
+        substitute_cost=None,
+        substitution_costs=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn fuzzy_prefix(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        prefix: &Bound<'_, PyAny>,
+        max_distance: &Bound<'_, PyAny>,
+        max_results: Option<usize>,
+        sort_by_distance: bool,
+        metric: &str,
+        insert_cost: Option<f64>,
+        delete_cost: Option<f64>,
+        substitute_cost: Option<f64>,
+        substitution_costs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<PyFuzzyIter> {
+        let slf = Self::live(slf)?;
+        let prefix_bytes = slf.encode_prefix(prefix)?;
+        let (cursor, weighted) = slf.fuzzy_cursor(
+            &prefix_bytes,
+            max_distance,
+            metric,
+            [insert_cost, delete_cost, substitute_cost],
+            substitution_costs,
+        )?;
+        let cursor = cursor.matching_prefixes();
+        Self::fuzzy_results(slf, py, cursor, weighted, max_results, sort_by_distance)
+    }
+
+    /// Run `fuzzy_search()` for many queries in one call.
+    ///
+    /// Meant for record linkage and batch spell checking: the queries are
+    /// encoded once, then searched in Rust with the GIL released, spread
+    /// over a thread pool unless `parallel=False`. Results are collected
+    /// eagerly instead of streamed.
+    ///
+    /// Args:
+    ///     queries: Iterable of keys to search for
+    ///     max_distance: Maximum edit distance allowed, shared by all
+    ///         queries
+    ///     max_results: Keep at most this many matches per query (defaults
+    ///         to no limit)
+    ///     sort_by_distance: Put the closest matches of each query first
+    ///         instead of in key order (defaults to False)
+    ///     metric: "levenshtein" (default), "damerau" or "hamming"
+    ///     insert_cost, delete_cost, substitute_cost, substitution_costs:
+    ///         Edit costs, as for `fuzzy_search()`
+    ///     parallel: Search the queries on several threads (defaults to
+    ///         True)
+    ///
+    /// Returns:
+    ///     List with one list of (key, value, distance) tuples per query,
+    ///     in the order of `queries`
+    ///
+    /// Raises:
+    ///     OverflowError: If max_distance is negative
+    ///     ValueError: If metric is not a known metric name, or a cost or a
+    ///         weighted max_distance is negative
+    ///
+    /// Examples:
+    ///     >>> names = TreeMap({"alice": 1, "alicia": 2, "bob": 3})
+    ///     >>> names.fuzzy_search_many(["alise", "bobb", "zed"], 1)
+    ///     [[('alice', 1, 1)], [('bob', 3, 1)], []]
+    #[pyo3(signature = (
+        queries,
+        max_distance,
+        *,
+        max_results=None,
+        sort_by_distance=false,
+        metric="levenshtein",
+        insert_cost=None,
+        delete_cost=None,
+        substitute_cost=None,
+        substitution_costs=None,
+        parallel=true,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn fuzzy_search_many(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        queries: &Bound<'_, PyAny>,
+        max_distance: &Bound<'_, PyAny>,
+        max_results: Option<usize>,
+        sort_by_distance: bool,
+        metric: &str,
+        insert_cost: Option<f64>,
+        delete_cost: Option<f64>,
+        substitute_cost: Option<f64>,
+        substitution_costs: Option<&Bound<'_, PyDict>>,
+        parallel: bool,
+    ) -> PyResult<Vec<Vec<FuzzyMatch>>> {
+        let this = &*Self::live(slf)?;
+        let (template, weighted) = this.fuzzy_cursor(
+            &[],
+            max_distance,
+            metric,
+            [insert_cost, delete_cost, substitute_cost],
+            substitution_costs,
+        )?;
+        let queries = queries
+            .try_iter()?
+            .map(|query| this.encode_key(&query?))
+            .collect::<PyResult<Vec<_>>>()?;
+        this.metrics.fuzzy_scan(queries.len());
+        let search = |query: &[u8]| {
+            let cursor = template.retarget(query);
+            let seek = |bound: std::ops::Bound<&[u8]>| this.seek(bound);
+            if sort_by_distance {
+                return cursor.ranked(seek, max_results);
+            }
+            let mut cursor = cursor;
+            std::iter::from_fn(|| cursor.next_match(seek))
+                .take(max_results.unwrap_or(usize::MAX))
+                .map(|(key, distance)| (distance, key))
+                .collect()
+        };
+        let found: Vec<Vec<(f64, &[u8])>> = py.detach(|| {
+            if parallel {
+                queries.par_iter().map(|query| search(query)).collect()
+            } else {
+                queries.iter().map(|query| search(query)).collect()
+            }
+        });
+        found
+            .into_iter()
+            .map(|matches| {
+                matches
+                    .into_iter()
+                    .filter_map(|(distance, key)| Some((distance, key, this.inner.get(key)?)))
+                    .map(|(distance, key, value)| {
+                        Ok((
+                            this.decode_key(py, key)?,
+                            this.value_out(py, value)?,
+                            fuzzy_distance(py, distance, weighted)?,
+                        ))
+                    })
+                    .collect()
             })
-            .collect();
-        Ok(PyFuzzyIter::new(items))
+            .collect()
+    }
+
+    /// Return the k stored keys closest to a key, however far away they are.
+    ///
+    /// Unlike `fuzzy_search()`, no distance cutoff has to be guessed. The
+    /// search is run with a radius of 1, 2, 4... until k keys are found,
+    /// so close matches stay cheap to find; once the radius reaches the
+    /// length of the key, a last pass drops the cutoff altogether and only
+    /// narrows as the k best keys are collected.
+    ///
+    /// Args:
+    ///     key: Key to search for
+    ///     k: Number of keys to return (defaults to 1)
+    ///     metric: "levenshtein" (default), "damerau" or "hamming"
+    ///
+    /// Returns:
+    ///     List of up to k (key, value, distance) tuples, closest first and
+    ///     in key order among equal distances. Fewer are returned only when
+    ///     the tree holds fewer comparable keys (with "hamming", keys of
+    ///     another length never match)
+    ///
+    /// Raises:
+    ///     ValueError: If metric is not a known metric name
+    ///
+    /// Examples:
+    ///     >>> tree = TreeMap({"apple": 1, "banana": 2, "cherry": 3})
+    ///     >>> tree.nearest("grape")
+    ///     [('apple', 1, 4)]
+    ///     >>> tree.nearest("bandana", k=2)
+    ///     [('banana', 2, 1), ('apple', 1, 6)]
+    #[pyo3(signature = (key, k=1, *, metric="levenshtein"))]
+    fn nearest(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        key: &Bound<'_, PyAny>,
+        k: usize,
+        metric: &str,
+    ) -> PyResult<Vec<FuzzyMatch>> {
+        let this = &*Self::live(slf)?;
+        let metric = Metric::from_name(metric)?;
+        let query = this.encode_key(key)?;
+        let chars = this.codec.is_str();
+        let found = py.detach(|| {
+            let mut radius = 1.0;
+            loop {
+                let cursor = FuzzyCursor::new(&query, radius, chars, metric, Costs::default());
+                let found = cursor.ranked(|bound| this.seek(bound), Some(k));
+                if found.len() >= k || radius.is_infinite() {
+                    return found;
+                }
+                radius = if radius >= query.len() as f64 {
+                    f64::INFINITY
+                } else {
+                    radius * 2.0
+                };
+            }
+        });
+        found
+            .into_iter()
+            .filter_map(|(distance, key)| Some((distance, key, this.inner.get(key)?)))
+            .map(|(distance, key, value)| {
+                Ok((
+                    this.decode_key(py, key)?,
+                    this.value_out(py, value)?,
+                    fuzzy_distance(py, distance, false)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Suggest stored keys close to a possibly misspelled word.
+    ///
+    /// A one-call speller for the common "did you mean..." case: keys are
+    /// ranked by edit distance, and keys at the same distance by value,
+    /// highest first, so storing word frequencies as values puts the most
+    /// common spelling first. Values that are not numbers rank after
+    /// numeric ones at the same distance, in key order.
+    ///
+    /// Args:
+    ///     word: Word to find suggestions for
+    ///     n: Maximum number of suggestions (defaults to 5)
+    ///     max_distance: Maximum edit distance of a suggestion (defaults
+    ///         to 2)
+    ///     metric: "levenshtein" (default), "damerau" or "hamming"
+    ///
+    /// Returns:
+    ///     List of up to `n` keys, best suggestion first. An exact match,
+    ///     if stored, always comes first
+    ///
+    /// Raises:
+    ///     ValueError: If metric is not a known metric name
+    ///
+    /// Examples:
+    ///     >>> words = TreeMap({"python": 120, "pylon": 3, "typhoon": 15})
+    ///     >>> words.suggest("pyton")
+    ///     ['python', 'pylon']
+    ///     >>> words.suggest("xyz")
+    ///     []
+    #[pyo3(signature = (word, n=5, *, max_distance=2, metric="levenshtein"))]
+    fn suggest(
+        slf: &Bound<'_, Self>,
+        py: Python,
+        word: &Bound<'_, PyAny>,
+        n: usize,
+        max_distance: usize,
+        metric: &str,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        let this = &*Self::live(slf)?;
+        let metric = Metric::from_name(metric)?;
+        let query = this.encode_key(word)?;
+        this.suggestions(py, &query, n, max_distance, metric)
     }
 }
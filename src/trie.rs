@@ -0,0 +1,180 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString, PyTuple};
+use pyo3::{PyTraverseError, PyVisit};
+use std::ops::Range;
+
+/// The entries below a prefix, captured for a node-by-node walk as in
+/// pygtrie's `Trie.traverse()`.
+#[pyclass(frozen)]
+pub struct PyTrieWalk {
+    /// Stored keys and their values, in key order
+    entries: Vec<(Box<[u8]>, Py<PyAny>)>,
+    /// Whether keys are text, split into characters rather than bytes
+    text: bool,
+    node_factory: Py<PyAny>,
+    path_conv: Py<PyAny>,
+}
+
+#[pymethods]
+impl PyTrieWalk {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        for (_, value) in &self.entries {
+            visit.call(value)?;
+        }
+        visit.call(&self.node_factory)?;
+        visit.call(&self.path_conv)
+    }
+}
+
+impl PyTrieWalk {
+    /// Number of bytes in the path step starting with byte `b`.
+    fn step_len(&self, b: u8) -> usize {
+        match (self.text, b) {
+            (false, _) | (true, 0x00..=0xBF) => 1,
+            (true, 0xC0..=0xDF) => 2,
+            (true, 0xE0..=0xEF) => 3,
+            (true, _) => 4,
+        }
+    }
+
+    /// A path step as pygtrie has it: a one-character str for text keys,
+    /// and an int for bytes keys.
+    fn step(&self, py: Python, bytes: &[u8]) -> PyResult<Py<PyAny>> {
+        Ok(match self.text {
+            true => PyString::new(py, &String::from_utf8_lossy(bytes))
+                .into_any()
+                .unbind(),
+            false => bytes[0].into_pyobject(py)?.into_any().unbind(),
+        })
+    }
+}
+
+/// Call the node factory on the node at `depth` bytes into the keys of
+/// `entries`, with its children to be visited lazily.
+fn visit_node(
+    py: Python,
+    walk: &Bound<'_, PyTrieWalk>,
+    entries: Range<usize>,
+    depth: usize,
+    path: Vec<Py<PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    let all = &walk.get().entries;
+    let mut start = entries.start;
+    // Keys sort before their extensions, so a key ending here comes first
+    let value = match all.get(start) {
+        Some((key, value)) if start < entries.end && key.len() == depth => {
+            start += 1;
+            Some(value.clone_ref(py))
+        }
+        _ => None,
+    };
+    let mut children = Vec::new();
+    while start < entries.end {
+        let key = &all[start].0;
+        let len = walk.get().step_len(key[depth]).min(key.len() - depth);
+        let step = &key[depth..depth + len];
+        let end =
+            start + all[start..entries.end].partition_point(|(k, _)| k[depth..].starts_with(step));
+        children.push((start..end, len));
+        start = end;
+    }
+    let children = PyTrieChildren {
+        walk: walk.clone().unbind(),
+        depth,
+        path: path.iter().map(|step| step.clone_ref(py)).collect(),
+        pending: children,
+        next: 0,
+    };
+    let walk = walk.get();
+    let path = PyTuple::new(py, path)?;
+    let node_factory = walk.node_factory.bind(py);
+    let path_conv = walk.path_conv.bind(py);
+    Ok(match value {
+        Some(value) => node_factory.call1((path_conv, path, children, value))?,
+        None => node_factory.call1((path_conv, path, children))?,
+    }
+    .unbind())
+}
+
+/// Walk the entries below `prefix` node by node, calling `node_factory`
+/// as `node_factory(path_conv, path, children[, value])` for each node.
+pub fn traverse(
+    py: Python,
+    entries: Vec<(Box<[u8]>, Py<PyAny>)>,
+    text: bool,
+    node_factory: Py<PyAny>,
+    prefix: &[u8],
+) -> PyResult<Py<PyAny>> {
+    // What pygtrie's CharTrie and Trie turn paths back into keys with
+    let path_conv = match text {
+        true => PyString::new(py, "").getattr("join")?.unbind(),
+        false => py.get_type::<PyBytes>().into_any().unbind(),
+    };
+    let len = entries.len();
+    let walk = Bound::new(
+        py,
+        PyTrieWalk {
+            entries,
+            text,
+            node_factory,
+            path_conv,
+        },
+    )?;
+    let mut path = Vec::new();
+    let mut depth = 0;
+    while depth < prefix.len() {
+        let step_len = walk.get().step_len(prefix[depth]).min(prefix.len() - depth);
+        path.push(walk.get().step(py, &prefix[depth..depth + step_len])?);
+        depth += step_len;
+    }
+    visit_node(py, &walk, 0..len, depth, path)
+}
+
+/// The children of a node passed to a `traverse()` node factory, each
+/// visited only when the iterator reaches it.
+#[pyclass]
+pub struct PyTrieChildren {
+    walk: Py<PyTrieWalk>,
+    depth: usize,
+    path: Vec<Py<PyAny>>,
+    /// Range of entries and length of the path step of each child
+    pending: Vec<(Range<usize>, usize)>,
+    next: usize,
+}
+
+#[pymethods]
+impl PyTrieChildren {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.walk)?;
+        for step in &self.path {
+            visit.call(step)?;
+        }
+        Ok(())
+    }
+
+    fn __clear__(&mut self) {
+        self.path.clear();
+        self.pending.clear();
+        self.next = 0;
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<Py<PyAny>>> {
+        let Some((entries, len)) = self.pending.get(self.next).cloned() else {
+            return Ok(None);
+        };
+        self.next += 1;
+        let walk = self.walk.bind(py);
+        let key = &walk.get().entries[entries.start].0;
+        let mut path: Vec<_> = self.path.iter().map(|step| step.clone_ref(py)).collect();
+        path.push(walk.get().step(py, &key[self.depth..self.depth + len])?);
+        visit_node(py, walk, entries, self.depth + len, path).map(Some)
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.pending.len() - self.next
+    }
+}
@@ -0,0 +1,382 @@
+use crate::iterators::{IterGuard, PyTreeMapItems, PyTreeMapKeys, PyTreeMapValues};
+use crate::treemap::PyTreeMap;
+use pyo3::basic::CompareOp;
+use pyo3::exceptions::PyIndexError;
+use pyo3::prelude::*;
+use pyo3::sync::PyOnceLock;
+use pyo3::types::{PyBool, PyList, PySet, PySlice, PyTuple, PyType};
+use pyo3::{PyTraverseError, PyVisit};
+
+/// Collect an iterable into a new set.
+fn to_set<'py>(items: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PySet>> {
+    let set = PySet::empty(items.py())?;
+    for item in items.try_iter()? {
+        set.add(item?)?;
+    }
+    Ok(set)
+}
+
+/// Apply a set method to a view and another iterable, like dict views do.
+///
+/// `reflected` swaps the operands for `other <op> view`.
+fn set_op(
+    view: &Bound<'_, PyAny>,
+    other: &Bound<'_, PyAny>,
+    method: &str,
+    reflected: bool,
+) -> PyResult<Py<PyAny>> {
+    let py = view.py();
+    if other.try_iter().is_err() {
+        return Ok(py.NotImplemented());
+    }
+    let (left, right) = if reflected {
+        (to_set(other)?, to_set(view)?)
+    } else {
+        (to_set(view)?, to_set(other)?)
+    };
+    Ok(left.call_method1(method, (right,))?.unbind())
+}
+
+/// Compare a view with a set-like object for equality.
+fn set_eq(view: &Bound<'_, PyAny>, other: &Bound<'_, PyAny>, op: CompareOp) -> PyResult<Py<PyAny>> {
+    static ABC_SET: PyOnceLock<Py<PyType>> = PyOnceLock::new();
+    let py = view.py();
+    let abc_set = ABC_SET.import(py, "collections.abc", "Set")?;
+    let equal = match op {
+        CompareOp::Eq => true,
+        CompareOp::Ne => false,
+        _ => return Ok(py.NotImplemented()),
+    };
+    if !other.is_instance(abc_set)? {
+        return Ok(py.NotImplemented());
+    }
+    let same = to_set(view)?.eq(to_set(other)?)?;
+    Ok(PyBool::new(py, same == equal)
+        .to_owned()
+        .into_any()
+        .unbind())
+}
+
+/// Live view of a TreeMap's keys (returned by .keys())
+#[pyclass]
+pub struct PyTreeMapKeysView {
+    map: Py<PyTreeMap>,
+}
+
+impl PyTreeMapKeysView {
+    pub fn new(map: Py<PyTreeMap>) -> Self {
+        Self { map }
+    }
+}
+
+#[pymethods]
+impl PyTreeMapKeysView {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.map)
+    }
+
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        Ok(PyTreeMap::live(self.map.bind(py))?.entry_count())
+    }
+
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapKeys> {
+        let map = PyTreeMap::live(self.map.bind(py))?;
+        Ok(PyTreeMapKeys::new(map.key_list(py)?, IterGuard::new(&map)))
+    }
+
+    fn __reversed__(&self, py: Python) -> PyResult<PyTreeMapKeys> {
+        let map = PyTreeMap::live(self.map.bind(py))?;
+        let mut keys = map.key_list(py)?;
+        keys.reverse();
+        Ok(PyTreeMapKeys::new(keys, IterGuard::new(&map)))
+    }
+
+    fn __contains__(&self, py: Python, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(PyTreeMap::live(self.map.bind(py))?
+            .lookup_value(key)?
+            .is_some())
+    }
+
+    fn __and__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "intersection", false)
+    }
+
+    fn __rand__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "intersection", true)
+    }
+
+    fn __or__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "union", false)
+    }
+
+    fn __ror__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "union", true)
+    }
+
+    fn __sub__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "difference", false)
+    }
+
+    fn __rsub__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "difference", true)
+    }
+
+    fn __xor__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "symmetric_difference", false)
+    }
+
+    fn __rxor__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "symmetric_difference", true)
+    }
+
+    fn __richcmp__(
+        slf: &Bound<'_, Self>,
+        other: &Bound<'_, PyAny>,
+        op: CompareOp,
+    ) -> PyResult<Py<PyAny>> {
+        set_eq(slf.as_any(), other, op)
+    }
+
+    /// Return True if the view has no keys in common with other.
+    fn isdisjoint(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        for item in other.try_iter()? {
+            if slf.contains(item?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("TreeMapKeysView(len={})", self.__len__(py)?))
+    }
+}
+
+/// Live view of a TreeMap's values (returned by .values())
+#[pyclass]
+pub struct PyTreeMapValuesView {
+    map: Py<PyTreeMap>,
+}
+
+impl PyTreeMapValuesView {
+    pub fn new(map: Py<PyTreeMap>) -> Self {
+        Self { map }
+    }
+}
+
+#[pymethods]
+impl PyTreeMapValuesView {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.map)
+    }
+
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        Ok(PyTreeMap::live(self.map.bind(py))?.entry_count())
+    }
+
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapValues> {
+        let map = PyTreeMap::live(self.map.bind(py))?;
+        Ok(PyTreeMapValues::new(
+            map.value_list(py)?,
+            IterGuard::new(&map),
+        ))
+    }
+
+    fn __reversed__(&self, py: Python) -> PyResult<PyTreeMapValues> {
+        let map = PyTreeMap::live(self.map.bind(py))?;
+        let mut values = map.value_list(py)?;
+        values.reverse();
+        Ok(PyTreeMapValues::new(values, IterGuard::new(&map)))
+    }
+
+    fn __contains__(&self, py: Python, value: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let values = PyTreeMap::live(self.map.bind(py))?.value_list(py)?;
+        for candidate in values {
+            if candidate.bind(py).is(value) || candidate.bind(py).eq(value)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("TreeMapValuesView(len={})", self.__len__(py)?))
+    }
+}
+
+/// Live view of a TreeMap's (key, value) pairs (returned by .items())
+#[pyclass]
+pub struct PyTreeMapItemsView {
+    map: Py<PyTreeMap>,
+}
+
+impl PyTreeMapItemsView {
+    pub fn new(map: Py<PyTreeMap>) -> Self {
+        Self { map }
+    }
+}
+
+#[pymethods]
+impl PyTreeMapItemsView {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.map)
+    }
+
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        Ok(PyTreeMap::live(self.map.bind(py))?.entry_count())
+    }
+
+    fn __iter__(&self, py: Python) -> PyResult<PyTreeMapItems> {
+        let map = PyTreeMap::live(self.map.bind(py))?;
+        Ok(PyTreeMapItems::new(
+            map.item_list(py)?,
+            IterGuard::new(&map),
+        ))
+    }
+
+    fn __reversed__(&self, py: Python) -> PyResult<PyTreeMapItems> {
+        let map = PyTreeMap::live(self.map.bind(py))?;
+        let mut items = map.item_list(py)?;
+        items.reverse();
+        Ok(PyTreeMapItems::new(items, IterGuard::new(&map)))
+    }
+
+    fn __contains__(&self, py: Python, item: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let Ok(pair) = item.cast::<PyTuple>() else {
+            return Ok(false);
+        };
+        if pair.len() != 2 {
+            return Ok(false);
+        }
+        let value = pair.get_item(1)?;
+        // Release the borrow before running Python comparisons
+        let stored = {
+            let map = PyTreeMap::live(self.map.bind(py))?;
+            map.lookup_value(&pair.get_item(0)?)?
+                .map(|v| map.value_out(py, v))
+                .transpose()?
+        };
+        match stored {
+            Some(stored) => Ok(stored.bind(py).is(&value) || stored.bind(py).eq(&value)?),
+            None => Ok(false),
+        }
+    }
+
+    fn __and__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "intersection", false)
+    }
+
+    fn __rand__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "intersection", true)
+    }
+
+    fn __or__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "union", false)
+    }
+
+    fn __ror__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "union", true)
+    }
+
+    fn __sub__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "difference", false)
+    }
+
+    fn __rsub__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "difference", true)
+    }
+
+    fn __xor__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "symmetric_difference", false)
+    }
+
+    fn __rxor__(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        set_op(slf.as_any(), other, "symmetric_difference", true)
+    }
+
+    fn __richcmp__(
+        slf: &Bound<'_, Self>,
+        other: &Bound<'_, PyAny>,
+        op: CompareOp,
+    ) -> PyResult<Py<PyAny>> {
+        set_eq(slf.as_any(), other, op)
+    }
+
+    /// Return True if the view has no items in common with other.
+    fn isdisjoint(slf: &Bound<'_, Self>, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        for item in other.try_iter()? {
+            if slf.contains(item?)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("TreeMapItemsView(len={})", self.__len__(py)?))
+    }
+}
+
+/// Positional view of a TreeMap's keys (returned by .iloc)
+#[pyclass]
+pub struct PyTreeMapILoc {
+    map: Py<PyTreeMap>,
+}
+
+impl PyTreeMapILoc {
+    pub fn new(map: Py<PyTreeMap>) -> Self {
+        Self { map }
+    }
+}
+
+/// The error for a position past either end of the tree.
+fn index_error() -> PyErr {
+    PyIndexError::new_err("TreeMap index out of range")
+}
+
+#[pymethods]
+impl PyTreeMapILoc {
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.map)
+    }
+
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        Ok(PyTreeMap::live(self.map.bind(py))?.entry_count())
+    }
+
+    fn __getitem__(&self, py: Python, index: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        let map = PyTreeMap::live(self.map.bind(py))?;
+        if let Ok(slice) = index.cast::<PySlice>() {
+            let keys = map
+                .keys_in_slice(slice)?
+                .iter()
+                .map(|key| map.decode_key(py, key))
+                .collect::<PyResult<Vec<_>>>()?;
+            return Ok(PyList::new(py, keys)?.into_any().unbind());
+        }
+        match map.key_at_position(py, index.extract()?) {
+            Some(key) => map.decode_key(py, key),
+            None => Err(index_error()),
+        }
+    }
+
+    fn __delitem__(&self, py: Python, index: &Bound<'_, PyAny>) -> PyResult<()> {
+        let mut map = self.map.borrow_mut(py);
+        map.purge_expired(py)?;
+        let keys = match index.cast::<PySlice>() {
+            Ok(slice) => map.keys_in_slice(slice)?,
+            Err(_) => match map.key_at_position(py, index.extract()?) {
+                Some(key) => vec![Box::from(key)],
+                None => return Err(index_error()),
+            },
+        };
+        for key in &keys {
+            map.remove_encoded(py, key)?;
+        }
+        Ok(())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("TreeMapILoc(len={})", self.__len__(py)?))
+    }
+}
@@ -1,5 +1,6 @@
 """Tests for advanced features: boundary operations and fuzzy search."""
 
+import pytest
 from blart import TreeMap
 
 
@@ -48,6 +49,16 @@ def test_last_empty():
     assert result is None
 
 
+def test_first_n_and_last_n():
+    """Test getting the n smallest and largest pairs in key order."""
+    tree = TreeMap({f"k{i}": i for i in range(10)})
+    assert tree.first_n(3) == [("k0", 0), ("k1", 1), ("k2", 2)]
+    assert tree.last_n(3) == [("k7", 7), ("k8", 8), ("k9", 9)]
+    assert tree.first_n(20) == tree.last_n(20) == list(tree.items())
+    assert tree.first_n(0) == tree.last_n(0) == []
+    assert TreeMap().first_n(5) == TreeMap().last_n(5) == []
+
+
 def test_pop_first():
     """Test removing and returning the first key-value pair."""
     tree = TreeMap()
@@ -102,6 +113,34 @@ def test_pop_last_empty_returns_none():
     assert result is None
 
 
+def test_closest_prefers_longer_shared_prefix():
+    """closest() picks floor or ceiling by shared prefix length."""
+    ids = TreeMap({"user-0100": 1, "user-0200": 2, "zone-1": 3})
+    assert ids.closest("user-0100") == ("user-0100", 1)
+    assert ids.closest("user-0150") == ("user-0100", 1)
+    assert ids.closest("user-02") == ("user-0200", 2)
+    assert ids.closest("user-0") == ("user-0100", 1)
+    assert ids.closest("a") == ("user-0100", 1)
+    assert ids.closest("zz") == ("zone-1", 3)
+    assert TreeMap().closest("a") is None
+
+
+def test_sample():
+    """sample() returns distinct entries, reproducibly with a seed."""
+    tree = TreeMap({f"k{i:02}": i for i in range(20)})
+    picked = tree.sample(5, seed=3)
+    assert len(picked) == 5
+    assert len({k for k, _ in picked}) == 5
+    assert all(tree[k] == v for k, v in picked)
+    assert tree.sample(5, seed=3) == picked
+    assert sorted(tree.sample(20)) == list(tree.items())
+    assert tree.sample(0) == []
+    with pytest.raises(ValueError):
+        tree.sample(21)
+    with pytest.raises(ValueError):
+        TreeMap().sample()
+
+
 def test_boundary_operations_single_item():
     """Test boundary operations with a single item."""
     tree = TreeMap()
@@ -287,3 +326,253 @@ def test_fuzzy_search_with_various_value_types():
             assert value == 42
         elif key == "key2":
             assert value == "string"
+
+
+def test_fuzzy_search_is_lazy_and_ordered():
+    """Fuzzy matches stream in key order, one next() at a time."""
+    tree = TreeMap({f"word{i:04d}": i for i in range(5000)})
+    it = tree.fuzzy_search("word0000", 2)
+    assert it.remaining() is None
+    assert next(it) == ("word0000", 0, 0)
+    assert next(it) == ("word0001", 1, 1)
+    keys = [key for key, _, _ in it]
+    assert keys == sorted(keys)
+    assert it.remaining() == 0
+
+
+def test_fuzzy_search_counts_characters():
+    """Distances for str keys count characters, not UTF-8 bytes."""
+    tree = TreeMap({"café": 1, "cafe": 2, "caffè": 3})
+    assert list(tree.fuzzy_search("cafe", 1)) == [("cafe", 2, 0), ("café", 1, 1)]
+
+
+def test_fuzzy_search_invalidated_by_mutation():
+    """A partly consumed fuzzy iterator raises if keys are added."""
+    tree = TreeMap({"test": 1, "text": 2})
+    it = tree.fuzzy_search("test", 1)
+    next(it)
+    tree["tent"] = 3
+    with pytest.raises(RuntimeError):
+        next(it)
+
+
+def test_fuzzy_search_max_results():
+    """max_results stops a key-ordered search after that many matches."""
+    tree = TreeMap({"hallo": 1, "hello": 2, "help": 3, "hullo": 4})
+    assert list(tree.fuzzy_search("hello", 2, max_results=2)) == [
+        ("hallo", 1, 1),
+        ("hello", 2, 0),
+    ]
+    assert list(tree.fuzzy_search("hello", 2, max_results=0)) == []
+
+
+def test_fuzzy_search_sort_by_distance():
+    """Ranked results come closest first, ties in key order."""
+    tree = TreeMap({"hallo": 1, "hello": 2, "help": 3, "hullo": 4, "world": 5})
+    results = list(tree.fuzzy_search("hello", 2, sort_by_distance=True))
+    assert results == [
+        ("hello", 2, 0),
+        ("hallo", 1, 1),
+        ("hullo", 4, 1),
+        ("help", 3, 2),
+    ]
+    it = tree.fuzzy_search("hello", 2, max_results=2, sort_by_distance=True)
+    assert it.remaining() == 2
+    assert list(it) == [("hello", 2, 0), ("hallo", 1, 1)]
+
+
+def test_fuzzy_search_top_k_on_large_tree():
+    """The k best candidates match a full sort of every match."""
+    tree = TreeMap({f"item{i:05d}": i for i in range(20000)})
+    every = sorted(tree.fuzzy_search("item12345", 2), key=lambda m: m[2])
+    best = list(tree.fuzzy_search("item12345", 2, max_results=10, sort_by_distance=True))
+    assert best == every[:10]
+
+
+def test_fuzzy_search_damerau_counts_swaps_once():
+    """The damerau metric treats an adjacent transposition as one edit."""
+    tree = TreeMap({"the": 1, "thin": 2, "tea": 3})
+    assert list(tree.fuzzy_search("teh", 1)) == [("tea", 3, 1)]
+    assert list(tree.fuzzy_search("teh", 1, metric="damerau")) == [
+        ("tea", 3, 1),
+        ("the", 1, 1),
+    ]
+
+
+def test_fuzzy_search_hamming_same_length_only():
+    """The hamming metric only matches keys of the query's length."""
+    tree = TreeMap({"AB12": 1, "AB13": 2, "AC1": 3, "AB2X3": 4, "XY99": 5})
+    assert list(tree.fuzzy_search("AB12", 1, metric="hamming")) == [
+        ("AB12", 1, 0),
+        ("AB13", 2, 1),
+    ]
+
+
+def test_fuzzy_search_unknown_metric():
+    """Unknown metric names raise ValueError."""
+    with pytest.raises(ValueError):
+        TreeMap({"a": 1}).fuzzy_search("a", 1, metric="jaro")
+
+
+def test_fuzzy_search_operation_costs():
+    """Per-operation costs change which keys are in range."""
+    tree = TreeMap({"cart": 1, "ct": 2, "cut": 3})
+    assert list(tree.fuzzy_search("cat", 1)) == [
+        ("cart", 1, 1),
+        ("ct", 2, 1),
+        ("cut", 3, 1),
+    ]
+    # Extra characters in the stored key are expensive, missing ones cheap
+    assert list(tree.fuzzy_search("cat", 1, insert_cost=2, delete_cost=0.5)) == [
+        ("ct", 2, 0.5),
+        ("cut", 3, 1.0),
+    ]
+
+
+def test_fuzzy_search_substitution_costs():
+    """A substitution table models keyboard-adjacent typos."""
+    tree = TreeMap({"cat": 1, "cut": 2, "cot": 3})
+    nearby = {("s", "a"): 0.25, ("y", "u"): 0.25}
+    results = list(tree.fuzzy_search("cst", 0.5, substitution_costs=nearby))
+    assert results == [("cat", 1, 0.25)]
+    ranked = list(
+        tree.fuzzy_search(
+            "cyt", 1, substitution_costs=nearby, sort_by_distance=True
+        )
+    )
+    assert ranked == [("cut", 2, 0.25), ("cat", 1, 1.0), ("cot", 3, 1.0)]
+
+
+def test_fuzzy_search_substitution_costs_bytes():
+    """Byte-keyed trees name substitution pairs by byte value."""
+    tree = TreeMap({b"ab": 1, b"ax": 2}, key_type=bytes)
+    costs = {(ord("b"), ord("x")): 0.5}
+    assert list(tree.fuzzy_search(b"ab", 0.5, substitution_costs=costs)) == [
+        (b"ab", 1, 0.0),
+        (b"ax", 2, 0.5),
+    ]
+
+
+def test_fuzzy_search_weighted_hamming():
+    """The hamming metric adds up substitution costs."""
+    tree = TreeMap({"AB12": 1, "AB17": 2})
+    assert list(
+        tree.fuzzy_search("AB11", 0.5, metric="hamming", substitute_cost=0.5)
+    ) == [("AB12", 1, 0.5), ("AB17", 2, 0.5)]
+
+
+def test_fuzzy_search_invalid_costs():
+    """Negative costs and malformed substitution pairs are rejected."""
+    tree = TreeMap({"a": 1})
+    with pytest.raises(ValueError):
+        tree.fuzzy_search("a", 1, insert_cost=-1)
+    with pytest.raises(ValueError):
+        tree.fuzzy_search("a", -0.5, delete_cost=1)
+    with pytest.raises(TypeError):
+        tree.fuzzy_search("a", 1, substitution_costs={("ab", "c"): 1})
+
+
+def test_fuzzy_prefix_matches_start_of_key():
+    """fuzzy_prefix compares the query with the closest prefix of each key."""
+    tree = TreeMap({"hello world": 1, "help": 2, "yellow": 3, "world": 4})
+    assert list(tree.fuzzy_search("helo", 1)) == [("help", 2, 1)]
+    assert list(tree.fuzzy_prefix("helo", 1)) == [
+        ("hello world", 1, 1),
+        ("help", 2, 1),
+    ]
+    assert list(tree.fuzzy_prefix("yel", 0)) == [("yellow", 3, 0)]
+    assert list(tree.fuzzy_prefix("", 0)) == list(
+        (k, v, 0) for k, v in tree.items()
+    )
+
+
+def test_fuzzy_prefix_ranked_and_limited():
+    """fuzzy_prefix supports the ranking options of fuzzy_search."""
+    tree = TreeMap({"apple pie": 1, "apply": 2, "maple": 3, "ample": 4})
+    ranked = list(tree.fuzzy_prefix("appl", 1, sort_by_distance=True))
+    assert ranked == [("apple pie", 1, 0), ("apply", 2, 0), ("ample", 4, 1)]
+    assert list(tree.fuzzy_prefix("appl", 1, max_results=1)) == [
+        ("ample", 4, 1)
+    ]
+
+
+def test_fuzzy_prefix_metrics():
+    """Metric and cost arguments apply to the prefix comparison."""
+    tree = TreeMap({"the end": 1, "then": 2})
+    assert list(tree.fuzzy_prefix("teh", 1, metric="damerau")) == [
+        ("the end", 1, 1),
+        ("then", 2, 1),
+    ]
+    assert list(tree.fuzzy_prefix("thx", 0.5, substitute_cost=0.5)) == [
+        ("the end", 1, 0.5),
+        ("then", 2, 0.5),
+    ]
+
+
+def test_suggest_ranks_by_distance_then_value():
+    """suggest orders by distance, then by value as a frequency."""
+    words = TreeMap({"cart": 5, "cast": 50, "cat": 1, "coat": 10, "dog": 99})
+    assert words.suggest("cat") == ["cat", "cast", "coat", "cart"]
+    assert words.suggest("cat", n=2) == ["cat", "cast"]
+    assert words.suggest("cxt", max_distance=1) == ["cat"]
+    assert words.suggest("zzzzzz") == []
+
+
+def test_suggest_non_numeric_values():
+    """Non-numeric values keep key order after numeric ones."""
+    tree = TreeMap({"bat": "x", "hat": 2, "mat": None, "rat": 7})
+    assert tree.suggest("cat") == ["rat", "hat", "bat", "mat"]
+
+
+def test_suggest_metric():
+    """suggest accepts the fuzzy search metrics."""
+    words = TreeMap({"the": 1, "tea": 1})
+    assert words.suggest("teh", max_distance=1) == ["tea"]
+    assert words.suggest("teh", max_distance=1, metric="damerau") == ["tea", "the"]
+    with pytest.raises(ValueError):
+        words.suggest("teh", metric="soundex")
+
+
+def test_fuzzy_search_many_matches_fuzzy_search():
+    """Batch results equal one fuzzy_search per query, in query order."""
+    tree = TreeMap({"alice": 1, "alicia": 2, "bob": 3, "bobby": 4})
+    queries = ["alise", "bobb", "zed", "alicia"]
+    for parallel in (True, False):
+        for kwargs in ({}, {"sort_by_distance": True, "max_results": 1}):
+            result = tree.fuzzy_search_many(queries, 1, parallel=parallel, **kwargs)
+            assert result == [list(tree.fuzzy_search(q, 1, **kwargs)) for q in queries]
+
+
+def test_fuzzy_search_many_options():
+    """Metrics and costs apply to every query; bad arguments raise."""
+    tree = TreeMap({"the": 1, "cat": 2})
+    assert tree.fuzzy_search_many(["teh", "act"], 1, metric="damerau") == [
+        [("the", 1, 1)],
+        [("cat", 2, 1)],
+    ]
+    assert tree.fuzzy_search_many(["cut"], 0.5, substitute_cost=0.5) == [
+        [("cat", 2, 0.5)]
+    ]
+    assert tree.fuzzy_search_many([], 1) == []
+    with pytest.raises(ValueError):
+        tree.fuzzy_search_many(["the"], 1, metric="jaro")
+
+
+def test_nearest_without_cutoff():
+    """nearest finds the k closest keys however far they are."""
+    tree = TreeMap({"apple": 1, "banana": 2, "cherry": 3})
+    assert tree.nearest("grape") == [("apple", 1, 4)]
+    assert tree.nearest("bandana", k=2) == [("banana", 2, 1), ("apple", 1, 6)]
+    assert [k for k, _, _ in tree.nearest("", k=10)] == ["apple", "banana", "cherry"]
+    assert tree.nearest("apple", k=0) == []
+    assert TreeMap().nearest("apple") == []
+
+
+def test_nearest_metric():
+    """Hamming only compares keys of the query's length."""
+    tree = TreeMap({"abc": 1, "abd": 2, "qrstuv": 3})
+    assert tree.nearest("abx", k=5, metric="hamming") == [
+        ("abc", 1, 1),
+        ("abd", 2, 1),
+    ]
+    assert tree.nearest("rqstuv", metric="damerau") == [("qrstuv", 3, 1)]
@@ -0,0 +1,69 @@
+"""Tests for building TreeMaps from Arrow data."""
+
+import pytest
+from blart import TreeMap
+
+pa = pytest.importorskip("pyarrow")
+
+
+def test_from_table():
+    table = pa.table({"word": ["b", "a", "c"], "count": [2, 1, 3]})
+    tree = TreeMap.from_arrow(table, "word", "count")
+    assert list(tree.items()) == [("a", 1), ("b", 2), ("c", 3)]
+
+
+def test_chunked_and_sliced_columns():
+    """Chunks and slices are read from the right place in their buffers."""
+    first = pa.array(["skip", "apple", "bob"]).slice(1)
+    table = pa.Table.from_arrays(
+        [pa.chunked_array([first, pa.array(["cat"])]), pa.array([1, 2, 3])],
+        names=["key", "value"],
+    )
+    tree = TreeMap.from_arrow(table, "key", "value")
+    assert list(tree.items()) == [("apple", 1), ("bob", 2), ("cat", 3)]
+
+
+def test_large_binary_keys_and_kwargs():
+    batch = pa.record_batch(
+        [pa.array([b"x", b"yy"], pa.large_binary()), pa.array([1, 2])],
+        names=["key", "value"],
+    )
+    tree = TreeMap.from_arrow(batch, "key", "value", key_type=bytes)
+    assert list(tree.items()) == [(b"x", 1), (b"yy", 2)]
+
+
+def test_other_key_types_are_converted():
+    table = pa.table({"id": [3, 1], "name": ["c", "a"]})
+    tree = TreeMap.from_arrow(table, "id", "name", key_type=int)
+    assert list(tree.items()) == [(1, "a"), (3, "c")]
+
+
+def test_null_keys_rejected():
+    table = pa.table({"key": ["a", None], "value": [1, 2]})
+    with pytest.raises(ValueError, match="nulls"):
+        TreeMap.from_arrow(table, "key", "value")
+
+
+def test_keys_to_arrow():
+    tree = TreeMap({"b": 2, "a": 1, "é": 3})
+    keys = tree.keys_to_arrow()
+    assert keys.type == pa.string()
+    assert keys.to_pylist() == ["a", "b", "é"]
+    raw = TreeMap({b"x": 1}, key_type=bytes).keys_to_arrow()
+    assert raw.type == pa.binary()
+    assert raw.to_pylist() == [b"x"]
+
+
+def test_keys_to_arrow_original_spelling_and_other_types():
+    tree = TreeMap({"Apple": 1, "bob": 2}, case_insensitive=True)
+    assert tree.keys_to_arrow().to_pylist() == ["Apple", "bob"]
+    ints = TreeMap({3: "c", 1: "a"}, key_type=int)
+    assert ints.keys_to_arrow().to_pylist() == [1, 3]
+
+
+def test_to_arrow_round_trip():
+    tree = TreeMap({"b": 2, "a": 1})
+    batch = tree.to_arrow()
+    assert batch.schema.names == ["key", "value"]
+    assert batch.to_pydict() == {"key": ["a", "b"], "value": [1, 2]}
+    assert dict(TreeMap.from_arrow(batch, "key", "value")) == dict(tree)
@@ -152,6 +152,28 @@ def test_repr():
     assert "TreeMap" in repr_str or "PyTreeMap" in repr_str
 
 
+def test_repr_previews_entries():
+    """Test __repr__ shows the first entries and the length."""
+    assert repr(TreeMap()) == "TreeMap({}, len=0)"
+    assert repr(TreeMap({"b": 2, "a": "x"})) == "TreeMap({'a': 'x', 'b': 2}, len=2)"
+    tree = TreeMap({f"k{i}": i for i in range(10)})
+    assert repr(tree) == "TreeMap({'k0': 0, 'k1': 1, 'k2': 2, 'k3': 3, 'k4': 4, ...}, len=10)"
+    previous = TreeMap.set_repr_limit(1)
+    try:
+        assert repr(tree) == "TreeMap({'k0': 0, ...}, len=10)"
+        TreeMap.set_repr_limit(0)
+        assert repr(tree) == "TreeMap({...}, len=10)"
+    finally:
+        assert TreeMap.set_repr_limit(previous) == 0
+
+
+def test_repr_of_self_containing_tree():
+    """Test __repr__ doesn't recurse into a tree holding itself."""
+    tree = TreeMap({"a": 1})
+    tree["self"] = tree
+    assert repr(tree) == "TreeMap({'a': 1, 'self': TreeMap(...)}, len=2)"
+
+
 def test_str():
     """Test __str__ returns readable representation."""
     tree = TreeMap({"apple": 1, "banana": 2})
@@ -214,3 +236,118 @@ def test_empty_string_key():
     tree[""] = "empty"
     assert tree[""] == "empty"
     assert "" in tree
+
+
+# default_factory
+def test_default_factory_inserts_missing_key():
+    """tree[key] should insert default_factory() for a missing key."""
+    tree = TreeMap(default_factory=list)
+    tree["a"].append(1)
+    tree["a"].append(2)
+    assert tree["a"] == [1, 2]
+    assert len(tree) == 1
+
+
+def test_default_factory_not_used_by_get_or_contains():
+    """get() and 'in' should not trigger the default_factory."""
+    tree = TreeMap(default_factory=int)
+    assert tree.get("missing") is None
+    assert "missing" not in tree
+    assert len(tree) == 0
+
+
+def test_default_factory_attribute():
+    """default_factory should be exposed as a read-only attribute."""
+    assert TreeMap().default_factory is None
+    assert TreeMap({"a": 1}, default_factory=list).default_factory is list
+
+
+# Case-insensitive mode
+def test_case_insensitive_lookup():
+    """Keys should match regardless of case in case-insensitive mode."""
+    tree = TreeMap(case_insensitive=True)
+    tree["Hello"] = 1
+    assert tree["hello"] == 1
+    assert tree["HELLO"] == 1
+    assert "hElLo" in tree
+    tree["HELLO"] = 2
+    assert len(tree) == 1
+    assert tree.get("hello") == 2
+
+
+def test_case_insensitive_preserves_original_key():
+    """Iteration should return keys as they were first inserted."""
+    tree = TreeMap({"Banana": 2, "apple": 1}, case_insensitive=True)
+    tree["BANANA"] = 3
+    assert list(tree.items()) == [("apple", 1), ("Banana", 3)]
+    assert tree.first() == ("apple", 1)
+    assert list(tree.prefix_iter("BAN")) == [("Banana", 3)]
+
+
+def test_case_insensitive_remove():
+    """Removing with a different case should remove the entry."""
+    tree = TreeMap({"Apple": 1}, case_insensitive=True)
+    assert tree.remove("APPLE") == 1
+    tree["aPPLE"] = 2
+    assert list(tree.keys()) == ["aPPLE"]
+
+
+def test_case_insensitive_uses_case_folding():
+    """Case folding should make keys that only lowercasing keeps apart equal."""
+    tree = TreeMap({"Straße": 1}, case_insensitive=True)
+    assert tree["STRASSE"] == 1
+    assert "strasse" in tree
+    tree["ΣΊΣΥΦΟΣ"] = 2
+    assert tree["σίσυφος"] == 2
+    assert len(tree) == 2
+    assert list(tree.keys()) == ["Straße", "ΣΊΣΥΦΟΣ"]
+
+
+def test_case_sensitive_by_default():
+    """Keys should be case-sensitive unless requested otherwise."""
+    tree = TreeMap({"Hello": 1})
+    assert tree.case_insensitive is False
+    assert "hello" not in tree
+
+
+# Unicode normalization
+def test_normalize_nfc_matches_decomposed_key():
+    """Composed and decomposed spellings should refer to the same entry."""
+    composed = "caf\u00e9"
+    decomposed = "cafe\u0301"
+    tree = TreeMap(normalize="NFC")
+    tree[decomposed] = 1
+    assert tree[composed] == 1
+    assert composed in tree
+    assert list(tree.keys()) == [composed]
+
+
+def test_normalize_nfkc_folds_compatibility_characters():
+    """NFKC should fold compatibility characters such as ligatures."""
+    tree = TreeMap({"\ufb01le": 1}, normalize="NFKC")
+    assert tree["file"] == 1
+    assert list(tree.prefix_iter("fi")) == [("file", 1)]
+
+
+@pytest.mark.parametrize("form", ["NFC", "NFD", "NFKC", "NFKD"])
+def test_normalize_matches_unicodedata(form):
+    """Keys should be stored in the same form unicodedata.normalize gives."""
+    import unicodedata
+
+    words = ["caf\u00e9", "cafe\u0301", "\ufb01le", "\u212b", "\uac00", "x\u0323\u0307"]
+    tree = TreeMap({word: i for i, word in enumerate(words)}, normalize=form)
+    expected = {unicodedata.normalize(form, word) for word in words}
+    assert set(tree.keys()) == expected
+
+
+def test_normalize_disabled_by_default():
+    """Keys should not be normalized unless requested."""
+    tree = TreeMap({"cafe\u0301": 1})
+    assert tree.normalize is None
+    assert "caf\u00e9" not in tree
+
+
+def test_normalize_invalid_form_raises_valueerror():
+    """An unknown normalization form should raise ValueError."""
+    with pytest.raises(ValueError):
+        TreeMap(normalize="NFX")
@@ -0,0 +1,292 @@
+"""Tests for pluggable key codecs."""
+
+import uuid
+from datetime import datetime, timedelta, timezone
+
+import pytest
+from blart import TreeMap
+
+
+class UInt32Codec:
+    """Order-preserving codec for non-negative integers below 2**32."""
+
+    def encode(self, key):
+        return key.to_bytes(4, "big")
+
+    def decode(self, data):
+        return int.from_bytes(data, "big")
+
+
+def test_default_key_type_is_str():
+    """Trees store str keys unless told otherwise."""
+    assert TreeMap().key_type == "str"
+    assert TreeMap(key_type=str).key_type == "str"
+
+
+def test_bytes_keys_round_trip():
+    """bytes keys are stored as-is and returned as bytes."""
+    tree = TreeMap({b"\x02b": 2, b"\x01a": 1}, key_type="bytes")
+    assert tree.key_type == "bytes"
+    assert tree[b"\x01a"] == 1
+    assert b"\x02b" in tree
+    assert list(tree.keys()) == [b"\x01a", b"\x02b"]
+    assert list(tree.prefix_iter(b"\x02")) == [(b"\x02b", 2)]
+
+
+def test_bytes_keys_allow_non_utf8():
+    """bytes keys may contain sequences that are not valid UTF-8."""
+    tree = TreeMap(key_type=bytes)
+    tree[b"\xff\xfe"] = "raw"
+    assert tree.first() == (b"\xff\xfe", "raw")
+
+
+def test_custom_codec_preserves_order():
+    """A custom codec decides both the stored bytes and iteration order."""
+    codec = UInt32Codec()
+    tree = TreeMap(key_type=codec)
+    for n in [300, 2, 70000, 1]:
+        tree[n] = str(n)
+    assert tree.key_type is codec
+    assert list(tree) == [1, 2, 300, 70000]
+    assert tree.pop_last() == (70000, "70000")
+
+
+def test_wrong_key_type_raises_type_error():
+    """Keys that don't match the key_type are rejected."""
+    with pytest.raises(TypeError):
+        TreeMap(key_type="bytes")["text"] = 1
+    with pytest.raises(TypeError):
+        TreeMap()[b"raw"] = 1
+
+
+def test_codec_must_return_bytes():
+    """A custom codec's encode() must return bytes."""
+
+    class BadCodec:
+        def encode(self, key):
+            return str(key)
+
+        def decode(self, data):
+            return data
+
+    with pytest.raises(TypeError):
+        TreeMap(key_type=BadCodec())[1] = "one"
+
+
+def test_invalid_key_type():
+    """Unknown codec names and objects without encode/decode are rejected."""
+    with pytest.raises(ValueError):
+        TreeMap(key_type="float")
+    with pytest.raises(TypeError):
+        TreeMap(key_type=42)
+
+
+def test_string_options_require_str_keys():
+    """case_insensitive and normalize only apply to str keys."""
+    with pytest.raises(ValueError):
+        TreeMap(key_type="bytes", case_insensitive=True)
+    with pytest.raises(ValueError):
+        TreeMap(key_type="bytes", normalize="NFC")
+
+
+def test_missing_bytes_key_error():
+    """KeyError carries the missing key itself."""
+    tree = TreeMap(key_type="bytes")
+    with pytest.raises(KeyError) as exc_info:
+        tree[b"missing"]
+    assert exc_info.value.args[0] == b"missing"
+
+
+def test_int_keys_numeric_order():
+    """Signed int keys iterate in numeric order, including negatives."""
+    tree = TreeMap(key_type="int")
+    for n in [10, -1, 2**40, -(2**63), 0, 9]:
+        tree[n] = n
+    assert list(tree) == [-(2**63), -1, 0, 9, 10, 2**40]
+    assert tree.first() == (-(2**63), -(2**63))
+    assert tree.last() == (2**40, 2**40)
+    assert tree.key_type == "int"
+    assert TreeMap(key_type=int).key_type == "int"
+
+
+def test_uint_keys():
+    """Unsigned int keys cover the full 64-bit range."""
+    tree = TreeMap({2**64 - 1: "max", 0: "min"}, key_type="uint")
+    assert list(tree.items()) == [(0, "min"), (2**64 - 1, "max")]
+    with pytest.raises(OverflowError):
+        tree[-1] = "negative"
+
+
+def test_int_keys_reject_out_of_range_and_non_int():
+    """Integer keys must fit in 64 bits and be actual ints."""
+    tree = TreeMap(key_type="int")
+    with pytest.raises(OverflowError):
+        tree[2**63] = 1
+    with pytest.raises(TypeError):
+        tree["1"] = 1
+    with pytest.raises(TypeError):
+        tree[1.5] = 1
+
+
+def test_tuple_keys_round_trip_and_order():
+    """Tuple keys sort field by field and decode back to tuples."""
+    tree = TreeMap(key_type="tuple")
+    keys = [
+        ("b", 1),
+        ("a", 10),
+        ("a", 2),
+        ("a", -5),
+        ("a",),
+        ("a", None),
+        ("a", 2, "x"),
+    ]
+    for i, key in enumerate(keys):
+        tree[key] = i
+    assert len(tree) == len(keys)
+    assert list(tree) == [
+        ("a",),
+        ("a", None),
+        ("a", -5),
+        ("a", 2),
+        ("a", 2, "x"),
+        ("a", 10),
+        ("b", 1),
+    ]
+    assert tree[("a", 2, "x")] == 6
+
+
+def test_tuple_key_field_types():
+    """Every supported field type survives a round trip."""
+    key = (None, True, False, -3, 2.5, "s\x00t", b"\x00\xff", ("nested", 1))
+    tree = TreeMap({key: "ok"}, key_type=tuple)
+    assert tree.first() == (key, "ok")
+
+
+def test_tuple_float_order():
+    """Floats in tuple keys sort numerically, including negatives."""
+    tree = TreeMap(key_type="tuple")
+    for x in [1.5, -2.0, 0.0, -0.5, 100.0]:
+        tree[(x,)] = x
+    assert [k[0] for k in tree] == [-2.0, -0.5, 0.0, 1.5, 100.0]
+
+
+def test_tuple_ints_and_floats_share_one_order():
+    """Ints and floats in tuple keys compare by value, as in Python."""
+    tree = TreeMap(key_type="tuple")
+    numbers = [2, 1.5, -1, -0.25, 10**18, 1e300, -1e300, float("inf"), float("-inf"), 0.5]
+    for x in numbers:
+        tree[(x,)] = x
+    assert [k[0] for k in tree] == sorted(numbers)
+    assert (2,) < (2.5,) and tree.first()[0] == (float("-inf"),)
+
+
+def test_tuple_equal_int_and_float_are_one_key():
+    """(1,) and (1.0,) are equal in Python, so they are the same key."""
+    tree = TreeMap({(1, "a"): "int"}, key_type="tuple")
+    tree[(1.0, "a")] = "float"
+    assert len(tree) == 1
+    assert tree[(1, "a")] == "float"
+    assert list(tree) == [(1, "a")]
+    assert list(tree.prefix_iter((1.0,))) == [((1, "a"), "float")]
+    nan = TreeMap({(float("nan"),): 1}, key_type="tuple")
+    assert next(iter(nan))[0] != next(iter(nan))[0]
+
+
+def test_tuple_prefix_scan():
+    """A shorter tuple scans every key starting with those fields."""
+    tree = TreeMap(key_type="tuple")
+    tree[(1, 100)] = "a"
+    tree[(1, 200)] = "b"
+    tree[(2, 100)] = "c"
+    tree[(1,)] = "root"
+    assert list(tree.prefix_iter((1,))) == [
+        ((1,), "root"),
+        ((1, 100), "a"),
+        ((1, 200), "b"),
+    ]
+    assert tree.get_prefix((2,)) == ((2, 100), "c")
+
+
+def test_tuple_string_prefix_is_not_field_prefix():
+    """A prefix field only matches whole field values."""
+    tree = TreeMap({("ab", 1): 1, ("a", 1): 2}, key_type="tuple")
+    assert list(tree.prefix_iter(("a",))) == [(("a", 1), 2)]
+
+
+def test_tuple_unsupported_field():
+    """Fields of unsupported types raise TypeError."""
+    with pytest.raises(TypeError):
+        TreeMap(key_type="tuple")[(object(),)] = 1
+
+
+def test_tuple_nesting_is_limited():
+    """Deeply nested tuple keys raise ValueError instead of crashing."""
+    def nested(depth):
+        key = ("leaf",)
+        for _ in range(depth):
+            key = (key,)
+        return key
+
+    tree = TreeMap({nested(500): 1}, key_type="tuple")
+    assert tree[nested(500)] == 1
+    assert list(tree) == [nested(500)]
+    with pytest.raises(ValueError, match="nest"):
+        tree[nested(5000)] = 2
+    with pytest.raises(ValueError, match="nest"):
+        nested(5000) in tree
+    assert len(tree) == 1
+
+
+def test_datetime_keys_chronological_order():
+    """Datetime keys iterate chronologically, including before 1970."""
+    tree = TreeMap(key_type="datetime")
+    stamps = [
+        datetime(2024, 3, 1, 12, 30, 0, 250),
+        datetime(1969, 12, 31, 23, 59, 59),
+        datetime(2024, 1, 1),
+    ]
+    for dt in stamps:
+        tree[dt] = dt.isoformat()
+    assert list(tree) == sorted(stamps)
+    assert tree[datetime(2024, 1, 1)] == "2024-01-01T00:00:00"
+    assert TreeMap(key_type=datetime).key_type == "datetime"
+
+
+def test_datetime_keys_aware_and_epoch():
+    """Aware datetimes and epoch seconds map to the same UTC instant."""
+    tree = TreeMap(key_type="datetime")
+    tz = timezone(timedelta(hours=2))
+    tree[datetime(2024, 1, 1, 2, 0, tzinfo=tz)] = "aware"
+    assert tree[datetime(2024, 1, 1, 0, 0)] == "aware"
+    assert tree[1704067200] == "aware"
+    assert tree[1704067200.0] == "aware"
+    assert list(tree) == [datetime(2024, 1, 1)]
+
+
+def test_datetime_keys_reject_other_types():
+    """Only datetimes and numbers are valid datetime keys."""
+    tree = TreeMap(key_type="datetime")
+    with pytest.raises(TypeError):
+        tree["2024-01-01"] = 1
+    with pytest.raises(TypeError):
+        tree[True] = 1
+
+
+def test_uuid_keys_round_trip():
+    """UUID keys are stored as raw bytes and decode back to equal UUIDs."""
+    ids = [uuid.uuid4() for _ in range(50)]
+    tree = TreeMap({u: str(u) for u in ids}, key_type="uuid")
+    assert tree.key_type == "uuid"
+    assert TreeMap(key_type=uuid.UUID).key_type == "uuid"
+    assert len(tree) == 50
+    assert list(tree) == sorted(ids, key=lambda u: u.bytes)
+    for u in ids:
+        assert tree[u] == str(u)
+        assert tree[uuid.UUID(str(u))] == str(u)
+
+
+def test_uuid_keys_reject_strings():
+    """UUID keys must be uuid.UUID instances."""
+    tree = TreeMap(key_type="uuid")
+    with pytest.raises(TypeError):
+        tree["12345678-1234-5678-1234-567812345678"] = 1
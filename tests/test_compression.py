@@ -0,0 +1,103 @@
+"""Tests for compressing large values with compress_values."""
+
+import pytest
+from blart import TreeMap
+
+TEXT = "lorem ipsum dolor sit amet " * 200
+DATA = bytes(range(256)) * 20
+
+
+def test_values_round_trip():
+    tree = TreeMap({"text": TEXT, "data": DATA}, compress_values="zlib")
+    assert tree.compress_values == "zlib"
+    assert TreeMap().compress_values is None
+    assert tree["text"] == TEXT
+    assert tree.get("data") == DATA
+    assert type(tree["data"]) is bytes
+    assert tree.to_dict() == {"data": DATA, "text": TEXT}
+    assert list(tree.values()) == [DATA, TEXT]
+    assert tree.pop("text") == TEXT
+    assert tree.compression_stats()["compressed_values"] == 1
+
+
+def test_small_and_other_values_are_not_compressed():
+    tree = TreeMap(compress_values="zlib", compress_threshold=100)
+    tree["short"] = "x" * 99
+    tree["list"] = ["x" * 1000]
+    tree["random"] = bytes(range(200))
+    assert tree.compression_stats()["compressed_values"] == 0
+    tree["long"] = "x" * 100
+    assert tree.compression_stats()["compressed_values"] == 1
+    assert tree.to_dict() == {
+        "list": ["x" * 1000],
+        "long": "x" * 100,
+        "random": bytes(range(200)),
+        "short": "x" * 99,
+    }
+
+
+def test_stats():
+    assert TreeMap().compression_stats() is None
+    tree = TreeMap(compress_values="zlib")
+    assert tree.compression_stats() == {
+        "codec": "zlib",
+        "threshold": 1024,
+        "compressed_values": 0,
+        "raw_bytes": 0,
+        "compressed_bytes": 0,
+        "ratio": 1.0,
+    }
+    tree.update({"a": TEXT, "b": "y" * 2000})
+    stats = tree.compression_stats()
+    assert stats["compressed_values"] == 2
+    assert stats["raw_bytes"] == len(TEXT) + 2000
+    assert stats["ratio"] == stats["raw_bytes"] / stats["compressed_bytes"]
+    assert stats["ratio"] > 10
+
+
+def test_updates_see_decompressed_values():
+    tree = TreeMap({"log": TEXT}, compress_values="zlib")
+    assert tree.apply("log", lambda log: log + "end") == TEXT + "end"
+    tree.retain(lambda key, value: value.endswith("end"))
+    assert len(tree) == 1
+    upper = tree.map_values(str.upper)
+    assert upper.compress_values == "zlib"
+    assert upper["log"] == (TEXT + "end").upper()
+    assert upper.compression_stats()["compressed_values"] == 1
+
+
+def test_observers_receive_decompressed_values():
+    tree = TreeMap({"a": TEXT}, compress_values="zlib")
+    changes = []
+    tree.on_change(lambda *change: changes.append(change))
+    tree["a"] = DATA
+    assert changes == [("overwrite", "a", TEXT, DATA)]
+
+
+def test_combines_with_store_pickled():
+    value = {"body": TEXT, "tags": ["a", "b"]}
+    tree = TreeMap({"a": value}, compress_values="zlib", store_pickled=True)
+    assert tree["a"] == value
+    assert tree.compression_stats()["compressed_values"] == 1
+
+
+def test_combines_with_intern_values():
+    tree = TreeMap(compress_values="zlib", intern_values=True)
+    tree["a"] = TEXT
+    tree["b"] = "".join(TEXT)
+    assert tree["a"] == tree["b"] == TEXT
+    assert tree.memory_usage(deep=True) < len(TEXT)
+
+
+def test_unknown_codec_is_rejected():
+    with pytest.raises(ValueError, match="compress_values"):
+        TreeMap(compress_values="lz4")
+
+
+def test_zstd():
+    tree = TreeMap({"a": TEXT, "b": "é€😀" * 500, "c": DATA}, compress_values="zstd")
+    assert tree["a"] == TEXT
+    assert tree["b"] == "é€😀" * 500
+    assert tree["c"] == DATA
+    assert tree.compression_stats()["compressed_values"] == 3
+    assert tree.compression_stats()["codec"] == "zstd"
@@ -0,0 +1,75 @@
+"""Tests for ConcurrentTreeMap."""
+
+import threading
+
+import pytest
+from blart import ConcurrentTreeMap
+
+
+def test_basic_operations():
+    """ConcurrentTreeMap supports the core mapping operations."""
+    index = ConcurrentTreeMap({"apple": 1, "apricot": 2})
+    index["banana"] = 3
+    index.insert("cherry", 4)
+    assert len(index) == 4
+    assert index["apple"] == 1
+    assert index.get("missing") is None
+    assert index.get("missing", 0) == 0
+    assert "banana" in index
+    assert index.remove("banana") == 3
+    del index["cherry"]
+    with pytest.raises(KeyError):
+        index["cherry"]
+    assert list(index) == ["apple", "apricot"]
+    assert index.items() == [("apple", 1), ("apricot", 2)]
+    assert index.values() == [1, 2]
+    assert repr(index) == "ConcurrentTreeMap(len=2)"
+    index.clear()
+    assert len(index) == 0
+
+
+def test_prefix_iter_and_key_types():
+    """Prefix scans and key codecs work as in TreeMap."""
+    index = ConcurrentTreeMap([("ap", 1), ("apx", 2), ("b", 3)])
+    assert list(index.prefix_iter("ap")) == [("apx", 2)]
+    numbers = ConcurrentTreeMap({3: "c", -1: "a"}, key_type="int")
+    assert numbers.key_type == "int"
+    assert numbers.keys() == [-1, 3]
+
+
+def test_iteration_is_a_snapshot():
+    """Writes during iteration do not affect a running iterator."""
+    index = ConcurrentTreeMap({"a1": 1, "b1": 2})
+    seen = []
+    for key in index:
+        seen.append(key)
+        index["c" + key] = 0
+    assert seen == ["a1", "b1"]
+    assert len(index) == 4
+
+
+def test_concurrent_readers_and_writers():
+    """Readers and writers can share one map without external locking."""
+    index = ConcurrentTreeMap({f"base_{i:04d}": i for i in range(1000)})
+    errors = []
+
+    def reader(_):
+        try:
+            for _ in range(50):
+                assert len(list(index.prefix_iter("base_00"))) == 100
+                assert index.get("base_0500") == 500
+        except Exception as exc:  # pragma: no cover - reported below
+            errors.append(exc)
+
+    def writer(n):
+        for i in range(200):
+            index[f"w{n}_{i:03d}"] = i
+
+    threads = [threading.Thread(target=reader, args=(i,)) for i in range(4)]
+    threads += [threading.Thread(target=writer, args=(i,)) for i in range(4)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    assert errors == []
+    assert len(index) == 1000 + 4 * 200
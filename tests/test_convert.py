@@ -0,0 +1,62 @@
+"""Tests for converting TreeMaps to and from other structures."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_to_nested_dict():
+    """Keys are exploded at the separator into nested dicts."""
+    config = TreeMap({"db.host": "localhost", "db.port": 5432, "debug": True})
+    assert config.to_nested_dict() == {
+        "db": {"host": "localhost", "port": 5432},
+        "debug": True,
+    }
+    assert TreeMap({"a:b:c": 1}).to_nested_dict(":") == {"a": {"b": {"c": 1}}}
+    assert TreeMap().to_nested_dict() == {}
+
+
+def test_to_nested_dict_key_types():
+    """Bytes trees split bytes keys; other key types are rejected."""
+    assert TreeMap({b"a.b": 1}, key_type=bytes).to_nested_dict() == {b"a": {b"b": 1}}
+    with pytest.raises(TypeError):
+        TreeMap({1: 1}, key_type="int").to_nested_dict()
+    with pytest.raises(ValueError):
+        TreeMap({"a": 1}).to_nested_dict("")
+
+
+def test_from_nested_dict_round_trip():
+    """from_nested_dict joins the keys of nested dicts."""
+    nested = {"db": {"host": "localhost", "port": 5432, "options": {}}, "debug": True}
+    tree = TreeMap.from_nested_dict(nested)
+    assert list(tree.items()) == [
+        ("db.host", "localhost"),
+        ("db.options", {}),
+        ("db.port", 5432),
+        ("debug", True),
+    ]
+    assert tree.to_nested_dict() == nested
+
+
+def test_from_nested_dict_options():
+    """A custom separator and constructor options are supported."""
+    tree = TreeMap.from_nested_dict({"A": {"b": 1}}, "/", case_insensitive=True)
+    assert tree.case_insensitive
+    assert tree["a/B"] == 1
+    assert list(tree) == ["A/b"]
+
+
+def test_to_dict_and_to_list():
+    """Copies hold every entry in key order and don't track later changes."""
+    tree = TreeMap({"c": 3, "a": 1, "b": 2})
+    copy = tree.to_dict()
+    pairs = tree.to_list()
+    assert list(copy.items()) == [("a", 1), ("b", 2), ("c", 3)]
+    assert pairs == [("a", 1), ("b", 2), ("c", 3)]
+    tree["d"] = 4
+    assert "d" not in copy and len(pairs) == 3
+    assert TreeMap().to_dict() == {} and TreeMap().to_list() == []
+
+
+def test_to_dict_decodes_keys():
+    assert TreeMap({2: "b", 1: "a"}, key_type=int).to_list() == [(1, "a"), (2, "b")]
+    assert TreeMap({"Apple": 1}, case_insensitive=True).to_dict() == {"Apple": 1}
@@ -0,0 +1,87 @@
+"""Tests for TreeCounter."""
+
+from collections import Counter
+
+import pytest
+from blart import TreeCounter
+
+
+def test_counting():
+    """Keys are counted like collections.Counter, with 0 for missing keys."""
+    words = TreeCounter(["apple", "apricot", "apple", "banana"])
+    assert words["apple"] == 2
+    assert words["missing"] == 0
+    assert "missing" not in words
+    assert words.increment("banana", 4) == 5
+    words.update(["apple"], cherry=2)
+    words.update({"apricot": 3})
+    assert words.items() == [("apple", 3), ("apricot", 4), ("banana", 5), ("cherry", 2)]
+    words["cherry"] = 7
+    del words["apricot"]
+    assert list(words) == ["apple", "banana", "cherry"]
+    assert words.values() == [3, 5, 7]
+    assert len(words) == 3
+    assert repr(words) == "TreeCounter(len=3)"
+
+
+def test_keys_prefixing_each_other_are_counted_apart():
+    """A key that prefixes another keeps its own count."""
+    words = TreeCounter(["a", "b", "a", "ab"])
+    assert words.items() == [("a", 2), ("ab", 1), ("b", 1)]
+    assert words.total() == 4
+    assert words.total(prefix="a") == 3
+    assert list(words.prefix_iter("ab")) == [("ab", 1)]
+    assert words.most_common(1) == [("a", 2)]
+    words["a\x00"] = 5
+    assert list(words) == ["a", "a\x00", "ab", "b"]
+    assert dict(words) == dict(Counter(["a", "b", "a", "ab"]), **{"a\x00": 5})
+
+
+def test_most_common():
+    """most_common() lists the highest counts first, ties in key order."""
+    hits = TreeCounter({"/a": 5, "/b": 9, "/c": 5, "x": 20})
+    assert hits.most_common() == [("x", 20), ("/b", 9), ("/a", 5), ("/c", 5)]
+    assert hits.most_common(3) == [("x", 20), ("/b", 9), ("/a", 5)]
+    assert hits.most_common(0) == []
+    assert hits.most_common(2, prefix="/") == [("/b", 9), ("/a", 5)]
+    assert hits.most_common(prefix="y") == []
+
+
+def test_total():
+    """total() sums all counts, or those under a prefix."""
+    hits = TreeCounter({"/a": 5, "/b": 9, "x": 20})
+    assert hits.total() == 34
+    assert hits.total(prefix="/") == 14
+    assert list(hits.prefix_iter("/")) == [("/a", 5), ("/b", 9)]
+
+
+def test_arithmetic():
+    """+, - and their in-place forms keep only positive counts, as Counter does."""
+    a = TreeCounter({"x": 3, "y": 1})
+    b = TreeCounter({"x": 1, "y": 2, "z": 4})
+    expected_sum = Counter({"x": 3, "y": 1}) + Counter({"x": 1, "y": 2, "z": 4})
+    expected_diff = Counter({"x": 3, "y": 1}) - Counter({"x": 1, "y": 2, "z": 4})
+    assert dict((a + b).items()) == dict(expected_sum)
+    assert dict((a - b).items()) == dict(expected_diff)
+    a -= b
+    assert a.items() == [("x", 2)]
+    a += a
+    assert a.items() == [("x", 4)]
+
+
+def test_subtract_keeps_non_positive_counts():
+    """subtract() keeps keys whose count drops to zero or below."""
+    counter = TreeCounter({"a": 1})
+    counter.subtract(["a", "b"])
+    assert counter.items() == [("a", 0), ("b", -1)]
+
+
+def test_errors():
+    """Counts are 64-bit, and counters with other key types cannot be combined."""
+    counter = TreeCounter({"a": 1})
+    with pytest.raises(OverflowError):
+        counter.increment("a", 2**63 - 1)
+    with pytest.raises(TypeError):
+        counter + TreeCounter(key_type="int")
+    with pytest.raises(KeyError):
+        del counter["missing"]
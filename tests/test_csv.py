@@ -0,0 +1,82 @@
+"""Tests for loading TreeMaps from CSV and TSV files."""
+
+import csv
+import io
+
+import pytest
+from blart import TreeMap
+
+
+def write(tmp_path, text, name="data.csv"):
+    path = tmp_path / name
+    path.write_bytes(text.encode())
+    return path
+
+
+def test_columns_by_name(tmp_path):
+    path = write(tmp_path, "word,count\nb,2\na,1\n")
+    tree = TreeMap.from_csv(path, "word", "count")
+    assert list(tree.items()) == [("a", "1"), ("b", "2")]
+
+
+def test_quoting_and_line_endings(tmp_path):
+    """Quoted fields hold delimiters, quotes and newlines; CRLF and blank lines are fine."""
+    text = '\ufeffkey,note\r\nb,"x, ""y"""\r\n\r\na,"multi\nline"\n'
+    tree = TreeMap.from_csv(write(tmp_path, text), "key", "note")
+    assert dict(tree) == {"a": "multi\nline", "b": 'x, "y"'}
+
+
+def test_tsv_by_position(tmp_path):
+    path = write(tmp_path, "x\t1\ny\t2", "data.tsv")
+    tree = TreeMap.from_csv(str(path), 0, 1, delimiter="\t", header=False)
+    assert list(tree.items()) == [("x", "1"), ("y", "2")]
+
+
+def test_constructor_options(tmp_path):
+    path = write(tmp_path, "k,v\nApple,1\n")
+    tree = TreeMap.from_csv(path, "k", "v", case_insensitive=True)
+    assert tree["APPLE"] == "1"
+
+
+@pytest.mark.parametrize(
+    "text",
+    [
+        'k,v\na,"one\ntwo"\nb,"x\r\ny"\n',
+        'k,v\na,"say ""hi"""\nb,""""\nc,""\n',
+        "k,v\r\na,1\r\nb,2\r\n",
+        "k,v\ra,1\rb,2",
+        "k,v,w\na,1\nb,2,3,4\nc,3,\n",
+        'k,v\n"a"b,1\nc"d,2\n',
+        'k,v\na,"unterminated\n',
+    ],
+)
+def test_parsing_matches_the_csv_module(tmp_path, text):
+    """Quoted newlines, doubled quotes, CRLF and ragged rows read as csv.reader() reads them."""
+    header, *rows = csv.reader(io.StringIO(text, newline=""))
+    expected = {row[0]: row[1] for row in rows if row}
+    tree = TreeMap.from_csv(write(tmp_path, text), "k", "v")
+    assert dict(tree) == expected
+    assert list(tree) == sorted(expected)
+
+
+def test_parse_errors_give_line_numbers(tmp_path):
+    with pytest.raises(ValueError, match="line 3: expected at least 2 fields"):
+        TreeMap.from_csv(write(tmp_path, "k,v\na,1\nb\n"), "k", "v")
+    with pytest.raises(ValueError, match="line 4: expected at least 2 fields"):
+        TreeMap.from_csv(write(tmp_path, 'k,v\na,"1\n2"\nb\n'), "k", "v")
+    with pytest.raises(ValueError, match="line 2: field is not valid UTF-8"):
+        path = tmp_path / "bad.csv"
+        path.write_bytes(b"k,v\na,\xff\n")
+        TreeMap.from_csv(path, "k", "v")
+
+
+def test_bad_arguments(tmp_path):
+    path = write(tmp_path, "k,v\na,1\n")
+    with pytest.raises(ValueError, match="no column named"):
+        TreeMap.from_csv(path, "missing", "v")
+    with pytest.raises(ValueError, match="no header"):
+        TreeMap.from_csv(path, "k", "v", header=False)
+    with pytest.raises(ValueError, match="delimiter"):
+        TreeMap.from_csv(path, "k", "v", delimiter=";;")
+    with pytest.raises(OSError):
+        TreeMap.from_csv(tmp_path / "missing.csv", 0, 1)
@@ -0,0 +1,60 @@
+"""Tests for the decode policy of str keys that are not valid UTF-8."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_default_policy_is_strict():
+    """str maps default to the strict policy; other key types have none."""
+    assert TreeMap().errors == "strict"
+    assert TreeMap(key_type="bytes").errors is None
+
+
+def test_surrogateescape_round_trip():
+    """Escaped bytes are stored raw and come back unchanged."""
+    key = b"caf\xe9".decode("utf-8", "surrogateescape")
+    tree = TreeMap(errors="surrogateescape")
+    tree[key] = 1
+    tree["plain"] = 2
+    assert list(tree) == [key, "plain"]
+    assert tree[key] == 1
+    assert list(tree)[0].encode("utf-8", "surrogateescape") == b"caf\xe9"
+
+
+def test_bytes_policy():
+    """With errors="bytes", invalid keys come back as bytes."""
+    tree = TreeMap(errors="bytes")
+    tree[b"\xff\xfe"] = "raw"
+    tree[b"text"] = "valid"
+    tree["other"] = "str"
+    assert list(tree.items()) == [
+        ("other", "str"),
+        ("text", "valid"),
+        (b"\xff\xfe", "raw"),
+    ]
+    assert tree[b"\xff\xfe"] == "raw"
+
+
+def test_strict_rejects_surrogates():
+    """Strict maps refuse keys that can't be encoded as UTF-8."""
+    tree = TreeMap()
+    with pytest.raises(UnicodeEncodeError):
+        tree["\udce9"] = 1
+    with pytest.raises(TypeError):
+        tree[b"raw"] = 1
+
+
+def test_replace_only_affects_decoding():
+    """The replace policy still requires keys that encode as UTF-8."""
+    tree = TreeMap(errors="replace")
+    assert tree.errors == "replace"
+    with pytest.raises(UnicodeEncodeError):
+        tree["bad\udcff"] = 1
+
+
+def test_invalid_errors_option():
+    """Unknown policies and non-str key types are rejected."""
+    with pytest.raises(ValueError):
+        TreeMap(errors="ignore")
+    with pytest.raises(ValueError):
+        TreeMap(key_type="int", errors="bytes")
@@ -300,3 +300,33 @@ def test_large_number_of_keys():
             break
 
     assert count > 100
+
+
+def test_keyerror_did_you_mean():
+    """With did_you_mean, KeyError names the closest stored keys."""
+    tree = TreeMap({"color": 1, "colour": 2, "name": 3}, did_you_mean=True)
+    with pytest.raises(KeyError, match=r"'colr' \(did you mean 'color'\?\)"):
+        tree["colr"]
+    with pytest.raises(KeyError, match=r"did you mean 'colour' or 'color'\?"):
+        tree.remove("colur")
+    with pytest.raises(KeyError, match=r"'nmae' \(did you mean 'name'\?\)"):
+        tree.pop("nmae")
+    # No close key: the plain KeyError
+    with pytest.raises(KeyError) as exc_info:
+        del tree["unrelated"]
+    assert exc_info.value.args == ("unrelated",)
+
+
+def test_keyerror_did_you_mean_toggle():
+    """The did_you_mean flag is off by default and can be switched."""
+    tree = TreeMap({"color": 1})
+    assert tree.did_you_mean is False
+    with pytest.raises(KeyError) as exc_info:
+        tree["colr"]
+    assert exc_info.value.args == ("colr",)
+    tree.did_you_mean = True
+    with pytest.raises(KeyError, match="did you mean 'color'"):
+        tree["colr"]
+    # Other lookups are unaffected
+    assert tree.get("colr") is None
+    assert tree.pop("colr", 0) == 0
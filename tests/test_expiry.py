@@ -0,0 +1,162 @@
+"""Tests for entries inserted with a ttl."""
+
+import time
+
+import pytest
+from blart import TreeMap
+
+
+def test_expired_entries_read_as_missing():
+    """Lookups treat an entry as missing once its ttl has run out."""
+    tree = TreeMap({"permanent": 0})
+    tree.insert("session:1", "token", ttl=0.05)
+    tree.insert("session:2", "token", ttl=60)
+    assert tree["session:1"] == "token"
+    time.sleep(0.1)
+    assert tree.get("session:1") is None
+    assert "session:1" not in tree
+    assert tree.get("session:2") == "token"
+    with pytest.raises(KeyError):
+        tree["session:1"]
+    assert list(tree) == ["permanent", "session:2"]
+
+
+def test_expire_sweeps_expired_entries():
+    """expire() removes expired entries and reports how many."""
+    tree = TreeMap({"permanent": 0})
+    tree.insert("a", 1, ttl=0.01)
+    tree.insert("b", 2, ttl=0.01)
+    tree.insert("c", 3, ttl=60)
+    time.sleep(0.05)
+    assert tree.expire() == 2
+    assert list(tree) == ["c", "permanent"]
+    assert tree.expire() == 0
+
+
+def test_new_value_without_ttl_is_permanent():
+    """Storing a new value without a ttl clears the old deadline."""
+    tree = TreeMap()
+    tree.insert("a", 1, ttl=0.01)
+    tree["a"] = 2
+    time.sleep(0.05)
+    assert tree.expire() == 0
+    assert tree["a"] == 2
+
+
+def test_invalid_ttl():
+    """A ttl must be a positive number of seconds."""
+    tree = TreeMap()
+    for ttl in (0, -1, float("nan")):
+        with pytest.raises(ValueError):
+            tree.insert("a", 1, ttl=ttl)
+    assert len(tree) == 0
+
+
+def expired_tree():
+    """A tree holding "ab:1" past its ttl, "ab:2" within it, and "b"."""
+    tree = TreeMap({"b": 0})
+    tree.insert("ab:1", 1, ttl=0.01)
+    tree.insert("ab:2", 2, ttl=60)
+    time.sleep(0.05)
+    return tree
+
+
+def test_expired_entries_are_not_counted():
+    """len() leaves out expired entries without an expire() call."""
+    tree = expired_tree()
+    assert len(tree) == 2
+    assert len(tree.keys()) == 2
+    assert tree.expire() == 0
+
+
+def test_expired_entries_are_not_iterated():
+    """Iteration, keys(), values() and items() skip expired entries."""
+    tree = expired_tree()
+    assert list(tree) == ["ab:2", "b"]
+    assert list(tree.keys()) == ["ab:2", "b"]
+    assert list(tree.values()) == [2, 0]
+    assert list(tree.items()) == [("ab:2", 2), ("b", 0)]
+    assert list(tree.items(offset=0, limit=1)) == [("ab:2", 2)]
+
+
+def test_expired_entries_are_not_in_prefix_queries():
+    """prefix_iter() and count_prefix() skip expired entries."""
+    tree = expired_tree()
+    assert list(tree.prefix_iter("ab")) == [("ab:2", 2)]
+    assert tree.count_prefix("ab") == 1
+
+
+def test_expired_entries_are_not_first():
+    """first() and last() skip expired entries."""
+    tree = TreeMap({"z": 0})
+    tree.insert("a", 1, ttl=0.01)
+    time.sleep(0.05)
+    assert tree.first() == ("z", 0)
+    assert tree.last() == ("z", 0)
+
+
+def test_expired_entries_are_not_exported():
+    """to_dict() and to_list() leave out expired entries."""
+    tree = expired_tree()
+    assert tree.to_dict() == {"ab:2": 2, "b": 0}
+    assert tree.to_list() == [("ab:2", 2), ("b", 0)]
+
+
+def test_pop_treats_expired_entries_as_missing():
+    """pop() of an expired key returns the default or raises KeyError."""
+    tree = expired_tree()
+    assert tree.pop("ab:1", None) is None
+    with pytest.raises(KeyError):
+        tree.pop("ab:1")
+    assert tree.popitem() == ("b", 0)
+
+
+def test_try_insert_replaces_expired_entries():
+    """try_insert() stores over an expired entry, without a ttl."""
+    tree = expired_tree()
+    assert tree.try_insert("ab:1", 10) is True
+    assert tree["ab:1"] == 10
+    time.sleep(0.05)
+    assert tree["ab:1"] == 10
+
+
+def test_increment_starts_over_after_expiry():
+    """increment() counts from zero once the old count has expired."""
+    tree = TreeMap()
+    tree.insert("hits", 5, ttl=0.01)
+    time.sleep(0.05)
+    assert tree.increment("hits") == 1
+
+
+def test_get_or_insert_and_setdefault_replace_expired_entries():
+    """get_or_insert() and setdefault() return the new value, not the
+    expired one."""
+    tree = expired_tree()
+    assert tree.get_or_insert("ab:1", lambda: 10) == 10
+    tree.insert("c", 3, ttl=0.01)
+    time.sleep(0.05)
+    assert tree.setdefault("c", 30) == 30
+    assert tree.to_dict() == {"ab:1": 10, "ab:2": 2, "b": 0, "c": 30}
+
+
+def test_expiry_is_reported_as_eviction():
+    """Expired entries swept by a read are reported to observers."""
+    tree = TreeMap()
+    changes = []
+    tree.on_change(lambda change, key, old, new: changes.append((change, key, old)))
+    tree.insert("a", 1, ttl=0.01)
+    time.sleep(0.05)
+    assert len(tree) == 0
+    assert changes[-1] == ("evict", "a", 1)
+
+
+def test_expiry_does_not_break_iteration():
+    """A read sweeping expired entries out during iteration is not a
+    change made during iteration."""
+    tree = expired_tree()
+    tree.insert("c", 3, ttl=0.05)
+    lengths = []
+    for _ in tree:
+        time.sleep(0.1)
+        lengths.append(len(tree))
+    assert lengths == [2, 2, 2]
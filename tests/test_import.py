@@ -21,3 +21,23 @@ def test_can_instantiate_treemap():
 
     tree = TreeMap()
     assert tree is not None
+
+
+def test_classes_can_be_parameterized():
+    """Test that the classes can be subscripted in type annotations."""
+    import types
+    import typing
+
+    from blart import ConcurrentTreeMap, TreeCounter, TreeMap, TreeMultiMap
+
+    alias = TreeMap[str, int]
+    assert isinstance(alias, types.GenericAlias)
+    assert typing.get_origin(alias) is TreeMap
+    assert typing.get_args(alias) == (str, int)
+    assert typing.get_args(ConcurrentTreeMap[str, int]) == (str, int)
+    assert typing.get_args(TreeMultiMap[bytes, float]) == (bytes, float)
+    assert typing.get_args(TreeCounter[str]) == (str,)
+
+    tree = alias({"a": 1})
+    assert type(tree) is TreeMap
+    assert tree["a"] == 1
@@ -0,0 +1,49 @@
+"""Tests for sharing equal values with intern_values."""
+
+from blart import TreeMap
+
+
+def label(i):
+    # Built at runtime so equal labels are separate objects
+    return "".join(["category-", str(i % 3)])
+
+
+def test_equal_values_share_one_object():
+    tree = TreeMap(intern_values=True)
+    assert tree.intern_values
+    assert not TreeMap().intern_values
+    for i in range(300):
+        tree[f"key{i:03}"] = label(i)
+    assert len({id(value) for value in tree.values()}) == 3
+    tree.update({"other": label(0)})
+    assert tree["other"] is tree["key000"]
+    assert tree.setdefault("more", label(1)) is tree["key001"]
+
+
+def test_tuples_are_interned():
+    tree = TreeMap({"a": (1, label(0)), "b": (1, label(0))}, intern_values=True)
+    assert tree["a"] is tree["b"]
+
+
+def test_equal_values_of_different_types_stay_distinct():
+    tree = TreeMap(intern_values=True)
+    tree.update({"a": 1, "b": 1.0, "c": True, "d": 0.0, "e": -0.0, "f": (1,), "g": (True,)})
+    assert [type(value) for value in tree.values()] == [int, float, bool, float, float, tuple, tuple]
+    assert str(tree["e"]) == "-0.0"
+    assert tree["g"] == (True,) and tree["g"][0] is True
+
+
+def test_mutable_values_are_not_shared():
+    tree = TreeMap(intern_values=True)
+    tree["a"] = [1]
+    tree["b"] = [1]
+    assert tree["a"] is not tree["b"]
+    tree["a"].append(2)
+    assert tree["b"] == [1]
+
+
+def test_map_values_interns_into_new_tree():
+    tree = TreeMap({"a": 1, "b": 2}, intern_values=True)
+    mapped = tree.map_values(lambda value: label(value - value))
+    assert mapped.intern_values
+    assert mapped["a"] is mapped["b"]
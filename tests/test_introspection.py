@@ -0,0 +1,172 @@
+"""Tests for tree structure introspection."""
+
+import io
+import ipaddress
+import re
+import uuid
+from datetime import datetime
+
+import pytest
+from blart import TreeMap
+
+
+def test_stats_empty_and_single():
+    """An empty tree has no nodes; a single key is a lone leaf."""
+    stats = TreeMap().stats()
+    assert stats["leaves"] == 0
+    assert stats["inner_nodes"] == 0
+    assert stats["max_depth"] == 0
+
+    stats = TreeMap({"only": 1}).stats()
+    assert stats["leaves"] == 1
+    assert stats["inner_nodes"] == 0
+    assert stats["key_bytes"] == 4
+
+
+def test_stats_branching():
+    """Inner nodes appear where keys diverge."""
+    tree = TreeMap({"apple": 1, "apricot": 2, "banana": 3})
+    stats = tree.stats()
+    assert stats["leaves"] == 3
+    assert stats["node4"] == 2
+    assert stats["inner_nodes"] == 2
+    assert stats["max_depth"] == 2
+    assert stats["avg_depth"] == 5 / 3
+    assert stats["prefix_bytes"] == 1
+
+
+def test_stats_node_types_and_compression():
+    """Wide fan-out uses bigger nodes; shared prefixes are compressed."""
+    tree = TreeMap({f"key_{i:05d}": i for i in range(10000)})
+    stats = tree.stats()
+    assert stats["node16"] == 1111
+    assert stats["node4"] + stats["node48"] + stats["node256"] == 0
+    assert stats["max_depth"] == 4
+    assert stats["key_bytes"] == 10000 * len("key_00000")
+    # "key_0" is shared by every key and stored once in the root
+    assert stats["prefix_bytes"] == 5
+    assert stats["prefix_savings"] > 0
+
+
+def test_to_dot_structure():
+    """The DOT graph shows node types, prefixes, edge bytes and leaf keys."""
+    dot = TreeMap({"apple": 1, "apricot": 2, "banana": 3}).to_dot()
+    assert dot.startswith("digraph TreeMap {")
+    assert dot.rstrip().endswith("}")
+    assert dot.count("shape=box") == 2
+    assert dot.count("shape=ellipse") == 3
+    assert "prefix: p" in dot
+    assert "label=\"'apricot'\"" in dot
+    assert '[label="r"]' in dot
+
+
+def test_to_dot_shows_actual_nodes():
+    """Node types come from the tree itself, so nodes that kept their size
+    after removals are drawn as they are."""
+    tree = TreeMap({f"key_{i:03d}": i for i in range(300)})
+    for i in range(300):
+        if i % 10 > 2:
+            del tree[f"key_{i:03d}"]
+    dot = tree.to_dot()
+    stats = tree.stats()
+    for kind in ("node4", "node16", "node48", "node256"):
+        assert dot.count(f'label="N{kind[1:]}') == stats[kind]
+    assert stats["node16"] > 0
+
+
+def test_to_dot_escapes_labels():
+    """Quotes and non-printable bytes are escaped."""
+    dot = TreeMap({'say "hi"': 1, "\x01": 2}).to_dot()
+    assert '\\"hi\\"' in dot
+    assert "\\\\x01" in dot
+
+
+def test_to_dot_max_nodes():
+    """Unexpanded subtrees are summarized instead of drawn."""
+    tree = TreeMap({f"key_{i:03d}": i for i in range(300)})
+    dot = tree.to_dot(max_nodes=5)
+    assert dot.count("shape=box") == 5
+    assert "more keys" in dot
+    assert TreeMap().to_dot() == 'digraph TreeMap {\n    node [fontname="monospace"];\n}\n'
+
+
+def test_to_dot_writes_file(tmp_path):
+    """With path=, the graph is written to a file."""
+    target = tmp_path / "tree.dot"
+    assert TreeMap({"a": 1}).to_dot(path=target) is None
+    assert target.read_text().startswith("digraph")
+
+
+def test_pretty_print_structure(capsys):
+    """pretty_print() draws nodes, prefixes, branch bytes and leaves."""
+    TreeMap({"apple": 1, "apricot": 2, "banana": 3}).pretty_print()
+    assert capsys.readouterr().out == (
+        "Node4\n"
+        '|-- a: Node4 prefix="p"\n'
+        "|   |-- p: 'apple'\n"
+        "|   `-- r: 'apricot'\n"
+        "`-- b: 'banana'\n"
+    )
+    TreeMap().pretty_print()
+    assert capsys.readouterr().out == "(empty)\n"
+
+
+def test_pretty_print_max_depth_and_file():
+    """Levels below max_depth are summarized, and file= redirects output."""
+    out = io.StringIO()
+    TreeMap({f"key_{i:02d}": i for i in range(25)}).pretty_print(max_depth=0, file=out)
+    assert out.getvalue() == 'Node4 prefix="key_"\n`-- ... 25 keys\n'
+    out = io.StringIO()
+    TreeMap({f"key_{i:02d}": i for i in range(25)}).pretty_print(max_depth=1, file=out)
+    assert out.getvalue().count("... 10 keys") == 2
+    assert "'key_00'" not in out.getvalue()
+
+
+def test_pretty_print_matches_the_node_graph():
+    """pretty_print() draws the nodes the tree holds, as to_dot() does."""
+    tree = TreeMap({f"{c}{i}": i for c in "abcdefghijklmnopqrst" for i in range(5)})
+    for key in list(tree)[::3]:
+        del tree[key]
+    out = io.StringIO()
+    tree.pretty_print(file=out)
+    kinds = re.findall(r"Node\d+", out.getvalue())
+    assert sorted(kinds) == sorted(re.findall(r"Node\d+", tree.to_dot()))
+    assert out.getvalue().count("'") == 2 * len(tree)
+
+
+def test_validate_accepts_healthy_trees():
+    """validate() passes for every built-in key type after mutations."""
+    trees = [
+        TreeMap({f"key_{i:03d}": i for i in range(500)}),
+        TreeMap({"Hello": 1, "WORLD": 2}, case_insensitive=True),
+        TreeMap({b"\x00\xff": 1}, key_type="bytes"),
+        TreeMap({-5: 1, 7: 2}, key_type="int"),
+        TreeMap({("a", 1): 1, ("a", 2.5, None): 2}, key_type="tuple"),
+        TreeMap({datetime(2024, 1, 1): 1}, key_type="datetime"),
+        TreeMap({uuid.uuid4(): 1}, key_type="uuid"),
+        TreeMap({ipaddress.ip_network("10.0.0.0/8"): 1}, key_type="ip"),
+        TreeMap({"src/lib.rs": 1, "src/a/b.rs": 2}, key_type="path"),
+    ]
+    for tree in trees:
+        tree.validate()
+    tree = trees[0]
+    for i in range(0, 500, 3):
+        del tree[f"key_{i:03d}"]
+    tree.validate()
+    tree.clear()
+    tree.validate()
+
+
+def test_validate_detects_inconsistent_codec():
+    """A custom codec whose decode() does not invert encode() is reported."""
+
+    class LossyCodec:
+        def encode(self, key):
+            return key.encode()
+
+        def decode(self, data):
+            return data.decode().upper()
+
+    tree = TreeMap({"abc": 1}, key_type=LossyCodec())
+    with pytest.raises(RuntimeError, match="invalid"):
+        tree.validate()
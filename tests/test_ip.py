@@ -0,0 +1,73 @@
+"""Tests for IP/CIDR keys and longest-prefix match."""
+
+import ipaddress
+
+import pytest
+from blart import TreeMap
+
+
+@pytest.fixture
+def routes():
+    return TreeMap(
+        {
+            "0.0.0.0/0": "default",
+            "10.0.0.0/8": "core",
+            "10.1.0.0/16": "lab",
+            "10.1.2.0/24": "rack",
+            "2001:db8::/32": "v6",
+        },
+        key_type="ip",
+    )
+
+
+def test_lookup_longest_prefix(routes):
+    """lookup() returns the most specific containing network."""
+    assert routes.lookup("10.1.2.3") == (ipaddress.ip_network("10.1.2.0/24"), "rack")
+    assert routes.lookup("10.1.9.9") == (ipaddress.ip_network("10.1.0.0/16"), "lab")
+    assert routes.lookup(ipaddress.ip_address("10.200.0.1"))[1] == "core"
+    assert routes.lookup("192.168.0.1")[1] == "default"
+
+
+def test_lookup_ipv6(routes):
+    """IPv6 addresses only match IPv6 networks."""
+    assert routes.lookup("2001:db8::1")[1] == "v6"
+    assert routes.lookup("2001:db9::1") is None
+
+
+def test_ip_keys_round_trip(routes):
+    """Keys decode to ip_network objects, networks before their subnets."""
+    keys = list(routes)
+    assert keys[:4] == [
+        ipaddress.ip_network("0.0.0.0/0"),
+        ipaddress.ip_network("10.0.0.0/8"),
+        ipaddress.ip_network("10.1.0.0/16"),
+        ipaddress.ip_network("10.1.2.0/24"),
+    ]
+    assert routes[ipaddress.ip_network("10.1.0.0/16")] == "lab"
+
+
+def test_ip_host_keys():
+    """Plain addresses are stored as host networks."""
+    tree = TreeMap(key_type="ip")
+    tree[ipaddress.ip_address("192.0.2.1")] = "host"
+    assert list(tree) == [ipaddress.ip_network("192.0.2.1/32")]
+    assert tree.lookup("192.0.2.1")[1] == "host"
+    assert tree.lookup("192.0.2.2") is None
+
+
+def test_prefix_iter_lists_subnets(routes):
+    """prefix_iter() with a network yields the subnets inside it."""
+    inside = [str(net) for net, _ in routes.prefix_iter("10.1.0.0/16")]
+    assert inside == ["10.1.0.0/16", "10.1.2.0/24"]
+
+
+def test_lookup_requires_ip_keys():
+    """lookup() is only available on ip-keyed trees."""
+    with pytest.raises(TypeError):
+        TreeMap({"a": 1}).lookup("10.0.0.1")
+
+
+def test_invalid_ip_key():
+    """Invalid addresses raise ValueError."""
+    with pytest.raises(ValueError):
+        TreeMap(key_type="ip")["not an ip"] = 1
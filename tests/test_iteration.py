@@ -1,5 +1,7 @@
 """Comprehensive tests for TreeMap iteration support."""
 
+import operator
+
 import pytest
 from blart import TreeMap
 
@@ -110,20 +112,16 @@ def test_modify_during_iteration():
     """Test behavior when modifying TreeMap during iteration."""
     tree = TreeMap({"apple": 1, "banana": 2, "cherry": 3})
 
-    # This test documents the behavior - either:
-    # 1. Iteration should work with a snapshot (no error)
-    # 2. Should raise a clear error about modification during iteration
-
-    # For now, we expect iteration to work with a snapshot
-    # since we're cloning data in the iterator
+    # Like dicts, adding keys mid-iteration raises a clear error
     keys = []
-    for key in tree:
-        keys.append(key)
-        if key == "banana":
-            tree["new_key"] = 99  # Modify during iteration
-
-    # Should have iterated over original 3 keys
-    assert len(keys) == 3
+    with pytest.raises(RuntimeError, match="changed size during iteration"):
+        for key in tree:
+            keys.append(key)
+            if key == "banana":
+                tree["new_key"] = 99  # Modify during iteration
+
+    # Iteration stopped right after the modification
+    assert keys == ["apple", "banana"]
     # New key should be present after iteration
     assert "new_key" in tree
 
@@ -210,3 +208,153 @@ def test_iteration_with_none_values():
     assert values.count(None) == 2
     assert ("key1", None) in items
     assert ("key3", None) in items
+
+
+# Length hints
+def test_length_hint_counts_down():
+    """Iterators report how many results remain."""
+    tree = TreeMap({"apple": 1, "apricot": 2, "banana": 3})
+    it = iter(tree)
+    assert operator.length_hint(it) == 3
+    next(it)
+    assert it.remaining() == 2
+    assert it.__length_hint__() == 2
+    list(it)
+    assert it.remaining() == 0
+
+
+def test_length_hint_on_query_iterators():
+    """Prefix, fuzzy and view iterators all expose remaining()."""
+    tree = TreeMap({"apple": 1, "apricot": 2, "banana": 3})
+    assert tree.prefix_iter("ap").remaining() == 2
+    assert tree.fuzzy_search("apple", max_distance=0).remaining() is None
+    assert operator.length_hint(iter(tree.values())) == 3
+    assert operator.length_hint(iter(tree.items())) == 3
+    assert iter(tree.keys()).remaining() == 3
+
+
+# Mutation during iteration
+def test_remove_during_query_iteration_raises():
+    """Query iterators are also invalidated by removals."""
+    tree = TreeMap({"apple": 1, "apricot": 2, "banana": 3})
+    it = tree.prefix_iter("ap")
+    next(it)
+    del tree["banana"]
+    with pytest.raises(RuntimeError):
+        next(it)
+
+
+def test_view_iteration_detects_clear():
+    """Iterators over views are invalidated by clear()."""
+    tree = TreeMap({"a": 1, "b": 2})
+    it = iter(tree.items())
+    tree.clear()
+    with pytest.raises(RuntimeError):
+        next(it)
+
+
+def test_overwriting_values_during_iteration_is_allowed():
+    """Updating existing keys does not invalidate iterators."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    for key in tree:
+        tree[key] = tree[key] * 10
+        tree.apply(key, lambda v: v + 1)
+    assert list(tree.values()) == [11, 21, 31]
+
+
+def test_exhausted_iterator_stays_exhausted():
+    """Once exhausted, an iterator keeps raising StopIteration."""
+    tree = TreeMap({"a": 1})
+    it = iter(tree)
+    assert list(it) == ["a"]
+    tree["b"] = 2
+    assert list(it) == []
+
+
+def test_items_pagination():
+    """items() with offset or limit returns one page of the pairs."""
+    tree = TreeMap({f"k{i:02}": i for i in range(10)})
+    assert list(tree.items(offset=3, limit=2)) == [("k03", 3), ("k04", 4)]
+    assert list(tree.items(limit=1)) == [("k00", 0)]
+    assert [v for _, v in tree.items(offset=8)] == [8, 9]
+    assert list(tree.items(offset=20)) == []
+    assert list(tree.items(limit=0)) == []
+    page = tree.items(offset=1, limit=1)
+    assert page.remaining() == 1
+    with pytest.raises(OverflowError):
+        tree.items(offset=-1)
+
+
+def test_prefix_and_range_pagination():
+    """prefix_iter() and range_between() page through their matches."""
+    tree = TreeMap({f"{group}{i}": i for group in "ab" for i in range(5)})
+    assert list(tree.prefix_iter("a", offset=1, limit=2)) == [("a1", 1), ("a2", 2)]
+    assert list(tree.prefix_iter("b", offset=4, limit=10)) == [("b4", 4)]
+    assert list(tree.range_between("a3", "b2", offset=1, limit=3)) == [
+        ("a4", 4),
+        ("b0", 0),
+        ("b1", 1),
+    ]
+    assert list(tree.range_between(None, None, offset=9)) == [("b4", 4)]
+
+
+def test_iter_chunks():
+    """iter_chunks() yields lists of pairs, the last one possibly shorter."""
+    tree = TreeMap({f"k{i:02}": i for i in range(7)})
+    chunks = list(tree.iter_chunks(3))
+    assert [len(chunk) for chunk in chunks] == [3, 3, 1]
+    assert [pair for chunk in chunks for pair in chunk] == list(tree.items())
+    assert list(tree.iter_chunks(7)) == [list(tree.items())]
+    assert list(TreeMap().iter_chunks(3)) == []
+    with pytest.raises(ValueError):
+        tree.iter_chunks(0)
+
+
+def test_iter_chunks_with_prefix():
+    """A prefix limits the chunks to the keys under it."""
+    tree = TreeMap({"a1": 1, "b1": 2, "b2": 3, "b3": 4, "c1": 5})
+    assert list(tree.iter_chunks(2, prefix="b")) == [[("b1", 2), ("b2", 3)], [("b3", 4)]]
+    assert list(tree.iter_chunks(2, prefix="z")) == []
+
+
+def test_iter_chunks_detects_changes():
+    """Adding keys between chunks raises RuntimeError."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    chunks = tree.iter_chunks(1)
+    next(chunks)
+    tree["d"] = 4
+    with pytest.raises(RuntimeError):
+        next(chunks)
+
+
+def test_scan_stops_on_false():
+    """scan() visits entries in order until the callback returns False."""
+    tree = TreeMap({f"k{i}": i for i in range(10)})
+    seen = []
+
+    def visit(key, value):
+        seen.append(key)
+        return value < 3
+
+    assert tree.scan(visit) == 4
+    assert seen == ["k0", "k1", "k2", "k3"]
+    # None and other non-False results keep going
+    assert tree.scan(lambda key, value: None) == 10
+    assert tree.scan(lambda key, value: 0) == 10
+
+
+def test_scan_prefix_and_reverse():
+    """scan() can be limited to a prefix and run backwards."""
+    tree = TreeMap({"a1": 1, "b1": 2, "b2": 3, "b3": 4, "c1": 5})
+    seen = []
+    assert tree.scan(lambda key, value: seen.append(key), prefix="b", reverse=True) == 3
+    assert seen == ["b3", "b2", "b1"]
+    assert tree.scan(lambda key, value: False, reverse=True) == 1
+    assert tree.scan(lambda key, value: None, prefix="z") == 0
+
+
+def test_scan_propagates_errors():
+    """An exception from the callback ends the scan."""
+    tree = TreeMap({"a": 1, "b": 2})
+    with pytest.raises(ZeroDivisionError):
+        tree.scan(lambda key, value: 1 / 0)
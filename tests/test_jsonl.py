@@ -0,0 +1,93 @@
+"""Tests for loading TreeMaps from JSON Lines files."""
+
+import json
+
+import pytest
+from blart import TreeMap
+
+
+def write(tmp_path, lines):
+    path = tmp_path / "data.jsonl"
+    path.write_text("\n".join(lines), encoding="utf-8")
+    return path
+
+
+def test_whole_records_as_values(tmp_path):
+    path = write(tmp_path, ['{"id": "b", "n": 2}', "", '{"id": "a", "n": 1, "tags": ["x"]}'])
+    tree = TreeMap.from_jsonl(path, "id")
+    assert list(tree.items()) == [
+        ("a", {"id": "a", "n": 1, "tags": ["x"]}),
+        ("b", {"id": "b", "n": 2}),
+    ]
+
+
+def test_value_field(tmp_path):
+    path = write(tmp_path, ['{"id": "a", "n": 1}', '{"id": "b", "n": null}'])
+    assert dict(TreeMap.from_jsonl(path, "id", "n")) == {"a": 1, "b": None}
+
+
+def test_values_match_the_json_module(tmp_path):
+    record = {
+        "id": "k",
+        "v": {"big": 10**30, "neg": -0.5, "exp": 1e-300, "s": 'é "q"\n\U0001f600', "ok": True},
+    }
+    path = write(tmp_path, [json.dumps(record), json.dumps({**record, "id": "u"}, ensure_ascii=False)])
+    tree = TreeMap.from_jsonl(path, "id", "v")
+    assert tree["k"] == tree["u"] == record["v"]
+
+
+@pytest.mark.parametrize(
+    "line",
+    [
+        '{"id": "k", "v": 1, "v": 2}',
+        '{"v": 1, "id": "k", "v": 2}',
+        '{"id": "a", "id": "k", "v": {"x": 1, "x": {"y": 2}, "z": -0}}',
+        '{"id": "k", "v": "\\ud800"}',
+        '{"id": "k", "v": "\\udc00x"}',
+        '{"id": "k", "v": "\\ud800\\u0041"}',
+        '{"id": "k", "v": "\\ud800\\ud83d\\ude00", "\\udfff": 1}',
+        '{"id": "k", "v": [NaN, Infinity, -Infinity, 1e400]}',
+        r'{"id": "k", "v": "\"\\\/\b\f\n\r\t\u0000\u001f\u00e9\u20AC"}',
+        r'{"id": "k", "v": "\ud83d\ude00 \uD834\uDD1E"}',
+        '{"id": "k", "v": "\U0001f600 \u00e9"}',
+        '{"id": "k", "v": [9223372036854775807, 9223372036854775808, -9223372036854775809]}',
+        '{"id": "k", "v": [123456789012345678901234567890, -0, 0]}',
+        '{"id": "k", "v": [0.1, -0.0, 1.5e10, 1E+2, 2e-2, 5e-324, 1.7976931348623157e308]}',
+        '{"id": "k", "v": [3.141592653589793238462643383279, 1.0000000000000002, 1e22]}',
+    ],
+)
+def test_parsing_matches_the_json_module(tmp_path, line):
+    expected = json.loads(line)
+    key = expected["id"]
+    path = write(tmp_path, [line])
+    assert repr(TreeMap.from_jsonl(path, "id", "v")[key]) == repr(expected["v"])
+    assert repr(TreeMap.from_jsonl(path, "id")[key]) == repr(expected)
+
+
+def test_key_type(tmp_path):
+    path = write(tmp_path, ['{"id": 2, "v": "b"}', '{"id": 1, "v": "a"}'])
+    tree = TreeMap.from_jsonl(path, "id", "v", key_type=int)
+    assert list(tree.items()) == [(1, "a"), (2, "b")]
+
+
+def test_key_type_errors_give_line_numbers(tmp_path):
+    path = write(tmp_path, ['{"id": "a"}', "", '{"id": 3}'])
+    with pytest.raises(TypeError, match="^line 3: "):
+        TreeMap.from_jsonl(path, "id")
+    with pytest.raises(TypeError, match="^line 1: "):
+        TreeMap.from_jsonl(path, "id", key_type=int)
+
+
+@pytest.mark.parametrize(
+    "line, message",
+    [
+        ('{"id": "a"', "line 2: expected ','"),
+        ("[1, 2]", "line 2: record is not an object"),
+        ('{"other": 1}', 'line 2: missing field "id"'),
+        ('{"id": "a"} extra', "line 2: extra data"),
+    ],
+)
+def test_errors_give_line_numbers(tmp_path, line, message):
+    path = write(tmp_path, ['{"id": "ok"}', line])
+    with pytest.raises(ValueError, match=message):
+        TreeMap.from_jsonl(path, "id")
@@ -0,0 +1,69 @@
+"""Tests for read-through loading of missing keys."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_misses_are_loaded_and_stored():
+    """get() and tree[key] call the loader once per missing key."""
+    calls = []
+
+    def loader(key):
+        calls.append(key)
+        return key.upper()
+
+    tree = TreeMap({"a": "stored"}, loader=loader)
+    assert tree.loader is loader
+    assert tree["a"] == "stored"
+    assert tree["b"] == "B"
+    assert tree.get("c") == "C"
+    assert tree.get("c", "default") == "C"
+    assert tree["b"] == "B"
+    assert calls == ["b", "c"]
+    assert dict(tree) == {"a": "stored", "b": "B", "c": "C"}
+
+
+def test_membership_does_not_load():
+    """`in` reports only keys that are stored."""
+    tree = TreeMap(loader=str.upper)
+    assert "a" not in tree
+    assert len(tree) == 0
+
+
+def test_loader_errors_become_key_errors():
+    """An exception raised by the loader is chained to a KeyError."""
+
+    def loader(key):
+        raise LookupError(f"no record for {key}")
+
+    tree = TreeMap(loader=loader)
+    with pytest.raises(KeyError) as excinfo:
+        tree["missing"]
+    assert isinstance(excinfo.value.__cause__, LookupError)
+    assert "missing" not in tree
+
+
+def test_get_returns_default_when_loader_raises():
+    """get() treats a key the loader raised for as not found."""
+
+    def loader(key):
+        raise LookupError(f"no record for {key}")
+
+    tree = TreeMap({"a": 1}, loader=loader)
+    assert tree.get("missing", "default") == "default"
+    assert tree.get("missing") is None
+    assert tree.get("a", "default") == 1
+    assert "missing" not in tree
+
+
+def test_loader_respects_max_len():
+    """Loaded values are inserted like any other, evicting when full."""
+    tree = TreeMap(loader=str.upper, max_len=2, eviction="evict_oldest")
+    for key in ["a", "b", "c"]:
+        tree[key]
+    assert list(tree.keys()) == ["b", "c"]
+
+
+def test_loader_excludes_default_factory():
+    with pytest.raises(ValueError, match="cannot be combined"):
+        TreeMap(loader=str.upper, default_factory=list)
@@ -0,0 +1,265 @@
+"""Tests for collections.abc.MutableMapping compliance."""
+
+from collections.abc import ItemsView, KeysView, Mapping, MutableMapping, ValuesView
+
+import pytest
+from blart import TreeMap
+
+
+def test_isinstance_mutable_mapping():
+    """TreeMap and its views register with the collections.abc ABCs."""
+    tree = TreeMap({"a": 1})
+    assert isinstance(tree, Mapping)
+    assert isinstance(tree, MutableMapping)
+    assert isinstance(tree.keys(), KeysView)
+    assert isinstance(tree.values(), ValuesView)
+    assert isinstance(tree.items(), ItemsView)
+
+
+def test_update_variants():
+    """update() accepts mappings, pairs and keyword arguments."""
+    tree = TreeMap({"a": 1})
+    tree.update({"b": 2})
+    tree.update([("c", 3)], d=4)
+    tree.update(TreeMap({"a": 10}))
+    tree.update()
+    assert dict(tree.items()) == {"a": 10, "b": 2, "c": 3, "d": 4}
+
+
+def test_update_from_self():
+    """Updating a TreeMap from itself is a no-op."""
+    tree = TreeMap({"a": 1, "b": 2})
+    tree.update(tree)
+    assert list(tree.items()) == [("a", 1), ("b", 2)]
+
+
+def test_pop():
+    """pop() removes the key, falling back to a default when missing."""
+    tree = TreeMap({"a": 1})
+    assert tree.pop("a") == 1
+    assert "a" not in tree
+    assert tree.pop("a", "missing") == "missing"
+    assert tree.pop("a", None) is None
+    with pytest.raises(KeyError):
+        tree.pop("a")
+    with pytest.raises(TypeError):
+        tree.pop("a", 1, 2)
+
+
+def test_popitem():
+    """popitem() removes entries from the end until the tree is empty."""
+    tree = TreeMap({"a": 1, "b": 2})
+    assert tree.popitem() == ("b", 2)
+    assert tree.popitem() == ("a", 1)
+    with pytest.raises(KeyError):
+        tree.popitem()
+
+
+def test_setdefault():
+    """setdefault() only inserts when the key is missing."""
+    tree = TreeMap({"a": 1})
+    assert tree.setdefault("a", 5) == 1
+    assert tree.setdefault("b", 5) == 5
+    assert tree.setdefault("c") is None
+    assert "c" in tree
+    assert tree["b"] == 5
+
+
+def test_equality_with_mappings():
+    """A TreeMap equals any mapping holding the same items."""
+    tree = TreeMap({"a": 1, "b": 2})
+    assert tree == {"a": 1, "b": 2}
+    assert {"b": 2, "a": 1} == tree
+    assert tree == TreeMap({"b": 2, "a": 1})
+    assert tree != {"a": 1}
+    assert tree != {"a": 1, "b": 3}
+    assert tree != [("a", 1), ("b", 2)]
+    assert tree == tree
+
+
+def test_diff():
+    """diff reports added, removed and changed keys in key order."""
+    old = TreeMap({"a": 1, "b": [2], "c": 3, "e": 5})
+    new = TreeMap({"b": [2], "c": 30, "d": 4, "e": 5})
+    assert old.diff(new) == (["d"], ["a"], ["c"])
+    assert new.diff(old) == (["a"], ["d"], ["c"])
+    assert old.diff(old) == ([], [], [])
+    assert TreeMap().diff(new) == (["b", "c", "d", "e"], [], [])
+
+
+def test_diff_requires_same_key_type():
+    """Trees with different key types cannot be diffed."""
+    with pytest.raises(TypeError):
+        TreeMap({"a": 1}).diff(TreeMap(key_type="int"))
+    with pytest.raises(TypeError):
+        TreeMap().diff({"a": 1})
+
+
+def test_key_set_algebra():
+    """Set operations on the keys of two trees return sorted key lists."""
+    a = TreeMap({"a": 1, "b": 2, "d": 4})
+    b = TreeMap({"b": 20, "c": 30, "d": 40})
+    assert a.union_keys(b) == ["a", "b", "c", "d"]
+    assert a.intersection_keys(b) == ["b", "d"]
+    assert a.difference_keys(b) == ["a"]
+    assert a.symmetric_difference_keys(b) == ["a", "c"]
+    assert a.intersection_keys(a) == ["a", "b", "d"]
+
+
+def test_key_set_algebra_with_iterables():
+    """The other side can be any iterable of keys, in any order."""
+    tree = TreeMap({"a": 1, "b": 2})
+    assert tree.union_keys(["c", "b", "c"]) == ["a", "b", "c"]
+    assert tree.symmetric_difference_keys(iter(["c", "a"])) == ["b", "c"]
+    assert tree.difference_keys([]) == ["a", "b"]
+    with pytest.raises(TypeError):
+        tree.union_keys(TreeMap(key_type="int"))
+    with pytest.raises(TypeError):
+        tree.union_keys([1])
+
+
+def test_subset_and_superset():
+    """Containment checks compare keys, and values when asked."""
+    small = TreeMap({"a": 1, "c": 3})
+    big = TreeMap({"a": 1, "b": 2, "c": 30})
+    assert small.issubset(big)
+    assert big.issuperset(small)
+    assert not small.issubset(big, values=True)
+    assert not big.issuperset(small, values=True)
+    assert not big.issubset(small)
+    assert TreeMap().issubset(small)
+    assert small.issubset(small, values=True)
+    with pytest.raises(TypeError):
+        small.issubset(TreeMap(key_type="int"))
+
+
+def test_apply_patch_from_diff():
+    """Applying a diff with the new tree as source reproduces it."""
+    old = TreeMap({"a": 1, "b": 2, "c": 3})
+    new = TreeMap({"b": 2, "c": 30, "d": 4})
+    assert old.apply_patch(old.diff(new), new) == 3
+    assert old == new
+
+
+def test_apply_patch_round_trips_diff_with_values():
+    """diff(values=True) is a patch apply_patch() applies on its own."""
+    old = TreeMap({"a": 1, "b": 2, "c": 3, "e": [5]})
+    new = TreeMap({"b": 2, "c": 30, "d": 4, "e": [5, 6]})
+    patch = old.diff(new, values=True)
+    assert patch == ([("d", 4)], ["a"], [("c", 30), ("e", [5, 6])])
+    assert old.apply_patch(patch) == 4
+    assert old == new
+    assert old.diff(new, values=True) == ([], [], [])
+    numbers = TreeMap({1: "one"}, key_type="int")
+    numbers.apply_patch(numbers.diff(TreeMap({2: "two"}, key_type="int"), values=True))
+    assert dict(numbers) == {2: "two"}
+
+
+def test_apply_patch_plain_keys_need_source():
+    """Plain keys without a source raise a clear error and change nothing."""
+    old = TreeMap({"a": 1})
+    new = TreeMap({"b": 2})
+    with pytest.raises(TypeError, match="source=.*diff\\(values=True\\)"):
+        old.apply_patch(old.diff(new))
+    assert dict(old) == {"a": 1}
+
+
+def test_apply_patch_with_values():
+    """Patches can carry their values as mappings or pairs."""
+    tree = TreeMap({"a": 1, "b": 2})
+    assert tree.apply_patch(({"c": 3}, ["a"], [("b", 20)])) == 3
+    assert dict(tree) == {"b": 20, "c": 3}
+
+
+def test_apply_patch_atomic():
+    """A patch with a conflicting operation leaves the tree unchanged."""
+    tree = TreeMap({"a": 1, "b": 2})
+    with pytest.raises(KeyError):
+        tree.apply_patch(({"c": 3}, ["missing"], {}))
+    with pytest.raises(KeyError, match="already exists"):
+        tree.apply_patch(({"a": 5}, [], {}))
+    with pytest.raises(KeyError, match="would evict"):
+        tree.apply_patch(({"ab": 5}, [], {}))
+    assert dict(tree) == {"a": 1, "b": 2}
+    # Deleting the evicted key in the same patch makes room
+    assert tree.apply_patch(({"ab": 5}, ["a"], {})) == 2
+    assert dict(tree) == {"ab": 5, "b": 2}
+
+
+def test_apply_patch_skips_conflicts():
+    """Without atomic, operations that do not apply are skipped."""
+    tree = TreeMap({"a": 1, "b": 2})
+    patch = ({"a": 5, "c": 3}, ["missing", "b"], {"zz": 0})
+    assert tree.apply_patch(patch, atomic=False) == 2
+    assert dict(tree) == {"a": 1, "c": 3}
+
+
+def test_apply_patch_shape():
+    """The patch must be a tuple of three parts."""
+    with pytest.raises(ValueError):
+        TreeMap().apply_patch(([], []))
+
+
+def test_merge():
+    """Only keys stored in both trees are passed to the resolver."""
+    a = TreeMap({"x": 1, "y": 2})
+    b = TreeMap({"y": 10, "z": 3})
+    calls = []
+
+    def resolve(key, ours, theirs):
+        calls.append((key, ours, theirs))
+        return ours + theirs
+
+    merged = a.merge(b, resolve)
+    assert dict(merged) == {"x": 1, "y": 12, "z": 3}
+    assert calls == [("y", 2, 10)]
+    assert dict(a) == {"x": 1, "y": 2}
+    assert dict(a.merge(b)) == {"x": 1, "y": 10, "z": 3}
+
+
+def test_merge_inplace():
+    """inplace updates the tree and returns None."""
+    a = TreeMap({"x": 1, "y": 2})
+    assert a.merge(TreeMap({"y": 10}), lambda k, o, t: max(o, t), inplace=True) is None
+    assert dict(a) == {"x": 1, "y": 10}
+    with pytest.raises(TypeError):
+        a.merge(TreeMap(key_type="int"))
+
+
+def test_merge3():
+    """One-sided changes merge; only real conflicts reach the resolver."""
+    base = TreeMap({"a": 1, "b": 2, "c": 3, "e": 5})
+    ours = TreeMap({"a": 10, "b": 2, "c": 30, "e": 50})
+    theirs = TreeMap({"a": 1, "c": 300, "d": 4, "e": 50})
+    calls = []
+
+    def resolve(key, base_value, our_value, their_value):
+        calls.append((key, base_value, our_value, their_value))
+        return our_value + their_value
+
+    assert ours.merge3(base, theirs, on_conflict=resolve) == ["c"]
+    assert calls == [("c", 3, 30, 300)]
+    assert dict(ours) == {"a": 10, "c": 330, "d": 4, "e": 50}
+
+
+def test_merge3_missing_sides():
+    """A side the conflicting key is missing from is passed as None."""
+    ours = TreeMap({"a": 2})
+    ours.merge3(TreeMap({"a": 1}), TreeMap(), on_conflict=lambda *args: args)
+    assert ours["a"] == ("a", 1, 2, None)
+
+
+def test_merge3_unresolved_conflict():
+    """Without a resolver a conflict raises and the tree is unchanged."""
+    ours = TreeMap({"a": 2, "b": 1})
+    with pytest.raises(ValueError, match="'a'"):
+        ours.merge3(TreeMap({"a": 1}), TreeMap({"a": 3, "c": 1}))
+    assert dict(ours) == {"a": 2, "b": 1}
+
+
+def test_mixin_methods():
+    """Mixin methods from MutableMapping work against TreeMap."""
+    tree = TreeMap({"a": 1, "b": 2})
+    MutableMapping.update(tree, {"c": 3})
+    assert MutableMapping.pop(tree, "c") == 3
+    assert Mapping.__eq__(tree, {"a": 1, "b": 2})
@@ -0,0 +1,61 @@
+"""Tests for TreeMaps bounded by max_len."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_reject():
+    """By default a full tree refuses new keys but still updates existing ones."""
+    tree = TreeMap({"a": 1, "b": 2}, max_len=2)
+    assert tree.max_len == 2
+    assert tree.eviction == "reject"
+    tree["a"] = 10
+    with pytest.raises(OverflowError, match="max_len=2"):
+        tree["c"] = 3
+    with pytest.raises(OverflowError):
+        tree.get_or_insert("c", lambda: 3)
+    with pytest.raises(OverflowError):
+        tree.update({"c": 3})
+    assert dict(tree) == {"a": 10, "b": 2}
+
+
+def test_key_displacing_a_prefix_fits():
+    """A key that replaces the keys it extends does not grow the tree."""
+    tree = TreeMap({"a": 1, "b": 2}, max_len=2)
+    tree["ab"] = 3
+    assert dict(tree) == {"ab": 3, "b": 2}
+
+
+@pytest.mark.parametrize(
+    "eviction, expected",
+    [
+        ("evict_first", ["m", "q", "x"]),
+        ("evict_last", ["a", "c", "q"]),
+        ("evict_oldest", ["a", "q", "x"]),
+    ],
+)
+def test_eviction_policies(eviction, expected):
+    """Each policy removes its own choice of entry to make room."""
+    tree = TreeMap(max_len=3, eviction=eviction)
+    for key in ["m", "c", "x", "a"]:
+        tree[key] = 1
+    tree["c"] = 2
+    tree["q"] = 1
+    assert list(tree) == expected
+    assert len(tree) == 3
+
+
+def test_evict_oldest_keeps_place_on_update():
+    """Replacing a value does not make its key newer."""
+    tree = TreeMap([("a", 1), ("b", 2)], max_len=2, eviction="evict_oldest")
+    tree["a"] = 10
+    tree.increment("c")
+    assert dict(tree) == {"b": 2, "c": 1}
+
+
+def test_invalid_options():
+    """max_len must be positive and eviction a known policy."""
+    with pytest.raises(ValueError):
+        TreeMap(max_len=0)
+    with pytest.raises(ValueError):
+        TreeMap(eviction="lru")
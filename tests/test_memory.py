@@ -2,6 +2,8 @@
 
 import gc
 import sys
+import weakref
+
 from blart import TreeMap
 
 
@@ -145,24 +147,102 @@ def test_circular_reference_handling():
     del obj
 
 
+class Marker:
+    """Weak-referenceable object used to observe collection."""
+
+
+def test_cycle_through_value_is_collected():
+    """A value referring back to its TreeMap does not leak."""
+    tree = TreeMap()
+    marker = Marker()
+    tree["marker"] = marker
+    tree["self"] = {"tree": tree}
+    alive = weakref.ref(marker)
+
+    assert gc.is_tracked(tree)
+    del tree, marker
+    gc.collect()
+    assert alive() is None
+
+
+def test_cycle_through_view_and_factory_is_collected():
+    """Cycles through views and default_factory are also collected."""
+    marker = Marker()
+    alive = weakref.ref(marker)
+    tree = TreeMap(default_factory=lambda: tree)
+    tree["marker"] = marker
+    tree["keys"] = tree.keys()
+    tree["iter"] = iter(tree.items())
+
+    del tree, marker
+    gc.collect()
+    assert alive() is None
+
+
+def test_values_shared_with_a_snapshot_survive_collection():
+    """A value shared by a map and its snapshot is not cleared while in use."""
+
+    def build():
+        tree = TreeMap()
+        payload = ["payload"]
+        tree["a"] = payload
+        snapshot = tree.snapshot()
+        payload += [tree, snapshot]
+        del tree, snapshot
+        gc.collect()
+        return payload
+
+    payload = build()
+    assert payload[0] == "payload"
+    assert payload[1]["a"] is payload
+    assert payload[2]["a"] is payload
+
+
+def test_values_shared_with_copies_survive_collection():
+    """Versions read back and merged copies share values safely too."""
+
+    def build():
+        tree = TreeMap(versioned=True)
+        payload = ["payload"]
+        tree["a"] = payload
+        old = tree.at_version(tree.version)
+        merged = tree.merge(TreeMap({"b": 1}))
+        payload += [tree, old, merged]
+        del tree, old, merged
+        gc.collect()
+        return payload
+
+    payload = build()
+    assert payload[0] == "payload"
+    assert all(copy["a"] is payload for copy in payload[1:])
+
+
 def test_treemap_garbage_collection():
     """Test that TreeMaps can be garbage collected."""
-    # Note: PyO3 classes don't support weak references by default
-    # This test verifies that deletion and GC work without crashes
-
     # Create a tree
     tree = TreeMap()
     for i in range(1000):
         tree[f"key_{i:04d}"] = i
 
     assert len(tree) == 1000
+    alive = weakref.ref(tree)
 
     # Delete the tree and force garbage collection
     del tree
     gc.collect()
 
-    # If we reach here without crashing, GC worked
-    assert True
+    assert alive() is None
+
+
+def test_weak_value_dictionary():
+    """TreeMaps can live in weak registries without being kept alive."""
+    registry = weakref.WeakValueDictionary()
+    tree = TreeMap({"a": 1})
+    registry["index"] = tree
+    assert registry["index"] is tree
+    del tree
+    gc.collect()
+    assert "index" not in registry
 
 
 def test_nested_treemaps():
@@ -273,6 +353,67 @@ def test_clear_and_reuse():
         assert list(tree.keys()) == []
 
 
+def test_memory_usage_grows_with_entries():
+    """memory_usage() reports the Rust-side footprint."""
+    tree = TreeMap()
+    assert tree.memory_usage() == 0
+    for i in range(1000):
+        tree[f"key_{i:04d}"] = i
+    shallow = tree.memory_usage()
+    assert shallow > 1000 * len("key_0000")
+    assert sys.getsizeof(tree) > shallow
+    assert tree.memory_usage(deep=True) > shallow
+
+
+def test_memory_usage_counts_auxiliary_structures():
+    """Indexes, deadlines, history and the intern table are counted."""
+    keys = {f"key_{i:04d}": i for i in range(1000)}
+    base = TreeMap(keys).memory_usage()
+    for options in (
+        {"substring_index": True},
+        {"order_statistics": True},
+        {"eviction": "evict_oldest", "max_len": 2000},
+        {"versioned": True},
+        {"intern_values": True},
+    ):
+        tree = TreeMap(keys, **options)
+        assert tree.memory_usage() > base, options
+
+    tree = TreeMap(keys)
+    for key in keys:
+        tree.insert(key, 0, ttl=60)
+    assert tree.memory_usage() > base
+
+    tree = TreeMap(keys)
+    with tree.transaction():
+        for key in keys:
+            tree[key] = -1
+        assert tree.memory_usage() > base
+
+
+def test_memory_usage_deep_counts_history_values():
+    """Values only kept by the history are counted by deep=True."""
+    tree = TreeMap({"a": "x" * 10000}, versioned=True)
+    tree["a"] = 1
+    assert tree.memory_usage(deep=True) > 10000
+
+
+def test_memory_usage_deep_counts_values_once():
+    """Shared values and self references are only counted once."""
+    big = "x" * 10000
+    tree = TreeMap({"a": big, "b": big})
+    tree["self"] = tree
+    deep = tree.memory_usage(deep=True)
+    assert sys.getsizeof(big) < deep < 2 * sys.getsizeof(big)
+
+
+def test_memory_usage_deep_recurses_into_treemaps():
+    """Nested TreeMaps are measured as part of their parent."""
+    inner = TreeMap({"payload": "x" * 10000})
+    outer = TreeMap({"inner": inner})
+    assert outer.memory_usage(deep=True) > inner.memory_usage(deep=True)
+
+
 def test_memory_efficiency_vs_dict():
     """Compare memory usage with dict (informational test)."""
     import sys
@@ -298,3 +439,30 @@ def test_memory_efficiency_vs_dict():
     # Print for information (will only show in verbose mode)
     print(f"\nTreeMap size: {tree_size} bytes")
     print(f"Dict size: {dict_size} bytes")
+
+
+def test_compact_after_mass_delete():
+    tree = TreeMap({f"user:{i:05d}": i for i in range(20000)}, case_insensitive=True)
+    tree.delete_range("user:00000", "user:19900")
+    before = tree.memory_usage()
+    reclaimed = tree.compact()
+    assert reclaimed > 0
+    assert tree.memory_usage() == before - reclaimed
+    assert tree.compact() == 0
+    assert list(tree.items()) == [(f"user:{i:05d}", i) for i in range(19900, 20000)]
+
+
+def test_compact_keeps_indexes_working():
+    tree = TreeMap({f"k{i:04d}": i for i in range(1000)}, order_statistics=True)
+    for i in range(0, 1000, 2):
+        del tree[f"k{i:04d}"]
+    tree.compact()
+    assert tree.select(0) == ("k0001", 1)
+    assert tree.rank("k0999") == 499
+    assert tree.count_prefix("k09") == 50
+    tree["k0000"] = 0
+    assert tree.select(0) == ("k0000", 0)
+
+
+def test_compact_empty_tree():
+    assert TreeMap().compact() == 0
@@ -0,0 +1,61 @@
+"""Tests for the operation counters reported by metrics()."""
+
+from blart import TreeMap
+
+
+def test_new_tree_counts_nothing():
+    assert TreeMap().metrics() == {
+        "lookups": 0,
+        "hits": 0,
+        "misses": 0,
+        "inserts": 0,
+        "removals": 0,
+        "evictions": 0,
+        "fuzzy_scans": 0,
+    }
+
+
+def test_lookups_hits_and_misses():
+    tree = TreeMap({"a": 1})
+    tree.get("a")
+    tree.get("b")
+    tree["a"]
+    assert "c" not in tree
+    metrics = tree.metrics()
+    assert (metrics["lookups"], metrics["hits"], metrics["misses"]) == (4, 2, 2)
+
+
+def test_inserts_removals_and_evictions():
+    tree = TreeMap({"ab": 1, "ac": 2})
+    tree["ab"] = 10
+    tree["a"] = 3
+    tree.update({"x": 1, "y": 2})
+    tree.pop("x")
+    tree.retain(lambda key, value: key != "y")
+    metrics = tree.metrics()
+    assert metrics["inserts"] == 6
+    assert metrics["evictions"] == 2
+    assert metrics["removals"] == 2
+
+
+def test_max_len_evictions():
+    tree = TreeMap(max_len=1, eviction="evict_first")
+    for key in "abc":
+        tree[key] = key
+    assert tree.metrics()["evictions"] == 2
+
+
+def test_fuzzy_scans():
+    tree = TreeMap({"hello": 1, "help": 2})
+    list(tree.fuzzy_search("helo", 1))
+    list(tree.fuzzy_prefix("hel", 1))
+    tree.fuzzy_search_many(["helo", "hepl"], 1)
+    tree.suggest("hepl")
+    assert tree.metrics()["fuzzy_scans"] == 5
+
+
+def test_reset_metrics():
+    tree = TreeMap({"a": 1})
+    tree.get("a")
+    tree.reset_metrics()
+    assert set(tree.metrics().values()) == {0}
@@ -0,0 +1,81 @@
+"""Tests for TreeMultiMap."""
+
+import pytest
+from blart import TreeMultiMap
+
+
+def test_insert_appends():
+    """Inserting an existing key appends to its values."""
+    index = TreeMultiMap([("apple", 1), ("banana", 2), ("apple", 3)])
+    index["apple"] = 5
+    index.insert("cherry", 6)
+    index.extend("cherry", [7, 8])
+    assert index.get_all("apple") == [1, 3, 5]
+    assert index["cherry"] == [6, 7, 8]
+    assert index.get_all("missing") == []
+    assert index.get("apple") == 1
+    assert index.get("missing", 0) == 0
+    with pytest.raises(KeyError):
+        index["missing"]
+    assert len(index) == 7
+    assert list(index) == ["apple", "banana", "cherry"]
+    assert repr(index) == "TreeMultiMap(keys=3, values=7)"
+
+
+def test_items():
+    """items() flattens by default, or groups values per key."""
+    index = TreeMultiMap({"a": 1})
+    index.insert("a", 2)
+    index.insert("b", 3)
+    assert index.items() == [("a", 1), ("a", 2), ("b", 3)]
+    assert index.items(flatten=False) == [("a", [1, 2]), ("b", [3])]
+    assert index.keys() == ["a", "b"]
+    assert index.values() == [1, 2, 3]
+    assert list(index.prefix_iter("a")) == [("a", 1), ("a", 2)]
+
+
+def test_keys_prefixing_each_other_coexist():
+    """A key that prefixes another keeps its own values."""
+    index = TreeMultiMap([("a", 1), ("ab", 2), ("a", 3), ("a\x00", 4), ("", 5)])
+    assert len(index) == 5
+    assert index.keys() == ["", "a", "a\x00", "ab"]
+    assert index.get_all("a") == [1, 3]
+    assert index.get_all("ab") == [2]
+    assert list(index.prefix_iter("a")) == [("a", 1), ("a", 3), ("a\x00", 4), ("ab", 2)]
+    assert list(index.prefix_iter("ab")) == [("ab", 2)]
+    del index["a"]
+    assert index.items() == [("", 5), ("a\x00", 4), ("ab", 2)]
+    raw = TreeMultiMap([(b"x", 1), (b"xy", 2)], key_type="bytes")
+    assert raw.items() == [(b"x", 1), (b"xy", 2)]
+
+
+def test_remove():
+    """Keys can be removed whole, or one value at a time."""
+    index = TreeMultiMap([("a", 1), ("a", 2), ("b", 3)])
+    index.remove_value("a", 2)
+    assert index["a"] == [1]
+    with pytest.raises(ValueError):
+        index.remove_value("a", 9)
+    index.remove_value("a", 1)
+    assert "a" not in index
+    with pytest.raises(KeyError):
+        index.remove_value("a", 1)
+    assert index.remove("b") == [3]
+    with pytest.raises(KeyError):
+        del index["b"]
+    assert len(index) == 0
+
+
+def test_prefix_insert_keeps_longer_keys():
+    """Inserting a prefix of a stored key evicts nothing."""
+    index = TreeMultiMap([("abc", 1), ("abc", 2)])
+    index.insert("ab", 3)
+    assert index.items() == [("ab", 3), ("abc", 1), ("abc", 2)]
+    assert len(index) == 3
+
+
+def test_key_types():
+    """Keys are encoded as for TreeMap."""
+    index = TreeMultiMap([(3, "c"), (1, "a"), (3, "cc")], key_type="int")
+    assert index.items() == [(1, "a"), (3, "c"), (3, "cc")]
+    assert index.key_type == "int"
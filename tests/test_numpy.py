@@ -0,0 +1,125 @@
+"""Tests for bulk insertion from arrays."""
+
+import array
+
+import pytest
+from blart import TreeMap
+
+
+def test_buffer_with_offsets():
+    """Keys are sliced out of a flat buffer at the given offsets."""
+    tree = TreeMap()
+    offsets = array.array("q", [0, 6, 10])
+    tree.insert_from_numpy(b"carrotdate", [3, 4], offsets=offsets)
+    assert list(tree.items()) == [("carrot", 3), ("date", 4)]
+
+
+def test_offsets_accept_32_bit_and_bytes_keys():
+    tree = TreeMap(key_type=bytes)
+    tree.insert_from_numpy(b"abcd", [1, 2], offsets=array.array("i", [0, 1, 4]))
+    assert list(tree.items()) == [(b"a", 1), (b"bcd", 2)]
+
+
+def test_invalid_offsets():
+    tree = TreeMap()
+    with pytest.raises(ValueError, match="within the 2 byte buffer"):
+        tree.insert_from_numpy(b"ab", [1], offsets=array.array("q", [0, 5]))
+    with pytest.raises(ValueError, match="non-decreasing"):
+        tree.insert_from_numpy(b"ab", [1], offsets=array.array("q", [2, 1]))
+    assert len(tree) == 0
+
+
+def test_invalid_utf8_rejected():
+    tree = TreeMap()
+    with pytest.raises(ValueError, match="index 1"):
+        tree.insert_from_numpy(b"a\xff", [1, 2], offsets=array.array("q", [0, 1, 2]))
+    assert len(tree) == 0
+
+
+def test_length_mismatch():
+    tree = TreeMap()
+    with pytest.raises(ValueError, match="1 keys but 2 values"):
+        tree.insert_from_numpy(["a"], [1, 2])
+
+
+def test_plain_sequences_fall_back():
+    """Inputs that aren't string arrays are inserted element by element."""
+    tree = TreeMap(key_type=int)
+    tree.insert_from_numpy([2, 1], (20, 10))
+    assert list(tree.items()) == [(1, 10), (2, 20)]
+
+
+def test_tracked_trees_go_through_store():
+    """Observers and size limits see keys inserted from arrays."""
+    events = []
+    tree = TreeMap(max_len=2, eviction="evict_oldest")
+    tree.on_change(lambda change, key, old, new: events.append((change, key)))
+    tree.insert_from_numpy(b"abc", [1, 2, 3], offsets=array.array("q", [0, 1, 2, 3]))
+    assert list(tree.keys()) == ["b", "c"]
+    assert ("evict", "a") in events
+
+
+def test_numpy_arrays():
+    np = pytest.importorskip("numpy")
+    tree = TreeMap()
+    tree.insert_from_numpy(np.array(["b", "a", "é"]), np.array([2, 1, 3]))
+    assert list(tree.items()) == [("a", 1), ("b", 2), ("é", 3)]
+
+    raw = TreeMap(key_type=bytes)
+    raw.insert_from_numpy(np.array([b"xy", b"z"]), np.arange(2))
+    assert list(raw.items()) == [(b"xy", 0), (b"z", 1)]
+    with pytest.raises(TypeError, match="str keys"):
+        raw.insert_from_numpy(np.array(["a"]), [1])
+
+
+def test_numpy_case_insensitive():
+    np = pytest.importorskip("numpy")
+    tree = TreeMap(case_insensitive=True)
+    tree.insert_from_numpy(np.array(["Apple", "banana"]), [1, 2])
+    assert tree["APPLE"] == 1
+
+
+def test_get_many_numpy_checks_before_importing():
+    """Bad dtypes and non-numeric values fail whether or not NumPy is installed."""
+    tree = TreeMap({"a": "text"})
+    with pytest.raises(ValueError, match="dtype"):
+        tree.get_many_numpy(["a"], dtype="float32")
+    with pytest.raises(TypeError):
+        tree.get_many_numpy(["a"])
+
+
+def test_get_many_numpy():
+    np = pytest.importorskip("numpy")
+    tree = TreeMap({"a": 1.5, "b": 2})
+    values, found = tree.get_many_numpy(np.array(["a", "x", "b"]))
+    assert values.dtype == np.float64
+    assert np.isnan(values[1])
+    assert values[[0, 2]].tolist() == [1.5, 2.0]
+    assert found.tolist() == [True, False, True]
+
+
+def test_get_many_numpy_int64_with_offsets():
+    np = pytest.importorskip("numpy")
+    tree = TreeMap({"a": 1, "b": 2})
+    offsets = np.array([0, 1, 2, 3])
+    values, found = tree.get_many_numpy(b"axb", dtype="int64", fill=-1, offsets=offsets)
+    assert values.dtype == np.int64
+    assert values.tolist() == [1, -1, 2]
+    assert found.tolist() == [True, False, True]
+
+
+@pytest.mark.parametrize("value_type", ["int", "float"])
+def test_get_many_numpy_native_values(value_type):
+    """Natively stored numbers convert to either dtype like Python objects do."""
+    np = pytest.importorskip("numpy")
+    tree = TreeMap({"a": 3, "b": 2**53 + 1}, value_type=value_type)
+    plain = TreeMap(tree)
+    keys = np.array(["a", "x", "b"])
+    for dtype in ["float64"] + (["int64"] if value_type == "int" else []):
+        values, found = tree.get_many_numpy(keys, dtype=dtype, fill=7)
+        expected, _ = plain.get_many_numpy(keys, dtype=dtype, fill=7)
+        assert values.tolist() == expected.tolist()
+        assert found.tolist() == [True, False, True]
+    if value_type == "float":
+        with pytest.raises(TypeError):
+            tree.get_many_numpy(keys, dtype="int64")
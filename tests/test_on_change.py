@@ -0,0 +1,97 @@
+"""Tests for change callbacks registered with on_change()."""
+
+import pytest
+from blart import TreeMap
+
+
+def observed(tree):
+    """Register a callback on tree and return the list it appends to."""
+    events = []
+    tree.on_change(lambda *change: events.append(change))
+    return events
+
+
+def test_insert_overwrite_remove():
+    tree = TreeMap()
+    events = observed(tree)
+    tree["a"] = 1
+    tree["a"] = 2
+    tree.update({"b": 3})
+    assert tree.pop("a") == 2
+    del tree["b"]
+    assert events == [
+        ("insert", "a", None, 1),
+        ("overwrite", "a", 1, 2),
+        ("insert", "b", None, 3),
+        ("remove", "a", 2, None),
+        ("remove", "b", 3, None),
+    ]
+
+
+def test_prefix_displacement_is_an_eviction():
+    """force_insert semantics drop stored keys that conflict with a new one."""
+    tree = TreeMap({"ab": 1, "ac": 2})
+    events = observed(tree)
+    tree["a"] = 3
+    assert events == [
+        ("evict", "ab", 1, None),
+        ("evict", "ac", 2, None),
+        ("insert", "a", None, 3),
+    ]
+
+
+def test_max_len_eviction():
+    tree = TreeMap({"a": 1}, max_len=1, eviction="evict_first")
+    events = observed(tree)
+    tree["b"] = 2
+    assert events == [("evict", "a", 1, None), ("insert", "b", None, 2)]
+
+
+def test_bulk_operations_report_each_key():
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    events = observed(tree)
+    tree.retain(lambda key, value: value != 2)
+    tree.clear()
+    assert events == [
+        ("remove", "b", 2, None),
+        ("remove", "a", 1, None),
+        ("remove", "c", 3, None),
+    ]
+
+
+def test_case_insensitive_keys_keep_their_spelling():
+    tree = TreeMap(case_insensitive=True)
+    events = observed(tree)
+    tree["Apple"] = 1
+    tree["APPLE"] = 2
+    tree.remove("apple")
+    assert [key for _, key, _, _ in events] == ["Apple", "Apple", "Apple"]
+
+
+def test_decorator_and_off_change():
+    tree = TreeMap()
+    events = []
+
+    @tree.on_change
+    def record(event, key, old_value, new_value):
+        events.append(event)
+
+    tree["a"] = 1
+    tree.off_change(record)
+    tree["b"] = 2
+    assert events == ["insert"]
+    with pytest.raises(ValueError):
+        tree.off_change(record)
+    with pytest.raises(TypeError):
+        tree.on_change(42)
+
+
+def test_callback_errors_propagate_after_the_change():
+    def fail(event, key, old_value, new_value):
+        raise RuntimeError("mirror unavailable")
+
+    tree = TreeMap()
+    tree.on_change(fail)
+    with pytest.raises(RuntimeError, match="mirror unavailable"):
+        tree["a"] = 1
+    assert tree["a"] == 1
@@ -0,0 +1,29 @@
+"""Tests for pandas conversion."""
+
+import pytest
+from blart import TreeMap
+
+pd = pytest.importorskip("pandas")
+pytest.importorskip("pyarrow")
+
+
+def test_from_series():
+    tree = TreeMap.from_pandas(pd.Series([2, 1], index=["b", "a"]))
+    assert list(tree.items()) == [("a", 1), ("b", 2)]
+
+
+def test_from_dataframe():
+    df = pd.DataFrame({"x": [1, 2], "y": [3, 4]}, index=["p", "q"])
+    assert list(TreeMap.from_pandas(df, "y").items()) == [("p", 3), ("q", 4)]
+    with pytest.raises(ValueError, match="column is required"):
+        TreeMap.from_pandas(df)
+    single = pd.DataFrame({"x": [5]}, index=["k"])
+    assert dict(TreeMap.from_pandas(single)) == {"k": 5}
+
+
+def test_round_trip():
+    tree = TreeMap({"b": 2, "a": 1, "c": 3})
+    series = tree.to_pandas()
+    assert list(series.index) == ["a", "b", "c"]
+    assert series["b"] == 2
+    assert dict(TreeMap.from_pandas(series)) == dict(tree)
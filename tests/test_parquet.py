@@ -0,0 +1,61 @@
+"""Tests for loading TreeMaps from Parquet files."""
+
+import datetime
+
+import pytest
+from blart import TreeMap
+
+
+def write(path, columns):
+    pa = pytest.importorskip("pyarrow")
+    pq = pytest.importorskip("pyarrow.parquet")
+    pq.write_table(pa.table(columns), path)
+    return path
+
+
+def test_from_parquet(tmp_path):
+    path = write(tmp_path / "words.parquet", {"word": ["b", "a"], "count": [2, 1], "other": [0.5, 1.5]})
+    tree = TreeMap.from_parquet(path, "word", "count")
+    assert list(tree.items()) == [("a", 1), ("b", 2)]
+    assert dict(TreeMap.from_parquet(str(path), "word", "word")) == {"a": "a", "b": "b"}
+
+
+def test_column_types(tmp_path):
+    pa = pytest.importorskip("pyarrow")
+    columns = {
+        "key": [b"b", b"a"],
+        "float": [0.5, None],
+        "flag": [True, False],
+        "big": pa.array([2**64 - 1, 0], pa.uint64()),
+        "n": pa.array([3, 4], pa.int8()),
+    }
+    path = write(tmp_path / "types.parquet", columns)
+    assert dict(TreeMap.from_parquet(path, "key", "float", key_type=bytes)) == {b"a": None, b"b": 0.5}
+    assert dict(TreeMap.from_parquet(path, "key", "flag", key_type=bytes)) == {b"a": False, b"b": True}
+    assert dict(TreeMap.from_parquet(path, "key", "big", key_type=bytes)) == {b"a": 0, b"b": 2**64 - 1}
+    assert dict(TreeMap.from_parquet(path, "n", "key", key_type=int)) == {3: b"b", 4: b"a"}
+
+
+def test_errors(tmp_path):
+    path = write(
+        tmp_path / "bad.parquet",
+        {"k": ["a", None], "day": [datetime.date(2024, 1, 1), None], "v": [1, 2]},
+    )
+    with pytest.raises(ValueError, match="key column contains nulls"):
+        TreeMap.from_parquet(path, "k", "v")
+    with pytest.raises(ValueError, match="no column named"):
+        TreeMap.from_parquet(path, "k", "missing")
+    with pytest.raises(TypeError, match='column "day"'):
+        TreeMap.from_parquet(path, "v", "day", key_type=int)
+
+
+def test_missing_file(tmp_path):
+    with pytest.raises(OSError):
+        TreeMap.from_parquet(tmp_path / "missing.parquet", "k", "v")
+
+
+def test_not_parquet(tmp_path):
+    path = tmp_path / "words.parquet"
+    path.write_text("word,count\n")
+    with pytest.raises(ValueError, match="Parquet"):
+        TreeMap.from_parquet(path, "word", "count")
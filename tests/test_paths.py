@@ -0,0 +1,98 @@
+"""Tests for path keys and directory-style helpers."""
+
+from pathlib import Path, PurePosixPath, PureWindowsPath
+
+import pytest
+from blart import TreeMap
+
+
+@pytest.fixture
+def fs():
+    return TreeMap(
+        {
+            "README.md": 1,
+            "src/lib.rs": 2,
+            "src/codec/mod.rs": 3,
+            "src/codec/path.rs": 4,
+            "src/treemap.rs": 5,
+            "src-old/main.rs": 6,
+            "tests/test_paths.py": 7,
+        },
+        key_type="path",
+    )
+
+
+def test_path_keys_are_normalized():
+    """Equivalent spellings of a path refer to the same key."""
+    tree = TreeMap(key_type="path")
+    tree[Path("dir") / "file.txt"] = 1
+    assert tree["dir/file.txt"] == 1
+    assert tree["./dir//file.txt"] == 1
+    assert tree[PureWindowsPath("dir\\file.txt")] == 1
+    assert tree["dir/file.txt/"] == 1
+    assert list(tree) == ["dir/file.txt"]
+    assert TreeMap(key_type=PurePosixPath).key_type == "path"
+
+
+def test_directory_and_file_keys_coexist():
+    """A directory key does not evict the files below it."""
+    tree = TreeMap(key_type="path")
+    tree["dir"] = "folder"
+    tree["dir/file"] = "file"
+    assert len(tree) == 2
+    assert list(tree.items()) == [("dir", "folder"), ("dir/file", "file")]
+
+
+def test_children(fs):
+    """children() lists immediate names, including implied directories."""
+    assert fs.children() == ["README.md", "src", "src-old", "tests"]
+    assert fs.children("src") == ["codec", "lib.rs", "treemap.rs"]
+    assert fs.children(Path("src/codec")) == ["mod.rs", "path.rs"]
+    assert fs.children("missing") == []
+
+
+def test_descendants(fs):
+    """descendants() yields every entry below a directory."""
+    assert [k for k, _ in fs.descendants("src/")] == [
+        "src/codec/mod.rs",
+        "src/codec/path.rs",
+        "src/lib.rs",
+        "src/treemap.rs",
+    ]
+    assert len(list(fs.descendants())) == len(fs)
+
+
+def test_custom_separator():
+    """A custom separator is used for storage and directory helpers."""
+    tree = TreeMap({"a/b": 1, "a\\c": 2, "a:d": 3}, key_type="path", separator=":")
+    assert list(tree) == ["a:b", "a:c", "a:d"]
+    assert tree.children("a") == ["b", "c", "d"]
+
+
+def test_path_option_errors():
+    """separator and directory helpers require path keys."""
+    with pytest.raises(ValueError):
+        TreeMap(separator="/")
+    with pytest.raises(ValueError):
+        TreeMap(key_type="path", separator="::")
+    with pytest.raises(TypeError):
+        TreeMap({1: "a"}, key_type="int").children()
+    with pytest.raises(ValueError):
+        TreeMap(key_type="path").children("a", separator=":")
+    with pytest.raises(TypeError):
+        TreeMap(key_type="path")[b"bytes/path"] = 1
+
+
+def test_absolute_and_relative_paths_are_distinct():
+    """A leading separator keeps an absolute path apart from a relative one."""
+    tree = TreeMap(key_type="path")
+    tree["/etc/passwd"] = "absolute"
+    tree["etc/passwd"] = "relative"
+    assert len(tree) == 2
+    assert tree["//etc/./passwd"] == "absolute"
+    assert tree[PureWindowsPath("\\etc\\passwd")] == "absolute"
+    assert list(tree) == ["/etc/passwd", "etc/passwd"]
+    assert tree.children() == ["/", "etc"]
+    assert tree.children("/") == ["etc"]
+    assert tree.children("/etc") == ["passwd"]
+    assert list(tree.descendants("/")) == [("/etc/passwd", "absolute")]
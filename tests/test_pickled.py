@@ -0,0 +1,95 @@
+"""Tests for storing values pickled with store_pickled."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_values_round_trip():
+    tree = TreeMap({"a": {"x": [1, 2]}, "b": (3, "c")}, store_pickled=True)
+    assert tree.store_pickled
+    assert not TreeMap().store_pickled
+    assert tree["a"] == {"x": [1, 2]}
+    assert tree.get("b") == (3, "c")
+    assert tree.to_dict() == {"a": {"x": [1, 2]}, "b": (3, "c")}
+    assert list(tree.values()) == [{"x": [1, 2]}, (3, "c")]
+    assert list(tree.prefix_iter("a")) == [("a", {"x": [1, 2]})]
+    assert tree == {"a": {"x": [1, 2]}, "b": (3, "c")}
+
+
+def test_recent_reads_are_cached():
+    tree = TreeMap({"a": [1], "b": [2], "c": [3]}, store_pickled=True, pickle_cache_size=2)
+    first = tree["a"]
+    assert tree["a"] is first
+    tree["b"]
+    tree["c"]
+    # "a" was evicted by the two later reads
+    assert tree["a"] is not first
+    assert tree["a"] == first
+    uncached = TreeMap({"a": [1]}, store_pickled=True, pickle_cache_size=0)
+    assert uncached["a"] is not uncached["a"]
+
+
+def test_reads_refresh_cached_values():
+    tree = TreeMap({"a": [1], "b": [2], "c": [3]}, store_pickled=True, pickle_cache_size=2)
+    a, b = tree["a"], tree["b"]
+    assert tree["a"] is a
+    tree["c"]
+    # "b" is now the least recently used and was evicted in place of "a"
+    assert tree["a"] is a
+    assert tree["b"] is not b
+
+
+def test_stored_values_are_copies():
+    value = [1, 2]
+    tree = TreeMap(store_pickled=True, pickle_cache_size=0)
+    tree["a"] = value
+    value.append(3)
+    assert tree["a"] == [1, 2]
+    tree["a"] = value
+    assert tree["a"] == [1, 2, 3]
+
+
+def test_removals_return_values():
+    tree = TreeMap({"a": [1], "b": [2], "c": [3], "d": [4]}, store_pickled=True)
+    assert tree.pop("a") == [1]
+    assert tree.remove("b") == [2]
+    assert tree.popitem() == ("d", [4])
+    assert tree.pop_first() == ("c", [3])
+    assert len(tree) == 0
+
+
+def test_updates_see_unpickled_values():
+    tree = TreeMap({"hits": 1, "seen": ["a"]}, store_pickled=True)
+    assert tree.increment("hits") == 2
+    assert tree.apply("seen", lambda seen: seen + ["b"]) == ["a", "b"]
+    assert tree.setdefault("seen", []) == ["a", "b"]
+    tree.retain(lambda key, value: key == "seen")
+    assert tree.to_list() == [("seen", ["a", "b"])]
+    doubled = tree.map_values(lambda seen: seen * 2)
+    assert doubled.store_pickled
+    assert doubled["seen"] == ["a", "b", "a", "b"]
+
+
+def test_observers_receive_unpickled_values():
+    tree = TreeMap({"a": [1]}, store_pickled=True)
+    changes = []
+    tree.on_change(lambda *change: changes.append(change))
+    tree["a"] = [2]
+    del tree["a"]
+    assert changes == [("overwrite", "a", [1], [2]), ("remove", "a", [2], None)]
+
+
+def test_unpicklable_value_is_rejected():
+    tree = TreeMap(store_pickled=True)
+    with pytest.raises(Exception):
+        tree["a"] = lambda: None
+    assert "a" not in tree
+
+
+def test_combines_with_value_type():
+    tree = TreeMap(value_type="float", store_pickled=True)
+    tree["a"] = 1
+    assert tree["a"] == 1.0
+    assert type(tree["a"]) is float
+    with pytest.raises(TypeError):
+        tree["b"] = "x"
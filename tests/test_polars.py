@@ -0,0 +1,21 @@
+"""Tests for polars conversion."""
+
+import pytest
+from blart import TreeMap
+
+pl = pytest.importorskip("polars")
+pytest.importorskip("pyarrow")
+
+
+def test_from_polars():
+    df = pl.DataFrame({"word": ["b", "a", "c"], "count": [2, 1, 3]})
+    tree = TreeMap.from_polars(df, "word", "count")
+    assert list(tree.items()) == [("a", 1), ("b", 2), ("c", 3)]
+
+
+def test_round_trip():
+    tree = TreeMap({"b": 2, "a": 1})
+    df = tree.to_polars()
+    assert df.columns == ["key", "value"]
+    assert df["key"].to_list() == ["a", "b"]
+    assert dict(TreeMap.from_polars(df, "key", "value")) == dict(tree)
@@ -1,5 +1,6 @@
 """Tests for prefix query functionality."""
 
+import pytest
 from blart import TreeMap
 
 
@@ -169,3 +170,235 @@ def test_prefix_with_various_value_types():
     assert [1, 2, 3] in values
     assert {"nested": "dict"} in values
     assert None in values
+
+
+def test_prefix_iter_diverging_inside_compressed_prefix():
+    """Prefixes that diverge inside a shared key segment match nothing."""
+    tree = TreeMap({"src-old/main.rs": 1, "src/lib.rs": 2, "tests/t.py": 3})
+    assert list(tree.prefix_iter("ss")) == []
+    assert list(tree.prefix_iter("srd")) == []
+    assert tree.get_prefix("sx") is None
+    assert [k for k, _ in tree.prefix_iter("sr")] == ["src-old/main.rs", "src/lib.rs"]
+
+
+def test_autocomplete_ranks_by_value():
+    """autocomplete returns the k highest values under the prefix."""
+    words = TreeMap({"help": 40, "hello": 120, "helm": 3, "world": 500})
+    assert words.autocomplete("hel", 2) == [("hello", 120), ("help", 40)]
+    assert words.autocomplete("hel") == [("hello", 120), ("help", 40), ("helm", 3)]
+    assert words.autocomplete("hel", 0) == []
+    assert words.autocomplete("xyz", 3) == []
+
+
+def test_autocomplete_ties_keep_key_order():
+    """Entries with equal scores come back in key order."""
+    tree = TreeMap({"ab": 1, "ac": 2, "ad": 2, "ae": 2})
+    assert tree.autocomplete("a", 2) == [("ac", 2), ("ad", 2)]
+
+
+def test_autocomplete_score_field_and_callable():
+    """score can name a field of the value or be a callable."""
+
+    class Page:
+        def __init__(self, views):
+            self.views = views
+
+    pages = TreeMap({"home": {"views": 9}, "how-to": {"views": 30}})
+    assert pages.autocomplete("ho", 1, score="views") == [("how-to", {"views": 30})]
+    objects = TreeMap({"home": Page(9), "how-to": Page(30)})
+    assert [k for k, _ in objects.autocomplete("ho", score="views")] == ["how-to", "home"]
+    words = TreeMap({"help": 40, "hello": 120, "helm": 3})
+    assert words.autocomplete("hel", 1, score=lambda count: -count) == [("helm", 3)]
+
+
+def test_autocomplete_non_numeric_score():
+    """Scores that are not numbers raise TypeError."""
+    tree = TreeMap({"apple": "red"})
+    with pytest.raises(TypeError):
+        tree.autocomplete("a")
+
+
+def test_contains_substring_with_index():
+    """The suffix index finds keys containing a fragment and follows updates."""
+    tree = TreeMap({"book": 1, "cook": 2, "cake": 3}, substring_index=True)
+    assert tree.substring_index is True
+    assert list(tree.contains_substring("oo")) == [("book", 1), ("cook", 2)]
+    assert list(tree.contains_substring("k")) == [("book", 1), ("cake", 3), ("cook", 2)]
+    tree["noon"] = 4
+    del tree["book"]
+    assert list(tree.contains_substring("oo")) == [("cook", 2), ("noon", 4)]
+    assert list(tree.contains_substring("xyz")) == []
+    assert len(list(tree.contains_substring(""))) == 3
+    tree.clear()
+    assert list(tree.contains_substring("oo")) == []
+
+
+def test_contains_substring_without_index():
+    """Without the index every key is scanned, with the same results."""
+    tree = TreeMap({"book": 1, "cook": 2, "cake": 3})
+    assert tree.substring_index is False
+    assert list(tree.contains_substring("oo")) == [("book", 1), ("cook", 2)]
+
+
+def test_contains_substring_unicode_and_bytes():
+    """Fragments match whole characters for str keys and bytes for bytes keys."""
+    tree = TreeMap({"café": 1, "éclair": 2, "tea": 3}, substring_index=True)
+    assert [k for k, _ in tree.contains_substring("é")] == ["café", "éclair"]
+    raw = TreeMap({b"\x00ab": 1, b"zab": 2}, key_type=bytes, substring_index=True)
+    assert [k for k, _ in raw.contains_substring(b"ab")] == [b"\x00ab", b"zab"]
+
+
+def test_substring_index_requires_text_keys():
+    """Only str and bytes trees can keep a substring index."""
+    with pytest.raises(ValueError):
+        TreeMap(key_type="int", substring_index=True)
+    with pytest.raises(TypeError):
+        TreeMap(key_type="int").contains_substring(1)
+
+
+def test_endswith_iter_with_suffix_index():
+    """The reversed-key index finds keys by suffix and follows updates."""
+    files = TreeMap({"a.json": 1, "b.txt": 2, "c/d.json": 3})
+    assert files.suffix_index is False
+    files.enable_suffix_index()
+    files.enable_suffix_index()
+    assert files.suffix_index is True
+    assert list(files.endswith_iter(".json")) == [("a.json", 1), ("c/d.json", 3)]
+    files["e.json"] = 4
+    files.pop("a.json")
+    assert list(files.endswith_iter(".json")) == [("c/d.json", 3), ("e.json", 4)]
+    assert list(files.endswith_iter("")) == list(files.items())
+    files.clear()
+    assert list(files.endswith_iter(".json")) == []
+
+
+def test_endswith_iter_without_index():
+    """Without the index every key is checked, with the same results."""
+    files = TreeMap({"a.json": 1, "b.txt": 2})
+    assert list(files.endswith_iter(".txt")) == [("b.txt", 2)]
+    with pytest.raises(TypeError):
+        TreeMap(key_type="int").enable_suffix_index()
+
+
+def test_regex_iter_fullmatch():
+    """regex_iter yields keys that fully match the pattern, in key order."""
+    logs = TreeMap({"log/2024-01-02": 1, "log/2024-02-10": 2, "tmp/x": 3})
+    assert list(logs.regex_iter(r"log/2024-01-\d+")) == [("log/2024-01-02", 1)]
+    assert [k for k, _ in logs.regex_iter(r".*-\d0")] == ["log/2024-02-10"]
+    assert [k for k, _ in logs.regex_iter("(log|tmp)/.*")] == [
+        "log/2024-01-02",
+        "log/2024-02-10",
+        "tmp/x",
+    ]
+    # Partial matches don't count
+    assert list(logs.regex_iter("log")) == []
+
+
+def test_regex_iter_is_lazy():
+    """Matches are found as the iterator advances, and mutation is detected."""
+    tree = TreeMap({f"k{i:03}": i for i in range(100)})
+    it = tree.regex_iter(r"k0\d5")
+    assert next(it) == ("k005", 5)
+    tree["k999"] = 0
+    with pytest.raises(RuntimeError):
+        next(it)
+
+
+def test_regex_iter_bytes_and_errors():
+    """Bytes keys match byte-wise; bad patterns raise ValueError."""
+    raw = TreeMap({b"\xffa": 1, b"a\x00": 2}, key_type=bytes)
+    assert [k for k, _ in raw.regex_iter(".a")] == [b"\xffa"]
+    with pytest.raises(ValueError):
+        TreeMap({"a": 1}).regex_iter("(")
+    with pytest.raises(TypeError):
+        TreeMap(key_type="int").regex_iter(".*")
+
+
+def test_group_by_prefix_counts():
+    """group_by_prefix counts the keys below each next segment."""
+    files = TreeMap(
+        {"src/a.rs": 10, "src/b.rs": 20, "src/x/y.rs": 1, "docs/x.md": 5, "README": 1}
+    )
+    assert files.group_by_prefix() == {"README": 1, "docs": 1, "src": 3}
+    assert files.group_by_prefix("src/") == {"a.rs": 1, "b.rs": 1, "x": 1}
+    assert files.group_by_prefix("nothing/") == {}
+    assert list(files.group_by_prefix()) == ["README", "docs", "src"]
+
+
+def test_group_by_prefix_separator_and_op():
+    """Custom separators and aggregates of the grouped values."""
+    sales = TreeMap({"eu:paris:a": 3, "eu:paris:b": 4, "eu:rome:a": 5, "us:nyc:a": 10})
+    assert sales.group_by_prefix(separator=":", op="sum") == {"eu": 12, "us": 10}
+    assert sales.group_by_prefix("eu:", ":", op="max") == {"paris": 4, "rome": 5}
+    indexed = TreeMap(dict(sales.items()), order_statistics=True)
+    assert indexed.group_by_prefix("eu:", ":") == {"paris": 2, "rome": 1}
+    with pytest.raises(ValueError):
+        sales.group_by_prefix(separator="")
+    with pytest.raises(TypeError):
+        TreeMap({1: "a"}, key_type="int").group_by_prefix()
+
+
+def test_children_of_str_keys():
+    """children lists unique next segments under a prefix."""
+    users = TreeMap(
+        {"user:1:name": "ann", "user:1:age": 3, "user:2:name": "bob", "team:7": 0}
+    )
+    assert users.children() == ["team", "user"]
+    assert users.children("user") == ["1", "2"]
+    assert users.children("user:") == ["1", "2"]
+    assert users.children("user:1") == ["age", "name"]
+    assert users.children("nobody") == []
+    files = TreeMap({"a/b/c": 1, "a/d": 2})
+    assert files.children("a", separator="/") == ["b", "d"]
+    with pytest.raises(ValueError):
+        files.children(separator="")
+
+
+def test_children_of_bytes_keys():
+    """Bytes trees take and return bytes."""
+    tree = TreeMap({b"k\x00a": 1, b"k\x00b\x00c": 2}, key_type=bytes)
+    assert tree.children(b"k", separator=b"\x00") == [b"a", b"b"]
+
+
+def test_prefix_page_walks_every_match():
+    """Pages resumed from their tokens cover the prefix exactly once."""
+    tree = TreeMap({f"user:{i:03}": i for i in range(25)})
+    tree["other"] = -1
+    seen = []
+    token = None
+    while True:
+        page, token = tree.prefix_page("user:", 10, after_key=token)
+        seen.extend(page)
+        if token is None:
+            break
+        assert token == page[-1][0]
+    assert seen == list(tree.prefix_iter("user:"))
+
+
+def test_prefix_page_edges():
+    """Empty prefixes, exact final pages and stale tokens are handled."""
+    tree = TreeMap({"a1": 1, "a2": 2, "b1": 3})
+    assert tree.prefix_page("a", 2) == ([("a1", 1), ("a2", 2)], None)
+    assert tree.prefix_page("z", 5) == ([], None)
+    assert tree.prefix_page("a", 0) == ([], None)
+    # A removed token key still resumes after its position
+    page, token = tree.prefix_page("a", 1)
+    del tree[token]
+    assert tree.prefix_page("a", 1, after_key=token) == ([("a2", 2)], None)
+    # Tokens before the prefix start at its first key
+    assert tree.prefix_page("b", 5, after_key="a") == ([("b1", 3)], None)
+
+
+def test_prefix_keys_and_values():
+    """prefix_keys() and prefix_values() yield one side of the matches."""
+    tree = TreeMap({"apple": 1, "application": 2, "apply": 3, "banana": 4})
+    assert list(tree.prefix_keys("app")) == ["apple", "application", "apply"]
+    assert list(tree.prefix_values("app")) == [1, 2, 3]
+    assert list(tree.prefix_keys("app", offset=1, limit=1)) == ["application"]
+    assert list(tree.prefix_values("", limit=2)) == [1, 2]
+    assert list(tree.prefix_keys("cherry")) == []
+    keys = tree.prefix_keys("a")
+    assert keys.remaining() == 3
+    tree["avocado"] = 5
+    with pytest.raises(RuntimeError):
+        next(keys)
@@ -0,0 +1,105 @@
+"""Tests for the pygtrie compatible methods."""
+
+import pytest
+from blart import TreeMap
+
+
+@pytest.fixture
+def tree():
+    return TreeMap({"foo/bar": 1, "foo/baz": 2, "qux": 3})
+
+
+def node(path_conv, path, children, *value):
+    """Node factory building (key, value, children) tuples."""
+    return (path_conv(path), value[0] if value else None, list(children))
+
+
+def test_has_subtrie(tree):
+    assert tree.has_subtrie("foo")
+    assert tree.has_subtrie("")
+    assert not tree.has_subtrie("qux")
+    assert not tree.has_subtrie("zzz")
+    assert tree.has_key("qux")
+    assert not tree.has_key("foo")
+
+
+def test_longest_and_shortest_prefix():
+    routes = TreeMap({"/api/": "api", "/static/": "files"})
+    assert routes.longest_prefix("/api/users") == ("/api/", "api")
+    assert routes.shortest_prefix("/api/users") == ("/api/", "api")
+    assert routes.longest_prefix("/api/") == ("/api/", "api")
+    assert routes.longest_prefix("/ap") is None
+    assert routes.longest_prefix("/admin") is None
+
+
+def test_longest_prefix_bytes():
+    tree = TreeMap({b"\x00\x01": "a"}, key_type="bytes")
+    assert tree.longest_prefix(b"\x00\x01\x02") == (b"\x00\x01", "a")
+    with pytest.raises(TypeError):
+        TreeMap({1: 1}, key_type="int").longest_prefix(1)
+
+
+def test_iteritems(tree):
+    assert list(tree.iteritems("foo/")) == [("foo/bar", 1), ("foo/baz", 2)]
+    assert list(tree.iteritems()) == [("foo/bar", 1), ("foo/baz", 2), ("qux", 3)]
+    assert list(tree.iterkeys("foo")) == ["foo/bar", "foo/baz"]
+    assert list(tree.itervalues("q")) == [3]
+    for method in (tree.iteritems, tree.iterkeys, tree.itervalues):
+        with pytest.raises(KeyError):
+            method("zzz")
+    assert list(TreeMap().iteritems()) == []
+
+
+def test_traverse(tree):
+    assert tree.traverse(node, "foo/b") == (
+        "foo/b",
+        None,
+        [("foo/ba", None, [("foo/bar", 1, []), ("foo/baz", 2, [])])],
+    )
+    root = tree.traverse(node)
+    assert root[0] == ""
+    assert [child[0] for child in root[2]] == ["f", "q"]
+    assert TreeMap().traverse(node) == ("", None, [])
+    with pytest.raises(KeyError):
+        tree.traverse(node, "zzz")
+
+
+def test_traverse_paths():
+    tree = TreeMap({"hé": 1, "ab": 2})
+    paths = []
+
+    def collect(path_conv, path, children, *value):
+        paths.append(path)
+        list(children)
+
+    tree.traverse(collect)
+    assert ("h", "é") in paths
+    assert ("a", "b") in paths
+
+    bytes_tree = TreeMap({b"ab": 1}, key_type="bytes")
+    assert bytes_tree.traverse(node) == (b"", None, [(b"a", None, [(b"ab", 1, [])])])
+
+
+def test_traverse_visits_children_lazily(tree):
+    visited = []
+
+    def prune(path_conv, path, children, *value):
+        key = path_conv(path)
+        visited.append(key)
+        if key != "foo":
+            return sum(children, len(value))
+        return 0
+
+    assert tree.traverse(prune) == 1
+    assert "foo/" not in visited
+    assert "qux" in visited
+
+
+def test_traverse_node_factory_may_modify_tree(tree):
+    def clear(path_conv, path, children, *value):
+        tree.clear()
+        return sum(children, len(value))
+
+    # The walk covers the entries stored when it started
+    assert tree.traverse(clear) == 3
+    assert len(tree) == 0
@@ -0,0 +1,164 @@
+"""Tests for range queries."""
+
+from datetime import datetime
+
+import pytest
+from blart import TreeMap
+
+
+def test_range_between_half_open():
+    """range_between() includes start and excludes end."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    assert list(tree.range_between("b", "d")) == [("b", 2), ("c", 3)]
+
+
+def test_range_between_open_bounds():
+    """Either bound may be None."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    assert list(tree.range_between(None, "b")) == [("a", 1)]
+    assert list(tree.range_between("b")) == [("b", 2), ("c", 3)]
+    assert list(tree.range_between()) == [("a", 1), ("b", 2), ("c", 3)]
+
+
+def test_range_between_empty_or_inverted():
+    """Empty and inverted ranges yield nothing."""
+    tree = TreeMap({"a": 1, "b": 2})
+    assert list(tree.range_between("b", "b")) == []
+    assert list(tree.range_between("c", "a")) == []
+    assert list(TreeMap().range_between("a", "z")) == []
+
+
+def test_range_between_int_keys():
+    """Integer ranges follow numeric order."""
+    tree = TreeMap({n: n * n for n in range(-5, 20)}, key_type="int")
+    assert [k for k, _ in tree.range_between(-2, 3)] == [-2, -1, 0, 1, 2]
+
+
+def test_range_between_datetimes():
+    """Datetime ranges select a time window."""
+    tree = TreeMap(key_type="datetime")
+    for day in range(1, 11):
+        tree[datetime(2024, 1, day)] = day
+    window = tree.range_between(datetime(2024, 1, 3), datetime(2024, 1, 6))
+    assert [v for _, v in window] == [3, 4, 5]
+
+
+def test_range_between_start_after_last_child():
+    """A start bound past the last branch of a shared prefix still finds later keys."""
+    tree = TreeMap({k: 1 for k in ["README.md", "src-old/x", "src/a", "src/b", "tests/t"]})
+    assert [k for k, _ in tree.range_between("src0")] == ["tests/t"]
+    assert [k for k, _ in tree.range_between("src/c", "u")] == ["tests/t"]
+    assert list(tree.range_between("zzz")) == []
+
+
+def test_delete_range_half_open():
+    """delete_range() removes start up to, but not including, stop."""
+    log = TreeMap({"2024-01": 1, "2024-02": 2, "2024-03": 3, "2024-04": 4})
+    assert log.delete_range("2024-01", "2024-03") == 2
+    assert list(log.keys()) == ["2024-03", "2024-04"]
+    assert log.delete_range("2024-05", "2024-09") == 0
+    assert len(log) == 2
+
+
+def test_delete_range_inclusive_flags_and_open_bounds():
+    """Either bound may be None, and each may be inclusive or not."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    assert tree.delete_range("a", "c", inclusive=(False, True)) == 2
+    assert list(tree.keys()) == ["a", "d"]
+    assert tree.delete_range(None, None) == 2
+    assert len(tree) == 0
+
+
+def test_delete_range_int_keys():
+    """Ranges follow the stored order of typed keys."""
+    tree = TreeMap({i: i for i in range(-5, 6)}, key_type="int")
+    assert tree.delete_range(-2, 3) == 5
+    assert list(tree.keys()) == [-5, -4, -3, 3, 4, 5]
+
+
+def test_count_range():
+    """count_range() counts keys in the same ranges delete_range() removes."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    assert tree.count_range("b", "d") == 2
+    assert tree.count_range("b", "d", inclusive=(False, True)) == 2
+    assert tree.count_range("b", "b", inclusive=(True, True)) == 1
+    assert tree.count_range("d", "a") == 0
+    assert tree.count_range(None, None) == 4
+    assert tree.count_range("b", None) == len(list(tree.range_between("b")))
+
+
+def test_aggregate_prefix():
+    """aggregate() rolls up the values under a prefix."""
+    sales = TreeMap({"eu:paris:a": 3, "eu:paris:b": 4, "eu:rome:a": 5, "us:nyc:a": 10})
+    assert sales.aggregate("eu:") == 12
+    assert sales.aggregate("eu:paris:", op="mean") == 3.5
+    assert sales.aggregate("eu:", op="min") == 3
+    assert sales.aggregate("eu:", op="max") == 5
+    assert sales.aggregate("eu:", op="count") == 3
+    assert sales.aggregate() == 22
+
+
+def test_aggregate_range():
+    """A slice selects a half-open key range."""
+    tree = TreeMap({"a": 1, "b": 2.5, "c": 4, "d": 8})
+    assert tree.aggregate(slice("b", "d")) == 6.5
+    assert tree.aggregate(slice(None, "c"), op="max") == 2.5
+    assert tree.aggregate(slice("e", None)) == 0
+    assert tree.aggregate(slice("e", None), op="mean") is None
+    with pytest.raises(ValueError):
+        tree.aggregate(slice("a", "c", 2))
+
+
+def test_aggregate_types():
+    """Integer sums stay exact; values must be numbers."""
+    big = TreeMap({"a": 2**62, "b": 2**62, "c": 2**62})
+    assert big.aggregate() == 3 * 2**62
+    mixed = TreeMap({"a": 1, "b": "x"})
+    assert mixed.aggregate(op="count") == 2
+    with pytest.raises(TypeError):
+        mixed.aggregate()
+    with pytest.raises(ValueError):
+        mixed.aggregate(op="median")
+
+
+@pytest.mark.parametrize("order_statistics", [False, True])
+def test_rank_select_and_counts(order_statistics):
+    """rank, select and counts agree with or without order statistics."""
+    tree = TreeMap(
+        {"apple": 1, "apply": 2, "banana": 3, "cherry": 4},
+        order_statistics=order_statistics,
+    )
+    assert tree.order_statistics is order_statistics
+    assert tree.rank("apple") == 0
+    assert tree.rank("b") == 2
+    assert tree.rank("zzz") == 4
+    assert tree.select(0) == ("apple", 1)
+    assert tree.select(-1) == ("cherry", 4)
+    with pytest.raises(IndexError):
+        tree.select(4)
+    with pytest.raises(IndexError):
+        tree.select(-5)
+    assert tree.count_prefix("app") == 2
+    assert tree.count_prefix("") == 4
+    assert tree.count_prefix("z") == 0
+    assert tree.count_range("apply", "cherry", inclusive=(True, True)) == 3
+
+
+def test_order_statistics_follow_updates():
+    """The order statistics stay in step with inserts and removals."""
+    tree = TreeMap(order_statistics=True)
+    for i in range(100):
+        tree[f"k{i:03}"] = i
+    del tree["k010"]
+    tree.pop("k020")
+    assert tree.delete_range("k050", "k060") == 10
+    tree.retain(lambda key, value: value % 2 == 0)
+    keys = list(tree.keys())
+    assert [tree.select(i)[0] for i in range(len(keys))] == keys
+    assert tree.rank("k050") == keys.index("k060")
+    assert tree.count_prefix("k09") == 5
+    copy = tree.map_values(lambda value: value)
+    tree.clear()
+    assert tree.count_prefix("k") == 0
+    assert copy.order_statistics
+    assert copy.count_prefix("k") == len(keys)
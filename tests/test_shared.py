@@ -0,0 +1,94 @@
+"""Tests for sharing a TreeMap between processes."""
+
+import multiprocessing
+import pickle
+
+import pytest
+from blart import TreeMap
+
+
+def lookup(args):
+    shared, key = args
+    return shared.get(key)
+
+
+def test_share_and_attach():
+    """An attached map sees the shared entries in key order."""
+    tree = TreeMap({"banana": [3], "apple": 1, "apricot": {"x": 2}})
+    with tree.share() as shared:
+        assert len(shared) == 3
+        attached = TreeMap.attach(shared.name)
+        assert list(attached) == ["apple", "apricot", "banana"]
+        assert attached["apricot"] == {"x": 2}
+        assert attached.get("cherry", 0) == 0
+        assert "banana" in attached
+        assert list(attached.prefix_iter("ap")) == [("apple", 1), ("apricot", {"x": 2})]
+        assert attached.first() == ("apple", 1)
+        assert attached.last() == ("banana", [3])
+        assert attached.values() == [1, {"x": 2}, [3]]
+        with pytest.raises(KeyError):
+            attached["cherry"]
+        attached.close()
+
+
+def test_shared_map_is_independent_of_tree():
+    """Later writes to the tree are not reflected in the shared copy."""
+    tree = TreeMap({"a": 1})
+    with tree.share() as shared:
+        tree["b"] = 2
+        assert shared.keys() == ["a"]
+
+
+def test_key_settings_are_shared():
+    """Key types and case-insensitive spellings survive sharing."""
+    with TreeMap({3: "c", -1: "a"}, key_type="int").share() as shared:
+        assert shared.key_type == "int"
+        assert shared.items() == [(-1, "a"), (3, "c")]
+    tree = TreeMap({"Apple": 1}, case_insensitive=True)
+    with tree.share() as shared:
+        assert shared["APPLE"] == 1
+        assert list(shared) == ["Apple"]
+
+
+def test_custom_codec_cannot_be_shared():
+    """Custom codec objects have no name to attach with."""
+
+    class Codec:
+        def encode(self, key):
+            return key
+
+        def decode(self, data):
+            return data
+
+    with pytest.raises(TypeError):
+        TreeMap(key_type=Codec()).share()
+
+
+def test_closed_and_unlinked():
+    """Closed maps refuse queries and unlinked blocks can't be attached."""
+    shared = TreeMap({"a": 1}).share()
+    name = shared.name
+    shared.close()
+    with pytest.raises(ValueError):
+        shared.get("a")
+    shared.unlink()
+    with pytest.raises(FileNotFoundError):
+        TreeMap.attach(name)
+
+
+def test_pickle_attaches():
+    """Pickling a shared map attaches to the same block."""
+    with TreeMap({"a": 1}).share() as shared:
+        copy = pickle.loads(pickle.dumps(shared))
+        assert copy.name == shared.name
+        assert copy["a"] == 1
+        copy.close()
+
+
+def test_pool_workers_query_shared_map():
+    """multiprocessing workers can query the shared map directly."""
+    tree = TreeMap({f"key_{i:03d}": i for i in range(100)})
+    with tree.share() as shared:
+        with multiprocessing.Pool(2) as pool:
+            results = pool.map(lookup, [(shared, "key_042"), (shared, "missing")])
+    assert results == [42, None]
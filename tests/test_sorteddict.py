@@ -0,0 +1,78 @@
+"""Tests for the sortedcontainers.SortedDict compatible methods."""
+
+import pytest
+from blart import TreeMap
+
+
+@pytest.fixture
+def tree():
+    return TreeMap({"a": 1, "c": 3, "e": 5, "g": 7})
+
+
+@pytest.mark.parametrize("order_statistics", [False, True])
+def test_bisect(order_statistics):
+    tree = TreeMap({"a": 1, "c": 3, "e": 5}, order_statistics=order_statistics)
+    assert tree.bisect_left("c") == 1
+    assert tree.bisect_right("c") == 2
+    assert tree.bisect_left("d") == tree.bisect_right("d") == 2
+    assert tree.bisect_left("") == 0
+    assert tree.bisect_right("z") == 3
+
+
+def test_index(tree):
+    assert [tree.index(key) for key in tree] == [0, 1, 2, 3]
+    assert tree.index("e", 2) == 2
+    assert tree.index("e", -2, -1) == 2
+    with pytest.raises(ValueError, match="'e' is not in TreeMap"):
+        tree.index("e", 0, 2)
+    with pytest.raises(ValueError):
+        tree.index("b")
+
+
+def test_peekitem(tree):
+    assert tree.peekitem() == ("g", 7)
+    assert tree.peekitem(0) == ("a", 1)
+    assert tree.peekitem(-3) == ("c", 3)
+    with pytest.raises(IndexError):
+        tree.peekitem(4)
+    with pytest.raises(IndexError):
+        TreeMap().peekitem()
+
+
+def test_irange(tree):
+    assert list(tree.irange()) == ["a", "c", "e", "g"]
+    assert list(tree.irange("c", "e")) == ["c", "e"]
+    assert list(tree.irange("b", "f")) == ["c", "e"]
+    assert list(tree.irange("c", "g", inclusive=(False, False))) == ["e"]
+    assert list(tree.irange(None, "e", reverse=True)) == ["e", "c", "a"]
+    assert list(tree.irange("x", None)) == []
+
+
+def test_irange_int_keys():
+    tree = TreeMap({n: n for n in [-5, 3, 10, 200]}, key_type="int")
+    assert list(tree.irange(0, 100)) == [3, 10]
+
+
+def test_iloc_get(tree):
+    assert len(tree.iloc) == 4
+    assert tree.iloc[0] == "a"
+    assert tree.iloc[-1] == "g"
+    assert tree.iloc[1:3] == ["c", "e"]
+    assert tree.iloc[::-2] == ["g", "c"]
+    with pytest.raises(IndexError):
+        tree.iloc[4]
+    with pytest.raises(TypeError):
+        tree.iloc["a"]
+
+
+def test_iloc_delete(tree):
+    changes = []
+    tree.on_change(lambda *change: changes.append(change))
+    del tree.iloc[0]
+    assert list(tree) == ["c", "e", "g"]
+    del tree.iloc[-2:]
+    assert list(tree) == ["c"]
+    assert changes[0] == ("remove", "a", 1, None)
+    assert len(changes) == 3
+    with pytest.raises(IndexError):
+        del tree.iloc[5]
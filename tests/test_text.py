@@ -0,0 +1,106 @@
+"""Tests for matching stored keys inside text."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_scan_text_finds_overlapping_occurrences():
+    """scan_text reports every occurrence, including overlapping ones."""
+    places = TreeMap({"New York": "US", "York": "UK", "Paris": "FR"})
+    assert places.scan_text("From New York to Paris") == [
+        (5, 13, "New York", "US"),
+        (9, 13, "York", "UK"),
+        (17, 22, "Paris", "FR"),
+    ]
+    assert places.scan_text("nothing here") == []
+    assert TreeMap().scan_text("text") == []
+
+
+def test_scan_text_character_offsets():
+    """Offsets count characters, so they slice the original string."""
+    tree = TreeMap({"café": 1, "thé": 2})
+    text = "un café, un thé"
+    matches = tree.scan_text(text)
+    assert [(s, e) for s, e, _, _ in matches] == [(3, 7), (12, 15)]
+    assert [text[s:e] for s, e, _, _ in matches] == ["café", "thé"]
+
+
+def test_scan_text_case_insensitive():
+    """Case-insensitive trees match the text regardless of case."""
+    tree = TreeMap({"Rust": 1}, case_insensitive=True)
+    assert tree.scan_text("RUST and rust") == [(0, 4, "Rust", 1), (9, 13, "Rust", 1)]
+    streets = TreeMap({"strasse": 1}, case_insensitive=True)
+    assert streets.scan_text("Straße") == [(0, 6, "strasse", 1)]
+
+
+def test_scan_text_bytes_and_types():
+    """Bytes trees scan bytes; other key types are rejected."""
+    tree = TreeMap({b"\x00\x01": "marker"}, key_type=bytes)
+    assert tree.scan_text(b"ab\x00\x01") == [(2, 4, b"\x00\x01", "marker")]
+    with pytest.raises(TypeError):
+        tree.scan_text("text")
+    with pytest.raises(TypeError):
+        TreeMap(key_type="int").scan_text("1")
+
+
+def test_segment_greedy():
+    """segment splits text into keys and unmatched runs."""
+    units = TreeMap({"kg": 1000, "g": 1, "mg": 0.001})
+    assert units.segment("12kg") == [("12", None), ("kg", 1000)]
+    assert units.segment("5mg and 3g") == [
+        ("5", None),
+        ("mg", 0.001),
+        (" and 3", None),
+        ("g", 1),
+    ]
+    assert units.segment("") == []
+
+
+def test_segment_optimal():
+    """The optimal strategy leaves the fewest characters unmatched."""
+    parts = TreeMap({"ab": 1, "bcd": 2})
+    assert parts.segment("abcd") == [("ab", 1), ("cd", None)]
+    assert parts.segment("abcd", strategy="optimal") == [("a", None), ("bcd", 2)]
+    with pytest.raises(ValueError):
+        parts.segment("abcd", strategy="viterbi")
+
+
+def test_segment_keeps_text_spelling():
+    """Tokens are slices of the text, also in case-insensitive trees."""
+    tree = TreeMap({"ice": 1, "cream": 2}, case_insensitive=True)
+    assert tree.segment("IceCream") == [("Ice", 1), ("Cream", 2)]
+    raw = TreeMap({b"\xff": 0}, key_type=bytes)
+    assert raw.segment(b"a\xffb") == [(b"a", None), (b"\xff", 0), (b"b", None)]
+
+
+def test_replace_all():
+    """replace_all substitutes values for keys, leaving other text alone."""
+    slang = TreeMap({"brb": "be right back", "imo": "in my opinion"})
+    assert slang.replace_all("imo it's fine, brb") == (
+        "in my opinion it's fine, be right back"
+    )
+    assert slang.replace_all("nothing here") == "nothing here"
+    assert slang.replace_all("") == ""
+
+
+def test_replace_all_formatter():
+    """A formatter builds the replacement from the key and value."""
+    users = TreeMap({"@ann": 1, "@bob": 2})
+    result = users.replace_all("hi @ann and @bob", formatter=lambda k, v: f"<{v}:{k}>")
+    assert result == "hi <1:@ann> and <2:@bob>"
+
+
+def test_replace_all_is_single_pass():
+    """Replacements are not rescanned for further keys."""
+    swap = TreeMap({"a": "b", "b": "a"})
+    assert swap.replace_all("abba") == "baab"
+
+
+def test_replace_all_types():
+    """Replacements must match the text type; bytes trees rewrite bytes."""
+    with pytest.raises(TypeError):
+        TreeMap({"one": 1}).replace_all("one")
+    raw = TreeMap({b"\r\n": b"\n"}, key_type=bytes)
+    assert raw.replace_all(b"a\r\nb\r\n") == b"a\nb\n"
+    with pytest.raises(TypeError):
+        raw.replace_all("a")
@@ -0,0 +1,163 @@
+"""Tests for using a TreeMap from several threads."""
+
+import threading
+
+import pytest
+
+from blart import TreeMap
+
+
+def run_threads(target, count=8):
+    threads = [threading.Thread(target=target, args=(i,)) for i in range(count)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+
+
+def test_concurrent_readers():
+    """Many threads can read the same TreeMap at once."""
+    tree = TreeMap({f"key_{i:04d}": i for i in range(1000)})
+    errors = []
+
+    def read(_):
+        try:
+            for _ in range(20):
+                assert len(list(tree.prefix_iter("key_00"))) == 100
+                assert tree["key_0500"] == 500
+        except Exception as exc:  # pragma: no cover - reported below
+            errors.append(exc)
+
+    run_threads(read)
+    assert errors == []
+
+
+def test_concurrent_lookups_and_misses():
+    """get(), [] and missed lookups only read the tree, even with a
+    default_factory or ttl entries, so readers never collide."""
+    tree = TreeMap({f"key_{i:04d}": i for i in range(1000)}, default_factory=list)
+    tree.insert("short", 1, ttl=3600)
+    errors = []
+
+    def read(_):
+        try:
+            for i in range(200):
+                assert tree.get(f"key_{i:04d}") == i
+                assert tree.get("missing", -1) == -1
+                assert tree[f"key_{i:04d}"] == i
+                assert tree["short"] == 1
+        except Exception as exc:  # pragma: no cover - reported below
+            errors.append(exc)
+
+    run_threads(read)
+    assert errors == []
+    assert "missing" not in tree
+
+
+def test_loader_may_read_the_tree():
+    """The loader runs without the tree borrowed, so it can look up other keys."""
+    tree = TreeMap({"base": 10}, loader=lambda key: tree["base"] + len(key))
+    assert tree["abc"] == 13
+    assert tree.get("abcd") == 14
+    assert tree["abc"] == 13
+
+
+def test_writers_with_per_object_lock():
+    """Writers sharing a lock never lose updates."""
+    tree = TreeMap()
+    lock = threading.Lock()
+
+    def write(n):
+        for i in range(500):
+            with lock:
+                tree.increment(f"counter_{i % 10}")
+                tree[f"thread_{n}_{i:03d}"] = i
+
+    run_threads(write)
+    assert sum(tree[f"counter_{i}"] for i in range(10)) == 8 * 500
+    assert len(tree) == 10 + 8 * 500
+    tree.validate()
+
+
+def test_snapshot_is_unaffected_by_writes():
+    """A snapshot keeps the state of the tree at the time it was taken."""
+    tree = TreeMap({"a": 1, "b": 2})
+    snap = tree.snapshot()
+    tree["c"] = 3
+    tree["a"] = 10
+    del tree["b"]
+    assert len(snap) == 2
+    assert list(snap) == ["a", "b"]
+    assert snap["a"] == 1
+    assert "c" not in snap
+    assert snap.get("c", 0) == 0
+    assert snap.items() == [("a", 1), ("b", 2)]
+    assert snap.first() == ("a", 1)
+    assert snap.last() == ("b", 2)
+    assert repr(snap) == "TreeMapSnapshot(len=2)"
+    with pytest.raises(KeyError):
+        snap["c"]
+    with pytest.raises(TypeError):
+        snap["d"] = 4
+
+
+def test_snapshots_share_until_written():
+    """Each snapshot keeps its own state when the tree is written between
+    snapshots, and writes never reach back into an earlier snapshot."""
+    tree = TreeMap({"a": 0}, case_insensitive=True, substring_index=True)
+    tree.insert("ttl", 1, ttl=3600)
+    snaps = []
+    for i in range(1, 5):
+        snaps.append(tree.snapshot())
+        snaps.append(tree.snapshot())
+        tree[f"K{i}"] = i
+    tree.clear()
+    assert len(tree) == 0
+    for i, snap in enumerate(snaps):
+        n = i // 2
+        assert len(snap) == n + 2
+        assert snap["TTL"] == 1
+        assert [k for k in snap if k.startswith("K")] == [f"K{j}" for j in range(1, n + 1)]
+    tree["x"] = 1
+    assert list(tree.contains_substring("x")) == [("x", 1)]
+
+
+def test_snapshot_keeps_key_settings():
+    """Snapshots look up and decode keys like the tree they came from."""
+    tree = TreeMap({"Apple": 1, "apricot": 2}, case_insensitive=True)
+    snap = tree.snapshot()
+    assert snap["APPLE"] == 1
+    assert list(snap.prefix_iter("AP")) == [("Apple", 1), ("apricot", 2)]
+    numbers = TreeMap({5: "five"}, key_type="int").snapshot()
+    assert numbers.keys() == [5]
+
+
+def test_snapshot_iterated_while_writing():
+    """A thread can iterate a snapshot while the tree keeps changing."""
+    tree = TreeMap({f"key_{i:04d}": i for i in range(1000)})
+    snap = tree.snapshot()
+    totals = []
+    reader = threading.Thread(target=lambda: totals.append(sum(snap.values())))
+    reader.start()
+    for i in range(1000, 2000):
+        tree[f"key_{i:04d}"] = i
+    reader.join()
+    assert totals == [sum(range(1000))]
+    assert len(snap) == 1000
+
+
+def test_bulk_operations_on_separate_trees():
+    """Building, updating and clearing separate trees from several threads."""
+    results = [None] * 4
+
+    def work(n):
+        data = {f"t{n}_{i:05d}": i for i in range(20000)}
+        tree = TreeMap(data)
+        tree.update({f"u{n}_{i:05d}": i for i in range(1000)})
+        size = len(tree)
+        tree.validate()
+        tree.clear()
+        results[n] = (size, len(tree))
+
+    run_threads(work, count=4)
+    assert results == [(21000, 0)] * 4
@@ -0,0 +1,79 @@
+"""Tests for TreeMap.transaction()."""
+
+import pytest
+from blart import TreeMap
+
+
+class Abort(Exception):
+    pass
+
+
+def test_changes_are_kept_on_success():
+    tree = TreeMap({"a": 1})
+    with tree.transaction() as same:
+        assert same is tree
+        tree["b"] = 2
+        del tree["a"]
+    assert dict(tree) == {"b": 2}
+
+
+def test_changes_are_undone_on_error():
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    with pytest.raises(Abort):
+        with tree.transaction():
+            tree["a"] = 10
+            tree["d"] = 4
+            del tree["b"]
+            tree.update({"e": 5})
+            tree.retain(lambda key, value: key != "c")
+            assert dict(tree) == {"a": 10, "d": 4, "e": 5}
+            raise Abort
+    assert dict(tree) == {"a": 1, "b": 2, "c": 3}
+
+
+def test_rollback_restores_displaced_and_evicted_keys():
+    tree = TreeMap({"ab": 1, "ac": 2, "x": 3}, max_len=3, eviction="evict_last")
+    with pytest.raises(Abort):
+        with tree.transaction():
+            tree["a"] = 0
+            tree["b"] = 4
+            tree["c"] = 5
+            raise Abort
+    assert dict(tree) == {"ab": 1, "ac": 2, "x": 3}
+
+
+def test_rollback_after_clear_keeps_spelling_and_indexes():
+    tree = TreeMap(
+        {"Apple": 1, "Banana": 2}, case_insensitive=True, order_statistics=True
+    )
+    with pytest.raises(Abort):
+        with tree.transaction():
+            tree.clear()
+            tree["cherry"] = 3
+            raise Abort
+    assert list(tree) == ["Apple", "Banana"]
+    assert tree.rank("banana") == 1
+    assert tree.count_prefix("") == 2
+
+
+def test_rollback_is_reported_to_observers():
+    tree = TreeMap({"a": 1})
+    events = []
+    tree.on_change(lambda *change: events.append(change))
+    with pytest.raises(Abort):
+        with tree.transaction():
+            tree["a"] = 2
+            tree["b"] = 3
+            raise Abort
+    assert events[2:] == [("remove", "b", 3, None), ("overwrite", "a", 2, 1)]
+
+
+def test_transactions_do_not_nest():
+    tree = TreeMap()
+    with tree.transaction():
+        with pytest.raises(RuntimeError, match="already open"):
+            with tree.transaction():
+                pass
+    with tree.transaction():
+        tree["a"] = 1
+    assert dict(tree) == {"a": 1}
@@ -0,0 +1,277 @@
+"""Tests for single-traversal update operations."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_try_insert_absent_key():
+    """try_insert() should insert and return True for a new key."""
+    tree = TreeMap()
+    assert tree.try_insert("hello", "world") is True
+    assert tree["hello"] == "world"
+    assert len(tree) == 1
+
+
+def test_try_insert_existing_key_keeps_value():
+    """try_insert() should not overwrite an existing value."""
+    tree = TreeMap({"hello": "world"})
+    assert tree.try_insert("hello", "universe") is False
+    assert tree["hello"] == "world"
+    assert len(tree) == 1
+
+
+def test_try_insert_prefix_conflict():
+    """try_insert() should follow insert() semantics for prefix conflicts."""
+    tree = TreeMap({"key": 1})
+    assert tree.try_insert("key123", 2) is True
+    assert "key123" in tree
+    assert "key" not in tree
+
+
+def test_get_or_insert_missing_calls_factory():
+    """get_or_insert() should insert the factory result for a missing key."""
+    tree = TreeMap()
+    result = tree.get_or_insert("memo", lambda: [1, 2, 3])
+    assert result == [1, 2, 3]
+    assert tree["memo"] is result
+
+
+def test_get_or_insert_existing_skips_factory():
+    """get_or_insert() should not call the factory when the key exists."""
+    calls = []
+
+    def factory():
+        calls.append(1)
+        return "new"
+
+    tree = TreeMap({"memo": "old"})
+    assert tree.get_or_insert("memo", factory) == "old"
+    assert calls == []
+
+
+def test_get_or_insert_factory_error_inserts_nothing():
+    """Exceptions from the factory should propagate without inserting."""
+    tree = TreeMap()
+
+    def factory():
+        raise ValueError("boom")
+
+    with pytest.raises(ValueError):
+        tree.get_or_insert("memo", factory)
+    assert "memo" not in tree
+
+
+def test_apply_updates_existing_value():
+    """apply() should store and return func(value)."""
+    tree = TreeMap({"hits": 1})
+    assert tree.apply("hits", lambda v: v + 1) == 2
+    assert tree["hits"] == 2
+
+
+def test_apply_missing_key_raises_keyerror():
+    """apply() without a default should raise KeyError for a missing key."""
+    tree = TreeMap()
+    with pytest.raises(KeyError):
+        tree.apply("hits", lambda v: v + 1)
+    assert "hits" not in tree
+
+
+def test_apply_missing_key_uses_default():
+    """apply() should call func(default) and insert the result when missing."""
+    tree = TreeMap()
+    assert tree.apply("seen", lambda v: v + ["a"], default=[]) == ["a"]
+    assert tree["seen"] == ["a"]
+
+
+def test_apply_func_error_keeps_value():
+    """Exceptions from func should propagate and leave the value unchanged."""
+    tree = TreeMap({"hits": "one"})
+    with pytest.raises(TypeError):
+        tree.apply("hits", lambda v: v + 1)
+    assert tree["hits"] == "one"
+
+
+def test_increment_missing_key_starts_at_zero():
+    """increment() should treat a missing key as 0."""
+    tree = TreeMap()
+    assert tree.increment("count") == 1
+    assert tree.increment("other", 5) == 5
+    assert tree["count"] == 1
+
+
+def test_increment_existing_value():
+    """increment() should add delta to the stored value."""
+    tree = TreeMap({"count": 10})
+    assert tree.increment("count") == 11
+    assert tree.increment("count", -3) == 8
+    assert tree.increment("count", 0.5) == 8.5
+
+
+def test_increment_non_numeric_raises_typeerror():
+    """increment() should raise TypeError for values that can't be added."""
+    tree = TreeMap({"count": "ten"})
+    with pytest.raises(TypeError):
+        tree.increment("count")
+    assert tree["count"] == "ten"
+
+
+def test_retain_filters_in_place():
+    """retain() should remove entries whose predicate result is falsy."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3, "d": 4})
+    tree.retain(lambda key, value: value % 2 == 0)
+    assert list(tree.items()) == [("b", 2), ("d", 4)]
+
+
+def test_retain_uses_truthiness():
+    """retain() should accept any truthy/falsy return value."""
+    tree = TreeMap({"a": "", "b": "x", "c": None})
+    tree.retain(lambda key, value: value)
+    assert list(tree.keys()) == ["b"]
+
+
+def test_retain_predicate_error_propagates():
+    """Exceptions raised by the predicate should propagate."""
+    tree = TreeMap({"a": 1, "b": 2})
+
+    def predicate(key, value):
+        raise RuntimeError("stop")
+
+    with pytest.raises(RuntimeError):
+        tree.retain(predicate)
+    assert len(tree) == 2
+
+
+def test_map_values_returns_transformed_copy():
+    """map_values() should return a new TreeMap with transformed values."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    doubled = tree.map_values(lambda v: v * 2)
+    assert isinstance(doubled, TreeMap)
+    assert list(doubled.items()) == [("a", 2), ("b", 4), ("c", 6)]
+    assert list(tree.items()) == [("a", 1), ("b", 2), ("c", 3)]
+
+
+def test_map_values_empty():
+    """map_values() on an empty TreeMap should return an empty TreeMap."""
+    assert len(TreeMap().map_values(str)) == 0
+
+
+def test_map_values_func_error_propagates():
+    """Exceptions raised by func should propagate."""
+    tree = TreeMap({"a": "x"})
+    with pytest.raises(TypeError):
+        tree.map_values(lambda v: v + 1)
+
+
+def test_update_with_bad_key_leaves_tree_unchanged():
+    """Keys are all encoded before any of them is inserted."""
+    tree = TreeMap({"a": 1})
+    with pytest.raises(TypeError):
+        tree.update([("b", 2), (3, "bad")])
+    assert list(tree.items()) == [("a", 1)]
+
+
+def test_bulk_insert_last_value_wins():
+    """Repeated keys in bulk input keep the last value, like dict()."""
+    tree = TreeMap([("a", 1), ("b", 2), ("a", 3)])
+    assert list(tree.items()) == [("a", 3), ("b", 2)]
+    tree.update([("b", 4), ("b", 5)])
+    assert tree["b"] == 5
+    assert len(tree) == 2
+
+
+def test_constructor_accepts_any_mapping_or_pairs():
+    """The constructor reads the same inputs as update() and dict()."""
+
+    class Subdict(dict):
+        pass
+
+    pairs = [("b", 2), ("a", 1)]
+    source = TreeMap(pairs)
+    for data in (
+        source,
+        source.items(),
+        iter(pairs),
+        tuple(pairs),
+        Subdict(pairs),
+        (["a", 1], ["b", 2]),
+    ):
+        assert list(TreeMap(data).items()) == [("a", 1), ("b", 2)]
+
+
+def test_constructor_rejects_other_data():
+    """Data that is not a mapping or an iterable of pairs raises."""
+    with pytest.raises(TypeError):
+        TreeMap(5)
+    with pytest.raises(TypeError):
+        TreeMap([1, 2])
+    with pytest.raises(ValueError):
+        TreeMap([("a", 1, 2)])
+
+
+def test_rename_moves_value():
+    """rename() removes the old key and stores the value under the new one."""
+    tree = TreeMap({"draft": 1, "final": 2})
+    tree.rename("draft", "published")
+    assert list(tree.items()) == [("final", 2), ("published", 1)]
+    tree.rename("published", "published")
+    assert tree["published"] == 1
+
+
+def test_rename_refuses_taken_destination():
+    """Without overwrite, a taken or conflicting destination is an error."""
+    tree = TreeMap({"draft": 1, "final": 2, "bc": 3})
+    with pytest.raises(KeyError):
+        tree.rename("draft", "final")
+    with pytest.raises(KeyError):
+        tree.rename("draft", "b")
+    with pytest.raises(KeyError):
+        tree.rename("missing", "other")
+    assert list(tree.items()) == [("bc", 3), ("draft", 1), ("final", 2)]
+    tree.rename("draft", "final", overwrite=True)
+    assert list(tree.items()) == [("bc", 3), ("final", 1)]
+
+
+def test_rename_to_extension_of_itself():
+    """The old key does not block a new key that extends it."""
+    tree = TreeMap({"b": 1})
+    tree.rename("b", "bcd")
+    assert list(tree.items()) == [("bcd", 1)]
+
+
+def test_rename_case_insensitive_spelling():
+    """In case-insensitive trees rename can change only the spelling."""
+    tree = TreeMap({"Apple": 1}, case_insensitive=True)
+    tree.rename("apple", "APPLE")
+    assert list(tree) == ["APPLE"]
+
+
+def test_rename_prefix_moves_subtree():
+    """rename_prefix() re-keys every entry under a prefix."""
+    tree = TreeMap({"acme:users:1": "ann", "acme:users:2": "bob", "other:1": 0})
+    assert tree.rename_prefix("acme:", "initech:") == 2
+    assert list(tree.items()) == [
+        ("initech:users:1", "ann"),
+        ("initech:users:2", "bob"),
+        ("other:1", 0),
+    ]
+    assert tree.rename_prefix("missing:", "x:") == 0
+
+
+def test_rename_prefix_conflicts():
+    """Conflicting destinations leave the tree unchanged unless overwriting."""
+    tree = TreeMap({"a:1": 1, "a:2": 2, "b:1": 10})
+    with pytest.raises(KeyError):
+        tree.rename_prefix("a:", "b:")
+    assert list(tree.items()) == [("a:1", 1), ("a:2", 2), ("b:1", 10)]
+    assert tree.rename_prefix("a:", "b:", overwrite=True) == 2
+    assert list(tree.items()) == [("b:1", 1), ("b:2", 2)]
+    with pytest.raises(TypeError):
+        TreeMap({1: 1}, key_type="int").rename_prefix(1, 2)
+
+
+def test_rename_prefix_keeps_spelling():
+    """Case-insensitive trees keep the spelling of the rest of each key."""
+    tree = TreeMap({"Acme:Ann": 1}, case_insensitive=True)
+    tree.rename_prefix("ACME:", "Initech:")
+    assert list(tree) == ["Initech:Ann"]
+    assert tree["initech:ann"] == 1
@@ -0,0 +1,88 @@
+"""Tests for restricting values with value_type."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_int_values():
+    tree = TreeMap({"a": 1, "b": True}, value_type="int")
+    assert tree.value_type == "int"
+    assert tree.to_dict() == {"a": 1, "b": 1}
+    assert type(tree["b"]) is int
+    with pytest.raises(TypeError, match="value_type=\"int\"\\), got 'float'"):
+        tree["c"] = 1.5
+    with pytest.raises(OverflowError):
+        tree["c"] = 2**70
+    assert "c" not in tree
+
+
+def test_float_and_bytes_values_are_converted():
+    floats = TreeMap({"a": 1}, value_type="float")
+    floats["b"] = 2.5
+    assert floats.to_list() == [("a", 1.0), ("b", 2.5)]
+    assert type(floats["a"]) is float
+    raw = TreeMap(value_type="bytes")
+    raw["a"] = bytearray(b"xy")
+    raw["b"] = memoryview(b"z")
+    assert raw.to_dict() == {"a": b"xy", "b": b"z"}
+    with pytest.raises(TypeError):
+        raw["c"] = "text"
+
+
+@pytest.mark.parametrize(
+    "write",
+    [
+        lambda tree: tree.update({"x": "bad"}),
+        lambda tree: tree.setdefault("x", "bad"),
+        lambda tree: tree.insert("x", "bad"),
+        lambda tree: tree.map_values(str),
+    ],
+)
+def test_every_write_is_checked(write):
+    tree = TreeMap({"a": 1}, value_type="int")
+    with pytest.raises(TypeError):
+        write(tree)
+    assert tree.to_dict() == {"a": 1}
+
+
+def test_factories_and_loaders_are_checked():
+    loaded = TreeMap(value_type="float", loader=lambda key: 3)
+    assert type(loaded["x"]) is float
+    with pytest.raises(TypeError):
+        TreeMap(value_type="int", default_factory=list)["x"]
+
+
+def test_unknown_value_type():
+    with pytest.raises(ValueError, match="value_type must be"):
+        TreeMap(value_type="i64")
+
+
+def test_values_are_stored_natively():
+    data = {f"k{i:05}": i * 1000 for i in range(10_000)}
+    typed = TreeMap(data, value_type="int")
+    untyped = TreeMap(data)
+    assert typed.memory_usage(deep=True) < untyped.memory_usage(deep=True)
+    assert typed.memory_usage(deep=True) == typed.memory_usage()
+    assert typed == untyped
+    assert typed.aggregate() == untyped.aggregate()
+
+
+def test_native_values_survive_snapshots_versions_and_rollback():
+    tree = TreeMap({"a": b"x"}, value_type="bytes", versioned=True)
+    snapshot = tree.snapshot()
+    tree["a"] = bytearray(b"y")
+    assert snapshot["a"] == b"x"
+    assert tree.at_version(1)["a"] == b"x"
+    with pytest.raises(KeyError):
+        with tree.transaction():
+            tree["b"] = b"z"
+            raise KeyError
+    assert tree.to_dict() == {"a": b"y"}
+
+
+@pytest.mark.parametrize("option", ["store_pickled", "intern_values"])
+def test_typed_values_combine_with_object_storage(option):
+    tree = TreeMap({"a": 1}, value_type="float", **{option: True})
+    tree["b"] = 2
+    assert tree.to_dict() == {"a": 1.0, "b": 2.0}
+    assert type(tree["b"]) is float
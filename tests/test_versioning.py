@@ -0,0 +1,103 @@
+"""Tests for versioned TreeMaps and at_version()."""
+
+import pytest
+from blart import TreeMap
+
+
+def test_each_change_bumps_the_version():
+    tree = TreeMap(versioned=True)
+    assert tree.version == 0
+    tree["a"] = 1
+    tree["a"] = 2
+    tree.update({"b": 3, "c": 4})
+    del tree["a"]
+    assert tree.version == 5
+
+
+def test_at_version_reads_past_states():
+    tree = TreeMap({"a": 1}, versioned=True)
+    v1 = tree.version
+    tree["a"] = 2
+    tree["b"] = 3
+    v2 = tree.version
+    tree.clear()
+    assert dict(tree.at_version(0).items()) == {}
+    assert dict(tree.at_version(v1).items()) == {"a": 1}
+    assert dict(tree.at_version(v2).items()) == {"a": 2, "b": 3}
+    assert len(tree.at_version(tree.version)) == 0
+
+
+def test_past_states_include_displaced_keys():
+    tree = TreeMap({"ab": 1, "ac": 2}, versioned=True)
+    before = tree.version
+    tree["a"] = 3
+    assert list(tree) == ["a"]
+    assert tree.at_version(before).keys() == ["ab", "ac"]
+
+
+def test_snapshots_are_independent_of_later_changes():
+    tree = TreeMap({"a": 1}, versioned=True)
+    past = tree.at_version(tree.version)
+    tree["b"] = 2
+    assert "b" not in past
+
+
+def test_case_insensitive_spelling_is_kept():
+    tree = TreeMap({"Apple": 1}, case_insensitive=True, versioned=True)
+    before = tree.version
+    del tree["apple"]
+    tree["APPLE"] = 2
+    assert tree.at_version(before).items() == [("Apple", 1)]
+    assert list(tree.items()) == [("APPLE", 2)]
+
+
+def test_unversioned_trees():
+    tree = TreeMap({"a": 1})
+    assert tree.version is None
+    with pytest.raises(ValueError, match="versioned=True"):
+        tree.at_version(0)
+
+
+def test_future_versions_are_rejected():
+    tree = TreeMap({"a": 1}, versioned=True)
+    with pytest.raises(IndexError):
+        tree.at_version(tree.version + 1)
+
+
+def test_max_versions_bounds_the_log():
+    tree = TreeMap(versioned=True, max_versions=2)
+    for value in range(5):
+        tree["a"] = value
+    assert tree.version == 5
+    assert tree.oldest_version == 3
+    assert tree.at_version(3)["a"] == 2
+    assert tree.at_version(4)["a"] == 3
+    with pytest.raises(IndexError, match="oldest version"):
+        tree.at_version(2)
+
+
+def test_max_versions_requires_versioned():
+    with pytest.raises(ValueError, match="versioned=True"):
+        TreeMap(max_versions=10)
+    with pytest.raises(ValueError, match="at least 1"):
+        TreeMap(versioned=True, max_versions=0)
+
+
+def test_prune_versions():
+    tree = TreeMap(versioned=True)
+    tree["a"] = 1
+    tree["b"] = 2
+    tree["a"] = 3
+    assert tree.prune_versions(2) == 2
+    assert tree.oldest_version == 2
+    assert tree.version == 3
+    assert dict(tree.at_version(2).items()) == {"a": 1, "b": 2}
+    with pytest.raises(IndexError):
+        tree.at_version(1)
+    assert tree.prune_versions(1) == 0
+    with pytest.raises(IndexError):
+        tree.prune_versions(4)
+    assert tree.prune_versions(tree.version) == 1
+    assert dict(tree.at_version(tree.version).items()) == {"a": 3, "b": 2}
+    with pytest.raises(ValueError, match="versioned=True"):
+        TreeMap().prune_versions(0)
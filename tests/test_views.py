@@ -0,0 +1,67 @@
+"""Tests for live keys/values/items views."""
+
+from blart import TreeMap
+
+
+def test_views_reflect_mutations():
+    """Views created before a mutation see the new contents."""
+    tree = TreeMap({"a": 1, "b": 2})
+    keys, values, items = tree.keys(), tree.values(), tree.items()
+    tree["c"] = 3
+    del tree["a"]
+    assert len(keys) == len(values) == len(items) == 2
+    assert list(keys) == ["b", "c"]
+    assert list(values) == [2, 3]
+    assert list(items) == [("b", 2), ("c", 3)]
+
+
+def test_view_membership():
+    """Membership follows dict view semantics."""
+    tree = TreeMap({"a": 1, "b": [2]})
+    assert "a" in tree.keys()
+    assert "z" not in tree.keys()
+    assert [2] in tree.values()
+    assert 3 not in tree.values()
+    assert ("a", 1) in tree.items()
+    assert ("a", 2) not in tree.items()
+    assert ("z", 1) not in tree.items()
+    assert "a" not in tree.items()
+
+
+def test_keys_set_operations():
+    """Keys views combine with sets and other views."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    assert tree.keys() & {"a", "z"} == {"a"}
+    assert {"a", "z"} & tree.keys() == {"a"}
+    assert tree.keys() | ["z"] == {"a", "b", "c", "z"}
+    assert tree.keys() - {"a"} == {"b", "c"}
+    assert {"a", "z"} - tree.keys() == {"z"}
+    assert tree.keys() ^ {"a", "z"} == {"b", "c", "z"}
+    assert tree.keys() & {"b": 0}.keys() == {"b"}
+    assert tree.keys().isdisjoint({"x", "y"})
+    assert not tree.keys().isdisjoint(["c"])
+
+
+def test_items_set_operations():
+    """Items views support set operations on (key, value) pairs."""
+    tree = TreeMap({"a": 1, "b": 2})
+    assert tree.items() & {("a", 1), ("b", 3)} == {("a", 1)}
+    assert tree.items() - {("a", 1)} == {("b", 2)}
+
+
+def test_view_equality():
+    """Keys and items views compare equal to matching sets."""
+    tree = TreeMap({"a": 1, "b": 2})
+    assert tree.keys() == {"a", "b"}
+    assert tree.keys() == {"b": 0, "a": 0}.keys()
+    assert tree.keys() != {"a"}
+    assert tree.keys() != ["a", "b"]
+    assert tree.items() == {("a", 1), ("b", 2)}
+
+
+def test_view_reversed():
+    """Views iterate in reverse key order with reversed()."""
+    tree = TreeMap({"a": 1, "b": 2, "c": 3})
+    assert list(reversed(tree.keys())) == ["c", "b", "a"]
+    assert list(reversed(tree.values())) == [3, 2, 1]
+    assert list(reversed(tree.items()))[0] == ("c", 3)